@@ -0,0 +1,201 @@
+//! Derive macros for comdrive
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{
+    parse_macro_input, Attribute, Data, DeriveInput, Error, Fields, FieldsNamed, LitInt, LitStr,
+    Token,
+};
+
+fn named_fields<'a>(input: &'a DeriveInput, derive: &str) -> syn::Result<&'a FieldsNamed> {
+    if !input.generics.params.is_empty() {
+        return Err(Error::new_spanned(
+            &input.generics,
+            format!("#[derive({})] does not support generic structs", derive),
+        ));
+    }
+    match &input.data {
+        Data::Struct(s) => match &s.fields {
+            Fields::Named(f) => Ok(f),
+            _ => Err(Error::new_spanned(
+                &input.ident,
+                format!("#[derive({})] requires named fields", derive),
+            )),
+        },
+        _ => Err(Error::new_spanned(
+            &input.ident,
+            format!("#[derive({})] is only for structs", derive),
+        )),
+    }
+}
+
+/// cbufferの詰め込み規則でHlslPackedとConstantBufferを実装する
+///
+/// ```ignore
+/// #[derive(ConstantBuffer)]
+/// pub struct SceneConstants {
+///     pub view_proj: Float4x4,
+///     pub light_dir: Float3,
+///     pub intensity: f32,
+/// }
+/// ```
+#[proc_macro_derive(ConstantBuffer)]
+pub fn derive_constant_buffer(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    constant_buffer(&input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+fn constant_buffer(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let fields = named_fields(input, "ConstantBuffer")?;
+    let name = &input.ident;
+    let idents = fields
+        .named
+        .iter()
+        .map(|f| f.ident.as_ref().expect("named field"))
+        .collect::<Vec<_>>();
+    let names = idents.iter().map(|i| i.to_string()).collect::<Vec<_>>();
+    let types = fields.named.iter().map(|f| &f.ty).collect::<Vec<_>>();
+    let h = quote!(::comdrive::hlsl);
+
+    Ok(quote! {
+        impl #h::HlslPacked for #name {
+            const SIZE: usize = {
+                #[allow(unused_mut)]
+                let mut o = 0;
+                #(
+                    o = #h::pack_offset(
+                        o,
+                        <#types as #h::HlslPacked>::SIZE,
+                        <#types as #h::HlslPacked>::ALIGN16,
+                    ) + <#types as #h::HlslPacked>::SIZE;
+                )*
+                o
+            };
+            const ALIGN16: bool = true;
+            #[allow(unused_variables, unused_mut, unused_assignments)]
+            fn write_packed(&self, out: &mut [u8]) {
+                let mut o = 0;
+                #(
+                    o = #h::pack_offset(
+                        o,
+                        <#types as #h::HlslPacked>::SIZE,
+                        <#types as #h::HlslPacked>::ALIGN16,
+                    );
+                    #h::HlslPacked::write_packed(&self.#idents, &mut out[o..]);
+                    o += <#types as #h::HlslPacked>::SIZE;
+                )*
+            }
+        }
+        impl #h::ConstantBuffer for #name {
+            #[allow(unused_mut, unused_assignments)]
+            fn fields() -> Vec<#h::PackedField> {
+                let mut o = 0;
+                let mut v = Vec::new();
+                #(
+                    o = #h::pack_offset(
+                        o,
+                        <#types as #h::HlslPacked>::SIZE,
+                        <#types as #h::HlslPacked>::ALIGN16,
+                    );
+                    v.push(#h::PackedField {
+                        name: #names,
+                        offset: o,
+                        size: <#types as #h::HlslPacked>::SIZE,
+                    });
+                    o += <#types as #h::HlslPacked>::SIZE;
+                )*
+                v
+            }
+        }
+    })
+}
+
+/// 頂点構造体にVertexを実装する(各メンバーに#[semantic("名前", インデックス)]を付ける、インデックスは省略で0)
+///
+/// メンバーの位置を入力エレメントに使うので#[repr(C)]が必要
+///
+/// ```ignore
+/// #[derive(Clone, Copy, Vertex)]
+/// #[repr(C)]
+/// pub struct MeshVertex {
+///     #[semantic("POSITION")]
+///     pub pos: [f32; 3],
+///     #[semantic("TEXCOORD", 0)]
+///     pub uv: [f32; 2],
+///     #[semantic("COLOR")]
+///     pub color: Normalized<[u8; 4]>,
+/// }
+/// ```
+#[proc_macro_derive(Vertex, attributes(semantic))]
+pub fn derive_vertex(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    vertex(&input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+fn is_repr_c(attrs: &[Attribute]) -> syn::Result<bool> {
+    let mut c = false;
+    for a in attrs.iter().filter(|a| a.path().is_ident("repr")) {
+        a.parse_nested_meta(|m| {
+            c |= m.path.is_ident("C");
+            Ok(())
+        })?;
+    }
+    Ok(c)
+}
+fn semantic(field: &syn::Field) -> syn::Result<(LitStr, u32)> {
+    let mut found = None;
+    for a in field.attrs.iter().filter(|a| a.path().is_ident("semantic")) {
+        if found.is_some() {
+            return Err(Error::new_spanned(a, "duplicate #[semantic] attribute"));
+        }
+        found = Some(a.parse_args_with(|p: syn::parse::ParseStream| {
+            let name: LitStr = p.parse()?;
+            let index = if p.parse::<Option<Token![,]>>()?.is_some() && !p.is_empty() {
+                p.parse::<LitInt>()?.base10_parse()?
+            } else {
+                0
+            };
+            Ok((name, index))
+        })?);
+    }
+    found.ok_or_else(|| Error::new_spanned(field, "vertex members require #[semantic(\"NAME\")]"))
+}
+fn vertex(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let fields = named_fields(input, "Vertex")?;
+    if !is_repr_c(&input.attrs)? {
+        return Err(Error::new_spanned(
+            &input.ident,
+            "#[derive(Vertex)] requires #[repr(C)]",
+        ));
+    }
+    let name = &input.ident;
+    let h = quote!(::comdrive::hlsl);
+    let elements = fields
+        .named
+        .iter()
+        .map(|f| {
+            let (sem, index) = semantic(f)?;
+            let ident = f.ident.as_ref().expect("named field");
+            let ty = &f.ty;
+            Ok(quote! {
+                #h::VertexElement {
+                    semantic: concat!(#sem, "\0"),
+                    semantic_index: #index,
+                    format: <#ty as #h::VertexAttribute>::FORMAT,
+                    offset: ::std::mem::offset_of!(#name, #ident) as u32,
+                }
+            })
+        })
+        .collect::<syn::Result<Vec<_>>>()?;
+
+    Ok(quote! {
+        impl #h::Vertex for #name {
+            fn elements() -> Vec<#h::VertexElement> {
+                vec![#(#elements),*]
+            }
+        }
+    })
+}
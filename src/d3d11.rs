@@ -68,6 +68,11 @@ unsafe impl MarkForSameBits<D3D11_BIND_FLAG> for BindFlags {}
 #[repr(transparent)]
 pub struct Texture2D(*mut ID3D11Texture2D); HandleWrapper!(for Texture2D[ID3D11Texture2D] + FromRawHandle);
 impl dxgi::SurfaceChild for Texture2D { fn base(&self) -> IOResult<dxgi::Surface> { self.query_interface() } }
+impl Texture2D
+{
+    /// 共有リソースの排他制御を取り出す(MiscFlagsにSHARED_KEYEDMUTEXをつけて作られたか、そのような共有リソースを開いたもの)
+    pub fn keyed_mutex(&self) -> IOResult<dxgi::KeyedMutex> { self.query_interface() }
+}
 #[repr(transparent)]
 pub struct TextureDesc2D(D3D11_TEXTURE2D_DESC);
 impl TextureDesc2D
@@ -191,6 +196,14 @@ impl InputElement
             InputSlotClass: D3D11_INPUT_PER_VERTEX_DATA, InstanceDataStepRate: 0
         })
     }
+    pub fn per_instance(sem_name: *const c_char, sem_index: u32, format: dxgi::Format, input_slot: u32, byte_offset: u32, step_rate: u32) -> Self
+    {
+        InputElement(D3D11_INPUT_ELEMENT_DESC
+        {
+            SemanticName: sem_name, SemanticIndex: sem_index, Format: format, InputSlot: input_slot, AlignedByteOffset: byte_offset,
+            InputSlotClass: D3D11_INPUT_PER_INSTANCE_DATA, InstanceDataStepRate: step_rate
+        })
+    }
 }
 
 /// サンプラーステート
@@ -436,3 +449,67 @@ impl<'c, 'r, R: Resource + 'r> MappedResource<'c, 'r, R>
     pub fn row_pitch(&self) -> usize { self.2.RowPitch as _ }
     pub fn depth_pitch(&self) -> usize { self.2.DepthPitch as _ }
 }
+
+DebugNameByPrivateData!(for Device, ImmediateContext, DeferredContext, Texture2D, Buffer, InputLayout, SamplerState,
+    VertexShader, PixelShader, RenderTargetView, DepthStencilView, ShaderResourceView);
+/// ID3DUserDefinedAnnotationによるイベント(色は使われない)
+impl EventMarker for ImmediateContext
+{
+    fn begin_event(&mut self, _color: u32, name: &str) -> &mut Self
+    {
+        let name: Vec<u16> = name.encode_utf16().chain(Some(0)).collect();
+        if let Ok(a) = self.annotation() { unsafe { (*a.0).BeginEvent(name.as_ptr()); } }
+        self
+    }
+    fn end_event(&mut self) -> &mut Self
+    {
+        if let Ok(a) = self.annotation() { unsafe { (*a.0).EndEvent(); } }
+        self
+    }
+    fn set_marker(&mut self, _color: u32, name: &str) -> &mut Self
+    {
+        let name: Vec<u16> = name.encode_utf16().chain(Some(0)).collect();
+        if let Ok(a) = self.annotation() { unsafe { (*a.0).SetMarker(name.as_ptr()); } }
+        self
+    }
+}
+impl ImmediateContext
+{
+    fn annotation(&self) -> IOResult<ComPtr<winapi::um::d3d11_1::ID3DUserDefinedAnnotation>>
+    {
+        use winapi::um::d3d11_1::ID3DUserDefinedAnnotation;
+        let mut handle = null_mut();
+        unsafe { (*self.0).QueryInterface(&ID3DUserDefinedAnnotation::uuidof(), &mut handle).to_result_with(|| ComPtr(handle as _)) }
+    }
+}
+
+impl Device
+{
+    fn device1(&self) -> IOResult<ComPtr<winapi::um::d3d11_1::ID3D11Device1>>
+    {
+        use winapi::um::d3d11_1::ID3D11Device1;
+        let mut handle = null_mut();
+        unsafe { (*self.0).QueryInterface(&ID3D11Device1::uuidof(), &mut handle).to_result_with(|| ComPtr(handle as _)) }
+    }
+    /// D3D12などで共有されたリソースを開く(Q: Texture2Dなど)
+    pub fn open_shared_resource<Q>(&self, handle: &crate::d3d12::SharedHandle<crate::d3d12::Resource>) -> IOResult<Q>
+        where Q: Handle + FromRawHandle<<Q as Handle>::RawType>
+    {
+        let dev1 = self.device1()?;
+        let mut p = null_mut();
+        unsafe { (*dev1.0).OpenSharedResource1(handle.as_raw(), &Q::RawType::uuidof(), &mut p).to_result_with(|| Q::from_raw_handle(p as _)) }
+    }
+    /// 名前つきで共有されたリソースを開く
+    pub fn open_shared_resource_by_name<Q>(&self, name: &str) -> IOResult<Q> where Q: Handle + FromRawHandle<<Q as Handle>::RawType>
+    {
+        use winapi::shared::dxgi1_2::{DXGI_SHARED_RESOURCE_READ, DXGI_SHARED_RESOURCE_WRITE};
+        let dev1 = self.device1()?;
+        let name: Vec<u16> = name.encode_utf16().chain(Some(0)).collect();
+        let mut p = null_mut();
+        unsafe
+        {
+            (*dev1.0).OpenSharedResourceByName(name.as_ptr(), DXGI_SHARED_RESOURCE_READ | DXGI_SHARED_RESOURCE_WRITE, &Q::RawType::uuidof(), &mut p)
+                .to_result_with(|| Q::from_raw_handle(p as _))
+        }
+    }
+}
@@ -15,6 +15,55 @@ use winapi::um::d3d12sdklayers::*;
 use winapi::um::d3dcommon::*;
 use winapi::um::d3dcompiler::{D3DGetBlobPart, D3D_BLOB_ROOT_SIGNATURE};
 
+mod bindless;
+mod buffer;
+mod bundle;
+mod commandpool;
+mod debug;
+mod dred;
+mod ext;
+mod features;
+mod graph;
+mod indirect;
+mod pipeline;
+mod query;
+mod raytracing;
+mod readback;
+mod recording;
+mod renderpass;
+mod residency;
+mod scheduler;
+mod sharing;
+mod streaming;
+mod tiled;
+mod upload;
+mod validation;
+mod views;
+pub use self::bindless::*;
+pub use self::buffer::*;
+pub use self::bundle::*;
+pub use self::commandpool::*;
+pub use self::debug::*;
+pub use self::dred::*;
+pub use self::ext::*;
+pub use self::features::*;
+pub use self::graph::*;
+pub use self::indirect::*;
+pub use self::pipeline::*;
+pub use self::query::*;
+pub use self::raytracing::*;
+pub use self::readback::*;
+pub use self::recording::*;
+pub use self::renderpass::*;
+pub use self::residency::*;
+pub use self::scheduler::*;
+pub use self::sharing::*;
+pub use self::streaming::*;
+pub use self::tiled::*;
+pub use self::upload::*;
+pub use self::validation::*;
+pub use self::views::*;
+
 pub use winapi::um::d3d12::D3D12_DEFAULT_SAMPLE_MASK as DefaultSampleMask;
 pub use winapi::um::d3d12::D3D12_GRAPHICS_PIPELINE_STATE_DESC as GraphicsPipelineStateDesc;
 pub use winapi::um::d3d12::D3D12_INDEX_BUFFER_VIEW as IndexBufferView;
@@ -24,6 +73,16 @@ pub use winapi::um::d3d12::D3D12_RASTERIZER_DESC as RasterizerDesc;
 pub use winapi::um::d3d12::D3D12_RESOURCE_ALLOCATION_INFO as ResourceAllocationInfo;
 pub use winapi::um::d3d12::D3D12_SHADER_BYTECODE as ShaderBytecode;
 pub use winapi::um::d3d12::D3D12_VERTEX_BUFFER_VIEW as VertexBufferView;
+
+/// 引数の誤り(ErrorKind::InvalidInput)
+pub(crate) fn invalid<T>(msg: String) -> IOResult<T> {
+    Err(IOError::new(std::io::ErrorKind::InvalidInput, msg))
+}
+/// alignmentの倍数に切り上げる(alignmentが0なら1とみなす)
+pub(crate) fn align_up(v: u64, alignment: u64) -> u64 {
+    let a = alignment.max(1);
+    v.div_ceil(a) * a
+}
 #[repr(C)]
 #[derive(Debug, Clone, PartialEq, Eq, Copy)]
 pub enum FillMode {
@@ -277,6 +336,14 @@ impl CommandQueue {
     pub fn wait(&mut self, fence: &Fence, value: u64) -> IOResult<&Self> {
         unsafe { (*self.0).Wait(fence.0, value).to_result(self) }
     }
+    /// キューのタイプ
+    pub fn command_type(&self) -> CommandType {
+        match unsafe { (*self.0).GetDesc() }.Type {
+            D3D12_COMMAND_LIST_TYPE_COMPUTE => CommandType::Compute,
+            D3D12_COMMAND_LIST_TYPE_COPY => CommandType::Copy,
+            _ => CommandType::Direct,
+        }
+    }
 }
 unsafe impl Sync for CommandQueue {}
 unsafe impl Send for CommandQueue {}
@@ -667,6 +734,10 @@ impl Resource {
     pub fn gpu_virtual_address(&self) -> GraphicsVirtualPtr {
         GraphicsVirtualPtr(unsafe { (*self.0).GetGPUVirtualAddress() })
     }
+    /// リソースの詳細を取得
+    pub fn desc(&self) -> ResourceDesc {
+        ResourceDesc(unsafe { (*self.0).GetDesc() })
+    }
 
     /// 強制リリース
     pub fn release(&mut self) {
@@ -722,6 +793,16 @@ impl HeapProperty {
             VisibleNodeMask: 0,
         })
     }
+    /// リードバックヒープ(CPU読みこみ可能)
+    pub fn readback() -> Self {
+        HeapProperty(D3D12_HEAP_PROPERTIES {
+            Type: D3D12_HEAP_TYPE_READBACK,
+            CPUPageProperty: D3D12_CPU_PAGE_PROPERTY_UNKNOWN,
+            MemoryPoolPreference: D3D12_MEMORY_POOL_UNKNOWN,
+            CreationNodeMask: 0,
+            VisibleNodeMask: 0,
+        })
+    }
 }
 
 /// ヒープオブジェクト(リソースをまとめる)
@@ -1360,6 +1441,15 @@ impl Fence {
     pub fn signal(&mut self, new_value: u64) -> IOResult<()> {
         unsafe { (*self.0).Signal(new_value) }.checked()
     }
+
+    /// 値がvalueに達するまで待つ
+    pub fn wait(&mut self, value: u64) -> IOResult<()> {
+        if self.completed_value() >= value {
+            return Ok(());
+        }
+        // イベントにnullを渡すと完了するまで戻らない
+        self.set_event_notification(value, std::ptr::null_mut())
+    }
 }
 unsafe impl AsRawHandle<ID3D12DeviceChild> for Fence {
     fn as_raw_handle(&self) -> *mut ID3D12DeviceChild {
@@ -1420,6 +1510,15 @@ impl GraphicsCommandList {
         unsafe { (*self.0).ResourceBarrier(b.len() as _, b.as_ptr()) };
         self
     }
+    /// リソースの内容を破棄する(エイリアスされたRT/DSの初期化などに使う)
+    pub fn discard_resource(
+        &mut self,
+        resource: &Resource,
+        region: Option<&D3D12_DISCARD_REGION>,
+    ) -> &mut Self {
+        unsafe { (*self.0).DiscardResource(resource.0, region.map_or(std::ptr::null(), |r| r)) };
+        self
+    }
 
     /// リソースをコピーする
     pub fn copy_resource(&mut self, src: &Resource, dst: &Resource) -> &mut Self {
@@ -1476,6 +1575,32 @@ impl GraphicsCommandList {
         unsafe { (*self.0).ClearRenderTargetView(target, color, 0, std::ptr::null()) };
         self
     }
+    /// 深度ステンシルバッファのクリア(Noneの方はクリアしない)
+    pub fn clear_depth_stencil_view(
+        &mut self,
+        target: D3D12_CPU_DESCRIPTOR_HANDLE,
+        depth: Option<f32>,
+        stencil: Option<u8>,
+    ) -> &mut Self {
+        let mut flags = 0;
+        if depth.is_some() {
+            flags |= D3D12_CLEAR_FLAG_DEPTH;
+        }
+        if stencil.is_some() {
+            flags |= D3D12_CLEAR_FLAG_STENCIL;
+        }
+        unsafe {
+            (*self.0).ClearDepthStencilView(
+                target,
+                flags,
+                depth.unwrap_or(0.0),
+                stencil.unwrap_or(0),
+                0,
+                std::ptr::null(),
+            )
+        };
+        self
+    }
 
     /// パイプラインステート/ルートシグネチャの設定
     pub fn set_pipeline_state(
@@ -1524,6 +1649,25 @@ impl GraphicsCommandList {
         };
         self
     }
+    /// ルート定数の設定(cbufferの規則で詰め込んだ構造体)
+    pub fn set_root_constant_struct<C: crate::hlsl::ConstantBuffer>(
+        &mut self,
+        param_index: u32,
+        offset: u32,
+        value: &C,
+    ) -> &mut Self {
+        let bytes = value.to_bytes();
+        let count = C::SIZE.div_ceil(4);
+        unsafe {
+            (*self.0).SetGraphicsRoot32BitConstants(
+                param_index,
+                count as _,
+                bytes.as_ptr() as _,
+                offset,
+            )
+        };
+        self
+    }
     /// ルート定数バッファの設定
     pub fn set_root_constant_buffer(
         &mut self,
@@ -1628,6 +1772,11 @@ impl GraphicsCommandList {
         unsafe { (*self.0).DrawIndexedInstanced(index_count, instance_count, 0, vertex_offset, 0) };
         self
     }
+    /// コンピュートシェーダのスレッドグループを発行
+    pub fn dispatch(&mut self, x: u32, y: u32, z: u32) -> &mut Self {
+        unsafe { (*self.0).Dispatch(x, y, z) };
+        self
+    }
 
     /// バンドルバッファを実行
     pub fn execute(&mut self, cmd: &GraphicsCommandList) -> &mut Self {
@@ -1765,11 +1914,35 @@ impl ResourceBarrier {
             },
         })
     }
+    /// UAVバリア(UAVへの書き込みの完了を待つ)
+    pub fn unordered_access(target: Option<&Resource>) -> Self {
+        let mut b = D3D12_RESOURCE_BARRIER {
+            Type: D3D12_RESOURCE_BARRIER_TYPE_UAV,
+            Flags: 0,
+            u: unsafe { std::mem::zeroed() },
+        };
+        unsafe {
+            *b.u.UAV_mut() = D3D12_RESOURCE_UAV_BARRIER {
+                pResource: target.map(|x| x.0).unwrap_or(std::ptr::null_mut()),
+            };
+        }
+        ResourceBarrier(b)
+    }
     /// トランジション(リソースの状態を変える)
     pub fn transition(
         target: &Resource,
         before: D3D12_RESOURCE_STATES,
         after: D3D12_RESOURCE_STATES,
+    ) -> Self {
+        Self::transition_subresource(target, 0, before, after)
+    }
+    /// サブリソースを指定してトランジション
+    /// (D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCESで全体)
+    pub fn transition_subresource(
+        target: &Resource,
+        subresource: u32,
+        before: D3D12_RESOURCE_STATES,
+        after: D3D12_RESOURCE_STATES,
     ) -> Self {
         ResourceBarrier(D3D12_RESOURCE_BARRIER {
             Type: D3D12_RESOURCE_BARRIER_TYPE_TRANSITION,
@@ -1777,7 +1950,7 @@ impl ResourceBarrier {
             u: unsafe {
                 *std::mem::transmute::<_, &_>(&D3D12_RESOURCE_TRANSITION_BARRIER {
                     pResource: target.0,
-                    Subresource: 0,
+                    Subresource: subresource,
                     StateBefore: before,
                     StateAfter: after,
                 })
@@ -0,0 +1,283 @@
+//! Bindless Descriptors
+
+use super::*;
+use std::collections::VecDeque;
+
+/// バインドレス用ルートシグネチャのルート定数のパラメータ番号(b0, space0)
+pub const BINDLESS_ROOT_CONSTANTS_PARAMETER: u32 = 0;
+/// バインドレス用ルートシグネチャのデスクリプタテーブルのパラメータ番号
+pub const BINDLESS_TABLE_PARAMETER: u32 = 1;
+/// SRVの配列のレジスタ空間(`Texture2D t[] : register(t0, space1)`)
+pub const BINDLESS_SRV_SPACE: u32 = 1;
+/// UAVの配列のレジスタ空間(`RWTexture2D<float4> u[] : register(u0, space2)`)
+pub const BINDLESS_UAV_SPACE: u32 = 2;
+/// CBVの配列のレジスタ空間(`ConstantBuffer<T> c[] : register(b0, space3)`)
+pub const BINDLESS_CBV_SPACE: u32 = 3;
+
+/// ヒープ全体を指す上限なしのデスクリプタレンジ(SRV/UAV/CBVがそれぞれ別の空間で同じ範囲を見る)
+pub fn bindless_descriptor_ranges() -> [D3D12_DESCRIPTOR_RANGE; 3] {
+    let range = |t, space| D3D12_DESCRIPTOR_RANGE {
+        RangeType: t,
+        NumDescriptors: !0,
+        BaseShaderRegister: 0,
+        RegisterSpace: space,
+        OffsetInDescriptorsFromTableStart: 0,
+    };
+    [
+        range(D3D12_DESCRIPTOR_RANGE_TYPE_SRV, BINDLESS_SRV_SPACE),
+        range(D3D12_DESCRIPTOR_RANGE_TYPE_UAV, BINDLESS_UAV_SPACE),
+        range(D3D12_DESCRIPTOR_RANGE_TYPE_CBV, BINDLESS_CBV_SPACE),
+    ]
+}
+
+/// バインドレスヒープの番号の割り当て(解放された番号はGPUが使い終わってから再利用する)
+pub struct BindlessIndexAllocator {
+    capacity: u32,
+    next: u32,
+    free: Vec<u32>,
+    /// (解放したときのフェンス値, 番号)
+    retired: VecDeque<(u64, u32)>,
+    live: Vec<bool>,
+}
+impl BindlessIndexAllocator {
+    pub fn new(capacity: u32) -> Self {
+        BindlessIndexAllocator {
+            capacity,
+            next: 0,
+            free: Vec::new(),
+            retired: VecDeque::new(),
+            live: Vec::new(),
+        }
+    }
+
+    pub fn capacity(&self) -> u32 {
+        self.capacity
+    }
+    /// 使用中の番号の数
+    pub fn live_count(&self) -> u32 {
+        self.next - self.free.len() as u32 - self.retired.len() as u32
+    }
+    /// 再利用を待っている番号の数
+    pub fn retired_count(&self) -> usize {
+        self.retired.len()
+    }
+    pub fn is_live(&self, index: u32) -> bool {
+        self.live.get(index as usize).copied().unwrap_or(false)
+    }
+
+    /// 番号を割り当てる(空きがなければNone)
+    pub fn allocate(&mut self) -> Option<u32> {
+        let index = match self.free.pop() {
+            Some(n) => n,
+            None if self.next < self.capacity => {
+                self.next += 1;
+                self.live.push(false);
+                self.next - 1
+            }
+            None => return None,
+        };
+        self.live[index as usize] = true;
+        Some(index)
+    }
+    /// 番号を解放する(fence_valueが完了するまで再利用しない)
+    pub fn release(&mut self, index: u32, fence_value: u64) -> IOResult<()> {
+        if !self.is_live(index) {
+            return invalid(format!("bindless index {} is not allocated", index));
+        }
+        self.live[index as usize] = false;
+        let at = self
+            .retired
+            .iter()
+            .rposition(|&(v, _)| v <= fence_value)
+            .map_or(0, |n| n + 1);
+        self.retired.insert(at, (fence_value, index));
+        Ok(())
+    }
+    /// completedまで完了した番号を再利用できるようにする
+    pub fn reclaim(&mut self, completed: u64) {
+        while let Some(&(v, index)) = self.retired.front() {
+            if v > completed {
+                break;
+            }
+            self.free.push(index);
+            self.retired.pop_front();
+        }
+    }
+}
+
+/// リソースごとに固定の番号を持つシェーダから見えるCBV/SRV/UAVヒープ
+///
+/// 番号はルート定数などでシェーダに渡し、ヒープ全体を指すデスクリプタテーブル(new_bindless_root_signature)から引く
+pub struct BindlessHeap {
+    device: Device,
+    heap: DescriptorHeap,
+    indices: BindlessIndexAllocator,
+}
+impl Device {
+    /// バインドレスヒープの作成
+    pub fn new_bindless_heap(&self, capacity: u32) -> IOResult<BindlessHeap> {
+        Ok(BindlessHeap {
+            device: self.clone(),
+            heap: self.new_descriptor_heap(
+                DescriptorHeapContents::ShaderViews,
+                capacity as _,
+                true,
+            )?,
+            indices: BindlessIndexAllocator::new(capacity),
+        })
+    }
+
+    /// バインドレスヒープ用のルートシグネチャを作成
+    ///
+    /// パラメータ0はroot_constants個のルート定数(b0, space0)、パラメータ1はヒープ全体を指すテーブル。
+    /// 上限なしのUAV/CBVのレンジにはリソースバインディングティア3が必要
+    pub fn new_bindless_root_signature(
+        &self,
+        root_constants: usize,
+        samplers: &[StaticSampler],
+        flags: D3D12_ROOT_SIGNATURE_FLAGS,
+    ) -> IOResult<RootSignature> {
+        let ranges = bindless_descriptor_ranges();
+        self.new_root_signature(
+            &[
+                RootParameter::constant(D3D12_SHADER_VISIBILITY_ALL, root_constants, 0, 0),
+                RootParameter::from_descriptor_table(D3D12_SHADER_VISIBILITY_ALL, &ranges),
+            ],
+            samplers,
+            flags,
+        )
+    }
+}
+impl BindlessHeap {
+    pub fn heap(&self) -> &DescriptorHeap {
+        &self.heap
+    }
+    pub fn indices(&self) -> &BindlessIndexAllocator {
+        &self.indices
+    }
+    /// BINDLESS_TABLE_PARAMETERに設定するテーブルの先頭
+    pub fn table_base(&self) -> DeviceDescriptorHandle {
+        self.heap.device_descriptor_handle_base()
+    }
+    /// 番号のデスクリプタのCPUハンドル
+    pub fn host_handle(&self, index: u32) -> HostDescriptorHandle {
+        self.heap.host_descriptor_handle_base().offset(index as _)
+    }
+
+    fn allocate(&mut self) -> IOResult<u32> {
+        self.indices.allocate().ok_or_else(|| {
+            IOError::other(format!(
+                "bindless heap is full ({} descriptors)",
+                self.indices.capacity()
+            ))
+        })
+    }
+    /// SRVを登録する(descがNoneならリソースの既定のビュー)
+    pub fn register_srv(&mut self, res: &Resource, desc: Option<&ViewDesc>) -> IOResult<u32> {
+        let desc = desc.map(ViewDesc::srv).transpose()?;
+        let index = self.allocate()?;
+        self.device
+            .create_shader_resource_view(res, desc.as_ref(), self.host_handle(index).into());
+        Ok(index)
+    }
+    /// UAVを登録する(descがNoneならリソースの既定のビュー)
+    pub fn register_uav(
+        &mut self,
+        res: &Resource,
+        counter: Option<&Resource>,
+        desc: Option<&ViewDesc>,
+    ) -> IOResult<u32> {
+        let desc = desc.map(ViewDesc::uav).transpose()?;
+        let index = self.allocate()?;
+        self.device.create_unordered_access_view(
+            res,
+            counter,
+            desc.as_ref(),
+            self.host_handle(index).into(),
+        );
+        Ok(index)
+    }
+    /// CBVを登録する
+    pub fn register_cbv(&mut self, location: GraphicsVirtualPtr, size: u32) -> IOResult<u32> {
+        let index = self.allocate()?;
+        if let Err(e) =
+            self.device
+                .create_constant_buffer_view(location, size, self.host_handle(index).into())
+        {
+            self.indices.release(index, 0)?;
+            return Err(e);
+        }
+        Ok(index)
+    }
+
+    /// 番号を解放する(フェンスがfence_valueに達するまで再利用しない)
+    pub fn release(&mut self, index: u32, fence_value: u64) -> IOResult<()> {
+        self.indices.release(index, fence_value)
+    }
+    /// GPUが使い終わった番号を再利用できるようにする
+    pub fn reclaim<F: FenceValue + ?Sized>(&mut self, fence: &F) {
+        self.indices.reclaim(fence.completed_value())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn indices_are_reused_after_their_fence() {
+        let mut a = BindlessIndexAllocator::new(3);
+        assert_eq!(
+            (a.allocate(), a.allocate(), a.allocate(), a.allocate()),
+            (Some(0), Some(1), Some(2), None)
+        );
+        a.release(1, 5).unwrap();
+        a.release(0, 3).unwrap();
+        assert_eq!(a.live_count(), 1);
+        assert_eq!(a.retired_count(), 2);
+        assert!(!a.is_live(0) && !a.is_live(1) && a.is_live(2));
+
+        // GPUが使い終わるまで再利用しない
+        assert_eq!(a.allocate(), None);
+        a.reclaim(4);
+        assert_eq!(a.retired_count(), 1);
+        assert_eq!(a.allocate(), Some(0));
+        assert_eq!(a.allocate(), None);
+        a.reclaim(5);
+        assert_eq!(a.retired_count(), 0);
+        assert_eq!(a.allocate(), Some(1));
+        assert_eq!(a.live_count(), 3);
+    }
+
+    #[test]
+    fn releasing_unallocated_indices_fails() {
+        let mut a = BindlessIndexAllocator::new(4);
+        let n = a.allocate().unwrap();
+        a.release(n, 1).unwrap();
+        let e = a.release(n, 1).unwrap_err();
+        assert_eq!(e.kind(), std::io::ErrorKind::InvalidInput);
+        assert!(a.release(3, 1).is_err());
+        assert!(a.release(7, 1).is_err());
+        assert_eq!(a.retired_count(), 1);
+    }
+
+    #[test]
+    fn descriptor_ranges_cover_the_whole_heap() {
+        let r = bindless_descriptor_ranges();
+        let summary = r
+            .iter()
+            .map(|r| (r.RangeType, r.RegisterSpace, r.BaseShaderRegister))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            [
+                (D3D12_DESCRIPTOR_RANGE_TYPE_SRV, BINDLESS_SRV_SPACE, 0),
+                (D3D12_DESCRIPTOR_RANGE_TYPE_UAV, BINDLESS_UAV_SPACE, 0),
+                (D3D12_DESCRIPTOR_RANGE_TYPE_CBV, BINDLESS_CBV_SPACE, 0),
+            ]
+        );
+        assert!(r
+            .iter()
+            .all(|r| r.NumDescriptors == u32::MAX && r.OffsetInDescriptorsFromTableStart == 0));
+    }
+}
@@ -0,0 +1,402 @@
+//! Typed Buffers
+
+use super::*;
+use std::marker::PhantomData;
+
+/// バッファの用途(要素の並べ方が変わる)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BufferKind {
+    Vertex,
+    Index,
+    /// 定数バッファの配列(各要素は256バイト単位に置く)
+    Constant,
+    Structured,
+    /// バイトアドレスバッファ(要素の大きさは4バイト単位)
+    Raw,
+}
+
+/// インデックスとして使える型
+pub trait IndexElement: Copy {
+    const FORMAT: dxgi::Format;
+}
+impl IndexElement for u16 {
+    const FORMAT: dxgi::Format = DXGI_FORMAT_R16_UINT;
+}
+impl IndexElement for u32 {
+    const FORMAT: dxgi::Format = DXGI_FORMAT_R32_UINT;
+}
+
+/// 型つきのインデックスバッファビューの作成
+pub fn typed_index_buffer_view<I: IndexElement>(
+    location: GraphicsVirtualPtr,
+    element_count: usize,
+) -> IndexBufferView {
+    IndexBufferView {
+        BufferLocation: location.0,
+        SizeInBytes: (size_of::<I>() * element_count) as _,
+        Format: I::FORMAT,
+    }
+}
+
+/// 型つきバッファの要素の配置
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BufferLayout {
+    pub kind: BufferKind,
+    /// 要素1つぶんの大きさ
+    pub element_size: u64,
+    /// 要素の間隔
+    pub stride: u64,
+    pub count: u64,
+}
+impl BufferLayout {
+    /// 要素の型から配置を決める
+    pub fn new<T>(kind: BufferKind, count: u64) -> IOResult<Self> {
+        let element_size = size_of::<T>() as u64;
+        if element_size == 0 {
+            return invalid("buffer elements must not be zero-sized".to_owned());
+        }
+        let stride = match kind {
+            BufferKind::Constant => align_up(element_size, CONSTANT_BUFFER_ALIGNMENT as _),
+            BufferKind::Raw if element_size % 4 != 0 => {
+                return invalid(format!(
+                    "raw buffer elements must be a multiple of 4 bytes (got {})",
+                    element_size
+                ))
+            }
+            _ => element_size,
+        };
+
+        Ok(BufferLayout {
+            kind,
+            element_size,
+            stride,
+            count,
+        })
+    }
+
+    /// バッファ全体の大きさ
+    pub fn byte_size(&self) -> u64 {
+        self.stride * self.count
+    }
+    /// 要素の位置(バッファ先頭からのバイト数)
+    pub fn element_offset(&self, index: u64) -> IOResult<u64> {
+        if index >= self.count {
+            return invalid(format!(
+                "element {} is out of range (buffer has {})",
+                index, self.count
+            ));
+        }
+        Ok(self.stride * index)
+    }
+    /// 要素の範囲が占めるバイト範囲(最後の要素の後ろのパディングは含まない)
+    pub fn byte_range(&self, elements: std::ops::Range<u64>) -> IOResult<std::ops::Range<u64>> {
+        if elements.start > elements.end || elements.end > self.count {
+            return invalid(format!(
+                "elements {:?} are out of range (buffer has {})",
+                elements, self.count
+            ));
+        }
+        if elements.start == elements.end {
+            let p = self.stride * elements.start;
+            return Ok(p..p);
+        }
+        Ok(self.stride * elements.start..self.stride * (elements.end - 1) + self.element_size)
+    }
+    /// SRV/UAV用のビューの記述(定数/頂点/インデックスバッファは構造化バッファとして見る)
+    pub fn view_desc(&self, elements: std::ops::Range<u64>) -> IOResult<ViewDesc> {
+        let bytes = self.byte_range(elements.clone())?;
+        match self.kind {
+            BufferKind::Raw => Ok(ViewDesc::raw_buffer(
+                bytes.start / 4,
+                ((bytes.end - bytes.start) / 4) as _,
+            )),
+            _ => Ok(ViewDesc::structured_buffer(
+                self.stride as _,
+                elements.start,
+                (elements.end - elements.start) as _,
+            )),
+        }
+    }
+}
+
+/// 型つきバッファ
+pub struct Buffer<T> {
+    resource: Resource,
+    layout: BufferLayout,
+    _element: PhantomData<T>,
+}
+unsafe impl<T> Sync for Buffer<T> {}
+unsafe impl<T> Send for Buffer<T> {}
+impl Device {
+    /// 型つきバッファの作成
+    pub fn new_typed_buffer<T: Copy>(
+        &self,
+        heap_props: &HeapProperty,
+        kind: BufferKind,
+        count: u64,
+        flags: ResourceFlag,
+        initial_state: ResourceState,
+    ) -> IOResult<Buffer<T>> {
+        let layout = BufferLayout::new::<T>(kind, count)?;
+        let desc = ResourceDesc::buffer(layout.byte_size() as _).flags(flags);
+        let resource = self.new_resource_committed(heap_props, &desc, initial_state, None)?;
+
+        Ok(Buffer {
+            resource,
+            layout,
+            _element: PhantomData,
+        })
+    }
+}
+impl<T: Copy> Buffer<T> {
+    /// 既存のバッファリソースを型つきで扱う
+    pub fn from_resource(resource: Resource, kind: BufferKind, count: u64) -> IOResult<Self> {
+        let layout = BufferLayout::new::<T>(kind, count)?;
+        let desc = resource.desc();
+        let desc = desc.as_ref();
+        if desc.Dimension != D3D12_RESOURCE_DIMENSION_BUFFER {
+            return invalid("resource is not a buffer".to_owned());
+        }
+        if desc.Width < layout.byte_size() {
+            return invalid(format!(
+                "buffer has {} bytes but {} elements need {}",
+                desc.Width,
+                count,
+                layout.byte_size()
+            ));
+        }
+
+        Ok(Buffer {
+            resource,
+            layout,
+            _element: PhantomData,
+        })
+    }
+
+    pub fn resource(&self) -> &Resource {
+        &self.resource
+    }
+    pub fn into_resource(self) -> Resource {
+        self.resource
+    }
+    pub fn layout(&self) -> &BufferLayout {
+        &self.layout
+    }
+    pub fn len(&self) -> u64 {
+        self.layout.count
+    }
+    pub fn is_empty(&self) -> bool {
+        self.layout.count == 0
+    }
+
+    /// 要素のGPU仮想アドレス
+    pub fn element_address(&self, index: u64) -> IOResult<GraphicsVirtualPtr> {
+        let offset = self.layout.element_offset(index)?;
+        Ok(self.resource.gpu_virtual_address().offset(offset as _))
+    }
+
+    /// 要素の範囲をマップする(範囲は要素の番号で、Option<Range>も要素単位として扱う)
+    pub fn map<R: MappingRange>(&mut self, elements: R) -> IOResult<MappedBuffer<'_, T>> {
+        let elements = match elements.into_range_object() {
+            Some(r) => r.Begin as u64..r.End as u64,
+            None => 0..self.layout.count,
+        };
+        let bytes = self.layout.byte_range(elements.clone())?;
+        let ptr = self
+            .resource
+            .map(bytes.start as usize..bytes.end as usize)?;
+
+        Ok(MappedBuffer {
+            ptr: ptr as *mut u8,
+            bytes,
+            elements,
+            stride: self.layout.stride,
+            buffer: self,
+        })
+    }
+
+    /// SRV/UAV用のビューの記述
+    pub fn view_desc(&self, elements: std::ops::Range<u64>) -> IOResult<ViewDesc> {
+        self.layout.view_desc(elements)
+    }
+    /// 頂点バッファビュー(全体)
+    pub fn vertex_buffer_view(&self) -> VertexBufferView {
+        VertexBufferView {
+            BufferLocation: self.resource.gpu_virtual_address().0,
+            StrideInBytes: self.layout.stride as _,
+            SizeInBytes: self.layout.byte_size() as _,
+        }
+    }
+    /// 要素ひとつぶんの定数バッファビューを作成する(Constantで作ったバッファのみ)
+    pub fn create_constant_buffer_view(
+        &self,
+        device: &Device,
+        index: u64,
+        handle: D3D12_CPU_DESCRIPTOR_HANDLE,
+    ) -> IOResult<()> {
+        if self.layout.kind != BufferKind::Constant {
+            return invalid(format!(
+                "{:?} buffer elements are not 256-byte aligned",
+                self.layout.kind
+            ));
+        }
+        device.create_constant_buffer_view(
+            self.element_address(index)?,
+            self.layout.stride as _,
+            handle,
+        )
+    }
+}
+impl<I: IndexElement> Buffer<I> {
+    /// インデックスバッファビュー(全体)
+    pub fn index_buffer_view(&self) -> IndexBufferView {
+        typed_index_buffer_view::<I>(self.resource.gpu_virtual_address(), self.layout.count as _)
+    }
+}
+
+/// マップ中の型つきバッファ(ドロップ時にマップした範囲を書き込み範囲としてアンマップする)
+pub struct MappedBuffer<'b, T: Copy> {
+    buffer: &'b mut Buffer<T>,
+    ptr: *mut u8,
+    bytes: std::ops::Range<u64>,
+    elements: std::ops::Range<u64>,
+    stride: u64,
+}
+impl<T: Copy> MappedBuffer<'_, T> {
+    /// マップした要素の範囲
+    pub fn elements(&self) -> std::ops::Range<u64> {
+        self.elements.clone()
+    }
+    fn element_ptr(&self, index: u64) -> Option<*mut T> {
+        if !self.elements.contains(&index) {
+            return None;
+        }
+        Some(unsafe { self.ptr.add((self.stride * index) as _) as *mut T })
+    }
+    /// 要素への参照(番号はバッファ全体での番号)
+    ///
+    /// # Safety
+    /// マップした領域の中身はTとして有効な値とは限らない(boolや列挙型など)。
+    /// 読み出す要素にはTとして有効な値が書き込まれていること
+    pub unsafe fn element_mut(&mut self, index: u64) -> Option<&mut T> {
+        self.element_ptr(index).map(|p| &mut *p)
+    }
+    /// 要素を隙間なく並べたスライスとして見る(定数バッファのように間隔が空く場合はエラー)
+    ///
+    /// # Safety
+    /// element_mutと同じく、読み出す要素にはTとして有効な値が書き込まれていること
+    pub unsafe fn as_mut_slice(&mut self) -> IOResult<&mut [T]> {
+        if self.stride != size_of::<T>() as u64 {
+            return invalid(format!(
+                "elements are {} bytes apart and cannot be viewed as a slice",
+                self.stride
+            ));
+        }
+        let len = (self.elements.end - self.elements.start) as usize;
+        Ok(std::slice::from_raw_parts_mut(
+            self.ptr.add(self.bytes.start as _) as *mut T,
+            len,
+        ))
+    }
+    /// 先頭の要素から順に書き込む
+    pub fn write(&mut self, values: &[T]) -> IOResult<()> {
+        if values.len() as u64 > self.elements.end - self.elements.start {
+            return invalid(format!(
+                "{} values do not fit into {} mapped elements",
+                values.len(),
+                self.elements.end - self.elements.start
+            ));
+        }
+        for (n, v) in values.iter().enumerate() {
+            let index = self.elements.start + n as u64;
+            // 元の中身を参照として読まないように直接書き込む
+            unsafe { self.element_ptr(index).expect("checked above").write(*v) };
+        }
+        Ok(())
+    }
+}
+impl<T: Copy> Drop for MappedBuffer<'_, T> {
+    fn drop(&mut self) {
+        self.buffer
+            .resource
+            .unmap(self.bytes.start as usize..self.bytes.end as usize);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Clone, Copy)]
+    #[allow(dead_code)]
+    struct Light {
+        position: [f32; 3],
+        range: f32,
+        color: [f32; 3],
+    }
+
+    #[test]
+    fn strides_by_kind() {
+        let l = BufferLayout::new::<Light>(BufferKind::Structured, 10).unwrap();
+        assert_eq!((l.element_size, l.stride, l.byte_size()), (28, 28, 280));
+        let l = BufferLayout::new::<Light>(BufferKind::Constant, 3).unwrap();
+        assert_eq!((l.element_size, l.stride, l.byte_size()), (28, 256, 768));
+        let l = BufferLayout::new::<[f32; 80]>(BufferKind::Constant, 2).unwrap();
+        assert_eq!(l.stride, 512);
+        let l = BufferLayout::new::<u32>(BufferKind::Raw, 64).unwrap();
+        assert_eq!(l.byte_size(), 256);
+
+        assert!(BufferLayout::new::<[u8; 6]>(BufferKind::Raw, 1).is_err());
+        assert!(BufferLayout::new::<()>(BufferKind::Vertex, 1).is_err());
+    }
+
+    #[test]
+    fn element_ranges() {
+        let l = BufferLayout::new::<Light>(BufferKind::Constant, 4).unwrap();
+        assert_eq!(l.element_offset(3).unwrap(), 768);
+        assert!(l.element_offset(4).is_err());
+        // 最後の要素の後ろのパディングは含まない
+        assert_eq!(l.byte_range(1..3).unwrap(), 256..540);
+        assert_eq!(l.byte_range(2..2).unwrap(), 512..512);
+        assert!(l.byte_range(3..5).is_err());
+        let (start, end) = (3, 1);
+        assert!(l.byte_range(start..end).is_err());
+    }
+
+    #[test]
+    fn view_descs() {
+        let raw = BufferLayout::new::<[u32; 4]>(BufferKind::Raw, 8).unwrap();
+        let v = raw.view_desc(2..5).unwrap();
+        assert_eq!(v.format, DXGI_FORMAT_R32_TYPELESS);
+        assert_eq!(
+            v.dimension,
+            ViewDimension::Buffer {
+                first_element: 8,
+                num_elements: 12,
+                layout: BufferViewLayout::Raw,
+                counter_offset: 0,
+            }
+        );
+
+        let cb = BufferLayout::new::<Light>(BufferKind::Constant, 4).unwrap();
+        assert_eq!(
+            cb.view_desc(1..4).unwrap().dimension,
+            ViewDimension::Buffer {
+                first_element: 1,
+                num_elements: 3,
+                layout: BufferViewLayout::Structured(256),
+                counter_offset: 0,
+            }
+        );
+        assert!(cb.view_desc(0..5).is_err());
+    }
+
+    #[test]
+    fn typed_index_views() {
+        let v = typed_index_buffer_view::<u16>(GraphicsVirtualPtr(0x1000), 30);
+        assert_eq!((v.BufferLocation, v.SizeInBytes), (0x1000, 60));
+        assert_eq!(v.Format, DXGI_FORMAT_R16_UINT);
+        let v = typed_index_buffer_view::<u32>(GraphicsVirtualPtr(0x1000), 30);
+        assert_eq!((v.SizeInBytes, v.Format), (120, DXGI_FORMAT_R32_UINT));
+    }
+}
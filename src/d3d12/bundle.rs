@@ -0,0 +1,513 @@
+//! Bundles
+
+use super::*;
+
+/// バンドルが呼び出し元から引き継ぐ状態
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InheritedState {
+    pub root_signature: Option<*mut ID3D12RootSignature>,
+    pub descriptor_heaps: Vec<*mut ID3D12DescriptorHeap>,
+}
+impl InheritedState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// 呼び出し元で設定されているルートシグネチャ
+    pub fn root_signature(mut self, signature: &RootSignature) -> Self {
+        self.root_signature = Some(signature.0);
+        self
+    }
+    /// 呼び出し元で設定されているデスクリプタヒープ
+    pub fn descriptor_heaps(mut self, heaps: &[*mut ID3D12DescriptorHeap]) -> Self {
+        self.descriptor_heaps = heaps.to_vec();
+        self
+    }
+}
+
+/// バンドルに記録された状態と、実行時に必要な条件
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BundleState {
+    pub pipeline_state: bool,
+    pub primitive_topology: bool,
+    /// バンドル内で設定したルートシグネチャ
+    pub root_signature: Option<*mut ID3D12RootSignature>,
+    /// バンドル内で設定したデスクリプタヒープ
+    pub descriptor_heaps: Option<Vec<*mut ID3D12DescriptorHeap>>,
+    /// 呼び出し元のルートシグネチャ(とその引数)を前提にしている
+    pub inherits_root_signature: bool,
+    /// 呼び出し元のデスクリプタヒープを前提にしている
+    pub inherits_descriptor_heaps: bool,
+    /// 記録中に見つかった最初の誤用
+    pub error: Option<&'static str>,
+}
+impl BundleState {
+    fn fail(&mut self, msg: &'static str) {
+        if self.error.is_none() {
+            self.error = Some(msg);
+        }
+    }
+    /// パイプラインステートが設定された
+    pub fn set_pipeline_state(&mut self) {
+        self.pipeline_state = true;
+    }
+    /// ルートシグネチャが設定された
+    pub fn set_root_signature(&mut self, signature: *mut ID3D12RootSignature) {
+        self.root_signature = Some(signature);
+    }
+    /// デスクリプタヒープが設定された
+    pub fn set_descriptor_heaps(&mut self, heaps: &[*mut ID3D12DescriptorHeap]) {
+        self.descriptor_heaps = Some(heaps.to_vec());
+    }
+    /// ルート引数が設定された
+    pub fn set_root_argument(&mut self) {
+        if self.root_signature.is_none() {
+            self.inherits_root_signature = true;
+        }
+    }
+    /// デスクリプタテーブルが設定された
+    pub fn set_root_descriptor_table(&mut self) {
+        self.set_root_argument();
+        if self.descriptor_heaps.is_none() {
+            self.inherits_descriptor_heaps = true;
+        }
+    }
+    /// プリミティブトポロジが設定された
+    pub fn set_primitive_topology(&mut self) {
+        self.primitive_topology = true;
+    }
+    /// ドローコールが記録された
+    pub fn draw(&mut self) {
+        if !self.pipeline_state {
+            self.fail("draw without a pipeline state (bundles do not inherit it)");
+        }
+        if !self.primitive_topology {
+            self.fail("draw without a primitive topology (bundles do not inherit it)");
+        }
+        if self.root_signature.is_none() {
+            self.inherits_root_signature = true;
+        }
+    }
+    /// ExecuteIndirectが記録された
+    pub fn execute_indirect(&mut self) {
+        if !self.pipeline_state {
+            self.fail("ExecuteIndirect without a pipeline state (bundles do not inherit it)");
+        }
+        if self.root_signature.is_none() {
+            self.inherits_root_signature = true;
+        }
+    }
+
+    /// 呼び出し元の状態と矛盾しないか調べる
+    /// (callerは呼び出し側の申告なので、実際にコマンドリストに設定されている状態とは照合しない)
+    pub fn check_compatibility(&self, caller: &InheritedState) -> IOResult<()> {
+        if let Some(ref heaps) = self.descriptor_heaps {
+            let (mut a, mut b) = (heaps.clone(), caller.descriptor_heaps.clone());
+            a.sort();
+            b.sort();
+            if a != b {
+                return invalid(
+                    "descriptor heaps set in the bundle do not match the caller's".to_owned(),
+                );
+            }
+        } else if self.inherits_descriptor_heaps && caller.descriptor_heaps.is_empty() {
+            return invalid(
+                "bundle uses descriptor tables but the caller has no descriptor heaps".to_owned(),
+            );
+        }
+        if self.inherits_root_signature {
+            match (self.root_signature, caller.root_signature) {
+                (_, None) => {
+                    return invalid(
+                        "bundle relies on the caller's root signature but none is set".to_owned(),
+                    )
+                }
+                (Some(own), Some(theirs)) if own != theirs => {
+                    return invalid(
+                        "bundle relies on the caller's root arguments but sets a different root signature"
+                            .to_owned(),
+                    )
+                }
+                _ => (),
+            }
+        }
+
+        Ok(())
+    }
+    /// 実行後の呼び出し元の状態(バンドル内での設定は呼び出し元に残る)
+    pub fn apply_to(&self, caller: &mut InheritedState) {
+        if let Some(sig) = self.root_signature {
+            caller.root_signature = Some(sig);
+        }
+    }
+}
+
+/// バンドル(バンドル内で使えるコマンドのみを公開する)
+pub struct Bundle {
+    list: GraphicsCommandList,
+    state: BundleState,
+    closed: bool,
+}
+impl Device {
+    /// バンドルを作る(初期状態では記録するようになってる)
+    /// allocはCommandType::Bundleで作られたものであること
+    pub fn new_bundle(
+        &self,
+        alloc: &mut CommandAllocator,
+        initial_ps: Option<&PipelineState>,
+    ) -> IOResult<Bundle> {
+        if !matches!(alloc.1, CommandType::Bundle) {
+            return invalid(
+                "bundles require a command allocator of CommandType::Bundle".to_owned(),
+            );
+        }
+        let list = self.new_graphics_command_list(alloc, initial_ps)?;
+
+        Ok(Bundle {
+            list,
+            state: BundleState {
+                pipeline_state: initial_ps.is_some(),
+                ..BundleState::default()
+            },
+            closed: false,
+        })
+    }
+}
+impl Bundle {
+    /// 記録された状態
+    pub fn state(&self) -> &BundleState {
+        &self.state
+    }
+    /// 記録を終えたかどうか
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+    /// 記録おしまい(記録中に誤用があった場合はエラー)
+    pub fn close(&mut self) -> IOResult<()> {
+        if let Some(msg) = self.state.error {
+            return invalid(msg.to_owned());
+        }
+        self.list.close()?;
+        self.closed = true;
+
+        Ok(())
+    }
+    /// バンドルの初期化
+    pub fn reset(
+        &mut self,
+        alloc: &CommandAllocator,
+        initial_ps: Option<&PipelineState>,
+    ) -> IOResult<&mut Self> {
+        self.list.reset(alloc, initial_ps)?;
+        self.state = BundleState {
+            pipeline_state: initial_ps.is_some(),
+            ..BundleState::default()
+        };
+        self.closed = false;
+
+        Ok(self)
+    }
+
+    /// パイプラインステート/ルートシグネチャの設定
+    pub fn set_pipeline_state(
+        &mut self,
+        ps: &PipelineState,
+        signature: Option<&RootSignature>,
+    ) -> &mut Self {
+        self.list.set_pipeline_state(ps, None);
+        self.state.set_pipeline_state();
+        if let Some(sig) = signature {
+            self.set_root_signature(sig)
+        } else {
+            self
+        }
+    }
+    /// ルートシグネチャのみ設定
+    pub fn set_root_signature(&mut self, signature: &RootSignature) -> &mut Self {
+        self.list.set_root_signature(signature);
+        self.state.set_root_signature(signature.0);
+        self
+    }
+    /// ルート定数の設定(複数)
+    pub fn set_root_constants(
+        &mut self,
+        param_index: u32,
+        offset: u32,
+        values: &[f32],
+    ) -> &mut Self {
+        self.list.set_root_constants(param_index, offset, values);
+        self.state.set_root_argument();
+        self
+    }
+    /// ルート定数の設定(ひとつ)
+    pub fn set_root_constant<C: RootConstant>(
+        &mut self,
+        param_index: u32,
+        offset: u32,
+        value: C,
+    ) -> &mut Self {
+        self.list.set_root_constant(param_index, offset, value);
+        self.state.set_root_argument();
+        self
+    }
+    /// ルート定数バッファの設定
+    pub fn set_root_constant_buffer(
+        &mut self,
+        param_index: u32,
+        resource_ptr: D3D12_GPU_VIRTUAL_ADDRESS,
+    ) -> &mut Self {
+        self.list
+            .set_root_constant_buffer(param_index, resource_ptr);
+        self.state.set_root_argument();
+        self
+    }
+    /// ルートリソースバッファの設定
+    pub fn set_root_resource_buffer(
+        &mut self,
+        param_index: u32,
+        resource_ptr: D3D12_GPU_VIRTUAL_ADDRESS,
+    ) -> &mut Self {
+        self.list
+            .set_root_resource_buffer(param_index, resource_ptr);
+        self.state.set_root_argument();
+        self
+    }
+    /// 参照されるデスクリプタヒープの設定(呼び出し元と同じものでなければならない)
+    pub fn set_descriptor_heaps(&mut self, heaps: &[*mut ID3D12DescriptorHeap]) -> &mut Self {
+        self.list.set_descriptor_heaps(heaps);
+        self.state.set_descriptor_heaps(heaps);
+        self
+    }
+    /// デスクリプタテーブルを設定
+    pub fn set_root_descriptor_table(
+        &mut self,
+        param_index: u32,
+        table_start: &DeviceDescriptorHandle,
+    ) -> &mut Self {
+        self.list
+            .set_root_descriptor_table(param_index, table_start);
+        self.state.set_root_descriptor_table();
+        self
+    }
+    /// プリミティブトポロジを指定
+    pub fn set_primitive_topology(&mut self, tp: D3D12_PRIMITIVE_TOPOLOGY) -> &mut Self {
+        self.list.set_primitive_topology(tp);
+        self.state.set_primitive_topology();
+        self
+    }
+    /// 頂点バッファの設定
+    pub fn set_vertex_buffers(
+        &mut self,
+        slot_from: u32,
+        buffers: &[VertexBufferView],
+    ) -> &mut Self {
+        self.list.set_vertex_buffers(slot_from, buffers);
+        self
+    }
+    /// インデックスバッファの設定
+    pub fn set_index_buffer(&mut self, buffer: &D3D12_INDEX_BUFFER_VIEW) -> &mut Self {
+        self.list.set_index_buffer(buffer);
+        self
+    }
+
+    /// ドローコールを発行
+    pub fn draw(&mut self, vertex_count: u32, instance_count: u32) -> &mut Self {
+        self.list.draw(vertex_count, instance_count);
+        self.state.draw();
+        self
+    }
+    /// インデックスを使うドローコールを発行
+    pub fn draw_indexed(
+        &mut self,
+        index_count: u32,
+        instance_count: u32,
+        vertex_offset: i32,
+    ) -> &mut Self {
+        self.list
+            .draw_indexed(index_count, instance_count, vertex_offset);
+        self.state.draw();
+        self
+    }
+    /// 引数バッファに従って間接的にコマンドを発行する
+    pub fn execute_indirect(
+        &mut self,
+        signature: &CommandSignature,
+        max_command_count: u32,
+        argument_buffer: &Resource,
+        argument_offset: u64,
+        count_buffer: Option<(&Resource, u64)>,
+    ) -> &mut Self {
+        self.list.execute_indirect(
+            signature,
+            max_command_count,
+            argument_buffer,
+            argument_offset,
+            count_buffer,
+        );
+        self.state.execute_indirect();
+        self
+    }
+    /// コマンドインジェクション(チェーン中にifとかで分かれたい場合)
+    pub fn inject(&mut self, injector: impl FnOnce(&mut Self) -> &mut Self) -> &mut Self {
+        injector(self)
+    }
+}
+unsafe impl Sync for Bundle {}
+unsafe impl Send for Bundle {}
+
+impl GraphicsCommandList {
+    /// バンドルを実行する
+    /// callerには現在このコマンドリストに設定されている状態を渡す(実行後の状態に更新される)
+    /// コマンドリスト自身は設定されたルートシグネチャやデスクリプタヒープを記録していないので、
+    /// 検査できるのはcallerで申告された状態との整合性だけ
+    pub fn execute_bundle(
+        &mut self,
+        bundle: &Bundle,
+        caller: &mut InheritedState,
+    ) -> IOResult<&mut Self> {
+        if !bundle.closed {
+            return invalid("bundle must be closed before execution".to_owned());
+        }
+        bundle.state.check_compatibility(caller)?;
+        bundle.state.apply_to(caller);
+
+        Ok(self.execute(&bundle.list))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signature(n: usize) -> *mut ID3D12RootSignature {
+        n as *mut _
+    }
+    fn heap(n: usize) -> *mut ID3D12DescriptorHeap {
+        n as *mut _
+    }
+    fn drawable() -> BundleState {
+        let mut s = BundleState::default();
+        s.set_pipeline_state();
+        s.set_primitive_topology();
+        s
+    }
+
+    #[test]
+    fn draws_require_pipeline_state_and_topology() {
+        let mut s = BundleState::default();
+        s.set_primitive_topology();
+        s.draw();
+        assert_eq!(
+            s.error,
+            Some("draw without a pipeline state (bundles do not inherit it)")
+        );
+
+        let mut s = BundleState::default();
+        s.set_pipeline_state();
+        s.draw();
+        assert_eq!(
+            s.error,
+            Some("draw without a primitive topology (bundles do not inherit it)")
+        );
+
+        let mut s = BundleState::default();
+        s.execute_indirect();
+        assert!(s.error.is_some());
+
+        let mut s = drawable();
+        s.draw();
+        assert_eq!(s.error, None);
+        // 最初の誤用だけが残る
+        let mut s = BundleState::default();
+        s.draw();
+        s.set_pipeline_state();
+        s.draw();
+        assert_eq!(
+            s.error,
+            Some("draw without a pipeline state (bundles do not inherit it)")
+        );
+    }
+
+    #[test]
+    fn descriptor_heaps_must_match_the_caller() {
+        let mut s = drawable();
+        s.set_descriptor_heaps(&[heap(1), heap(2)]);
+        s.set_root_signature(signature(1));
+        s.set_root_descriptor_table();
+        s.draw();
+        assert!(!s.inherits_descriptor_heaps);
+
+        let caller = InheritedState {
+            descriptor_heaps: vec![heap(2), heap(1)],
+            ..InheritedState::default()
+        };
+        assert!(s.check_compatibility(&caller).is_ok());
+        let other = InheritedState {
+            descriptor_heaps: vec![heap(1), heap(3)],
+            ..InheritedState::default()
+        };
+        assert!(s.check_compatibility(&other).is_err());
+        assert!(s.check_compatibility(&InheritedState::default()).is_err());
+    }
+
+    #[test]
+    fn descriptor_tables_inherit_the_caller_heaps() {
+        let mut s = drawable();
+        s.set_root_signature(signature(1));
+        s.set_root_descriptor_table();
+        s.draw();
+        assert!(s.inherits_descriptor_heaps);
+        assert!(!s.inherits_root_signature);
+
+        let e = s
+            .check_compatibility(&InheritedState::default())
+            .unwrap_err();
+        assert_eq!(e.kind(), std::io::ErrorKind::InvalidInput);
+        let caller = InheritedState {
+            descriptor_heaps: vec![heap(1)],
+            ..InheritedState::default()
+        };
+        assert!(s.check_compatibility(&caller).is_ok());
+    }
+
+    #[test]
+    fn inherited_root_arguments_require_the_caller_signature() {
+        let mut s = drawable();
+        s.set_root_argument();
+        s.draw();
+        assert!(s.inherits_root_signature);
+
+        assert!(s.check_compatibility(&InheritedState::default()).is_err());
+        let caller = InheritedState {
+            root_signature: Some(signature(1)),
+            ..InheritedState::default()
+        };
+        assert!(s.check_compatibility(&caller).is_ok());
+
+        // 引き継いだ引数を使ったあとで別のルートシグネチャに切り替えるのは矛盾
+        s.set_root_signature(signature(2));
+        assert!(s.check_compatibility(&caller).is_err());
+        let same = InheritedState {
+            root_signature: Some(signature(2)),
+            ..InheritedState::default()
+        };
+        assert!(s.check_compatibility(&same).is_ok());
+    }
+
+    #[test]
+    fn own_root_signature_is_left_to_the_caller() {
+        let mut s = drawable();
+        s.set_root_signature(signature(2));
+        s.set_root_argument();
+        s.draw();
+        assert!(!s.inherits_root_signature);
+        assert!(s.check_compatibility(&InheritedState::default()).is_ok());
+
+        let mut caller = InheritedState {
+            root_signature: Some(signature(1)),
+            ..InheritedState::default()
+        };
+        assert!(s.check_compatibility(&caller).is_ok());
+        s.apply_to(&mut caller);
+        assert_eq!(caller.root_signature, Some(signature(2)));
+    }
+}
@@ -0,0 +1,352 @@
+//! Command List Pooling
+
+use super::*;
+use std::collections::VecDeque;
+use std::sync::{Mutex, MutexGuard};
+
+/// 完了したフェンス値を返すもの
+pub trait FenceValue {
+    fn completed_value(&self) -> u64;
+}
+impl FenceValue for Fence {
+    fn completed_value(&self) -> u64 {
+        Fence::completed_value(self)
+    }
+}
+
+struct RecycleState<T> {
+    free: Vec<T>,
+    /// (使ったフレームのフェンス値, 要素)
+    pending: VecDeque<(u64, T)>,
+}
+
+/// フェンスの完了を待って要素を再利用するプール(スレッドセーフ)
+pub struct RecyclePool<T> {
+    state: Mutex<RecycleState<T>>,
+}
+impl<T> Default for RecyclePool<T> {
+    fn default() -> Self {
+        RecyclePool {
+            state: Mutex::new(RecycleState {
+                free: Vec::new(),
+                pending: VecDeque::new(),
+            }),
+        }
+    }
+}
+impl<T> RecyclePool<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    fn lock(&self) -> MutexGuard<'_, RecycleState<T>> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// 要素を借りる
+    ///
+    /// fenceが完了したものがあればresetしてから返し、なければcreateで作る
+    pub fn acquire<F, C, R>(&self, fence: &F, create: C, reset: R) -> IOResult<T>
+    where
+        F: FenceValue + ?Sized,
+        C: FnOnce() -> IOResult<T>,
+        R: FnOnce(&mut T) -> IOResult<()>,
+    {
+        let reusable = {
+            let mut s = self.lock();
+            let completed = fence.completed_value();
+            while s.pending.front().is_some_and(|&(v, _)| v <= completed) {
+                let (_, item) = s.pending.pop_front().expect("checked above");
+                s.free.push(item);
+            }
+            s.free.pop()
+        };
+        match reusable {
+            Some(mut item) => {
+                reset(&mut item)?;
+                Ok(item)
+            }
+            None => create(),
+        }
+    }
+    /// fence_valueが完了するまで再利用しない要素として返す
+    pub fn release<I: IntoIterator<Item = T>>(&self, fence_value: u64, items: I) {
+        let mut s = self.lock();
+        // 値の順に並べておく(ほとんどは末尾に追加される)
+        for item in items {
+            let at = s
+                .pending
+                .iter()
+                .rposition(|&(v, _)| v <= fence_value)
+                .map_or(0, |n| n + 1);
+            s.pending.insert(at, (fence_value, item));
+        }
+    }
+    /// 完了を待たずに再利用できる要素として返す(投入しなかった要素)
+    pub fn release_unused(&self, item: T) {
+        self.lock().free.push(item);
+    }
+
+    /// すぐに再利用できる要素の数
+    pub fn free_count(&self) -> usize {
+        self.lock().free.len()
+    }
+    /// 完了待ちの要素の数
+    pub fn pending_count(&self) -> usize {
+        self.lock().pending.len()
+    }
+}
+
+/// コマンドアロケータと、それで記録するコマンドリストの組
+pub struct CommandContext {
+    allocator: CommandAllocator,
+    list: GraphicsCommandList,
+}
+impl CommandContext {
+    pub fn list(&mut self) -> &mut GraphicsCommandList {
+        &mut self.list
+    }
+    pub fn allocator(&self) -> &CommandAllocator {
+        &self.allocator
+    }
+}
+
+/// スレッド間で共有できるコマンドリストのプール
+pub struct CommandListPool {
+    device: Device,
+    kind: CommandType,
+    contexts: RecyclePool<CommandContext>,
+}
+impl Device {
+    /// コマンドリストのプールの作成
+    pub fn new_command_list_pool(&self, kind: CommandType) -> CommandListPool {
+        CommandListPool {
+            device: self.clone(),
+            kind,
+            contexts: RecyclePool::new(),
+        }
+    }
+}
+impl CommandListPool {
+    pub fn kind(&self) -> CommandType {
+        self.kind
+    }
+    pub fn contexts(&self) -> &RecyclePool<CommandContext> {
+        &self.contexts
+    }
+
+    /// 記録を始めた状態のコマンドリストを借りる(fenceが完了したものを再利用する)
+    pub fn acquire<F: FenceValue + ?Sized>(&self, fence: &F) -> IOResult<CommandContext> {
+        self.contexts.acquire(
+            fence,
+            || {
+                let mut allocator = self.device.new_command_allocator(self.kind)?;
+                let list = self
+                    .device
+                    .new_graphics_command_list(&mut allocator, None)?;
+                Ok(CommandContext { allocator, list })
+            },
+            |c| {
+                c.allocator.reset()?;
+                c.list.reset(&c.allocator, None).map(drop)
+            },
+        )
+    }
+    /// フェンスがfence_valueに達したら再利用する
+    pub fn release<I: IntoIterator<Item = CommandContext>>(&self, fence_value: u64, contexts: I) {
+        self.contexts.release(fence_value, contexts)
+    }
+
+    /// 1フレームぶんの記録を始める
+    pub fn begin_frame<'p, F: FenceValue + Sync + ?Sized>(
+        &'p self,
+        fence: &'p F,
+    ) -> FrameCommands<'p, F> {
+        FrameCommands {
+            pool: self,
+            fence,
+            recorded: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+/// 1フレームぶんの記録されたコマンドリスト(投入順に並べて1回のexecuteで実行する)
+pub struct FrameCommands<'p, F: FenceValue + ?Sized> {
+    pool: &'p CommandListPool,
+    fence: &'p F,
+    /// (投入順, コマンドリスト)
+    recorded: Mutex<Vec<(usize, CommandContext)>>,
+}
+impl<F: FenceValue + Sync + ?Sized> FrameCommands<'_, F> {
+    /// 記録を始めた状態のコマンドリストを借りる
+    pub fn acquire(&self) -> IOResult<CommandContext> {
+        self.pool.acquire(self.fence)
+    }
+    /// 記録し終えたコマンドリストを閉じて、投入順orderの位置に置く
+    pub fn push(&self, order: usize, mut context: CommandContext) -> IOResult<()> {
+        if let Err(e) = context.list.close() {
+            self.pool.contexts.release_unused(context);
+            return Err(e);
+        }
+        self.recorded
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push((order, context));
+        Ok(())
+    }
+    /// jobsをそれぞれ別のスレッドで別のコマンドリストに記録する(投入順はjobsの順で、既存の分の後ろに続く)
+    pub fn record_parallel<I, J>(&self, jobs: I) -> IOResult<()>
+    where
+        I: IntoIterator<Item = J>,
+        J: FnOnce(&mut GraphicsCommandList) -> IOResult<()> + Send,
+    {
+        let base = self.len();
+        std::thread::scope(|s| {
+            let workers = jobs
+                .into_iter()
+                .map(|job| {
+                    s.spawn(move || {
+                        let mut c = self.acquire()?;
+                        if let Err(e) = job(&mut c.list) {
+                            let _ = c.list.close();
+                            self.pool.contexts.release_unused(c);
+                            return Err(e);
+                        }
+                        Ok(c)
+                    })
+                })
+                .collect::<Vec<_>>();
+            let mut result = Ok(());
+            for (n, w) in workers.into_iter().enumerate() {
+                let r = w
+                    .join()
+                    .unwrap_or_else(|e| std::panic::resume_unwind(e))
+                    .and_then(|c| self.push(base + n, c));
+                if result.is_ok() {
+                    result = r;
+                }
+            }
+            result
+        })
+    }
+    /// 記録されたコマンドリストの数
+    pub fn len(&self) -> usize {
+        self.recorded
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+impl FrameCommands<'_, Fence> {
+    /// 投入順に並べたコマンドリストを1回で実行し、begin_frameに渡したフェンスをvalueにする(完了後にプールで再利用される)
+    pub fn submit(self, queue: &mut CommandQueue, value: u64) -> IOResult<()> {
+        let mut recorded = self
+            .recorded
+            .into_inner()
+            .unwrap_or_else(|e| e.into_inner());
+        recorded.sort_by_key(|&(order, _)| order);
+        let lists = recorded
+            .iter()
+            .map(|(_, c)| c.list.0 as *mut ID3D12CommandList)
+            .collect::<Vec<_>>();
+        if !lists.is_empty() {
+            queue.execute(&lists);
+        }
+        let signaled = queue.signal(self.fence, value).map(drop);
+        self.pool
+            .release(value, recorded.into_iter().map(|(_, c)| c));
+
+        signaled
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+
+    struct FakeFence(AtomicU64);
+    impl FenceValue for FakeFence {
+        fn completed_value(&self) -> u64 {
+            self.0.load(Ordering::SeqCst)
+        }
+    }
+
+    /// (作った順番, resetされた回数)
+    type Item = (u32, u32);
+    fn reset(item: &mut Item) -> IOResult<()> {
+        item.1 += 1;
+        Ok(())
+    }
+
+    #[test]
+    fn reuses_items_after_the_fence_completes() {
+        let fence = FakeFence(AtomicU64::new(0));
+        let pool = RecyclePool::<Item>::new();
+        let created = AtomicU32::new(0);
+        let create = || Ok((created.fetch_add(1, Ordering::SeqCst), 0));
+
+        let a = pool.acquire(&fence, create, reset).unwrap();
+        let b = pool.acquire(&fence, create, reset).unwrap();
+        pool.release(2, vec![a]);
+        pool.release(1, vec![b]);
+        assert_eq!(pool.pending_count(), 2);
+
+        // どれも完了していないので新しく作る
+        let c = pool.acquire(&fence, create, reset).unwrap();
+        assert_eq!(c, (2, 0));
+        // 値の順に並んでいるので後から返した値1のものが先に使える
+        fence.0.store(1, Ordering::SeqCst);
+        assert_eq!(pool.acquire(&fence, create, reset).unwrap(), (1, 1));
+        assert_eq!(pool.pending_count(), 1);
+
+        pool.release_unused(c);
+        assert_eq!(pool.free_count(), 1);
+        assert_eq!(pool.acquire(&fence, create, reset).unwrap(), (2, 1));
+        fence.0.store(2, Ordering::SeqCst);
+        assert_eq!(pool.acquire(&fence, create, reset).unwrap(), (0, 1));
+        assert_eq!(created.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn failed_reset_does_not_return_the_item() {
+        let fence = FakeFence(AtomicU64::new(5));
+        let pool = RecyclePool::<Item>::new();
+        pool.release(1, vec![(0, 0)]);
+        let r = pool.acquire(
+            &fence,
+            || Ok((1, 0)),
+            |_| Err(IOError::other("reset failed")),
+        );
+        assert!(r.is_err());
+        assert_eq!(pool.free_count() + pool.pending_count(), 0);
+    }
+
+    #[test]
+    fn concurrent_acquire_and_release() {
+        let fence = FakeFence(AtomicU64::new(0));
+        let pool = RecyclePool::<Item>::new();
+        let created = AtomicU32::new(0);
+        let create = || Ok((created.fetch_add(1, Ordering::SeqCst), 0));
+        for _ in 0..4 {
+            let x = pool.acquire(&fence, create, reset).unwrap();
+            pool.release(1, vec![x]);
+        }
+        fence.0.store(1, Ordering::SeqCst);
+
+        std::thread::scope(|s| {
+            for _ in 0..8 {
+                s.spawn(|| {
+                    let x = pool.acquire(&fence, create, reset).unwrap();
+                    pool.release(2, vec![x]);
+                });
+            }
+        });
+        assert_eq!(pool.pending_count() + pool.free_count(), 8);
+        assert_eq!(pool.pending_count(), 8);
+        // 完了済みの4つは再利用され、残りの4つだけが作られる
+        assert_eq!(created.load(Ordering::SeqCst), 8);
+    }
+}
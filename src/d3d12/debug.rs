@@ -0,0 +1,157 @@
+//! Debug Names and PIX Event Markers
+
+use super::*;
+
+DebugNameBySetName!(for Device, CommandQueue, CommandAllocator, DescriptorHeap, Resource, Heap,
+    RootSignature, PipelineState, Fence, GraphicsCommandList);
+
+/// PIX3形式のイベントデータであることを表すメタデータ
+pub const PIX_EVENT_PIX3BLOB_VERSION: u32 = 2;
+/// イベントデータの最大長(u64単位)
+const PIX_EVENTS_GRAPHICS_RECORD_SPACE_QWORDS: usize = 64;
+const PIX_EVENTS_RESERVED_TAIL_SPACE_QWORDS: usize = 2;
+const PIX_EVENTS_BLOCK_END_MARKER: u64 = 0x0000_0000_000F_FF80;
+
+/// PIXイベントの種類
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PixEventType {
+    EndEvent = 0x000,
+    BeginEvent = 0x002,
+    SetMarker = 0x008,
+}
+
+/// PIX_COLOR相当
+pub const fn pix_color(r: u8, g: u8, b: u8) -> u32 {
+    0xff00_0000 | (r as u32) << 16 | (g as u32) << 8 | b as u32
+}
+
+/// GPUイベント(BeginEvent/SetMarker)に渡すPIX3形式のデータを作る
+/// 長すぎる名前は切り詰められる
+pub fn encode_pix_event(ty: PixEventType, color: u32, name: &str) -> Vec<u64> {
+    let limit = PIX_EVENTS_GRAPHICS_RECORD_SPACE_QWORDS - PIX_EVENTS_RESERVED_TAIL_SPACE_QWORDS;
+    // timestampは0(GPUイベントでは使われない)
+    let mut out = vec![(ty as u64 & 0x3ff) << 10, color as u64];
+    // alignment=0, chunk size=8, UTF-16, not a shortcut
+    out.push(8 << 55);
+
+    let mut chars = name.encode_utf16();
+    while out.len() < limit {
+        let mut x = 0u64;
+        let mut terminated = false;
+        for shift in (0..4).map(|n| n * 16) {
+            match chars.next() {
+                Some(c) if c != 0 => x |= (c as u64) << shift,
+                _ => {
+                    terminated = true;
+                    break;
+                }
+            }
+        }
+        out.push(x);
+        if terminated {
+            break;
+        }
+    }
+
+    out
+}
+
+/// PIX3形式のイベントデータを終端マーカーつきのバイト列にする(終端マーカーはサイズに含めない)
+fn pix_event_payload(ty: PixEventType, color: u32, name: &str) -> (Vec<u64>, u32) {
+    let mut data = encode_pix_event(ty, color, name);
+    let size = (data.len() * size_of::<u64>()) as u32;
+    data.push(PIX_EVENTS_BLOCK_END_MARKER);
+
+    (data, size)
+}
+
+macro_rules! EventMarkerByPixEvents {
+    (for $($t: ty),*) => {
+        $(impl EventMarker for $t {
+            fn begin_event(&mut self, color: u32, name: &str) -> &mut Self {
+                let (data, size) = pix_event_payload(PixEventType::BeginEvent, color, name);
+                unsafe { (*self.0).BeginEvent(PIX_EVENT_PIX3BLOB_VERSION, data.as_ptr() as _, size) };
+                self
+            }
+            fn end_event(&mut self) -> &mut Self {
+                unsafe { (*self.0).EndEvent() };
+                self
+            }
+            fn set_marker(&mut self, color: u32, name: &str) -> &mut Self {
+                let (data, size) = pix_event_payload(PixEventType::SetMarker, color, name);
+                unsafe { (*self.0).SetMarker(PIX_EVENT_PIX3BLOB_VERSION, data.as_ptr() as _, size) };
+                self
+            }
+        })*
+    }
+}
+EventMarkerByPixEvents!(for GraphicsCommandList, CommandQueue);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chars(s: &str) -> u64 {
+        s.encode_utf16()
+            .enumerate()
+            .fold(0, |x, (n, c)| x | (c as u64) << (n * 16))
+    }
+
+    #[test]
+    fn pix_color_is_opaque_argb() {
+        assert_eq!(pix_color(0x12, 0x34, 0x56), 0xff12_3456);
+        assert_eq!(pix_color(0, 0, 0), 0xff00_0000);
+    }
+
+    #[test]
+    fn event_header_and_string_chunks() {
+        let e = encode_pix_event(PixEventType::BeginEvent, pix_color(255, 0, 0), "Shadow");
+        assert_eq!(
+            e,
+            vec![
+                0x002 << 10,
+                0xffff_0000,
+                8 << 55,
+                chars("Shad"),
+                chars("ow")
+            ]
+        );
+        let e = encode_pix_event(PixEventType::SetMarker, 0, "");
+        assert_eq!(e, vec![0x008 << 10, 0, 8 << 55, 0]);
+    }
+
+    #[test]
+    fn names_filling_a_chunk_are_terminated() {
+        // 4文字ちょうどなら終端用の0のチャンクが続く
+        let e = encode_pix_event(PixEventType::SetMarker, 0, "Main");
+        assert_eq!(&e[3..], &[chars("Main"), 0]);
+        // 途中の0文字で打ち切る
+        let e = encode_pix_event(PixEventType::SetMarker, 0, "ab\0cd");
+        assert_eq!(&e[3..], &[chars("ab")]);
+        // UTF-16のコード単位で数える(サロゲートペアは2単位)
+        let e = encode_pix_event(PixEventType::SetMarker, 0, "影🌙");
+        assert_eq!(&e[3..], &[chars("影🌙")]);
+        let e = encode_pix_event(PixEventType::SetMarker, 0, "月影🌙");
+        assert_eq!(&e[3..], &[chars("月影🌙"), 0]);
+    }
+
+    #[test]
+    fn long_names_are_truncated() {
+        let name = "x".repeat(1000);
+        let e = encode_pix_event(PixEventType::BeginEvent, 0, &name);
+        assert_eq!(
+            e.len(),
+            PIX_EVENTS_GRAPHICS_RECORD_SPACE_QWORDS - PIX_EVENTS_RESERVED_TAIL_SPACE_QWORDS
+        );
+        assert_eq!(e[e.len() - 1], chars("xxxx"));
+    }
+
+    #[test]
+    fn payload_size_excludes_end_marker() {
+        let (data, size) = pix_event_payload(PixEventType::BeginEvent, 0, "Shadow");
+        assert_eq!(data.len(), 6);
+        assert_eq!(size, 40);
+        assert_eq!(data[5], PIX_EVENTS_BLOCK_END_MARKER);
+    }
+}
@@ -0,0 +1,468 @@
+//! Device Removed Extended Data
+
+use super::*;
+use std::fmt;
+
+/// DREDの各機能の有効化状態
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DredEnablement {
+    SystemControlled = D3D12_DRED_ENABLEMENT_SYSTEM_CONTROLLED as _,
+    ForcedOff = D3D12_DRED_ENABLEMENT_FORCED_OFF as _,
+    ForcedOn = D3D12_DRED_ENABLEMENT_FORCED_ON as _,
+}
+
+/// DREDの設定
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DredConfig {
+    pub auto_breadcrumbs: DredEnablement,
+    pub page_fault: DredEnablement,
+    pub watson_dump: DredEnablement,
+}
+impl Default for DredConfig {
+    /// ブレッドクラムとページフォルト情報を有効にする
+    fn default() -> Self {
+        DredConfig {
+            auto_breadcrumbs: DredEnablement::ForcedOn,
+            page_fault: DredEnablement::ForcedOn,
+            watson_dump: DredEnablement::SystemControlled,
+        }
+    }
+}
+
+impl Device {
+    /// DREDを設定する(デバイスを作る前に呼ぶこと)
+    pub fn enable_dred(config: &DredConfig) -> IOResult<()> {
+        let mut settings = std::ptr::null_mut();
+        let settings = unsafe {
+            D3D12GetDebugInterface(
+                &ID3D12DeviceRemovedExtendedDataSettings::uuidof(),
+                &mut settings,
+            )
+            .to_result_with(|| ComPtr(settings as *mut ID3D12DeviceRemovedExtendedDataSettings))?
+        };
+
+        unsafe {
+            (*settings.0).SetAutoBreadcrumbsEnablement(config.auto_breadcrumbs as _);
+            (*settings.0).SetPageFaultEnablement(config.page_fault as _);
+            (*settings.0).SetWatsonDumpEnablement(config.watson_dump as _);
+        }
+        Ok(())
+    }
+}
+
+macro_rules! BreadcrumbOps {
+    ($($name: ident = $v: expr),*) => {
+        /// ブレッドクラムに記録される操作
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub enum BreadcrumbOp {
+            $($name,)*
+            Unknown(u32),
+        }
+        impl From<D3D12_AUTO_BREADCRUMB_OP> for BreadcrumbOp {
+            fn from(v: D3D12_AUTO_BREADCRUMB_OP) -> Self {
+                match v {
+                    $($v => BreadcrumbOp::$name,)*
+                    v => BreadcrumbOp::Unknown(v),
+                }
+            }
+        }
+    };
+}
+BreadcrumbOps!(
+    SetMarker = 0,
+    BeginEvent = 1,
+    EndEvent = 2,
+    DrawInstanced = 3,
+    DrawIndexedInstanced = 4,
+    ExecuteIndirect = 5,
+    Dispatch = 6,
+    CopyBufferRegion = 7,
+    CopyTextureRegion = 8,
+    CopyResource = 9,
+    CopyTiles = 10,
+    ResolveSubresource = 11,
+    ClearRenderTargetView = 12,
+    ClearUnorderedAccessView = 13,
+    ClearDepthStencilView = 14,
+    ResourceBarrier = 15,
+    ExecuteBundle = 16,
+    Present = 17,
+    ResolveQueryData = 18,
+    BeginSubmission = 19,
+    EndSubmission = 20,
+    DecodeFrame = 21,
+    ProcessFrames = 22,
+    AtomicCopyBufferUint = 23,
+    AtomicCopyBufferUint64 = 24,
+    ResolveSubresourceRegion = 25,
+    WriteBufferImmediate = 26,
+    DecodeFrame1 = 27,
+    SetProtectedResourceSession = 28,
+    DecodeFrame2 = 29,
+    ProcessFrames1 = 30,
+    BuildRaytracingAccelerationStructure = 31,
+    EmitRaytracingAccelerationStructurePostbuildInfo = 32,
+    CopyRaytracingAccelerationStructure = 33,
+    DispatchRays = 34,
+    InitializeMetaCommand = 35,
+    ExecuteMetaCommand = 36,
+    EstimateMotion = 37,
+    ResolveMotionVectorHeap = 38,
+    SetPipelineState1 = 39,
+    InitializeExtensionCommand = 40,
+    ExecuteExtensionCommand = 41,
+    DispatchMesh = 42
+);
+
+macro_rules! DredAllocationTypes {
+    ($($name: ident = $v: expr),*) => {
+        /// ページフォルト情報に含まれるオブジェクトの種類
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub enum DredAllocationType {
+            $($name,)*
+            Unknown(u32),
+        }
+        impl From<D3D12_DRED_ALLOCATION_TYPE> for DredAllocationType {
+            fn from(v: D3D12_DRED_ALLOCATION_TYPE) -> Self {
+                match v {
+                    $($v => DredAllocationType::$name,)*
+                    v => DredAllocationType::Unknown(v),
+                }
+            }
+        }
+    };
+}
+DredAllocationTypes!(
+    CommandQueue = 19,
+    CommandAllocator = 20,
+    PipelineState = 21,
+    CommandList = 22,
+    Fence = 23,
+    DescriptorHeap = 24,
+    Heap = 25,
+    QueryHeap = 27,
+    CommandSignature = 28,
+    PipelineLibrary = 29,
+    VideoDecoder = 30,
+    VideoProcessor = 32,
+    Resource = 34,
+    Pass = 35,
+    CryptoSession = 36,
+    CryptoSessionPolicy = 37,
+    ProtectedResourceSession = 38,
+    VideoDecoderHeap = 39,
+    CommandPool = 40,
+    CommandRecorder = 41,
+    StateObject = 42,
+    MetaCommand = 43,
+    SchedulingGroup = 44,
+    VideoMotionEstimator = 45,
+    VideoMotionVectorHeap = 46,
+    VideoExtensionCommand = 47
+);
+
+/// コマンドリストひとつ分のブレッドクラム
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BreadcrumbNode {
+    pub command_list_name: Option<String>,
+    pub command_queue_name: Option<String>,
+    /// 識別用のポインタ値
+    pub command_list: usize,
+    pub command_queue: usize,
+    pub history: Vec<BreadcrumbOp>,
+    /// GPUで完了した操作の数
+    pub completed: u32,
+}
+impl BreadcrumbNode {
+    /// すべての操作が完了している
+    pub fn is_complete(&self) -> bool {
+        self.completed as usize >= self.history.len()
+    }
+    /// 最後に完了した操作(インデックスつき)
+    pub fn last_completed(&self) -> Option<(usize, BreadcrumbOp)> {
+        let n = (self.completed as usize).min(self.history.len());
+        n.checked_sub(1).map(|i| (i, self.history[i]))
+    }
+    /// 最初に完了しなかった操作(インデックスつき)
+    pub fn first_incomplete(&self) -> Option<(usize, BreadcrumbOp)> {
+        let i = self.completed as usize;
+        self.history.get(i).map(|&op| (i, op))
+    }
+}
+
+/// ページフォルトに関係しそうなオブジェクト
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DredAllocation {
+    pub name: Option<String>,
+    pub kind: DredAllocationType,
+}
+
+/// ページフォルト情報
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PageFault {
+    pub address: D3D12_GPU_VIRTUAL_ADDRESS,
+    /// フォルト時点で存在していたオブジェクト
+    pub existing: Vec<DredAllocation>,
+    /// 直前に解放されたオブジェクト
+    pub recently_freed: Vec<DredAllocation>,
+}
+
+/// デバイス消失時の診断情報
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceRemovedReport {
+    /// GetDeviceRemovedReasonの値
+    pub reason: i32,
+    pub breadcrumbs: Vec<BreadcrumbNode>,
+    pub page_fault: Option<PageFault>,
+}
+impl DeviceRemovedReport {
+    /// 実行中に止まったコマンドリスト
+    pub fn incomplete_lists(&self) -> impl Iterator<Item = &BreadcrumbNode> {
+        self.breadcrumbs.iter().filter(|n| !n.is_complete())
+    }
+}
+impl fmt::Display for DeviceRemovedReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn name(n: &Option<String>, p: usize) -> String {
+            n.clone().unwrap_or_else(|| format!("{:#x}", p))
+        }
+
+        writeln!(f, "device removed: reason {:#010x}", self.reason as u32)?;
+        for n in self.incomplete_lists() {
+            writeln!(
+                f,
+                "command list {} on queue {}: {}/{} operations completed",
+                name(&n.command_list_name, n.command_list),
+                name(&n.command_queue_name, n.command_queue),
+                n.completed,
+                n.history.len()
+            )?;
+            match n.last_completed() {
+                Some((i, op)) => writeln!(f, "  last completed:   #{} {:?}", i, op)?,
+                None => writeln!(f, "  last completed:   (none)")?,
+            }
+            if let Some((i, op)) = n.first_incomplete() {
+                writeln!(f, "  first incomplete: #{} {:?}", i, op)?;
+            }
+        }
+        if let Some(ref pf) = self.page_fault {
+            writeln!(f, "page fault at {:#x}", pf.address)?;
+            for (label, list) in &[
+                ("existing", &pf.existing),
+                ("recently freed", &pf.recently_freed),
+            ] {
+                for a in list.iter() {
+                    writeln!(
+                        f,
+                        "  {}: {:?} {}",
+                        label,
+                        a.kind,
+                        a.name.as_deref().unwrap_or("(unnamed)")
+                    )?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+unsafe fn debug_name(a: *const i8, w: *const u16) -> Option<String> {
+    if !w.is_null() {
+        Some(widestring::WideCStr::from_ptr_str(w).to_string_lossy())
+    } else if !a.is_null() {
+        Some(std::ffi::CStr::from_ptr(a).to_string_lossy().into_owned())
+    } else {
+        None
+    }
+}
+unsafe fn allocations(mut p: *const D3D12_DRED_ALLOCATION_NODE) -> Vec<DredAllocation> {
+    let mut v = Vec::new();
+    while !p.is_null() {
+        v.push(DredAllocation {
+            name: debug_name((*p).ObjectNameA, (*p).ObjectNameW),
+            kind: (*p).AllocationType.into(),
+        });
+        p = (*p).pNext;
+    }
+
+    v
+}
+
+impl Device {
+    /// デバイス消失の理由(消失していなければOk)
+    pub fn removed_reason(&self) -> IOResult<()> {
+        unsafe { (*self.0).GetDeviceRemovedReason().checked() }
+    }
+    /// デバイス消失時の診断情報を集める(enable_dredしてから作ったデバイスであること)
+    pub fn device_removed_report(&self) -> IOResult<DeviceRemovedReport> {
+        let dred = query_ext::<ID3D12DeviceRemovedExtendedData>(self.0 as _).ok_or_else(|| {
+            IOError::other("ID3D12DeviceRemovedExtendedData is not supported on this device")
+        })?;
+        let reason = unsafe { (*self.0).GetDeviceRemovedReason() };
+
+        let mut breadcrumbs = Vec::new();
+        let mut out = D3D12_DRED_AUTO_BREADCRUMBS_OUTPUT {
+            pHeadAutoBreadcrumbNode: std::ptr::null(),
+        };
+        // 無効にされている場合は失敗するので空のまま
+        if unsafe { SUCCEEDED((*dred.0).GetAutoBreadcrumbsOutput(&mut out)) } {
+            let mut p = out.pHeadAutoBreadcrumbNode;
+            while !p.is_null() {
+                let n = unsafe { &*p };
+                let history = if n.pCommandHistory.is_null() {
+                    &[][..]
+                } else {
+                    unsafe { std::slice::from_raw_parts(n.pCommandHistory, n.BreadcrumbCount as _) }
+                };
+                breadcrumbs.push(BreadcrumbNode {
+                    command_list_name: unsafe {
+                        debug_name(n.pCommandListDebugNameA, n.pCommandListDebugNameW)
+                    },
+                    command_queue_name: unsafe {
+                        debug_name(n.pCommandQueueDebugNameA, n.pCommandQueueDebugNameW)
+                    },
+                    command_list: n.pCommandList as usize,
+                    command_queue: n.pCommandQueue as usize,
+                    history: history.iter().map(|&op| op.into()).collect(),
+                    completed: if n.pLastBreadcrumbValue.is_null() {
+                        0
+                    } else {
+                        unsafe { *n.pLastBreadcrumbValue }
+                    },
+                });
+                p = n.pNext;
+            }
+        }
+
+        let mut out = D3D12_DRED_PAGE_FAULT_OUTPUT {
+            PageFaultVA: 0,
+            pHeadExistingAllocationNode: std::ptr::null(),
+            pHeadRecentFreedAllocationNode: std::ptr::null(),
+        };
+        let page_fault = if unsafe { SUCCEEDED((*dred.0).GetPageFaultAllocationOutput(&mut out)) }
+            && (out.PageFaultVA != 0
+                || !out.pHeadExistingAllocationNode.is_null()
+                || !out.pHeadRecentFreedAllocationNode.is_null())
+        {
+            Some(PageFault {
+                address: out.PageFaultVA,
+                existing: unsafe { allocations(out.pHeadExistingAllocationNode) },
+                recently_freed: unsafe { allocations(out.pHeadRecentFreedAllocationNode) },
+            })
+        } else {
+            None
+        };
+
+        Ok(DeviceRemovedReport {
+            reason,
+            breadcrumbs,
+            page_fault,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(completed: u32) -> BreadcrumbNode {
+        BreadcrumbNode {
+            command_list_name: Some("main".to_owned()),
+            command_queue_name: None,
+            command_list: 0x10,
+            command_queue: 0x20,
+            history: vec![
+                BreadcrumbOp::ResourceBarrier,
+                BreadcrumbOp::DrawInstanced,
+                BreadcrumbOp::Dispatch,
+            ],
+            completed,
+        }
+    }
+
+    #[test]
+    fn nothing_completed() {
+        let n = node(0);
+        assert!(!n.is_complete());
+        assert_eq!(n.last_completed(), None);
+        assert_eq!(
+            n.first_incomplete(),
+            Some((0, BreadcrumbOp::ResourceBarrier))
+        );
+    }
+
+    #[test]
+    fn partially_completed() {
+        let n = node(2);
+        assert!(!n.is_complete());
+        assert_eq!(n.last_completed(), Some((1, BreadcrumbOp::DrawInstanced)));
+        assert_eq!(n.first_incomplete(), Some((2, BreadcrumbOp::Dispatch)));
+    }
+
+    #[test]
+    fn fully_completed() {
+        for completed in 3..5 {
+            let n = node(completed);
+            assert!(n.is_complete());
+            assert_eq!(n.last_completed(), Some((2, BreadcrumbOp::Dispatch)));
+            assert_eq!(n.first_incomplete(), None);
+        }
+
+        let empty = BreadcrumbNode {
+            history: Vec::new(),
+            ..node(0)
+        };
+        assert!(empty.is_complete());
+        assert_eq!(empty.last_completed(), None);
+        assert_eq!(empty.first_incomplete(), None);
+    }
+
+    #[test]
+    fn report_names_incomplete_lists_and_page_faults() {
+        let report = DeviceRemovedReport {
+            reason: 0x887A0006u32 as i32,
+            breadcrumbs: vec![
+                node(3),
+                node(1),
+                BreadcrumbNode {
+                    command_list_name: None,
+                    command_queue_name: Some("copy".to_owned()),
+                    ..node(0)
+                },
+            ],
+            page_fault: Some(PageFault {
+                address: 0x1234_0000,
+                existing: vec![DredAllocation {
+                    name: Some("gbuffer".to_owned()),
+                    kind: DredAllocationType::Resource,
+                }],
+                recently_freed: vec![DredAllocation {
+                    name: None,
+                    kind: DredAllocationType::Heap,
+                }],
+            }),
+        };
+        assert_eq!(report.incomplete_lists().count(), 2);
+        assert_eq!(
+            report.to_string(),
+            "device removed: reason 0x887a0006\n\
+             command list main on queue 0x20: 1/3 operations completed\n\
+             \x20 last completed:   #0 ResourceBarrier\n\
+             \x20 first incomplete: #1 DrawInstanced\n\
+             command list 0x10 on queue copy: 0/3 operations completed\n\
+             \x20 last completed:   (none)\n\
+             \x20 first incomplete: #0 ResourceBarrier\n\
+             page fault at 0x12340000\n\
+             \x20 existing: Resource gbuffer\n\
+             \x20 recently freed: Heap (unnamed)\n"
+        );
+
+        let healthy = DeviceRemovedReport {
+            reason: 0,
+            breadcrumbs: vec![node(3)],
+            page_fault: None,
+        };
+        assert_eq!(healthy.to_string(), "device removed: reason 0x00000000\n");
+    }
+}
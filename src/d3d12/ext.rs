@@ -0,0 +1,835 @@
+//! Interfaces not covered by winapi
+
+use super::*;
+use winapi::shared::guiddef::{GUID, REFIID};
+use winapi::shared::minwindef::BOOL;
+use winapi::shared::ntdef::HRESULT;
+use winapi::um::unknwnbase::IUnknownVtbl;
+
+/// 拡張インターフェイスを問い合わせる(対応していなければNone)
+pub(super) fn query_ext<I: Interface>(p: *mut IUnknown) -> Option<ComPtr<I>> {
+    let mut handle = std::ptr::null_mut();
+    let hr = unsafe { (*p).QueryInterface(&I::uuidof(), &mut handle) };
+
+    if SUCCEEDED(hr) && !handle.is_null() {
+        Some(ComPtr(handle as _))
+    } else {
+        None
+    }
+}
+
+#[allow(non_snake_case)]
+#[repr(C)]
+pub struct ID3D12GraphicsCommandList2Vtbl {
+    pub parent: ID3D12GraphicsCommandList1Vtbl,
+    /// ID3D12GraphicsCommandList1の最後のメソッド(winapiのID3D12GraphicsCommandList1Vtblに無い)
+    pub SetViewInstanceMask: unsafe extern "system" fn(*mut ID3D12GraphicsCommandList2, u32),
+    pub WriteBufferImmediate: unsafe extern "system" fn(
+        *mut ID3D12GraphicsCommandList2,
+        u32,
+        *const c_void,
+        *const c_void,
+    ),
+}
+#[allow(non_snake_case)]
+#[repr(C)]
+pub struct ID3D12GraphicsCommandList3Vtbl {
+    pub parent: ID3D12GraphicsCommandList2Vtbl,
+    pub SetProtectedResourceSession:
+        unsafe extern "system" fn(*mut ID3D12GraphicsCommandList3, *mut c_void),
+}
+#[allow(non_snake_case)]
+#[repr(C)]
+pub struct ID3D12GraphicsCommandList4Vtbl {
+    pub parent: ID3D12GraphicsCommandList3Vtbl,
+    pub BeginRenderPass: unsafe extern "system" fn(
+        *mut ID3D12GraphicsCommandList4,
+        u32,
+        *const D3D12_RENDER_PASS_RENDER_TARGET_DESC,
+        *const D3D12_RENDER_PASS_DEPTH_STENCIL_DESC,
+        D3D12_RENDER_PASS_FLAGS,
+    ),
+    pub EndRenderPass: unsafe extern "system" fn(*mut ID3D12GraphicsCommandList4),
+    pub InitializeMetaCommand: unsafe extern "system" fn(
+        *mut ID3D12GraphicsCommandList4,
+        *mut c_void,
+        *const c_void,
+        usize,
+    ),
+    pub ExecuteMetaCommand: unsafe extern "system" fn(
+        *mut ID3D12GraphicsCommandList4,
+        *mut c_void,
+        *const c_void,
+        usize,
+    ),
+    pub BuildRaytracingAccelerationStructure: unsafe extern "system" fn(
+        *mut ID3D12GraphicsCommandList4,
+        *const D3D12_BUILD_RAYTRACING_ACCELERATION_STRUCTURE_DESC,
+        u32,
+        *const D3D12_RAYTRACING_ACCELERATION_STRUCTURE_POSTBUILD_INFO_DESC,
+    ),
+    pub EmitRaytracingAccelerationStructurePostbuildInfo: unsafe extern "system" fn(
+        *mut ID3D12GraphicsCommandList4,
+        *const D3D12_RAYTRACING_ACCELERATION_STRUCTURE_POSTBUILD_INFO_DESC,
+        u32,
+        *const D3D12_GPU_VIRTUAL_ADDRESS,
+    ),
+    pub CopyRaytracingAccelerationStructure: unsafe extern "system" fn(
+        *mut ID3D12GraphicsCommandList4,
+        D3D12_GPU_VIRTUAL_ADDRESS,
+        D3D12_GPU_VIRTUAL_ADDRESS,
+        u32,
+    ),
+    pub SetPipelineState1:
+        unsafe extern "system" fn(*mut ID3D12GraphicsCommandList4, *mut ID3D12StateObject),
+    pub DispatchRays:
+        unsafe extern "system" fn(*mut ID3D12GraphicsCommandList4, *const D3D12_DISPATCH_RAYS_DESC),
+}
+#[allow(non_snake_case)]
+#[repr(C)]
+pub struct ID3D12GraphicsCommandList5Vtbl {
+    pub parent: ID3D12GraphicsCommandList4Vtbl,
+    pub RSSetShadingRate:
+        unsafe extern "system" fn(*mut ID3D12GraphicsCommandList5, u32, *const u32),
+    pub RSSetShadingRateImage:
+        unsafe extern "system" fn(*mut ID3D12GraphicsCommandList5, *mut ID3D12Resource),
+}
+#[allow(non_snake_case)]
+#[repr(C)]
+pub struct ID3D12GraphicsCommandList6Vtbl {
+    pub parent: ID3D12GraphicsCommandList5Vtbl,
+    pub DispatchMesh: unsafe extern "system" fn(*mut ID3D12GraphicsCommandList6, u32, u32, u32),
+}
+#[allow(non_snake_case)]
+#[repr(C)]
+pub struct ID3D12DeviceRemovedExtendedDataSettingsVtbl {
+    pub parent: IUnknownVtbl,
+    pub SetAutoBreadcrumbsEnablement: unsafe extern "system" fn(
+        *mut ID3D12DeviceRemovedExtendedDataSettings,
+        D3D12_DRED_ENABLEMENT,
+    ),
+    pub SetPageFaultEnablement: unsafe extern "system" fn(
+        *mut ID3D12DeviceRemovedExtendedDataSettings,
+        D3D12_DRED_ENABLEMENT,
+    ),
+    pub SetWatsonDumpEnablement: unsafe extern "system" fn(
+        *mut ID3D12DeviceRemovedExtendedDataSettings,
+        D3D12_DRED_ENABLEMENT,
+    ),
+}
+#[allow(non_snake_case)]
+#[repr(C)]
+pub struct ID3D12DeviceRemovedExtendedDataVtbl {
+    pub parent: IUnknownVtbl,
+    pub GetAutoBreadcrumbsOutput: unsafe extern "system" fn(
+        *mut ID3D12DeviceRemovedExtendedData,
+        *mut D3D12_DRED_AUTO_BREADCRUMBS_OUTPUT,
+    ) -> HRESULT,
+    pub GetPageFaultAllocationOutput: unsafe extern "system" fn(
+        *mut ID3D12DeviceRemovedExtendedData,
+        *mut D3D12_DRED_PAGE_FAULT_OUTPUT,
+    ) -> HRESULT,
+}
+#[allow(non_snake_case)]
+#[repr(C)]
+pub struct ID3D12Device3Vtbl {
+    pub parent: ID3D12Device2Vtbl,
+    /// OpenExistingHeapFromAddress, OpenExistingHeapFromFileMapping(未使用)
+    pub _open_existing_heap: [usize; 2],
+    pub EnqueueMakeResident: unsafe extern "system" fn(
+        *mut ID3D12Device3,
+        D3D12_RESIDENCY_FLAGS,
+        u32,
+        *const *mut ID3D12Pageable,
+        *mut ID3D12Fence,
+        u64,
+    ) -> HRESULT,
+}
+#[allow(non_snake_case)]
+#[repr(C)]
+pub struct ID3D12Device5Vtbl {
+    pub parent: ID3D12Device2Vtbl,
+    /// ID3D12Device3(3), ID3D12Device4(6)のメソッド(未使用)
+    pub _device3_4: [usize; 9],
+    /// CreateLifetimeTracker..CreateMetaCommand(未使用)
+    pub _device5: [usize; 5],
+    pub CreateStateObject: unsafe extern "system" fn(
+        *mut ID3D12Device5,
+        *const D3D12_STATE_OBJECT_DESC,
+        REFIID,
+        *mut *mut c_void,
+    ) -> HRESULT,
+    pub GetRaytracingAccelerationStructurePrebuildInfo: unsafe extern "system" fn(
+        *mut ID3D12Device5,
+        *const D3D12_BUILD_RAYTRACING_ACCELERATION_STRUCTURE_INPUTS,
+        *mut D3D12_RAYTRACING_ACCELERATION_STRUCTURE_PREBUILD_INFO,
+    ),
+    pub CheckDriverMatchingIdentifier:
+        unsafe extern "system" fn(*mut ID3D12Device5, u32, *const c_void) -> u32,
+}
+#[allow(non_snake_case)]
+#[repr(C)]
+pub struct ID3D12StateObjectVtbl {
+    pub parent: ID3D12DeviceChildVtbl,
+}
+#[allow(non_snake_case)]
+#[repr(C)]
+pub struct ID3D12StateObjectPropertiesVtbl {
+    pub parent: IUnknownVtbl,
+    pub GetShaderIdentifier:
+        unsafe extern "system" fn(*mut ID3D12StateObjectProperties, *const u16) -> *mut c_void,
+    pub GetShaderStackSize:
+        unsafe extern "system" fn(*mut ID3D12StateObjectProperties, *const u16) -> u64,
+    pub GetPipelineStackSize: unsafe extern "system" fn(*mut ID3D12StateObjectProperties) -> u64,
+    pub SetPipelineStackSize: unsafe extern "system" fn(*mut ID3D12StateObjectProperties, u64),
+}
+
+macro_rules! ExtInterface {
+    ($i: ident($vtbl: ident): $parent: ident; $d1: expr, $d2: expr, $d3: expr, [$($d4: expr),*]) => {
+        #[repr(transparent)]
+        pub struct $i(*const $vtbl);
+        impl Deref for $i {
+            type Target = $parent;
+            fn deref(&self) -> &$parent {
+                unsafe { &*(self as *const $i as *const $parent) }
+            }
+        }
+        impl Interface for $i {
+            fn uuidof() -> GUID {
+                GUID {
+                    Data1: $d1,
+                    Data2: $d2,
+                    Data3: $d3,
+                    Data4: [$($d4),*],
+                }
+            }
+        }
+    };
+}
+ExtInterface!(ID3D12GraphicsCommandList2(ID3D12GraphicsCommandList2Vtbl): ID3D12GraphicsCommandList1;
+    0x38c3e585, 0xff17, 0x412c, [0x91, 0x50, 0x4f, 0xc6, 0xf9, 0xd7, 0x2a, 0x28]);
+ExtInterface!(ID3D12GraphicsCommandList3(ID3D12GraphicsCommandList3Vtbl): ID3D12GraphicsCommandList2;
+    0x6fda83a7, 0xb84c, 0x4e38, [0x9a, 0xc8, 0xc7, 0xbd, 0x22, 0x01, 0x6b, 0x3d]);
+ExtInterface!(ID3D12GraphicsCommandList4(ID3D12GraphicsCommandList4Vtbl): ID3D12GraphicsCommandList3;
+    0x8754318e, 0xd3a9, 0x4541, [0x98, 0xcf, 0x64, 0x5b, 0x50, 0xdc, 0x48, 0x74]);
+ExtInterface!(ID3D12GraphicsCommandList5(ID3D12GraphicsCommandList5Vtbl): ID3D12GraphicsCommandList4;
+    0x55050859, 0x4024, 0x474c, [0x87, 0xf5, 0x64, 0x72, 0xea, 0xee, 0x44, 0xea]);
+ExtInterface!(ID3D12GraphicsCommandList6(ID3D12GraphicsCommandList6Vtbl): ID3D12GraphicsCommandList5;
+    0xc3827890, 0xe548, 0x4cfa, [0x96, 0xcf, 0x56, 0x89, 0xa9, 0x37, 0x0f, 0x80]);
+ExtInterface!(ID3D12DeviceRemovedExtendedDataSettings(ID3D12DeviceRemovedExtendedDataSettingsVtbl): IUnknown;
+    0x82bc481c, 0x6b9b, 0x4030, [0xae, 0xdb, 0x7e, 0xe3, 0xd1, 0xdf, 0x1e, 0x63]);
+ExtInterface!(ID3D12DeviceRemovedExtendedData(ID3D12DeviceRemovedExtendedDataVtbl): IUnknown;
+    0x98931d33, 0x5ae8, 0x4791, [0xaa, 0x3c, 0x1a, 0x73, 0xa2, 0x93, 0x4e, 0x71]);
+ExtInterface!(ID3D12Device3(ID3D12Device3Vtbl): ID3D12Device2;
+    0x81dadc15, 0x2bad, 0x4392, [0x93, 0xc5, 0x10, 0x13, 0x45, 0xc4, 0xaa, 0x98]);
+ExtInterface!(ID3D12Device5(ID3D12Device5Vtbl): ID3D12Device2;
+    0x8b4f173b, 0x2fea, 0x4b80, [0x8f, 0x58, 0x43, 0x07, 0x19, 0x1a, 0xb9, 0x5d]);
+ExtInterface!(ID3D12StateObject(ID3D12StateObjectVtbl): ID3D12DeviceChild;
+    0x47016943, 0xfca8, 0x4594, [0x93, 0xea, 0xaf, 0x25, 0x8b, 0x55, 0x34, 0x6d]);
+ExtInterface!(ID3D12StateObjectProperties(ID3D12StateObjectPropertiesVtbl): IUnknown;
+    0xde5fa827, 0x9bf9, 0x4f26, [0x89, 0xff, 0xd7, 0xf5, 0x6f, 0xde, 0x38, 0x60]);
+
+#[allow(non_snake_case, clippy::missing_safety_doc)]
+impl ID3D12GraphicsCommandList4 {
+    pub unsafe fn BeginRenderPass(
+        &self,
+        num_render_targets: u32,
+        render_targets: *const D3D12_RENDER_PASS_RENDER_TARGET_DESC,
+        depth_stencil: *const D3D12_RENDER_PASS_DEPTH_STENCIL_DESC,
+        flags: D3D12_RENDER_PASS_FLAGS,
+    ) {
+        ((*self.0).BeginRenderPass)(
+            self as *const _ as _,
+            num_render_targets,
+            render_targets,
+            depth_stencil,
+            flags,
+        )
+    }
+    pub unsafe fn EndRenderPass(&self) {
+        ((*self.0).EndRenderPass)(self as *const _ as _)
+    }
+    pub unsafe fn BuildRaytracingAccelerationStructure(
+        &self,
+        desc: *const D3D12_BUILD_RAYTRACING_ACCELERATION_STRUCTURE_DESC,
+        num_postbuild_info_descs: u32,
+        postbuild_info_descs: *const D3D12_RAYTRACING_ACCELERATION_STRUCTURE_POSTBUILD_INFO_DESC,
+    ) {
+        ((*self.0).BuildRaytracingAccelerationStructure)(
+            self as *const _ as _,
+            desc,
+            num_postbuild_info_descs,
+            postbuild_info_descs,
+        )
+    }
+    pub unsafe fn SetPipelineState1(&self, state_object: *mut ID3D12StateObject) {
+        ((*self.0).SetPipelineState1)(self as *const _ as _, state_object)
+    }
+    pub unsafe fn DispatchRays(&self, desc: *const D3D12_DISPATCH_RAYS_DESC) {
+        ((*self.0).DispatchRays)(self as *const _ as _, desc)
+    }
+}
+#[allow(non_snake_case, clippy::missing_safety_doc)]
+impl ID3D12GraphicsCommandList2 {
+    pub unsafe fn SetViewInstanceMask(&self, mask: u32) {
+        ((*self.0).SetViewInstanceMask)(self as *const _ as _, mask)
+    }
+}
+#[allow(non_snake_case, clippy::missing_safety_doc)]
+impl ID3D12GraphicsCommandList6 {
+    pub unsafe fn DispatchMesh(&self, x: u32, y: u32, z: u32) {
+        ((*self.0).DispatchMesh)(self as *const _ as _, x, y, z)
+    }
+}
+#[allow(non_snake_case, clippy::missing_safety_doc)]
+impl ID3D12Device3 {
+    pub unsafe fn EnqueueMakeResident(
+        &self,
+        flags: D3D12_RESIDENCY_FLAGS,
+        num_objects: u32,
+        objects: *const *mut ID3D12Pageable,
+        fence_to_signal: *mut ID3D12Fence,
+        fence_value_to_signal: u64,
+    ) -> HRESULT {
+        ((*self.0).EnqueueMakeResident)(
+            self as *const _ as _,
+            flags,
+            num_objects,
+            objects,
+            fence_to_signal,
+            fence_value_to_signal,
+        )
+    }
+}
+#[allow(non_snake_case, clippy::missing_safety_doc)]
+impl ID3D12Device5 {
+    pub unsafe fn CreateStateObject(
+        &self,
+        desc: *const D3D12_STATE_OBJECT_DESC,
+        riid: REFIID,
+        object: *mut *mut c_void,
+    ) -> HRESULT {
+        ((*self.0).CreateStateObject)(self as *const _ as _, desc, riid, object)
+    }
+    pub unsafe fn GetRaytracingAccelerationStructurePrebuildInfo(
+        &self,
+        inputs: *const D3D12_BUILD_RAYTRACING_ACCELERATION_STRUCTURE_INPUTS,
+        info: *mut D3D12_RAYTRACING_ACCELERATION_STRUCTURE_PREBUILD_INFO,
+    ) {
+        ((*self.0).GetRaytracingAccelerationStructurePrebuildInfo)(
+            self as *const _ as _,
+            inputs,
+            info,
+        )
+    }
+}
+#[allow(non_snake_case, clippy::missing_safety_doc)]
+impl ID3D12StateObjectProperties {
+    pub unsafe fn GetShaderIdentifier(&self, export_name: *const u16) -> *mut c_void {
+        ((*self.0).GetShaderIdentifier)(self as *const _ as _, export_name)
+    }
+    pub unsafe fn GetShaderStackSize(&self, export_name: *const u16) -> u64 {
+        ((*self.0).GetShaderStackSize)(self as *const _ as _, export_name)
+    }
+    pub unsafe fn GetPipelineStackSize(&self) -> u64 {
+        ((*self.0).GetPipelineStackSize)(self as *const _ as _)
+    }
+    pub unsafe fn SetPipelineStackSize(&self, size: u64) {
+        ((*self.0).SetPipelineStackSize)(self as *const _ as _, size)
+    }
+}
+
+pub const D3D12_FEATURE_D3D12_OPTIONS3: D3D12_FEATURE = 21;
+pub const D3D12_FEATURE_D3D12_OPTIONS4: D3D12_FEATURE = 23;
+pub const D3D12_FEATURE_D3D12_OPTIONS5: D3D12_FEATURE = 27;
+pub const D3D12_FEATURE_D3D12_OPTIONS6: D3D12_FEATURE = 30;
+pub const D3D12_FEATURE_D3D12_OPTIONS7: D3D12_FEATURE = 32;
+#[allow(non_snake_case, non_camel_case_types)]
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct D3D12_FEATURE_DATA_D3D12_OPTIONS3 {
+    pub CopyQueueTimestampQueriesSupported: BOOL,
+    pub CastingFullyTypedFormatSupported: BOOL,
+    pub WriteBufferImmediateSupportFlags: u32,
+    pub ViewInstancingTier: u32,
+    pub BarycentricsSupported: BOOL,
+}
+#[allow(non_snake_case, non_camel_case_types)]
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct D3D12_FEATURE_DATA_D3D12_OPTIONS4 {
+    pub MSAA64KBAlignedTextureSupported: BOOL,
+    pub SharedResourceCompatibilityTier: u32,
+    pub Native16BitShaderOpsSupported: BOOL,
+}
+#[allow(non_snake_case, non_camel_case_types)]
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct D3D12_FEATURE_DATA_D3D12_OPTIONS5 {
+    pub SRVOnlyTiledResourceTier3: BOOL,
+    pub RenderPassesTier: u32,
+    pub RaytracingTier: u32,
+}
+#[allow(non_snake_case, non_camel_case_types)]
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct D3D12_FEATURE_DATA_D3D12_OPTIONS6 {
+    pub AdditionalShadingRatesSupported: BOOL,
+    pub PerPrimitiveShadingRateSupportedWithViewportIndexing: BOOL,
+    pub VariableShadingRateTier: u32,
+    pub ShadingRateImageTileSize: u32,
+    pub BackgroundProcessingSupported: BOOL,
+}
+#[allow(non_snake_case, non_camel_case_types)]
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct D3D12_FEATURE_DATA_D3D12_OPTIONS7 {
+    pub MeshShaderTier: u32,
+    pub SamplerFeedbackTier: u32,
+}
+
+#[allow(non_camel_case_types)]
+pub type D3D12_RESIDENCY_FLAGS = u32;
+pub const D3D12_RESIDENCY_FLAG_NONE: u32 = 0;
+pub const D3D12_RESIDENCY_FLAG_DENY_OVERBUDGET: u32 = 0x1;
+
+#[allow(non_camel_case_types)]
+pub type D3D12_RENDER_PASS_BEGINNING_ACCESS_TYPE = u32;
+pub const D3D12_RENDER_PASS_BEGINNING_ACCESS_TYPE_DISCARD: u32 = 0;
+pub const D3D12_RENDER_PASS_BEGINNING_ACCESS_TYPE_PRESERVE: u32 = 1;
+pub const D3D12_RENDER_PASS_BEGINNING_ACCESS_TYPE_CLEAR: u32 = 2;
+pub const D3D12_RENDER_PASS_BEGINNING_ACCESS_TYPE_NO_ACCESS: u32 = 3;
+#[allow(non_camel_case_types)]
+pub type D3D12_RENDER_PASS_ENDING_ACCESS_TYPE = u32;
+pub const D3D12_RENDER_PASS_ENDING_ACCESS_TYPE_DISCARD: u32 = 0;
+pub const D3D12_RENDER_PASS_ENDING_ACCESS_TYPE_PRESERVE: u32 = 1;
+pub const D3D12_RENDER_PASS_ENDING_ACCESS_TYPE_RESOLVE: u32 = 2;
+pub const D3D12_RENDER_PASS_ENDING_ACCESS_TYPE_NO_ACCESS: u32 = 3;
+#[allow(non_camel_case_types)]
+pub type D3D12_RENDER_PASS_FLAGS = u32;
+pub const D3D12_RENDER_PASS_FLAG_NONE: u32 = 0;
+pub const D3D12_RENDER_PASS_FLAG_ALLOW_UAV_WRITES: u32 = 0x1;
+pub const D3D12_RENDER_PASS_FLAG_SUSPENDING_PASS: u32 = 0x2;
+pub const D3D12_RENDER_PASS_FLAG_RESUMING_PASS: u32 = 0x4;
+
+#[allow(non_snake_case, non_camel_case_types)]
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct D3D12_RENDER_PASS_BEGINNING_ACCESS {
+    pub Type: D3D12_RENDER_PASS_BEGINNING_ACCESS_TYPE,
+    /// Clear時のみ有効(union)
+    pub ClearValue: D3D12_CLEAR_VALUE,
+}
+#[allow(non_snake_case, non_camel_case_types)]
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct D3D12_RENDER_PASS_ENDING_ACCESS_RESOLVE_SUBRESOURCE_PARAMETERS {
+    pub SrcSubresource: u32,
+    pub DstSubresource: u32,
+    pub DstX: u32,
+    pub DstY: u32,
+    pub SrcRect: D3D12_RECT,
+}
+#[allow(non_snake_case, non_camel_case_types)]
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct D3D12_RENDER_PASS_ENDING_ACCESS_RESOLVE_PARAMETERS {
+    pub pSrcResource: *mut ID3D12Resource,
+    pub pDstResource: *mut ID3D12Resource,
+    pub SubresourceCount: u32,
+    pub pSubresourceParameters:
+        *const D3D12_RENDER_PASS_ENDING_ACCESS_RESOLVE_SUBRESOURCE_PARAMETERS,
+    pub Format: DXGI_FORMAT,
+    pub ResolveMode: D3D12_RESOLVE_MODE,
+    pub PreserveResolveSource: BOOL,
+}
+#[allow(non_snake_case, non_camel_case_types)]
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct D3D12_RENDER_PASS_ENDING_ACCESS {
+    pub Type: D3D12_RENDER_PASS_ENDING_ACCESS_TYPE,
+    /// Resolve時のみ有効(union)
+    pub Resolve: D3D12_RENDER_PASS_ENDING_ACCESS_RESOLVE_PARAMETERS,
+}
+#[allow(non_snake_case, non_camel_case_types)]
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct D3D12_RENDER_PASS_RENDER_TARGET_DESC {
+    pub cpuDescriptor: D3D12_CPU_DESCRIPTOR_HANDLE,
+    pub BeginningAccess: D3D12_RENDER_PASS_BEGINNING_ACCESS,
+    pub EndingAccess: D3D12_RENDER_PASS_ENDING_ACCESS,
+}
+#[allow(non_snake_case, non_camel_case_types)]
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct D3D12_RENDER_PASS_DEPTH_STENCIL_DESC {
+    pub cpuDescriptor: D3D12_CPU_DESCRIPTOR_HANDLE,
+    pub DepthBeginningAccess: D3D12_RENDER_PASS_BEGINNING_ACCESS,
+    pub StencilBeginningAccess: D3D12_RENDER_PASS_BEGINNING_ACCESS,
+    pub DepthEndingAccess: D3D12_RENDER_PASS_ENDING_ACCESS,
+    pub StencilEndingAccess: D3D12_RENDER_PASS_ENDING_ACCESS,
+}
+
+#[allow(non_snake_case, clippy::missing_safety_doc)]
+impl ID3D12DeviceRemovedExtendedDataSettings {
+    pub unsafe fn SetAutoBreadcrumbsEnablement(&self, enablement: D3D12_DRED_ENABLEMENT) {
+        ((*self.0).SetAutoBreadcrumbsEnablement)(self as *const _ as _, enablement)
+    }
+    pub unsafe fn SetPageFaultEnablement(&self, enablement: D3D12_DRED_ENABLEMENT) {
+        ((*self.0).SetPageFaultEnablement)(self as *const _ as _, enablement)
+    }
+    pub unsafe fn SetWatsonDumpEnablement(&self, enablement: D3D12_DRED_ENABLEMENT) {
+        ((*self.0).SetWatsonDumpEnablement)(self as *const _ as _, enablement)
+    }
+}
+#[allow(non_snake_case, clippy::missing_safety_doc)]
+impl ID3D12DeviceRemovedExtendedData {
+    pub unsafe fn GetAutoBreadcrumbsOutput(
+        &self,
+        output: *mut D3D12_DRED_AUTO_BREADCRUMBS_OUTPUT,
+    ) -> HRESULT {
+        ((*self.0).GetAutoBreadcrumbsOutput)(self as *const _ as _, output)
+    }
+    pub unsafe fn GetPageFaultAllocationOutput(
+        &self,
+        output: *mut D3D12_DRED_PAGE_FAULT_OUTPUT,
+    ) -> HRESULT {
+        ((*self.0).GetPageFaultAllocationOutput)(self as *const _ as _, output)
+    }
+}
+
+#[allow(non_camel_case_types)]
+pub type D3D12_DRED_ENABLEMENT = u32;
+pub const D3D12_DRED_ENABLEMENT_SYSTEM_CONTROLLED: u32 = 0;
+pub const D3D12_DRED_ENABLEMENT_FORCED_OFF: u32 = 1;
+pub const D3D12_DRED_ENABLEMENT_FORCED_ON: u32 = 2;
+#[allow(non_camel_case_types)]
+pub type D3D12_AUTO_BREADCRUMB_OP = u32;
+#[allow(non_camel_case_types)]
+pub type D3D12_DRED_ALLOCATION_TYPE = u32;
+
+#[allow(non_snake_case, non_camel_case_types)]
+#[repr(C)]
+pub struct D3D12_AUTO_BREADCRUMB_NODE {
+    pub pCommandListDebugNameA: *const i8,
+    pub pCommandListDebugNameW: *const u16,
+    pub pCommandQueueDebugNameA: *const i8,
+    pub pCommandQueueDebugNameW: *const u16,
+    pub pCommandList: *mut ID3D12GraphicsCommandList,
+    pub pCommandQueue: *mut ID3D12CommandQueue,
+    pub BreadcrumbCount: u32,
+    pub pLastBreadcrumbValue: *const u32,
+    pub pCommandHistory: *const D3D12_AUTO_BREADCRUMB_OP,
+    pub pNext: *const D3D12_AUTO_BREADCRUMB_NODE,
+}
+#[allow(non_snake_case, non_camel_case_types)]
+#[repr(C)]
+pub struct D3D12_DRED_AUTO_BREADCRUMBS_OUTPUT {
+    pub pHeadAutoBreadcrumbNode: *const D3D12_AUTO_BREADCRUMB_NODE,
+}
+#[allow(non_snake_case, non_camel_case_types)]
+#[repr(C)]
+pub struct D3D12_DRED_ALLOCATION_NODE {
+    pub ObjectNameA: *const i8,
+    pub ObjectNameW: *const u16,
+    pub AllocationType: D3D12_DRED_ALLOCATION_TYPE,
+    pub pNext: *const D3D12_DRED_ALLOCATION_NODE,
+}
+#[allow(non_snake_case, non_camel_case_types)]
+#[repr(C)]
+pub struct D3D12_DRED_PAGE_FAULT_OUTPUT {
+    pub PageFaultVA: D3D12_GPU_VIRTUAL_ADDRESS,
+    pub pHeadExistingAllocationNode: *const D3D12_DRED_ALLOCATION_NODE,
+    pub pHeadRecentFreedAllocationNode: *const D3D12_DRED_ALLOCATION_NODE,
+}
+
+pub const D3D12_RAYTRACING_SHADER_RECORD_BYTE_ALIGNMENT: u32 = 32;
+pub const D3D12_RAYTRACING_SHADER_TABLE_BYTE_ALIGNMENT: u32 = 64;
+pub const D3D12_SHADER_IDENTIFIER_SIZE_IN_BYTES: u32 = 32;
+pub const D3D12_RAYTRACING_ACCELERATION_STRUCTURE_BYTE_ALIGNMENT: u32 = 256;
+pub const D3D12_RAYTRACING_INSTANCE_DESCS_BYTE_ALIGNMENT: u32 = 16;
+pub const D3D12_RESOURCE_STATE_RAYTRACING_ACCELERATION_STRUCTURE: u32 = 0x400000;
+pub const D3D12_SRV_DIMENSION_RAYTRACING_ACCELERATION_STRUCTURE: u32 = 11;
+pub const D3D12_ROOT_SIGNATURE_FLAG_LOCAL_ROOT_SIGNATURE: u32 = 0x80;
+
+#[allow(non_camel_case_types)]
+pub type D3D12_RAYTRACING_GEOMETRY_TYPE = u32;
+pub const D3D12_RAYTRACING_GEOMETRY_TYPE_TRIANGLES: u32 = 0;
+pub const D3D12_RAYTRACING_GEOMETRY_TYPE_PROCEDURAL_PRIMITIVE_AABBS: u32 = 1;
+#[allow(non_camel_case_types)]
+pub type D3D12_RAYTRACING_GEOMETRY_FLAGS = u32;
+pub const D3D12_RAYTRACING_GEOMETRY_FLAG_NONE: u32 = 0;
+pub const D3D12_RAYTRACING_GEOMETRY_FLAG_OPAQUE: u32 = 0x1;
+pub const D3D12_RAYTRACING_GEOMETRY_FLAG_NO_DUPLICATE_ANYHIT_INVOCATION: u32 = 0x2;
+#[allow(non_camel_case_types)]
+pub type D3D12_RAYTRACING_ACCELERATION_STRUCTURE_TYPE = u32;
+pub const D3D12_RAYTRACING_ACCELERATION_STRUCTURE_TYPE_TOP_LEVEL: u32 = 0;
+pub const D3D12_RAYTRACING_ACCELERATION_STRUCTURE_TYPE_BOTTOM_LEVEL: u32 = 1;
+#[allow(non_camel_case_types)]
+pub type D3D12_RAYTRACING_ACCELERATION_STRUCTURE_BUILD_FLAGS = u32;
+pub const D3D12_RAYTRACING_ACCELERATION_STRUCTURE_BUILD_FLAG_NONE: u32 = 0;
+pub const D3D12_RAYTRACING_ACCELERATION_STRUCTURE_BUILD_FLAG_ALLOW_UPDATE: u32 = 0x1;
+pub const D3D12_RAYTRACING_ACCELERATION_STRUCTURE_BUILD_FLAG_ALLOW_COMPACTION: u32 = 0x2;
+pub const D3D12_RAYTRACING_ACCELERATION_STRUCTURE_BUILD_FLAG_PREFER_FAST_TRACE: u32 = 0x4;
+pub const D3D12_RAYTRACING_ACCELERATION_STRUCTURE_BUILD_FLAG_PREFER_FAST_BUILD: u32 = 0x8;
+pub const D3D12_RAYTRACING_ACCELERATION_STRUCTURE_BUILD_FLAG_MINIMIZE_MEMORY: u32 = 0x10;
+pub const D3D12_RAYTRACING_ACCELERATION_STRUCTURE_BUILD_FLAG_PERFORM_UPDATE: u32 = 0x20;
+#[allow(non_camel_case_types)]
+pub type D3D12_ELEMENTS_LAYOUT = u32;
+pub const D3D12_ELEMENTS_LAYOUT_ARRAY: u32 = 0;
+pub const D3D12_ELEMENTS_LAYOUT_ARRAY_OF_POINTERS: u32 = 1;
+#[allow(non_camel_case_types)]
+pub type D3D12_RAYTRACING_INSTANCE_FLAGS = u32;
+pub const D3D12_RAYTRACING_INSTANCE_FLAG_NONE: u32 = 0;
+pub const D3D12_RAYTRACING_INSTANCE_FLAG_TRIANGLE_CULL_DISABLE: u32 = 0x1;
+pub const D3D12_RAYTRACING_INSTANCE_FLAG_TRIANGLE_FRONT_COUNTERCLOCKWISE: u32 = 0x2;
+pub const D3D12_RAYTRACING_INSTANCE_FLAG_FORCE_OPAQUE: u32 = 0x4;
+pub const D3D12_RAYTRACING_INSTANCE_FLAG_FORCE_NON_OPAQUE: u32 = 0x8;
+
+#[allow(non_snake_case, non_camel_case_types)]
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct D3D12_GPU_VIRTUAL_ADDRESS_AND_STRIDE {
+    pub StartAddress: D3D12_GPU_VIRTUAL_ADDRESS,
+    pub StrideInBytes: u64,
+}
+#[allow(non_snake_case, non_camel_case_types)]
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct D3D12_GPU_VIRTUAL_ADDRESS_RANGE {
+    pub StartAddress: D3D12_GPU_VIRTUAL_ADDRESS,
+    pub SizeInBytes: u64,
+}
+#[allow(non_snake_case, non_camel_case_types)]
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct D3D12_GPU_VIRTUAL_ADDRESS_RANGE_AND_STRIDE {
+    pub StartAddress: D3D12_GPU_VIRTUAL_ADDRESS,
+    pub SizeInBytes: u64,
+    pub StrideInBytes: u64,
+}
+#[allow(non_snake_case, non_camel_case_types)]
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct D3D12_RAYTRACING_GEOMETRY_TRIANGLES_DESC {
+    pub Transform3x4: D3D12_GPU_VIRTUAL_ADDRESS,
+    pub IndexFormat: DXGI_FORMAT,
+    pub VertexFormat: DXGI_FORMAT,
+    pub IndexCount: u32,
+    pub VertexCount: u32,
+    pub IndexBuffer: D3D12_GPU_VIRTUAL_ADDRESS,
+    pub VertexBuffer: D3D12_GPU_VIRTUAL_ADDRESS_AND_STRIDE,
+}
+#[allow(non_snake_case, non_camel_case_types)]
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct D3D12_RAYTRACING_GEOMETRY_AABBS_DESC {
+    pub AABBCount: u64,
+    pub AABBs: D3D12_GPU_VIRTUAL_ADDRESS_AND_STRIDE,
+}
+#[allow(non_snake_case, non_camel_case_types)]
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub union D3D12_RAYTRACING_GEOMETRY_DESC_u {
+    pub Triangles: D3D12_RAYTRACING_GEOMETRY_TRIANGLES_DESC,
+    pub AABBs: D3D12_RAYTRACING_GEOMETRY_AABBS_DESC,
+}
+#[allow(non_snake_case, non_camel_case_types)]
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct D3D12_RAYTRACING_GEOMETRY_DESC {
+    pub Type: D3D12_RAYTRACING_GEOMETRY_TYPE,
+    pub Flags: D3D12_RAYTRACING_GEOMETRY_FLAGS,
+    pub u: D3D12_RAYTRACING_GEOMETRY_DESC_u,
+}
+#[allow(non_snake_case, non_camel_case_types)]
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct D3D12_RAYTRACING_INSTANCE_DESC {
+    pub Transform: [f32; 12],
+    /// InstanceID(24bit) | InstanceMask(8bit)
+    pub InstanceID_InstanceMask: u32,
+    /// InstanceContributionToHitGroupIndex(24bit) | Flags(8bit)
+    pub InstanceContributionToHitGroupIndex_Flags: u32,
+    pub AccelerationStructure: D3D12_GPU_VIRTUAL_ADDRESS,
+}
+#[allow(non_snake_case, non_camel_case_types)]
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct D3D12_BUILD_RAYTRACING_ACCELERATION_STRUCTURE_INPUTS {
+    pub Type: D3D12_RAYTRACING_ACCELERATION_STRUCTURE_TYPE,
+    pub Flags: D3D12_RAYTRACING_ACCELERATION_STRUCTURE_BUILD_FLAGS,
+    pub NumDescs: u32,
+    pub DescsLayout: D3D12_ELEMENTS_LAYOUT,
+    /// トップレベルではInstanceDescs(GPU仮想アドレス)、ボトムレベルではpGeometryDescs(union)
+    pub InstanceDescsOrGeometryDescs: u64,
+}
+#[allow(non_snake_case, non_camel_case_types)]
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct D3D12_BUILD_RAYTRACING_ACCELERATION_STRUCTURE_DESC {
+    pub DestAccelerationStructureData: D3D12_GPU_VIRTUAL_ADDRESS,
+    pub Inputs: D3D12_BUILD_RAYTRACING_ACCELERATION_STRUCTURE_INPUTS,
+    pub SourceAccelerationStructureData: D3D12_GPU_VIRTUAL_ADDRESS,
+    pub ScratchAccelerationStructureData: D3D12_GPU_VIRTUAL_ADDRESS,
+}
+#[allow(non_snake_case, non_camel_case_types)]
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct D3D12_RAYTRACING_ACCELERATION_STRUCTURE_PREBUILD_INFO {
+    pub ResultDataMaxSizeInBytes: u64,
+    pub ScratchDataSizeInBytes: u64,
+    pub UpdateScratchDataSizeInBytes: u64,
+}
+#[allow(non_snake_case, non_camel_case_types)]
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct D3D12_RAYTRACING_ACCELERATION_STRUCTURE_POSTBUILD_INFO_DESC {
+    pub DestBuffer: D3D12_GPU_VIRTUAL_ADDRESS,
+    pub InfoType: u32,
+}
+#[allow(non_snake_case, non_camel_case_types)]
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct D3D12_DISPATCH_RAYS_DESC {
+    pub RayGenerationShaderRecord: D3D12_GPU_VIRTUAL_ADDRESS_RANGE,
+    pub MissShaderTable: D3D12_GPU_VIRTUAL_ADDRESS_RANGE_AND_STRIDE,
+    pub HitGroupTable: D3D12_GPU_VIRTUAL_ADDRESS_RANGE_AND_STRIDE,
+    pub CallableShaderTable: D3D12_GPU_VIRTUAL_ADDRESS_RANGE_AND_STRIDE,
+    pub Width: u32,
+    pub Height: u32,
+    pub Depth: u32,
+}
+
+#[allow(non_camel_case_types)]
+pub type D3D12_STATE_SUBOBJECT_TYPE = u32;
+pub const D3D12_STATE_SUBOBJECT_TYPE_STATE_OBJECT_CONFIG: u32 = 0;
+pub const D3D12_STATE_SUBOBJECT_TYPE_GLOBAL_ROOT_SIGNATURE: u32 = 1;
+pub const D3D12_STATE_SUBOBJECT_TYPE_LOCAL_ROOT_SIGNATURE: u32 = 2;
+pub const D3D12_STATE_SUBOBJECT_TYPE_NODE_MASK: u32 = 3;
+pub const D3D12_STATE_SUBOBJECT_TYPE_DXIL_LIBRARY: u32 = 5;
+pub const D3D12_STATE_SUBOBJECT_TYPE_EXISTING_COLLECTION: u32 = 6;
+pub const D3D12_STATE_SUBOBJECT_TYPE_SUBOBJECT_TO_EXPORTS_ASSOCIATION: u32 = 7;
+pub const D3D12_STATE_SUBOBJECT_TYPE_DXIL_SUBOBJECT_TO_EXPORTS_ASSOCIATION: u32 = 8;
+pub const D3D12_STATE_SUBOBJECT_TYPE_RAYTRACING_SHADER_CONFIG: u32 = 9;
+pub const D3D12_STATE_SUBOBJECT_TYPE_RAYTRACING_PIPELINE_CONFIG: u32 = 10;
+pub const D3D12_STATE_SUBOBJECT_TYPE_HIT_GROUP: u32 = 11;
+#[allow(non_camel_case_types)]
+pub type D3D12_STATE_OBJECT_TYPE = u32;
+pub const D3D12_STATE_OBJECT_TYPE_COLLECTION: u32 = 0;
+pub const D3D12_STATE_OBJECT_TYPE_RAYTRACING_PIPELINE: u32 = 3;
+#[allow(non_camel_case_types)]
+pub type D3D12_HIT_GROUP_TYPE = u32;
+pub const D3D12_HIT_GROUP_TYPE_TRIANGLES: u32 = 0;
+pub const D3D12_HIT_GROUP_TYPE_PROCEDURAL_PRIMITIVE: u32 = 1;
+
+#[allow(non_snake_case, non_camel_case_types)]
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct D3D12_STATE_SUBOBJECT {
+    pub Type: D3D12_STATE_SUBOBJECT_TYPE,
+    pub pDesc: *const c_void,
+}
+#[allow(non_snake_case, non_camel_case_types)]
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct D3D12_STATE_OBJECT_DESC {
+    pub Type: D3D12_STATE_OBJECT_TYPE,
+    pub NumSubobjects: u32,
+    pub pSubobjects: *const D3D12_STATE_SUBOBJECT,
+}
+#[allow(non_snake_case, non_camel_case_types)]
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct D3D12_EXPORT_DESC {
+    pub Name: *const u16,
+    pub ExportToRename: *const u16,
+    pub Flags: u32,
+}
+#[allow(non_snake_case, non_camel_case_types)]
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct D3D12_DXIL_LIBRARY_DESC {
+    pub DXILLibrary: D3D12_SHADER_BYTECODE,
+    pub NumExports: u32,
+    pub pExports: *const D3D12_EXPORT_DESC,
+}
+#[allow(non_snake_case, non_camel_case_types)]
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct D3D12_HIT_GROUP_DESC {
+    pub HitGroupExport: *const u16,
+    pub Type: D3D12_HIT_GROUP_TYPE,
+    pub AnyHitShaderImport: *const u16,
+    pub ClosestHitShaderImport: *const u16,
+    pub IntersectionShaderImport: *const u16,
+}
+#[allow(non_snake_case, non_camel_case_types)]
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct D3D12_RAYTRACING_SHADER_CONFIG {
+    pub MaxPayloadSizeInBytes: u32,
+    pub MaxAttributeSizeInBytes: u32,
+}
+#[allow(non_snake_case, non_camel_case_types)]
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct D3D12_RAYTRACING_PIPELINE_CONFIG {
+    pub MaxTraceRecursionDepth: u32,
+}
+/// GLOBAL/LOCAL_ROOT_SIGNATUREで共通
+#[allow(non_snake_case, non_camel_case_types)]
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct D3D12_ROOT_SIGNATURE_SUBOBJECT {
+    pub pRootSignature: *mut ID3D12RootSignature,
+}
+#[allow(non_snake_case, non_camel_case_types)]
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct D3D12_SUBOBJECT_TO_EXPORTS_ASSOCIATION {
+    pub pSubobjectToAssociate: *const D3D12_STATE_SUBOBJECT,
+    pub NumExports: u32,
+    pub pExports: *const *const u16,
+}
+
+pub const D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_VIEW_INSTANCING: u32 = 22;
+pub const D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_AS: u32 = 24;
+pub const D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_MS: u32 = 25;
+pub const D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_RASTERIZER1: u32 = 27;
+pub const D3D12_VIEW_INSTANCING_FLAG_NONE: u32 = 0;
+pub const D3D12_VIEW_INSTANCING_FLAG_ENABLE_VIEW_INSTANCE_MASKING: u32 = 0x1;
+pub const D3D12_MAX_VIEW_INSTANCE_COUNT: u32 = 4;
+
+#[allow(non_snake_case, non_camel_case_types)]
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct D3D12_VIEW_INSTANCE_LOCATION {
+    pub ViewportArrayIndex: u32,
+    pub RenderTargetArrayIndex: u32,
+}
+#[allow(non_snake_case, non_camel_case_types)]
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct D3D12_VIEW_INSTANCING_DESC {
+    pub ViewInstanceCount: u32,
+    pub pViewInstanceLocations: *const D3D12_VIEW_INSTANCE_LOCATION,
+    pub Flags: u32,
+}
+/// DepthBiasがFLOATになったD3D12_RASTERIZER_DESC
+#[allow(non_snake_case, non_camel_case_types)]
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct D3D12_RASTERIZER_DESC1 {
+    pub FillMode: D3D12_FILL_MODE,
+    pub CullMode: D3D12_CULL_MODE,
+    pub FrontCounterClockwise: BOOL,
+    pub DepthBias: f32,
+    pub DepthBiasClamp: f32,
+    pub SlopeScaledDepthBias: f32,
+    pub DepthClipEnable: BOOL,
+    pub MultisampleEnable: BOOL,
+    pub AntialiasedLineEnable: BOOL,
+    pub ForcedSampleCount: u32,
+    pub ConservativeRaster: D3D12_CONSERVATIVE_RASTERIZATION_MODE,
+}
@@ -0,0 +1,472 @@
+//! Feature Support Report
+
+use super::*;
+use std::fmt;
+
+macro_rules! DeviceFeatures {
+    ($($t: ty => $f: expr),*) => {
+        $(impl DeviceFeature for $t {
+            const FEATURE_TYPE: D3D12_FEATURE = $f;
+        })*
+    };
+}
+DeviceFeatures!(
+    D3D12_FEATURE_DATA_ARCHITECTURE => D3D12_FEATURE_ARCHITECTURE,
+    D3D12_FEATURE_DATA_ARCHITECTURE1 => D3D12_FEATURE_ARCHITECTURE1,
+    D3D12_FEATURE_DATA_FEATURE_LEVELS => D3D12_FEATURE_FEATURE_LEVELS,
+    D3D12_FEATURE_DATA_FORMAT_SUPPORT => D3D12_FEATURE_FORMAT_SUPPORT,
+    D3D12_FEATURE_DATA_FORMAT_INFO => D3D12_FEATURE_FORMAT_INFO,
+    D3D12_FEATURE_DATA_GPU_VIRTUAL_ADDRESS_SUPPORT => D3D12_FEATURE_GPU_VIRTUAL_ADDRESS_SUPPORT,
+    D3D12_FEATURE_DATA_SHADER_MODEL => D3D12_FEATURE_SHADER_MODEL,
+    D3D12_FEATURE_DATA_ROOT_SIGNATURE => D3D12_FEATURE_ROOT_SIGNATURE,
+    D3D12_FEATURE_DATA_D3D12_OPTIONS1 => D3D12_FEATURE_D3D12_OPTIONS1,
+    D3D12_FEATURE_DATA_D3D12_OPTIONS2 => D3D12_FEATURE_D3D12_OPTIONS2,
+    D3D12_FEATURE_DATA_D3D12_OPTIONS3 => D3D12_FEATURE_D3D12_OPTIONS3,
+    D3D12_FEATURE_DATA_D3D12_OPTIONS4 => D3D12_FEATURE_D3D12_OPTIONS4,
+    D3D12_FEATURE_DATA_D3D12_OPTIONS5 => D3D12_FEATURE_D3D12_OPTIONS5,
+    D3D12_FEATURE_DATA_D3D12_OPTIONS6 => D3D12_FEATURE_D3D12_OPTIONS6,
+    D3D12_FEATURE_DATA_D3D12_OPTIONS7 => D3D12_FEATURE_D3D12_OPTIONS7,
+    D3D12_FEATURE_DATA_SHADER_CACHE => D3D12_FEATURE_SHADER_CACHE
+);
+
+/// 新しいバージョン(上位4ビット.下位4ビット)の形式の値を(メジャー, マイナー)にする
+fn version_nibbles(v: u32) -> (u8, u8) {
+    (((v >> 4) & 0x0f) as u8, (v & 0x0f) as u8)
+}
+/// D3D_FEATURE_LEVEL(0xMm00)を(メジャー, マイナー)にする
+pub fn feature_level_version(level: D3D_FEATURE_LEVEL) -> (u8, u8) {
+    (((level >> 12) & 0x0f) as u8, ((level >> 8) & 0x0f) as u8)
+}
+/// 機能ティアの表記(0は非対応、10以上は"1.0"のような形式の値)
+fn tier_name(tier: u32) -> String {
+    match tier {
+        0 => "none".to_owned(),
+        t if t >= 10 => format!("{}.{}", t / 10, t % 10),
+        t => t.to_string(),
+    }
+}
+
+/// D3D12_OPTIONSの内容
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Options {
+    pub double_precision_float_shader_ops: bool,
+    pub output_merger_logic_op: bool,
+    pub min_precision_support: u32,
+    pub tiled_resources_tier: u32,
+    pub resource_binding_tier: u32,
+    pub ps_specified_stencil_ref: bool,
+    pub typed_uav_load_additional_formats: bool,
+    pub rovs: bool,
+    pub conservative_rasterization_tier: u32,
+    pub standard_swizzle_64kb: bool,
+    pub cross_node_sharing_tier: u32,
+    pub cross_adapter_row_major_texture: bool,
+    pub vp_and_rt_array_index_from_any_shader: bool,
+    pub resource_heap_tier: u32,
+}
+impl From<D3D12_FEATURE_DATA_D3D12_OPTIONS> for Options {
+    fn from(o: D3D12_FEATURE_DATA_D3D12_OPTIONS) -> Self {
+        Options {
+            double_precision_float_shader_ops: o.DoublePrecisionFloatShaderOps != 0,
+            output_merger_logic_op: o.OutputMergerLogicOp != 0,
+            min_precision_support: o.MinPrecisionSupport,
+            tiled_resources_tier: o.TiledResourcesTier,
+            resource_binding_tier: o.ResourceBindingTier,
+            ps_specified_stencil_ref: o.PSSpecifiedStencilRefSupported != 0,
+            typed_uav_load_additional_formats: o.TypedUAVLoadAdditionalFormats != 0,
+            rovs: o.ROVsSupported != 0,
+            conservative_rasterization_tier: o.ConservativeRasterizationTier,
+            standard_swizzle_64kb: o.StandardSwizzle64KBSupported != 0,
+            cross_node_sharing_tier: o.CrossNodeSharingTier,
+            cross_adapter_row_major_texture: o.CrossAdapterRowMajorTextureSupported != 0,
+            vp_and_rt_array_index_from_any_shader: o
+                .VPAndRTArrayIndexFromAnyShaderFeedingRasterizerSupportedWithoutGSEmulation
+                != 0,
+            resource_heap_tier: o.ResourceHeapTier,
+        }
+    }
+}
+/// D3D12_OPTIONS1の内容
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Options1 {
+    pub wave_ops: bool,
+    pub wave_lane_count_min: u32,
+    pub wave_lane_count_max: u32,
+    pub total_lane_count: u32,
+    pub expanded_compute_resource_states: bool,
+    pub int64_shader_ops: bool,
+}
+impl From<D3D12_FEATURE_DATA_D3D12_OPTIONS1> for Options1 {
+    fn from(o: D3D12_FEATURE_DATA_D3D12_OPTIONS1) -> Self {
+        Options1 {
+            wave_ops: o.WaveOps != 0,
+            wave_lane_count_min: o.WaveLaneCountMin,
+            wave_lane_count_max: o.WaveLaneCountMax,
+            total_lane_count: o.TotalLaneCount,
+            expanded_compute_resource_states: o.ExpandedComputeResourceStates != 0,
+            int64_shader_ops: o.Int64ShaderOps != 0,
+        }
+    }
+}
+/// D3D12_OPTIONS2の内容
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Options2 {
+    pub depth_bounds_test: bool,
+    pub programmable_sample_positions_tier: u32,
+}
+impl From<D3D12_FEATURE_DATA_D3D12_OPTIONS2> for Options2 {
+    fn from(o: D3D12_FEATURE_DATA_D3D12_OPTIONS2) -> Self {
+        Options2 {
+            depth_bounds_test: o.DepthBoundsTestSupported != 0,
+            programmable_sample_positions_tier: o.ProgrammableSamplePositionsTier,
+        }
+    }
+}
+/// D3D12_OPTIONS3の内容
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Options3 {
+    pub copy_queue_timestamp_queries: bool,
+    pub casting_fully_typed_format: bool,
+    pub write_buffer_immediate_support_flags: u32,
+    pub view_instancing_tier: u32,
+    pub barycentrics: bool,
+}
+impl From<D3D12_FEATURE_DATA_D3D12_OPTIONS3> for Options3 {
+    fn from(o: D3D12_FEATURE_DATA_D3D12_OPTIONS3) -> Self {
+        Options3 {
+            copy_queue_timestamp_queries: o.CopyQueueTimestampQueriesSupported != 0,
+            casting_fully_typed_format: o.CastingFullyTypedFormatSupported != 0,
+            write_buffer_immediate_support_flags: o.WriteBufferImmediateSupportFlags,
+            view_instancing_tier: o.ViewInstancingTier,
+            barycentrics: o.BarycentricsSupported != 0,
+        }
+    }
+}
+/// D3D12_OPTIONS4の内容
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Options4 {
+    pub msaa_64kb_aligned_texture: bool,
+    pub shared_resource_compatibility_tier: u32,
+    pub native_16bit_shader_ops: bool,
+}
+impl From<D3D12_FEATURE_DATA_D3D12_OPTIONS4> for Options4 {
+    fn from(o: D3D12_FEATURE_DATA_D3D12_OPTIONS4) -> Self {
+        Options4 {
+            msaa_64kb_aligned_texture: o.MSAA64KBAlignedTextureSupported != 0,
+            shared_resource_compatibility_tier: o.SharedResourceCompatibilityTier,
+            native_16bit_shader_ops: o.Native16BitShaderOpsSupported != 0,
+        }
+    }
+}
+/// D3D12_OPTIONS5の内容
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Options5 {
+    pub srv_only_tiled_resource_tier3: bool,
+    pub render_passes_tier: u32,
+    /// 0: 非対応, 10: 1.0, 11: 1.1
+    pub raytracing_tier: u32,
+}
+impl From<D3D12_FEATURE_DATA_D3D12_OPTIONS5> for Options5 {
+    fn from(o: D3D12_FEATURE_DATA_D3D12_OPTIONS5) -> Self {
+        Options5 {
+            srv_only_tiled_resource_tier3: o.SRVOnlyTiledResourceTier3 != 0,
+            render_passes_tier: o.RenderPassesTier,
+            raytracing_tier: o.RaytracingTier,
+        }
+    }
+}
+/// D3D12_OPTIONS6の内容
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Options6 {
+    pub additional_shading_rates: bool,
+    pub per_primitive_shading_rate_with_viewport_indexing: bool,
+    /// 0: 非対応, 1, 2
+    pub variable_shading_rate_tier: u32,
+    pub shading_rate_image_tile_size: u32,
+    pub background_processing: bool,
+}
+impl From<D3D12_FEATURE_DATA_D3D12_OPTIONS6> for Options6 {
+    fn from(o: D3D12_FEATURE_DATA_D3D12_OPTIONS6) -> Self {
+        Options6 {
+            additional_shading_rates: o.AdditionalShadingRatesSupported != 0,
+            per_primitive_shading_rate_with_viewport_indexing: o
+                .PerPrimitiveShadingRateSupportedWithViewportIndexing
+                != 0,
+            variable_shading_rate_tier: o.VariableShadingRateTier,
+            shading_rate_image_tile_size: o.ShadingRateImageTileSize,
+            background_processing: o.BackgroundProcessingSupported != 0,
+        }
+    }
+}
+/// D3D12_OPTIONS7の内容
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Options7 {
+    /// 0: 非対応, 10: 1.0
+    pub mesh_shader_tier: u32,
+    /// 0: 非対応, 90: 0.9, 100: 1.0
+    pub sampler_feedback_tier: u32,
+}
+impl From<D3D12_FEATURE_DATA_D3D12_OPTIONS7> for Options7 {
+    fn from(o: D3D12_FEATURE_DATA_D3D12_OPTIONS7) -> Self {
+        Options7 {
+            mesh_shader_tier: o.MeshShaderTier,
+            sampler_feedback_tier: o.SamplerFeedbackTier,
+        }
+    }
+}
+
+/// GPUのメモリ構成
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Architecture {
+    pub tile_based_renderer: bool,
+    pub uma: bool,
+    pub cache_coherent_uma: bool,
+    /// ARCHITECTURE1に対応していない場合はNone
+    pub isolated_mmu: Option<bool>,
+}
+/// フォーマットの対応状況
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct FormatCapabilities {
+    pub format: dxgi::Format,
+    /// D3D12_FORMAT_SUPPORT1のビット
+    pub support1: u32,
+    /// D3D12_FORMAT_SUPPORT2のビット
+    pub support2: u32,
+}
+impl FormatCapabilities {
+    pub fn supports(&self, support1: D3D12_FORMAT_SUPPORT1) -> bool {
+        (self.support1 & support1) == support1
+    }
+    pub fn supports2(&self, support2: D3D12_FORMAT_SUPPORT2) -> bool {
+        (self.support2 & support2) == support2
+    }
+}
+
+/// デバイスの機能の一覧(起動時のログ出力用)
+///
+/// 新しいランタイムでしか問い合わせできない項目はNoneになる
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct DeviceCapabilities {
+    /// (メジャー, マイナー)
+    pub max_feature_level: (u8, u8),
+    /// (メジャー, マイナー)
+    pub shader_model: (u8, u8),
+    /// (メジャー, マイナー)
+    pub root_signature_version: (u8, u8),
+    pub architecture: Architecture,
+    pub max_gpu_virtual_address_bits_per_resource: u32,
+    pub max_gpu_virtual_address_bits_per_process: u32,
+    pub options: Options,
+    pub options1: Option<Options1>,
+    pub options2: Option<Options2>,
+    pub options3: Option<Options3>,
+    pub options4: Option<Options4>,
+    pub options5: Option<Options5>,
+    pub options6: Option<Options6>,
+    pub options7: Option<Options7>,
+    pub formats: Vec<FormatCapabilities>,
+}
+impl DeviceCapabilities {
+    /// 0: 非対応
+    pub fn raytracing_tier(&self) -> u32 {
+        self.options5.as_ref().map_or(0, |o| o.raytracing_tier)
+    }
+    /// 0: 非対応
+    pub fn mesh_shader_tier(&self) -> u32 {
+        self.options7.as_ref().map_or(0, |o| o.mesh_shader_tier)
+    }
+    /// 0: 非対応
+    pub fn variable_shading_rate_tier(&self) -> u32 {
+        self.options6
+            .as_ref()
+            .map_or(0, |o| o.variable_shading_rate_tier)
+    }
+    pub fn format(&self, format: dxgi::Format) -> Option<&FormatCapabilities> {
+        self.formats.iter().find(|f| f.format == format)
+    }
+}
+/// 1行の要約
+impl fmt::Display for DeviceCapabilities {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let a = &self.architecture;
+        write!(
+            f,
+            "feature level {}_{}, shader model {}.{}, root signature {}.{}, \
+             resource binding tier {}, raytracing tier {}, mesh shader tier {}, VRS tier {}, \
+             {}, GPU VA {}/{} bits",
+            self.max_feature_level.0,
+            self.max_feature_level.1,
+            self.shader_model.0,
+            self.shader_model.1,
+            self.root_signature_version.0,
+            self.root_signature_version.1,
+            self.options.resource_binding_tier,
+            tier_name(self.raytracing_tier()),
+            tier_name(self.mesh_shader_tier()),
+            tier_name(self.variable_shading_rate_tier()),
+            match (a.uma, a.cache_coherent_uma) {
+                (true, true) => "cache-coherent UMA",
+                (true, false) => "UMA",
+                _ => "discrete",
+            },
+            self.max_gpu_virtual_address_bits_per_resource,
+            self.max_gpu_virtual_address_bits_per_process
+        )
+    }
+}
+
+/// 問い合わせる機能レベル(新しいもの順)
+const FEATURE_LEVELS: [D3D_FEATURE_LEVEL; 7] = [
+    0xc200, // 12_2
+    D3D_FEATURE_LEVEL_12_1,
+    D3D_FEATURE_LEVEL_12_0,
+    D3D_FEATURE_LEVEL_11_1,
+    D3D_FEATURE_LEVEL_11_0,
+    D3D_FEATURE_LEVEL_10_1,
+    D3D_FEATURE_LEVEL_10_0,
+];
+/// 問い合わせるシェーダモデル(新しいもの順)
+const SHADER_MODELS: [D3D_SHADER_MODEL; 9] = [0x67, 0x66, 0x65, 0x64, 0x63, 0x62, 0x61, 0x60, 0x51];
+
+impl Device {
+    fn feature<F: DeviceFeature + Copy>(&self, mut data: F) -> IOResult<F> {
+        self.check_feature_support(&mut data)?;
+        Ok(data)
+    }
+    fn feature_zeroed<F: DeviceFeature + Copy>(&self) -> IOResult<F> {
+        self.feature(unsafe { std::mem::zeroed() })
+    }
+
+    /// 対応している最高の機能レベル
+    pub fn max_feature_level(&self) -> IOResult<D3D_FEATURE_LEVEL> {
+        // 古いランタイムは知らない機能レベルが含まれていると失敗する
+        let mut last_err = None;
+        for first in 0..2 {
+            let levels = &FEATURE_LEVELS[first..];
+            let r = self.feature(D3D12_FEATURE_DATA_FEATURE_LEVELS {
+                NumFeatureLevels: levels.len() as _,
+                pFeatureLevelsRequested: levels.as_ptr(),
+                MaxSupportedFeatureLevel: 0,
+            });
+            match r {
+                Ok(d) => return Ok(d.MaxSupportedFeatureLevel),
+                Err(e) => last_err = Some(e),
+            }
+        }
+        Err(last_err.expect("at least one query was made"))
+    }
+    /// 対応している最高のシェーダモデル
+    pub fn highest_shader_model(&self) -> IOResult<D3D_SHADER_MODEL> {
+        // ランタイムが知らないシェーダモデルを指定すると失敗するので順に下げていく
+        let mut last_err = None;
+        for &sm in &SHADER_MODELS {
+            match self.feature(D3D12_FEATURE_DATA_SHADER_MODEL {
+                HighestShaderModel: sm,
+            }) {
+                Ok(d) => return Ok(d.HighestShaderModel),
+                Err(e) => last_err = Some(e),
+            }
+        }
+        Err(last_err.expect("at least one query was made"))
+    }
+    /// 対応している最高のルートシグネチャのバージョン
+    pub fn highest_root_signature_version(&self) -> D3D_ROOT_SIGNATURE_VERSION {
+        self.feature(D3D12_FEATURE_DATA_ROOT_SIGNATURE {
+            HighestVersion: D3D_ROOT_SIGNATURE_VERSION_1_1,
+        })
+        .map_or(D3D_ROOT_SIGNATURE_VERSION_1_0, |d| d.HighestVersion)
+    }
+    /// フォーマットの対応状況
+    pub fn format_support(&self, format: dxgi::Format) -> IOResult<FormatCapabilities> {
+        let d = self.feature(D3D12_FEATURE_DATA_FORMAT_SUPPORT {
+            Format: format,
+            Support1: 0,
+            Support2: 0,
+        })?;
+        Ok(FormatCapabilities {
+            format,
+            support1: d.Support1,
+            support2: d.Support2,
+        })
+    }
+
+    /// デバイスの機能を一通り問い合わせる(formatsは対応状況を調べるフォーマット)
+    pub fn capabilities(&self, formats: &[dxgi::Format]) -> IOResult<DeviceCapabilities> {
+        let architecture = match self.feature_zeroed::<D3D12_FEATURE_DATA_ARCHITECTURE1>() {
+            Ok(a) => Architecture {
+                tile_based_renderer: a.TileBasedRenderer != 0,
+                uma: a.UMA != 0,
+                cache_coherent_uma: a.CacheCoherentUMA != 0,
+                isolated_mmu: Some(a.IsolatedMMU != 0),
+            },
+            Err(_) => {
+                let a = self.feature_zeroed::<D3D12_FEATURE_DATA_ARCHITECTURE>()?;
+                Architecture {
+                    tile_based_renderer: a.TileBasedRenderer != 0,
+                    uma: a.UMA != 0,
+                    cache_coherent_uma: a.CacheCoherentUMA != 0,
+                    isolated_mmu: None,
+                }
+            }
+        };
+        let va = self.feature_zeroed::<D3D12_FEATURE_DATA_GPU_VIRTUAL_ADDRESS_SUPPORT>()?;
+
+        Ok(DeviceCapabilities {
+            max_feature_level: feature_level_version(self.max_feature_level()?),
+            shader_model: version_nibbles(self.highest_shader_model()?),
+            root_signature_version: match self.highest_root_signature_version() {
+                D3D_ROOT_SIGNATURE_VERSION_1_1 => (1, 1),
+                _ => (1, 0),
+            },
+            architecture,
+            max_gpu_virtual_address_bits_per_resource: va.MaxGPUVirtualAddressBitsPerResource,
+            max_gpu_virtual_address_bits_per_process: va.MaxGPUVirtualAddressBitsPerProcess,
+            options: self
+                .feature_zeroed::<D3D12_FEATURE_DATA_D3D12_OPTIONS>()?
+                .into(),
+            options1: self
+                .feature_zeroed::<D3D12_FEATURE_DATA_D3D12_OPTIONS1>()
+                .ok()
+                .map(From::from),
+            options2: self
+                .feature_zeroed::<D3D12_FEATURE_DATA_D3D12_OPTIONS2>()
+                .ok()
+                .map(From::from),
+            options3: self
+                .feature_zeroed::<D3D12_FEATURE_DATA_D3D12_OPTIONS3>()
+                .ok()
+                .map(From::from),
+            options4: self
+                .feature_zeroed::<D3D12_FEATURE_DATA_D3D12_OPTIONS4>()
+                .ok()
+                .map(From::from),
+            options5: self
+                .feature_zeroed::<D3D12_FEATURE_DATA_D3D12_OPTIONS5>()
+                .ok()
+                .map(From::from),
+            options6: self
+                .feature_zeroed::<D3D12_FEATURE_DATA_D3D12_OPTIONS6>()
+                .ok()
+                .map(From::from),
+            options7: self
+                .feature_zeroed::<D3D12_FEATURE_DATA_D3D12_OPTIONS7>()
+                .ok()
+                .map(From::from),
+            formats: formats
+                .iter()
+                .map(|&f| self.format_support(f))
+                .collect::<IOResult<_>>()?,
+        })
+    }
+}
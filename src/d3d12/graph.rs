@@ -0,0 +1,858 @@
+//! Render Graph
+
+use super::*;
+use std::ops::Range;
+
+/// グラフ内のリソースの識別子
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ResourceHandle(pub u32);
+/// グラフ内のパスの識別子
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PassHandle(pub u32);
+
+/// グラフが扱うリソースの種類
+#[derive(Clone, Copy)]
+pub enum GraphResourceKind {
+    /// 外部のリソース(バックバッファなど)
+    Imported {
+        initial_state: ResourceState,
+        /// グラフ実行後に遷移させる状態
+        final_state: Option<ResourceState>,
+    },
+    /// グラフ内でだけ使う一時リソース(ヒープ上でエイリアスされる)
+    Transient { desc: D3D12_RESOURCE_DESC },
+}
+/// グラフが扱うリソース
+#[derive(Clone)]
+pub struct GraphResource {
+    pub name: String,
+    pub kind: GraphResourceKind,
+}
+
+/// パスからのリソースアクセス
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResourceAccess {
+    pub resource: ResourceHandle,
+    pub state: ResourceState,
+    pub write: bool,
+}
+
+/// パスの記録処理
+pub type PassRecorder<'a> = Box<dyn FnMut(&mut GraphicsCommandList, &GraphResources) + 'a>;
+/// グラフ内のパス
+pub struct GraphPass<'a> {
+    pub name: String,
+    pub accesses: Vec<ResourceAccess>,
+    /// 出力がなくても削除しない
+    pub side_effect: bool,
+    recorder: Option<PassRecorder<'a>>,
+}
+
+/// レンダーグラフ
+/// パスは宣言順に実行される。書き込みのみの宣言は内容をすべて上書きするものとみなす
+#[derive(Default)]
+pub struct RenderGraph<'a> {
+    resources: Vec<GraphResource>,
+    passes: Vec<GraphPass<'a>>,
+}
+impl<'a> RenderGraph<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// 外部のリソースを登録する
+    pub fn import(
+        &mut self,
+        name: &str,
+        initial_state: ResourceState,
+        final_state: Option<ResourceState>,
+    ) -> ResourceHandle {
+        self.resources.push(GraphResource {
+            name: name.to_owned(),
+            kind: GraphResourceKind::Imported {
+                initial_state,
+                final_state,
+            },
+        });
+        ResourceHandle(self.resources.len() as u32 - 1)
+    }
+    /// 一時リソースを宣言する
+    pub fn create(&mut self, name: &str, desc: ResourceDesc) -> ResourceHandle {
+        self.resources.push(GraphResource {
+            name: name.to_owned(),
+            kind: GraphResourceKind::Transient { desc: desc.into() },
+        });
+        ResourceHandle(self.resources.len() as u32 - 1)
+    }
+    /// パスを追加する
+    pub fn add_pass(&mut self, name: &str) -> PassBuilder<'_, 'a> {
+        self.passes.push(GraphPass {
+            name: name.to_owned(),
+            accesses: Vec::new(),
+            side_effect: false,
+            recorder: None,
+        });
+        let index = self.passes.len() - 1;
+
+        PassBuilder { graph: self, index }
+    }
+
+    pub fn resources(&self) -> &[GraphResource] {
+        &self.resources
+    }
+    pub fn passes(&self) -> &[GraphPass<'a>] {
+        &self.passes
+    }
+    pub fn resource(&self, handle: ResourceHandle) -> &GraphResource {
+        &self.resources[handle.0 as usize]
+    }
+    pub fn pass(&self, handle: PassHandle) -> &GraphPass<'a> {
+        &self.passes[handle.0 as usize]
+    }
+}
+
+/// パスの宣言
+pub struct PassBuilder<'g, 'a> {
+    graph: &'g mut RenderGraph<'a>,
+    index: usize,
+}
+impl<'g, 'a> PassBuilder<'g, 'a> {
+    fn access(self, resource: ResourceHandle, state: ResourceState, write: bool) -> Self {
+        self.graph.passes[self.index].accesses.push(ResourceAccess {
+            resource,
+            state,
+            write,
+        });
+        self
+    }
+    /// stateの状態で読む
+    pub fn read(self, resource: ResourceHandle, state: ResourceState) -> Self {
+        self.access(resource, state, false)
+    }
+    /// stateの状態で書く
+    pub fn write(self, resource: ResourceHandle, state: ResourceState) -> Self {
+        self.access(resource, state, true)
+    }
+    /// 出力が使われなくても削除しない
+    pub fn side_effect(self) -> Self {
+        self.graph.passes[self.index].side_effect = true;
+        self
+    }
+    /// コマンドの記録処理を設定して宣言を終える
+    pub fn record(
+        self,
+        recorder: impl FnMut(&mut GraphicsCommandList, &GraphResources) + 'a,
+    ) -> PassHandle {
+        self.graph.passes[self.index].recorder = Some(Box::new(recorder));
+        PassHandle(self.index as _)
+    }
+    /// 宣言を終える
+    pub fn finish(self) -> PassHandle {
+        PassHandle(self.index as _)
+    }
+}
+
+/// 計画されたバリア
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlannedBarrier {
+    Transition {
+        resource: ResourceHandle,
+        before: ResourceState,
+        after: ResourceState,
+    },
+    /// beforeがNoneの場合はヒープ上のどのリソースからでもよい
+    Aliasing {
+        before: Option<ResourceHandle>,
+        after: ResourceHandle,
+    },
+    UnorderedAccess {
+        resource: ResourceHandle,
+    },
+}
+/// 一時リソースのヒープ上の配置
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Placement {
+    pub offset: u64,
+    pub size: u64,
+    /// 最初と最後に使うパスの実行順の位置
+    pub lifetime: Range<usize>,
+}
+/// 実行されるパス
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompiledPass {
+    pub pass: PassHandle,
+    /// パスの前に発行するバリア
+    pub barriers: Vec<PlannedBarrier>,
+    /// バリアの後にDiscardResourceで初期化する一時リソース
+    /// (ヒープ上でエイリアスされるレンダーターゲット/深度ステンシルは使い始める前に初期化が必要)
+    pub discards: Vec<ResourceHandle>,
+}
+/// コンパイル済みのグラフ
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompiledGraph {
+    pub passes: Vec<CompiledPass>,
+    /// すべてのパスの後に発行するバリア
+    pub final_barriers: Vec<PlannedBarrier>,
+    /// リソースごとの配置(外部リソースと使われない一時リソースはNone)
+    pub placements: Vec<Option<Placement>>,
+    /// 一時リソースの作成時の状態
+    pub initial_states: Vec<Option<ResourceState>>,
+    pub culled: Vec<PassHandle>,
+    pub heap_size: u64,
+}
+impl CompiledGraph {
+    /// 実行順のパス
+    pub fn pass_order(&self) -> Vec<PassHandle> {
+        self.passes.iter().map(|p| p.pass).collect()
+    }
+    pub fn is_culled(&self, pass: PassHandle) -> bool {
+        self.culled.contains(&pass)
+    }
+    pub fn placement(&self, resource: ResourceHandle) -> Option<&Placement> {
+        self.placements[resource.0 as usize].as_ref()
+    }
+}
+
+/// ヒープ上の領域の割り当て(最初に収まる空きを使い、なければ末尾を伸ばす)
+struct HeapPlanner {
+    free: FreeRanges<u64>,
+    end: u64,
+}
+impl HeapPlanner {
+    fn allocate(&mut self, size: u64, alignment: u64) -> u64 {
+        let found = self
+            .free
+            .ranges()
+            .iter()
+            .position(|r| align_up(r.start, alignment) + size <= r.end || r.end == self.end);
+        let start = match found {
+            Some(n) => align_up(self.free.ranges()[n].start, alignment),
+            None => align_up(self.end, alignment),
+        };
+        if size == 0 {
+            return start;
+        }
+        if start + size > self.end {
+            // 末尾の空きを広げる(末尾に接する空きと結合される)
+            self.free.insert(self.end..start + size);
+            self.end = start + size;
+        }
+        let n = found.unwrap_or(self.free.ranges().len() - 1);
+        self.free.remove(n, start..start + size);
+
+        start
+    }
+    fn release(&mut self, range: Range<u64>) {
+        self.free.insert(range);
+    }
+}
+
+/// 使い始めにDiscardResourceが必要か
+/// (レンダーターゲット/深度ステンシルの一時リソースを、DiscardResourceできる状態で使い始める場合)
+fn needs_discard(kind: &GraphResourceKind, state: ResourceState) -> bool {
+    let flags = match *kind {
+        GraphResourceKind::Transient { ref desc } => desc.Flags,
+        GraphResourceKind::Imported { .. } => return false,
+    };
+    let rt_ds = D3D12_RESOURCE_FLAG_ALLOW_RENDER_TARGET | D3D12_RESOURCE_FLAG_ALLOW_DEPTH_STENCIL;
+    flags & rt_ds != 0
+        && matches!(
+            state,
+            ResourceState::RenderTarget
+                | ResourceState::DepthWrite
+                | ResourceState::UnorderedAccess
+        )
+}
+
+impl<'a> RenderGraph<'a> {
+    /// グラフをコンパイルする
+    /// allocation_infoは一時リソースの(サイズ, アラインメント)を返す
+    pub fn compile(
+        &self,
+        mut allocation_info: impl FnMut(&D3D12_RESOURCE_DESC) -> (u64, u64),
+    ) -> IOResult<CompiledGraph> {
+        let is_transient = |h: ResourceHandle| {
+            matches!(
+                self.resources[h.0 as usize].kind,
+                GraphResourceKind::Transient { .. }
+            )
+        };
+
+        // 宣言の検査
+        for p in &self.passes {
+            for (n, a) in p.accesses.iter().enumerate() {
+                if a.resource.0 as usize >= self.resources.len() {
+                    return invalid(format!(
+                        "pass {:?} accesses an unknown resource {:?}",
+                        p.name, a.resource
+                    ));
+                }
+                if p.accesses[..n]
+                    .iter()
+                    .any(|b| b.resource == a.resource && b.state != a.state)
+                {
+                    return invalid(format!(
+                        "pass {:?} accesses {:?} in different states",
+                        p.name, self.resources[a.resource.0 as usize].name
+                    ));
+                }
+            }
+        }
+
+        // 後ろから辿って出力が使われないパスを削除する
+        let mut live = vec![false; self.resources.len()];
+        let mut alive = vec![false; self.passes.len()];
+        for (pi, p) in self.passes.iter().enumerate().rev() {
+            let needed = p.side_effect
+                || p.accesses
+                    .iter()
+                    .any(|a| a.write && (live[a.resource.0 as usize] || !is_transient(a.resource)));
+            if !needed {
+                continue;
+            }
+            alive[pi] = true;
+            for a in p.accesses.iter().filter(|a| a.write) {
+                live[a.resource.0 as usize] = false;
+            }
+            for a in p.accesses.iter().filter(|a| !a.write) {
+                live[a.resource.0 as usize] = true;
+            }
+        }
+        let order = (0..self.passes.len())
+            .filter(|&n| alive[n])
+            .collect::<Vec<_>>();
+
+        // 一時リソースの寿命
+        let mut lifetimes: Vec<Option<Range<usize>>> = vec![None; self.resources.len()];
+        for (i, &pi) in order.iter().enumerate() {
+            for a in &self.passes[pi].accesses {
+                if !is_transient(a.resource) {
+                    continue;
+                }
+                let lt = &mut lifetimes[a.resource.0 as usize];
+                match lt {
+                    Some(r) => r.end = i + 1,
+                    None => {
+                        let written = self.passes[pi]
+                            .accesses
+                            .iter()
+                            .any(|b| b.resource == a.resource && b.write);
+                        if !written {
+                            return invalid(format!(
+                                "pass {:?} reads {:?} before it is written",
+                                self.passes[pi].name, self.resources[a.resource.0 as usize].name
+                            ));
+                        }
+                        *lt = Some(i..i + 1);
+                    }
+                }
+            }
+        }
+
+        // ヒープ上の配置とエイリアシング
+        let mut placements: Vec<Option<Placement>> = vec![None; self.resources.len()];
+        let mut aliasing: Vec<Vec<PlannedBarrier>> = vec![Vec::new(); order.len()];
+        let mut planner = HeapPlanner {
+            free: FreeRanges::new(),
+            end: 0,
+        };
+        let mut history: Vec<(ResourceHandle, Range<u64>)> = Vec::new();
+        for (i, barriers) in aliasing.iter_mut().enumerate() {
+            for (h, lt) in lifetimes.iter().enumerate() {
+                if let (Some(lt), Some(p)) = (lt, &placements[h]) {
+                    if lt.end == i {
+                        planner.release(p.offset..p.offset + p.size);
+                    }
+                }
+            }
+            for (h, lt) in lifetimes.iter().enumerate() {
+                let lt = match lt {
+                    Some(lt) if lt.start == i => lt,
+                    _ => continue,
+                };
+                let desc = match self.resources[h].kind {
+                    GraphResourceKind::Transient { ref desc } => desc,
+                    _ => unreachable!(),
+                };
+                let (size, alignment) = allocation_info(desc);
+                let offset = planner.allocate(size, alignment);
+                let range = offset..offset + size;
+                let overlapped = history
+                    .iter()
+                    .filter(|(_, r)| r.start < range.end && range.start < r.end)
+                    .map(|&(o, _)| o)
+                    .collect::<Vec<_>>();
+                if !overlapped.is_empty() {
+                    barriers.push(PlannedBarrier::Aliasing {
+                        before: if overlapped.len() == 1 {
+                            Some(overlapped[0])
+                        } else {
+                            None
+                        },
+                        after: ResourceHandle(h as _),
+                    });
+                }
+                history.push((ResourceHandle(h as _), range));
+                placements[h] = Some(Placement {
+                    offset,
+                    size,
+                    lifetime: lt.clone(),
+                });
+            }
+        }
+
+        // 状態遷移の計画
+        let mut current: Vec<Option<(ResourceState, bool)>> = self
+            .resources
+            .iter()
+            .map(|r| match r.kind {
+                GraphResourceKind::Imported { initial_state, .. } => Some((initial_state, false)),
+                GraphResourceKind::Transient { .. } => None,
+            })
+            .collect();
+        let mut initial_states = vec![None; self.resources.len()];
+        let mut passes = Vec::with_capacity(order.len());
+        for (i, &pi) in order.iter().enumerate() {
+            let mut barriers = std::mem::take(&mut aliasing[i]);
+            let mut discards = Vec::new();
+            let mut seen = Vec::new();
+            for a in &self.passes[pi].accesses {
+                let h = a.resource.0 as usize;
+                if seen.contains(&h) {
+                    if a.write {
+                        current[h] = Some((a.state, true));
+                    }
+                    continue;
+                }
+                seen.push(h);
+                match current[h] {
+                    None => {
+                        initial_states[h] = Some(a.state);
+                        if needs_discard(&self.resources[h].kind, a.state) {
+                            discards.push(a.resource);
+                        }
+                    }
+                    Some((s, _)) if s != a.state => barriers.push(PlannedBarrier::Transition {
+                        resource: a.resource,
+                        before: s,
+                        after: a.state,
+                    }),
+                    Some((_, last_write)) => {
+                        if a.state == ResourceState::UnorderedAccess && (last_write || a.write) {
+                            barriers.push(PlannedBarrier::UnorderedAccess {
+                                resource: a.resource,
+                            });
+                        }
+                    }
+                }
+                current[h] = Some((a.state, a.write));
+            }
+            passes.push(CompiledPass {
+                pass: PassHandle(pi as _),
+                barriers,
+                discards,
+            });
+        }
+        let mut final_barriers = Vec::new();
+        for (h, r) in self.resources.iter().enumerate() {
+            let target = match r.kind {
+                GraphResourceKind::Imported { final_state, .. } => final_state,
+                // 次の実行でも同じ計画が使えるように作成時の状態に戻す
+                GraphResourceKind::Transient { .. } => initial_states[h],
+            };
+            if let (Some(t), Some((s, _))) = (target, current[h]) {
+                if s != t {
+                    final_barriers.push(PlannedBarrier::Transition {
+                        resource: ResourceHandle(h as _),
+                        before: s,
+                        after: t,
+                    });
+                }
+            }
+        }
+
+        Ok(CompiledGraph {
+            passes,
+            final_barriers,
+            placements,
+            initial_states,
+            culled: (0..self.passes.len())
+                .filter(|&n| !alive[n])
+                .map(|n| PassHandle(n as _))
+                .collect(),
+            heap_size: planner.end,
+        })
+    }
+}
+
+/// グラフの実行に使う実際のリソース
+pub struct GraphResources {
+    heap: Option<Heap>,
+    resources: Vec<Option<Resource>>,
+}
+impl GraphResources {
+    /// 外部のリソースを結びつける
+    pub fn import(&mut self, handle: ResourceHandle, resource: Resource) -> &mut Self {
+        self.resources[handle.0 as usize] = Some(resource);
+        self
+    }
+    /// リソースを取得する(結びつけられていなければNone)
+    pub fn get(&self, handle: ResourceHandle) -> Option<&Resource> {
+        self.resources
+            .get(handle.0 as usize)
+            .and_then(Option::as_ref)
+    }
+    /// 一時リソースを配置しているヒープ
+    pub fn heap(&self) -> Option<&Heap> {
+        self.heap.as_ref()
+    }
+}
+
+impl Device {
+    /// デバイスからリソースのサイズを得てグラフをコンパイルする
+    pub fn compile_render_graph(&self, graph: &RenderGraph) -> IOResult<CompiledGraph> {
+        graph.compile(|desc| {
+            let info = self.get_resource_allocation_info(&[ResourceDesc(*desc)]);
+            (info.SizeInBytes, info.Alignment)
+        })
+    }
+    /// 一時リソース用のヒープを作り、リソースを配置する
+    /// (バッファとテクスチャを混在させるためリソースヒープTier2が必要)
+    pub fn instantiate_render_graph(
+        &self,
+        graph: &RenderGraph,
+        compiled: &CompiledGraph,
+    ) -> IOResult<GraphResources> {
+        let count = graph.resources.len();
+        if compiled.placements.len() != count || compiled.initial_states.len() != count {
+            return invalid(format!(
+                "compiled graph has {} placements and {} initial states for {} resources",
+                compiled.placements.len(),
+                compiled.initial_states.len(),
+                count
+            ));
+        }
+        let mut heap = if compiled.heap_size > 0 {
+            Some(self.new_heap(
+                &HeapProperty::default(),
+                compiled.heap_size as _,
+                D3D12_HEAP_FLAG_ALLOW_ALL_BUFFERS_AND_TEXTURES,
+            )?)
+        } else {
+            None
+        };
+        let mut resources = Vec::with_capacity(graph.resources.len());
+        for (h, r) in graph.resources.iter().enumerate() {
+            let placed = match (r.kind, &compiled.placements[h], &mut heap) {
+                (GraphResourceKind::Transient { desc }, Some(p), Some(heap)) => {
+                    let state = match compiled.initial_states[h] {
+                        Some(s) => s,
+                        None => {
+                            return invalid(format!(
+                                "transient resource {:?} has a placement but no use",
+                                r.name
+                            ))
+                        }
+                    };
+                    Some(heap.place_resource(p.offset as _, &desc, state as _)?)
+                }
+                _ => None,
+            };
+            resources.push(placed);
+        }
+
+        Ok(GraphResources { heap, resources })
+    }
+}
+
+impl<'a> RenderGraph<'a> {
+    /// コンパイル済みの計画に従ってコマンドを記録する
+    pub fn execute(
+        &mut self,
+        compiled: &CompiledGraph,
+        cmd: &mut GraphicsCommandList,
+        resources: &GraphResources,
+    ) -> IOResult<()> {
+        for p in &compiled.passes {
+            let barriers = p
+                .barriers
+                .iter()
+                .map(|b| resolve_barrier(b, resources))
+                .collect::<IOResult<Vec<_>>>()?;
+            if !barriers.is_empty() {
+                cmd.resource_barrier(&barriers);
+            }
+            for &h in &p.discards {
+                cmd.discard_resource(resolve(h, resources)?, None);
+            }
+            if let Some(ref mut rec) = self.passes[p.pass.0 as usize].recorder {
+                rec(cmd, resources);
+            }
+        }
+        let barriers = compiled
+            .final_barriers
+            .iter()
+            .map(|b| resolve_barrier(b, resources))
+            .collect::<IOResult<Vec<_>>>()?;
+        if !barriers.is_empty() {
+            cmd.resource_barrier(&barriers);
+        }
+
+        Ok(())
+    }
+}
+fn resolve(h: ResourceHandle, resources: &GraphResources) -> IOResult<&Resource> {
+    resources.get(h).ok_or_else(|| {
+        IOError::new(
+            std::io::ErrorKind::NotFound,
+            format!("resource {:?} is not bound", h),
+        )
+    })
+}
+fn resolve_barrier(b: &PlannedBarrier, resources: &GraphResources) -> IOResult<ResourceBarrier> {
+    let get = |h| resolve(h, resources);
+
+    Ok(match *b {
+        PlannedBarrier::Transition {
+            resource,
+            before,
+            after,
+        } => ResourceBarrier::transition_subresource(
+            get(resource)?,
+            D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES,
+            before as _,
+            after as _,
+        ),
+        PlannedBarrier::Aliasing { before, after } => ResourceBarrier::aliasing(
+            match before {
+                Some(h) => Some(get(h)?),
+                None => None,
+            },
+            get(after)?,
+        ),
+        PlannedBarrier::UnorderedAccess { resource } => {
+            ResourceBarrier::unordered_access(Some(get(resource)?))
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ResourceState::*;
+
+    const KB64: u64 = 64 * 1024;
+
+    fn texture(size: u32, flags: ResourceFlag) -> ResourceDesc {
+        ResourceDesc(D3D12_RESOURCE_DESC {
+            Dimension: D3D12_RESOURCE_DIMENSION_TEXTURE2D,
+            Width: size as _,
+            Height: size,
+            Format: DXGI_FORMAT_R8G8B8A8_UNORM,
+            Layout: D3D12_TEXTURE_LAYOUT_UNKNOWN,
+            ..ResourceDesc::buffer(0).into()
+        })
+        .flags(flags)
+    }
+    /// 128x128のRGBA8が64KB
+    fn allocation_info(desc: &D3D12_RESOURCE_DESC) -> (u64, u64) {
+        (desc.Width * desc.Height as u64 * 4, KB64)
+    }
+    fn transition(
+        resource: ResourceHandle,
+        before: ResourceState,
+        after: ResourceState,
+    ) -> PlannedBarrier {
+        PlannedBarrier::Transition {
+            resource,
+            before,
+            after,
+        }
+    }
+
+    /// (グラフ, [backbuffer, gbuffer, depth, unused, hdr, bloom])
+    fn deferred() -> (RenderGraph<'static>, [ResourceHandle; 6]) {
+        let rt = ResourceFlag::new().allow_render_target();
+        let mut g = RenderGraph::new();
+        let backbuffer = g.import("backbuffer", Present, Some(Present));
+        let gbuffer = g.create("gbuffer", texture(128, rt));
+        let depth = g.create(
+            "depth",
+            texture(128, ResourceFlag::new().allow_depth_stencil()),
+        );
+        let unused = g.create(
+            "unused",
+            texture(128, ResourceFlag::new().allow_unordered_access()),
+        );
+        let hdr = g.create("hdr", texture(128, rt));
+        let bloom = g.create("bloom", texture(128, rt));
+        g.add_pass("gbuffer")
+            .write(gbuffer, RenderTarget)
+            .write(depth, DepthWrite)
+            .finish();
+        g.add_pass("unused").write(unused, UnorderedAccess).finish();
+        g.add_pass("lighting")
+            .read(gbuffer, PixelShaderResource)
+            .read(depth, DepthRead)
+            .write(hdr, RenderTarget)
+            .finish();
+        g.add_pass("bloom")
+            .read(hdr, PixelShaderResource)
+            .write(bloom, RenderTarget)
+            .finish();
+        g.add_pass("tonemap")
+            .read(hdr, PixelShaderResource)
+            .read(bloom, PixelShaderResource)
+            .write(backbuffer, RenderTarget)
+            .finish();
+
+        (g, [backbuffer, gbuffer, depth, unused, hdr, bloom])
+    }
+
+    #[test]
+    fn culls_unused_passes() {
+        let (g, [.., unused, _, _]) = deferred();
+        let c = g.compile(allocation_info).unwrap();
+        assert_eq!(
+            c.pass_order(),
+            vec![PassHandle(0), PassHandle(2), PassHandle(3), PassHandle(4)]
+        );
+        assert!(c.is_culled(PassHandle(1)));
+        assert!(c.placement(unused).is_none());
+        assert!(c.initial_states[unused.0 as usize].is_none());
+    }
+
+    #[test]
+    fn places_and_aliases_transients() {
+        let (g, [backbuffer, gbuffer, depth, _, hdr, bloom]) = deferred();
+        let c = g.compile(allocation_info).unwrap();
+        let placed = |h| c.placement(h).map(|p| (p.offset, p.lifetime.clone()));
+        assert_eq!(placed(backbuffer), None);
+        assert_eq!(placed(gbuffer), Some((0, 0..2)));
+        assert_eq!(placed(depth), Some((KB64, 0..2)));
+        assert_eq!(placed(hdr), Some((2 * KB64, 1..4)));
+        // gbufferが使い終わった領域を再利用する
+        assert_eq!(placed(bloom), Some((0, 2..4)));
+        assert_eq!(c.heap_size, 3 * KB64);
+        assert_eq!(
+            c.passes[2].barriers[0],
+            PlannedBarrier::Aliasing {
+                before: Some(gbuffer),
+                after: bloom
+            }
+        );
+    }
+
+    #[test]
+    fn plans_transitions_and_discards() {
+        let (g, [backbuffer, gbuffer, depth, _, hdr, bloom]) = deferred();
+        let c = g.compile(allocation_info).unwrap();
+        let discards = c
+            .passes
+            .iter()
+            .map(|p| p.discards.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            discards,
+            vec![vec![gbuffer, depth], vec![hdr], vec![bloom], vec![]]
+        );
+        assert!(c.passes[0].barriers.is_empty());
+        assert_eq!(
+            c.passes[1].barriers,
+            vec![
+                transition(gbuffer, RenderTarget, PixelShaderResource),
+                transition(depth, DepthWrite, DepthRead),
+            ]
+        );
+        assert_eq!(
+            c.passes[3].barriers,
+            vec![
+                transition(bloom, RenderTarget, PixelShaderResource),
+                transition(backbuffer, Present, RenderTarget),
+            ]
+        );
+        // 一時リソースは作成時の状態に戻す
+        assert_eq!(
+            c.final_barriers,
+            vec![
+                transition(backbuffer, RenderTarget, Present),
+                transition(gbuffer, PixelShaderResource, RenderTarget),
+                transition(depth, DepthRead, DepthWrite),
+                transition(hdr, PixelShaderResource, RenderTarget),
+                transition(bloom, PixelShaderResource, RenderTarget),
+            ]
+        );
+        assert_eq!(c.initial_states[depth.0 as usize], Some(DepthWrite));
+    }
+
+    #[test]
+    fn compilation_is_deterministic() {
+        let first = deferred().0.compile(allocation_info).unwrap();
+        for _ in 0..8 {
+            let (g, _) = deferred();
+            assert_eq!(g.compile(allocation_info).unwrap(), first);
+            assert_eq!(g.compile(allocation_info).unwrap(), first);
+        }
+    }
+
+    #[test]
+    fn repeated_uav_writes_get_uav_barriers() {
+        let mut g = RenderGraph::new();
+        let buf = g.import("particles", UnorderedAccess, None);
+        g.add_pass("emit").write(buf, UnorderedAccess).finish();
+        g.add_pass("simulate").write(buf, UnorderedAccess).finish();
+        let c = g.compile(allocation_info).unwrap();
+        // 外部での書き込みとの間にも張る
+        let uav = vec![PlannedBarrier::UnorderedAccess { resource: buf }];
+        assert_eq!(c.passes[0].barriers, uav);
+        assert_eq!(c.passes[1].barriers, uav);
+        assert!(c.final_barriers.is_empty());
+    }
+
+    #[test]
+    fn invalid_graphs() {
+        let mut g = RenderGraph::new();
+        let out = g.import("out", Present, None);
+        let t = g.create("t", texture(128, ResourceFlag::new()));
+        g.add_pass("read")
+            .read(t, PixelShaderResource)
+            .write(out, RenderTarget)
+            .finish();
+        assert!(g.compile(allocation_info).is_err());
+
+        let mut g = RenderGraph::new();
+        let out = g.import("out", Present, None);
+        g.add_pass("mixed")
+            .read(out, CopySource)
+            .write(out, RenderTarget)
+            .finish();
+        assert!(g.compile(allocation_info).is_err());
+
+        let mut g = RenderGraph::new();
+        g.add_pass("unknown")
+            .write(ResourceHandle(3), RenderTarget)
+            .side_effect()
+            .finish();
+        assert!(g.compile(allocation_info).is_err());
+    }
+
+    #[test]
+    fn unbound_resources_are_reported() {
+        let mut resources = GraphResources {
+            heap: None,
+            resources: vec![None, None],
+        };
+        resources.import(ResourceHandle(1), Resource(std::ptr::null_mut()));
+        assert!(resources.get(ResourceHandle(0)).is_none());
+        assert!(resources.get(ResourceHandle(1)).is_some());
+        assert!(resources.get(ResourceHandle(2)).is_none());
+
+        match resolve(ResourceHandle(0), &resources) {
+            Ok(_) => panic!("unbound resource resolved"),
+            Err(e) => assert_eq!(e.kind(), std::io::ErrorKind::NotFound),
+        }
+        let barrier = PlannedBarrier::UnorderedAccess {
+            resource: ResourceHandle(2),
+        };
+        assert!(resolve_barrier(&barrier, &resources).is_err());
+    }
+}
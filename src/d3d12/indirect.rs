@@ -0,0 +1,466 @@
+//! Indirect Execution(ExecuteIndirect/Command Signature)
+
+use super::*;
+
+pub use winapi::um::d3d12::D3D12_DISPATCH_ARGUMENTS as DispatchArguments;
+pub use winapi::um::d3d12::D3D12_DRAW_ARGUMENTS as DrawArguments;
+pub use winapi::um::d3d12::D3D12_DRAW_INDEXED_ARGUMENTS as DrawIndexedArguments;
+
+/// 間接実行で引数バッファから読まれる引数の種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndirectArgument {
+    /// DrawInstanced(DrawArgumentsを読む)
+    Draw,
+    /// DrawIndexedInstanced(DrawIndexedArgumentsを読む)
+    DrawIndexed,
+    /// Dispatch(DispatchArgumentsを読む)
+    Dispatch,
+    /// 頂点バッファビューの差し替え(VertexBufferViewを読む)
+    VertexBufferView { slot: u32 },
+    /// インデックスバッファビューの差し替え(IndexBufferViewを読む)
+    IndexBufferView,
+    /// ルート定数の差し替え(u32をcount個読む)
+    Constant {
+        root_parameter_index: u32,
+        dest_offset: u32,
+        count: u32,
+    },
+    /// ルートCBVの差し替え(GPU仮想アドレスを読む)
+    ConstantBufferView { root_parameter_index: u32 },
+    /// ルートSRVの差し替え(GPU仮想アドレスを読む)
+    ShaderResourceView { root_parameter_index: u32 },
+    /// ルートUAVの差し替え(GPU仮想アドレスを読む)
+    UnorderedAccessView { root_parameter_index: u32 },
+}
+impl IndirectArgument {
+    /// 引数バッファ上で占めるバイト数
+    pub fn byte_size(&self) -> u32 {
+        match *self {
+            IndirectArgument::Draw => size_of::<DrawArguments>() as _,
+            IndirectArgument::DrawIndexed => size_of::<DrawIndexedArguments>() as _,
+            IndirectArgument::Dispatch => size_of::<DispatchArguments>() as _,
+            IndirectArgument::VertexBufferView { .. } => size_of::<VertexBufferView>() as _,
+            IndirectArgument::IndexBufferView => size_of::<IndexBufferView>() as _,
+            IndirectArgument::Constant { count, .. } => count * size_of::<u32>() as u32,
+            IndirectArgument::ConstantBufferView { .. }
+            | IndirectArgument::ShaderResourceView { .. }
+            | IndirectArgument::UnorderedAccessView { .. } => {
+                size_of::<D3D12_GPU_VIRTUAL_ADDRESS>() as _
+            }
+        }
+    }
+    /// 描画/ディスパッチを発行する引数かどうか(シグネチャの最後に1つだけ置ける)
+    pub fn is_command(&self) -> bool {
+        matches!(
+            *self,
+            IndirectArgument::Draw | IndirectArgument::DrawIndexed | IndirectArgument::Dispatch
+        )
+    }
+    /// ルートシグネチャの指定が必要な引数かどうか
+    pub fn requires_root_signature(&self) -> bool {
+        matches!(
+            *self,
+            IndirectArgument::Constant { .. }
+                | IndirectArgument::ConstantBufferView { .. }
+                | IndirectArgument::ShaderResourceView { .. }
+                | IndirectArgument::UnorderedAccessView { .. }
+        )
+    }
+
+    fn to_desc(self) -> D3D12_INDIRECT_ARGUMENT_DESC {
+        let mut desc = D3D12_INDIRECT_ARGUMENT_DESC {
+            Type: 0,
+            u: unsafe { std::mem::zeroed() },
+        };
+        unsafe {
+            match self {
+                IndirectArgument::Draw => desc.Type = D3D12_INDIRECT_ARGUMENT_TYPE_DRAW,
+                IndirectArgument::DrawIndexed => {
+                    desc.Type = D3D12_INDIRECT_ARGUMENT_TYPE_DRAW_INDEXED
+                }
+                IndirectArgument::Dispatch => desc.Type = D3D12_INDIRECT_ARGUMENT_TYPE_DISPATCH,
+                IndirectArgument::VertexBufferView { slot } => {
+                    desc.Type = D3D12_INDIRECT_ARGUMENT_TYPE_VERTEX_BUFFER_VIEW;
+                    desc.u.VertexBuffer_mut().Slot = slot;
+                }
+                IndirectArgument::IndexBufferView => {
+                    desc.Type = D3D12_INDIRECT_ARGUMENT_TYPE_INDEX_BUFFER_VIEW
+                }
+                IndirectArgument::Constant {
+                    root_parameter_index,
+                    dest_offset,
+                    count,
+                } => {
+                    desc.Type = D3D12_INDIRECT_ARGUMENT_TYPE_CONSTANT;
+                    *desc.u.Constant_mut() = D3D12_INDIRECT_ARGUMENT_DESC_Constant {
+                        RootParameterIndex: root_parameter_index,
+                        DestOffsetIn32BitValues: dest_offset,
+                        Num32BitValuesToSet: count,
+                    };
+                }
+                IndirectArgument::ConstantBufferView {
+                    root_parameter_index,
+                } => {
+                    desc.Type = D3D12_INDIRECT_ARGUMENT_TYPE_CONSTANT_BUFFER_VIEW;
+                    desc.u.ConstantBufferView_mut().RootParameterIndex = root_parameter_index;
+                }
+                IndirectArgument::ShaderResourceView {
+                    root_parameter_index,
+                } => {
+                    desc.Type = D3D12_INDIRECT_ARGUMENT_TYPE_SHADER_RESOURCE_VIEW;
+                    desc.u.ShaderResourceView_mut().RootParameterIndex = root_parameter_index;
+                }
+                IndirectArgument::UnorderedAccessView {
+                    root_parameter_index,
+                } => {
+                    desc.Type = D3D12_INDIRECT_ARGUMENT_TYPE_UNORDERED_ACCESS_VIEW;
+                    desc.u.UnorderedAccessView_mut().RootParameterIndex = root_parameter_index;
+                }
+            }
+        }
+        desc
+    }
+}
+
+/// 引数バッファ1コマンドぶんのレイアウト
+/// 引数は指定順に隙間なく(4バイト単位で)並ぶので、
+/// 64bitアドレスを奇数個のu32のあとに置く場合は`#[repr(C, packed(4))]`な構造体で受けること
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndirectArgumentLayout {
+    offsets: Vec<u32>,
+    byte_stride: u32,
+}
+impl IndirectArgumentLayout {
+    /// 1コマンドぶんのバイト数(ByteStride)
+    pub fn byte_stride(&self) -> u32 {
+        self.byte_stride
+    }
+    /// 各引数のコマンド先頭からのオフセット
+    pub fn offsets(&self) -> &[u32] {
+        &self.offsets
+    }
+    /// index番目のコマンドのargument番目の引数の、バッファ先頭からのオフセット
+    pub fn argument_offset(&self, index: usize, argument: usize) -> usize {
+        index * self.byte_stride as usize + self.offsets[argument] as usize
+    }
+    /// count個のコマンドを格納するのに必要な引数バッファのサイズ
+    pub fn buffer_size(&self, count: usize) -> usize {
+        count * self.byte_stride as usize
+    }
+}
+
+/// コマンドシグネチャの構築
+#[derive(Debug, Clone, Default)]
+pub struct CommandSignatureBuilder {
+    arguments: Vec<IndirectArgument>,
+    byte_stride: Option<u32>,
+}
+impl CommandSignatureBuilder {
+    pub fn new() -> Self {
+        CommandSignatureBuilder {
+            arguments: Vec::new(),
+            byte_stride: None,
+        }
+    }
+
+    /// 引数を追加
+    pub fn argument(mut self, arg: IndirectArgument) -> Self {
+        self.arguments.push(arg);
+        self
+    }
+    /// 描画
+    pub fn draw(self) -> Self {
+        self.argument(IndirectArgument::Draw)
+    }
+    /// インデックス付き描画
+    pub fn draw_indexed(self) -> Self {
+        self.argument(IndirectArgument::DrawIndexed)
+    }
+    /// ディスパッチ
+    pub fn dispatch(self) -> Self {
+        self.argument(IndirectArgument::Dispatch)
+    }
+    /// 頂点バッファビュー
+    pub fn vertex_buffer(self, slot: u32) -> Self {
+        self.argument(IndirectArgument::VertexBufferView { slot })
+    }
+    /// インデックスバッファビュー
+    pub fn index_buffer(self) -> Self {
+        self.argument(IndirectArgument::IndexBufferView)
+    }
+    /// ルート定数
+    pub fn constants(self, root_parameter_index: u32, dest_offset: u32, count: u32) -> Self {
+        self.argument(IndirectArgument::Constant {
+            root_parameter_index,
+            dest_offset,
+            count,
+        })
+    }
+    /// ルート定数バッファ
+    pub fn constant_buffer_view(self, root_parameter_index: u32) -> Self {
+        self.argument(IndirectArgument::ConstantBufferView {
+            root_parameter_index,
+        })
+    }
+    /// ルートシェーダリソース
+    pub fn shader_resource_view(self, root_parameter_index: u32) -> Self {
+        self.argument(IndirectArgument::ShaderResourceView {
+            root_parameter_index,
+        })
+    }
+    /// ルートアンオーダードアクセス
+    pub fn unordered_access_view(self, root_parameter_index: u32) -> Self {
+        self.argument(IndirectArgument::UnorderedAccessView {
+            root_parameter_index,
+        })
+    }
+    /// 1コマンドぶんのバイト数を明示(省略時は引数サイズの合計)
+    pub fn byte_stride(mut self, stride: u32) -> Self {
+        self.byte_stride = Some(stride);
+        self
+    }
+
+    /// 追加済みの引数
+    pub fn arguments(&self) -> &[IndirectArgument] {
+        &self.arguments
+    }
+    /// ルートシグネチャの指定が必要かどうか
+    pub fn requires_root_signature(&self) -> bool {
+        self.arguments
+            .iter()
+            .any(IndirectArgument::requires_root_signature)
+    }
+
+    /// 引数の並びを検証してレイアウトを計算する
+    pub fn layout(&self) -> IOResult<IndirectArgumentLayout> {
+        let (last, heads) = match self.arguments.split_last() {
+            Some(x) => x,
+            None => return invalid("command signature has no arguments".to_owned()),
+        };
+        if !last.is_command() {
+            return invalid(
+                "the last argument of a command signature must be Draw, DrawIndexed or Dispatch"
+                    .to_owned(),
+            );
+        }
+        if heads.iter().any(IndirectArgument::is_command) {
+            return invalid(
+                "a command signature can contain only one Draw, DrawIndexed or Dispatch argument"
+                    .to_owned(),
+            );
+        }
+        let changes_ia = heads.iter().any(|a| {
+            matches!(
+                *a,
+                IndirectArgument::VertexBufferView { .. } | IndirectArgument::IndexBufferView
+            )
+        });
+        if changes_ia && *last == IndirectArgument::Dispatch {
+            return invalid(
+                "vertex/index buffer arguments cannot be used with Dispatch".to_owned(),
+            );
+        }
+        if heads.contains(&IndirectArgument::IndexBufferView)
+            && *last != IndirectArgument::DrawIndexed
+        {
+            return invalid("index buffer argument requires DrawIndexed as the command".to_owned());
+        }
+        if heads.iter().any(|a| match *a {
+            IndirectArgument::Constant { count, .. } => count == 0,
+            _ => false,
+        }) {
+            return invalid("constant argument must set at least one value".to_owned());
+        }
+
+        let mut offsets = Vec::with_capacity(self.arguments.len());
+        let mut offset = 0;
+        for a in &self.arguments {
+            offsets.push(offset);
+            offset += a.byte_size();
+        }
+        let byte_stride = match self.byte_stride {
+            Some(s) if s < offset => {
+                return invalid(
+                    "byte stride is smaller than the total size of the arguments".to_owned(),
+                )
+            }
+            Some(s) if s % 4 != 0 => {
+                return invalid("byte stride must be a multiple of 4".to_owned())
+            }
+            Some(s) => s,
+            None => offset,
+        };
+
+        Ok(IndirectArgumentLayout {
+            offsets,
+            byte_stride,
+        })
+    }
+}
+
+/// コマンドシグネチャ
+pub struct CommandSignature(*mut ID3D12CommandSignature, IndirectArgumentLayout);
+HandleWrapper!(for CommandSignature[ID3D12CommandSignature]);
+DebugNameBySetName!(for CommandSignature);
+impl Device {
+    /// コマンドシグネチャの作成
+    /// ルート引数を差し替える場合はroot_signatureが必要
+    pub fn new_command_signature(
+        &self,
+        builder: &CommandSignatureBuilder,
+        root_signature: Option<&RootSignature>,
+    ) -> IOResult<CommandSignature> {
+        let layout = builder.layout()?;
+        if builder.requires_root_signature() && root_signature.is_none() {
+            return invalid(
+                "command signature changing root arguments requires a root signature".to_owned(),
+            );
+        }
+        let args = builder
+            .arguments
+            .iter()
+            .map(|a| a.to_desc())
+            .collect::<Vec<_>>();
+        let desc = D3D12_COMMAND_SIGNATURE_DESC {
+            ByteStride: layout.byte_stride,
+            NumArgumentDescs: args.len() as _,
+            pArgumentDescs: args.as_ptr(),
+            NodeMask: 0,
+        };
+        let mut handle = std::ptr::null_mut();
+        unsafe {
+            (*self.0).CreateCommandSignature(
+                &desc,
+                // 定数/ビューを差し替えない場合はNULLでなければならない
+                if builder.requires_root_signature() {
+                    root_signature.map_or(std::ptr::null_mut(), |r| r.0)
+                } else {
+                    std::ptr::null_mut()
+                },
+                &ID3D12CommandSignature::uuidof(),
+                &mut handle,
+            )
+        }
+        .to_result_with(|| CommandSignature(handle as _, layout))
+    }
+}
+impl CommandSignature {
+    /// 引数バッファのレイアウト
+    pub fn layout(&self) -> &IndirectArgumentLayout {
+        &self.1
+    }
+    /// 1コマンドぶんのバイト数
+    pub fn byte_stride(&self) -> u32 {
+        self.1.byte_stride
+    }
+}
+unsafe impl Sync for CommandSignature {}
+unsafe impl Send for CommandSignature {}
+
+impl GraphicsCommandList {
+    /// 間接実行
+    /// count_bufferを指定した場合、実行数はmin(max_command_count, count_bufferの値)になる
+    pub fn execute_indirect(
+        &mut self,
+        signature: &CommandSignature,
+        max_command_count: u32,
+        argument_buffer: &Resource,
+        argument_offset: u64,
+        count_buffer: Option<(&Resource, u64)>,
+    ) -> &mut Self {
+        let (cb, cb_offset) = count_buffer.map_or((std::ptr::null_mut(), 0), |(r, o)| (r.0, o));
+        unsafe {
+            (*self.0).ExecuteIndirect(
+                signature.0,
+                max_command_count,
+                argument_buffer.0,
+                argument_offset,
+                cb,
+                cb_offset,
+            )
+        };
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn argument_sizes() {
+        assert_eq!(IndirectArgument::Draw.byte_size(), 16);
+        assert_eq!(IndirectArgument::DrawIndexed.byte_size(), 20);
+        assert_eq!(IndirectArgument::Dispatch.byte_size(), 12);
+        assert_eq!(
+            IndirectArgument::VertexBufferView { slot: 0 }.byte_size(),
+            16
+        );
+        assert_eq!(IndirectArgument::IndexBufferView.byte_size(), 16);
+        let c = IndirectArgument::Constant {
+            root_parameter_index: 0,
+            dest_offset: 0,
+            count: 3,
+        };
+        assert_eq!(c.byte_size(), 12);
+        let srv = IndirectArgument::ShaderResourceView {
+            root_parameter_index: 1,
+        };
+        assert_eq!(srv.byte_size(), 8);
+    }
+
+    #[test]
+    fn offsets_are_packed_in_order() {
+        let layout = CommandSignatureBuilder::new()
+            .constants(0, 0, 1)
+            .constant_buffer_view(1)
+            .vertex_buffer(0)
+            .index_buffer()
+            .draw_indexed()
+            .layout()
+            .unwrap();
+        // u32のあとの64bitアドレスは8バイト境界に揃えない
+        assert_eq!(layout.offsets(), &[0, 4, 12, 28, 44]);
+        assert_eq!(layout.byte_stride(), 64);
+        assert_eq!(layout.argument_offset(2, 1), 2 * 64 + 4);
+        assert_eq!(layout.buffer_size(10), 640);
+    }
+
+    #[test]
+    fn explicit_byte_stride() {
+        let b = CommandSignatureBuilder::new().constants(0, 0, 2).dispatch();
+        assert_eq!(b.layout().unwrap().byte_stride(), 20);
+        let layout = b.clone().byte_stride(32).layout().unwrap();
+        assert_eq!(layout.offsets(), &[0, 8]);
+        assert_eq!(layout.argument_offset(1, 1), 40);
+        assert!(b.clone().byte_stride(16).layout().is_err());
+        assert!(b.byte_stride(22).layout().is_err());
+    }
+
+    #[test]
+    fn invalid_argument_orders() {
+        let b = CommandSignatureBuilder::new;
+        assert!(b().layout().is_err());
+        assert!(b().draw().constants(0, 0, 1).layout().is_err());
+        assert!(b().draw().dispatch().layout().is_err());
+        assert!(b().vertex_buffer(0).dispatch().layout().is_err());
+        assert!(b().index_buffer().draw().layout().is_err());
+        assert!(b().constants(0, 0, 0).draw().layout().is_err());
+        assert!(b().vertex_buffer(1).draw().layout().is_ok());
+    }
+
+    #[test]
+    fn root_signature_requirement() {
+        let b = CommandSignatureBuilder::new;
+        assert!(!b()
+            .vertex_buffer(0)
+            .index_buffer()
+            .draw_indexed()
+            .requires_root_signature());
+        assert!(b()
+            .unordered_access_view(2)
+            .dispatch()
+            .requires_root_signature());
+        assert!(b().constants(0, 1, 1).draw().requires_root_signature());
+    }
+}
@@ -0,0 +1,485 @@
+//! Pipeline State Streams
+
+use super::*;
+use std::marker::PhantomData;
+
+/// サブオブジェクトの配置
+/// (D3D12_PIPELINE_STATE_STREAM_SUBOBJECTはポインタ境界に揃えられ、型の直後に中身が続く)
+const STREAM_ALIGNMENT: usize = std::mem::align_of::<*const c_void>();
+
+/// ストリーム中のサブオブジェクト1つ
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamSubobject {
+    pub ty: D3D12_PIPELINE_STATE_SUBOBJECT_TYPE,
+    /// 型の位置
+    pub offset: usize,
+    /// 中身の位置
+    pub data_offset: usize,
+    /// 次のサブオブジェクトの位置
+    pub end: usize,
+}
+
+/// サブオブジェクトを並べてパイプラインステートを作る(ID3D12Device2::CreatePipelineState)
+pub struct PipelineStateStream<'a> {
+    buffer: Vec<u64>,
+    len: usize,
+    subobjects: Vec<StreamSubobject>,
+    _borrows: PhantomData<&'a ()>,
+}
+impl<'a> Default for PipelineStateStream<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl<'a> PipelineStateStream<'a> {
+    pub fn new() -> Self {
+        PipelineStateStream {
+            buffer: Vec::new(),
+            len: 0,
+            subobjects: Vec::new(),
+            _borrows: PhantomData,
+        }
+    }
+
+    /// サブオブジェクトを追加する(tyに対応する型の値であること)
+    ///
+    /// # Safety
+    /// valueがポインタを含む場合、その参照先は'aの間有効であること
+    pub unsafe fn push_raw<T: Copy>(
+        &mut self,
+        ty: D3D12_PIPELINE_STATE_SUBOBJECT_TYPE,
+        value: &T,
+    ) -> &mut Self {
+        let offset = self.len;
+        let data_offset = align_up(
+            (offset + size_of::<u32>()) as u64,
+            std::mem::align_of::<T>() as u64,
+        ) as usize;
+        let end = align_up(
+            (data_offset + size_of::<T>()) as u64,
+            STREAM_ALIGNMENT as u64,
+        ) as usize;
+        self.buffer.resize(end.div_ceil(size_of::<u64>()), 0);
+
+        let bytes = std::slice::from_raw_parts_mut(self.buffer.as_mut_ptr() as *mut u8, end);
+        bytes[offset..offset + size_of::<u32>()].copy_from_slice(&ty.to_ne_bytes());
+        std::ptr::copy_nonoverlapping(
+            value as *const T as *const u8,
+            bytes[data_offset..].as_mut_ptr(),
+            size_of::<T>(),
+        );
+        self.len = end;
+        self.subobjects.push(StreamSubobject {
+            ty,
+            offset,
+            data_offset,
+            end,
+        });
+
+        self
+    }
+    fn push<T: Copy>(&mut self, ty: D3D12_PIPELINE_STATE_SUBOBJECT_TYPE, value: T) -> &mut Self {
+        unsafe { self.push_raw(ty, &value) }
+    }
+    fn shader<S: AsRef<D3D12_SHADER_BYTECODE>>(
+        &mut self,
+        ty: D3D12_PIPELINE_STATE_SUBOBJECT_TYPE,
+        shader: &'a S,
+    ) -> &mut Self {
+        self.push(ty, *shader.as_ref())
+    }
+
+    pub fn root_signature(&mut self, rs: &'a RootSignature) -> &mut Self {
+        self.push(D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_ROOT_SIGNATURE, rs.0)
+    }
+    pub fn vertex_shader<S: AsRef<D3D12_SHADER_BYTECODE>>(&mut self, shader: &'a S) -> &mut Self {
+        self.shader(D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_VS, shader)
+    }
+    pub fn hull_shader<S: AsRef<D3D12_SHADER_BYTECODE>>(&mut self, shader: &'a S) -> &mut Self {
+        self.shader(D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_HS, shader)
+    }
+    pub fn domain_shader<S: AsRef<D3D12_SHADER_BYTECODE>>(&mut self, shader: &'a S) -> &mut Self {
+        self.shader(D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_DS, shader)
+    }
+    pub fn geometry_shader<S: AsRef<D3D12_SHADER_BYTECODE>>(&mut self, shader: &'a S) -> &mut Self {
+        self.shader(D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_GS, shader)
+    }
+    pub fn pixel_shader<S: AsRef<D3D12_SHADER_BYTECODE>>(&mut self, shader: &'a S) -> &mut Self {
+        self.shader(D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_PS, shader)
+    }
+    pub fn compute_shader<S: AsRef<D3D12_SHADER_BYTECODE>>(&mut self, shader: &'a S) -> &mut Self {
+        self.shader(D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_CS, shader)
+    }
+    /// 増幅シェーダ(メッシュシェーダパイプライン)
+    pub fn amplification_shader<S: AsRef<D3D12_SHADER_BYTECODE>>(
+        &mut self,
+        shader: &'a S,
+    ) -> &mut Self {
+        self.shader(D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_AS, shader)
+    }
+    /// メッシュシェーダ(頂点シェーダ/入力レイアウトの代わり)
+    pub fn mesh_shader<S: AsRef<D3D12_SHADER_BYTECODE>>(&mut self, shader: &'a S) -> &mut Self {
+        self.shader(D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_MS, shader)
+    }
+
+    pub fn blend_state(&mut self, desc: &D3D12_BLEND_DESC) -> &mut Self {
+        self.push(D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_BLEND, *desc)
+    }
+    pub fn sample_mask(&mut self, mask: u32) -> &mut Self {
+        self.push(D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_SAMPLE_MASK, mask)
+    }
+    /// ラスタライザステート(コンサバティブラスタライズ、ForcedSampleCountを含む)
+    pub fn rasterizer_state(&mut self, desc: &D3D12_RASTERIZER_DESC) -> &mut Self {
+        self.push(D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_RASTERIZER, *desc)
+    }
+    /// 深度バイアスを浮動小数で指定するラスタライザステート(対応するランタイムが必要)
+    pub fn rasterizer_state1(&mut self, desc: &D3D12_RASTERIZER_DESC1) -> &mut Self {
+        self.push(D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_RASTERIZER1, *desc)
+    }
+    pub fn depth_stencil_state(&mut self, desc: &D3D12_DEPTH_STENCIL_DESC) -> &mut Self {
+        self.push(D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_DEPTH_STENCIL, *desc)
+    }
+    /// 深度境界テストを含む深度ステンシルステート
+    pub fn depth_stencil_state1(&mut self, desc: &D3D12_DEPTH_STENCIL_DESC1) -> &mut Self {
+        self.push(D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_DEPTH_STENCIL1, *desc)
+    }
+    pub fn input_layout(&mut self, elements: &'a [D3D12_INPUT_ELEMENT_DESC]) -> &mut Self {
+        self.push(
+            D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_INPUT_LAYOUT,
+            D3D12_INPUT_LAYOUT_DESC {
+                pInputElementDescs: elements.as_ptr(),
+                NumElements: elements.len() as _,
+            },
+        )
+    }
+    pub fn index_buffer_strip_cut_value(
+        &mut self,
+        value: D3D12_INDEX_BUFFER_STRIP_CUT_VALUE,
+    ) -> &mut Self {
+        self.push(
+            D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_IB_STRIP_CUT_VALUE,
+            value,
+        )
+    }
+    pub fn primitive_topology_type(&mut self, ty: D3D12_PRIMITIVE_TOPOLOGY_TYPE) -> &mut Self {
+        self.push(D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_PRIMITIVE_TOPOLOGY, ty)
+    }
+    /// レンダーターゲットのフォーマット(8つまで)
+    pub fn render_target_formats(&mut self, formats: &[dxgi::Format]) -> &mut Self {
+        let mut a = D3D12_RT_FORMAT_ARRAY {
+            RTFormats: [DXGI_FORMAT_UNKNOWN; 8],
+            NumRenderTargets: formats.len().min(8) as _,
+        };
+        a.RTFormats[..a.NumRenderTargets as usize]
+            .copy_from_slice(&formats[..a.NumRenderTargets as usize]);
+        self.push(D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_RENDER_TARGET_FORMATS, a)
+    }
+    pub fn depth_stencil_format(&mut self, format: dxgi::Format) -> &mut Self {
+        self.push(
+            D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_DEPTH_STENCIL_FORMAT,
+            format,
+        )
+    }
+    pub fn sample_desc(&mut self, count: u32, quality: u32) -> &mut Self {
+        self.push(
+            D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_SAMPLE_DESC,
+            DXGI_SAMPLE_DESC {
+                Count: count,
+                Quality: quality,
+            },
+        )
+    }
+    pub fn node_mask(&mut self, mask: u32) -> &mut Self {
+        self.push(D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_NODE_MASK, mask)
+    }
+    /// 以前に取り出したパイプラインのキャッシュ
+    pub fn cached_pso(&mut self, blob: &'a [u8]) -> &mut Self {
+        self.push(
+            D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_CACHED_PSO,
+            D3D12_CACHED_PIPELINE_STATE {
+                pCachedBlob: blob.as_ptr() as _,
+                CachedBlobSizeInBytes: blob.len() as _,
+            },
+        )
+    }
+    pub fn flags(&mut self, flags: D3D12_PIPELINE_STATE_FLAGS) -> &mut Self {
+        self.push(D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_FLAGS, flags)
+    }
+    /// ビューインスタンシング(各ビューの出力先、4つまで)
+    pub fn view_instancing(
+        &mut self,
+        locations: &'a [D3D12_VIEW_INSTANCE_LOCATION],
+        flags: u32,
+    ) -> &mut Self {
+        self.push(
+            D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_VIEW_INSTANCING,
+            D3D12_VIEW_INSTANCING_DESC {
+                ViewInstanceCount: locations.len() as _,
+                pViewInstanceLocations: locations.as_ptr(),
+                Flags: flags,
+            },
+        )
+    }
+
+    /// 並べたサブオブジェクト
+    pub fn subobjects(&self) -> &[StreamSubobject] {
+        &self.subobjects
+    }
+    /// ストリームのバイト列
+    pub fn as_bytes(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.buffer.as_ptr() as *const u8, self.len) }
+    }
+    fn has(&self, ty: D3D12_PIPELINE_STATE_SUBOBJECT_TYPE) -> bool {
+        self.subobjects.iter().any(|s| s.ty == ty)
+    }
+
+    /// 作成前に分かる誤りを調べる(重複したサブオブジェクト、メッシュシェーダと頂点処理の混在など)
+    pub fn validate(&self) -> IOResult<()> {
+        for (n, s) in self.subobjects.iter().enumerate() {
+            if self.subobjects[..n].iter().any(|p| p.ty == s.ty) {
+                return invalid(format!("pipeline subobject type {} appears twice", s.ty));
+            }
+        }
+        let mesh = self.has(D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_MS);
+        if mesh {
+            for (ty, name) in [
+                (D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_VS, "vertex shader"),
+                (D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_HS, "hull shader"),
+                (D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_DS, "domain shader"),
+                (D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_GS, "geometry shader"),
+                (
+                    D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_INPUT_LAYOUT,
+                    "input layout",
+                ),
+                (
+                    D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_STREAM_OUTPUT,
+                    "stream output",
+                ),
+            ] {
+                if self.has(ty) {
+                    return invalid(format!("a mesh shader pipeline cannot have a {}", name));
+                }
+            }
+        } else if self.has(D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_AS) {
+            return invalid("an amplification shader requires a mesh shader".to_owned());
+        }
+        if self.has(D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_CS)
+            && self.subobjects.iter().any(|s| {
+                matches!(
+                    s.ty,
+                    D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_VS
+                        | D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_PS
+                        | D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_MS
+                        | D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_AS
+                )
+            })
+        {
+            return invalid("a compute shader cannot be combined with graphics shaders".to_owned());
+        }
+        for (a, b) in [
+            (
+                D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_RASTERIZER,
+                D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_RASTERIZER1,
+            ),
+            (
+                D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_DEPTH_STENCIL,
+                D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_DEPTH_STENCIL1,
+            ),
+        ] {
+            if self.has(a) && self.has(b) {
+                return invalid(format!(
+                    "pipeline subobject types {} and {} are mutually exclusive",
+                    a, b
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Device {
+    /// サブオブジェクトのストリームからパイプラインステートを作る
+    pub fn new_pipeline_state(&self, stream: &PipelineStateStream) -> IOResult<PipelineState> {
+        stream.validate()?;
+        let device2 = query_ext::<ID3D12Device2>(self.0 as _).ok_or_else(|| {
+            IOError::other("pipeline state streams are not supported on this device")
+        })?;
+        let desc = D3D12_PIPELINE_STATE_STREAM_DESC {
+            SizeInBytes: stream.len as _,
+            pPipelineStateSubobjectStream: stream.buffer.as_ptr() as _,
+        };
+        let mut handle = std::ptr::null_mut();
+
+        unsafe {
+            (*device2.0)
+                .CreatePipelineState(&desc, &ID3D12PipelineState::uuidof(), &mut handle)
+                .to_result_with(|| PipelineState(handle as _))
+        }
+    }
+}
+
+impl GraphicsCommandList {
+    /// メッシュシェーダのスレッドグループを起動する(ID3D12GraphicsCommandList6)
+    pub fn dispatch_mesh(&mut self, x: u32, y: u32, z: u32) -> IOResult<&mut Self> {
+        let list6 = query_ext::<ID3D12GraphicsCommandList6>(self.0 as _)
+            .ok_or_else(|| IOError::other("mesh shaders are not supported on this command list"))?;
+        unsafe { (*list6.0).DispatchMesh(x, y, z) };
+        Ok(self)
+    }
+    /// 深度境界テストの範囲
+    pub fn set_depth_bounds(&mut self, min: f32, max: f32) -> IOResult<&mut Self> {
+        let list1 = query_ext::<ID3D12GraphicsCommandList1>(self.0 as _).ok_or_else(|| {
+            IOError::other("depth bounds test is not supported on this command list")
+        })?;
+        unsafe { (*list1.0).OMSetDepthBounds(min, max) };
+        Ok(self)
+    }
+    /// 有効にするビューインスタンスのマスク
+    pub fn set_view_instance_mask(&mut self, mask: u32) -> IOResult<&mut Self> {
+        let list2 = query_ext::<ID3D12GraphicsCommandList2>(self.0 as _).ok_or_else(|| {
+            IOError::other("view instancing is not supported on this command list")
+        })?;
+        unsafe { (*list2.0).SetViewInstanceMask(mask) };
+        Ok(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::ErrorKind;
+
+    struct Bytecode(D3D12_SHADER_BYTECODE);
+    impl AsRef<D3D12_SHADER_BYTECODE> for Bytecode {
+        fn as_ref(&self) -> &D3D12_SHADER_BYTECODE {
+            &self.0
+        }
+    }
+    static DXBC: [u8; 12] = *b"DXBC\0\0\0\0\0\0\0\0";
+
+    fn read<T: Copy>(bytes: &[u8], offset: usize) -> T {
+        assert!(offset + size_of::<T>() <= bytes.len());
+        unsafe { std::ptr::read_unaligned(bytes[offset..].as_ptr() as *const T) }
+    }
+
+    #[test]
+    fn serializes_subobjects_at_pointer_alignment() {
+        let rs = RootSignature(std::ptr::null_mut());
+        let vs = Bytecode(D3D12_SHADER_BYTECODE {
+            pShaderBytecode: DXBC.as_ptr() as _,
+            BytecodeLength: DXBC.len() as _,
+        });
+        let mut stream = PipelineStateStream::new();
+        stream
+            .root_signature(&rs)
+            .vertex_shader(&vs)
+            .sample_mask(0xffff_ffff)
+            .primitive_topology_type(D3D12_PRIMITIVE_TOPOLOGY_TYPE_TRIANGLE);
+
+        let p = STREAM_ALIGNMENT;
+        let up = |x: usize| align_up(x as u64, p as u64) as usize;
+        let sub = |ty, offset, data_offset, end| StreamSubobject {
+            ty,
+            offset,
+            data_offset,
+            end,
+        };
+        // 型(u32)の後ろはポインタ境界まで詰め物が入る
+        let vs_end = up(3 * p + size_of::<D3D12_SHADER_BYTECODE>());
+        assert_eq!(
+            stream.subobjects(),
+            &[
+                sub(
+                    D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_ROOT_SIGNATURE,
+                    0,
+                    p,
+                    2 * p
+                ),
+                sub(D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_VS, 2 * p, 3 * p, vs_end),
+                sub(
+                    D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_SAMPLE_MASK,
+                    vs_end,
+                    vs_end + 4,
+                    up(vs_end + 8)
+                ),
+                sub(
+                    D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_PRIMITIVE_TOPOLOGY,
+                    up(vs_end + 8),
+                    up(vs_end + 8) + 4,
+                    up(vs_end + 16)
+                ),
+            ]
+        );
+
+        let bytes = stream.as_bytes();
+        assert_eq!(bytes.len(), up(vs_end + 16));
+        for s in stream.subobjects() {
+            assert_eq!(read::<u32>(bytes, s.offset), s.ty);
+            assert_eq!(s.offset % p, 0);
+        }
+        assert!(read::<*mut ID3D12RootSignature>(bytes, p).is_null());
+        let vs = read::<D3D12_SHADER_BYTECODE>(bytes, 3 * p);
+        assert_eq!(vs.pShaderBytecode, DXBC.as_ptr() as _);
+        assert_eq!(vs.BytecodeLength, 12);
+        assert_eq!(read::<u32>(bytes, vs_end + 4), 0xffff_ffff);
+        assert_eq!(
+            read::<u32>(bytes, up(vs_end + 8) + 4),
+            D3D12_PRIMITIVE_TOPOLOGY_TYPE_TRIANGLE
+        );
+    }
+
+    #[test]
+    fn buffer_covers_unaligned_tails() {
+        let mut stream = PipelineStateStream::new();
+        for n in 0..5 {
+            unsafe {
+                stream.push_raw(D3D12_PIPELINE_STATE_SUBOBJECT_TYPE_NODE_MASK, &[n as u8; 3])
+            };
+            assert!(stream.buffer.len() * size_of::<u64>() >= stream.len);
+            assert_eq!(
+                stream.as_bytes()[stream.subobjects()[n].data_offset + 2],
+                n as u8
+            );
+        }
+    }
+
+    #[test]
+    fn validation() {
+        let vs = Bytecode(D3D12_SHADER_BYTECODE {
+            pShaderBytecode: DXBC.as_ptr() as _,
+            BytecodeLength: DXBC.len() as _,
+        });
+        let rejected = |stream: &PipelineStateStream| {
+            let e = stream.validate().expect_err("should be rejected");
+            assert_eq!(e.kind(), ErrorKind::InvalidInput);
+            e.to_string()
+        };
+
+        let mut ok = PipelineStateStream::new();
+        ok.vertex_shader(&vs).pixel_shader(&vs).sample_mask(!0);
+        assert!(ok.validate().is_ok());
+
+        let mut dup = PipelineStateStream::new();
+        dup.sample_mask(!0).sample_mask(1);
+        assert!(rejected(&dup).contains("appears twice"));
+
+        let mut mesh = PipelineStateStream::new();
+        mesh.mesh_shader(&vs).vertex_shader(&vs);
+        assert!(rejected(&mesh).contains("vertex shader"));
+
+        let mut amp = PipelineStateStream::new();
+        amp.amplification_shader(&vs).pixel_shader(&vs);
+        assert!(rejected(&amp).contains("requires a mesh shader"));
+
+        let mut compute = PipelineStateStream::new();
+        compute.compute_shader(&vs).pixel_shader(&vs);
+        assert!(rejected(&compute).contains("compute shader"));
+
+        let mut raster = PipelineStateStream::new();
+        raster
+            .rasterizer_state(&unsafe { std::mem::zeroed() })
+            .rasterizer_state1(&unsafe { std::mem::zeroed() });
+        assert!(rejected(&raster).contains("mutually exclusive"));
+    }
+}
@@ -0,0 +1,594 @@
+//! Queries and GPU Profiler
+
+use super::*;
+use std::collections::HashMap;
+
+pub use winapi::um::d3d12::D3D12_QUERY_DATA_PIPELINE_STATISTICS as PipelineStatistics;
+
+/// クエリヒープの種類
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueryHeapType {
+    Occlusion = D3D12_QUERY_HEAP_TYPE_OCCLUSION,
+    Timestamp = D3D12_QUERY_HEAP_TYPE_TIMESTAMP,
+    PipelineStatistics = D3D12_QUERY_HEAP_TYPE_PIPELINE_STATISTICS,
+}
+/// クエリの種類
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueryType {
+    Occlusion = D3D12_QUERY_TYPE_OCCLUSION,
+    BinaryOcclusion = D3D12_QUERY_TYPE_BINARY_OCCLUSION,
+    Timestamp = D3D12_QUERY_TYPE_TIMESTAMP,
+    PipelineStatistics = D3D12_QUERY_TYPE_PIPELINE_STATISTICS,
+}
+impl QueryType {
+    /// 解決後の1クエリぶんのバイト数
+    pub fn result_size(self) -> usize {
+        match self {
+            QueryType::PipelineStatistics => size_of::<PipelineStatistics>(),
+            _ => size_of::<u64>(),
+        }
+    }
+}
+
+/// クエリヒープ
+pub struct QueryHeap(*mut ID3D12QueryHeap, QueryHeapType, u32);
+HandleWrapper!(for QueryHeap[ID3D12QueryHeap]);
+DebugNameBySetName!(for QueryHeap);
+impl Device {
+    /// クエリヒープの作成
+    pub fn new_query_heap(&self, heap_type: QueryHeapType, count: u32) -> IOResult<QueryHeap> {
+        let desc = D3D12_QUERY_HEAP_DESC {
+            Type: heap_type as _,
+            Count: count,
+            NodeMask: 0,
+        };
+        let mut handle = std::ptr::null_mut();
+        unsafe { (*self.0).CreateQueryHeap(&desc, &ID3D12QueryHeap::uuidof(), &mut handle) }
+            .to_result_with(|| QueryHeap(handle as _, heap_type, count))
+    }
+}
+impl QueryHeap {
+    /// ヒープの種類
+    pub fn heap_type(&self) -> QueryHeapType {
+        self.1
+    }
+    /// 格納できるクエリの数
+    pub fn count(&self) -> u32 {
+        self.2
+    }
+}
+unsafe impl Sync for QueryHeap {}
+unsafe impl Send for QueryHeap {}
+
+impl GraphicsCommandList {
+    /// クエリを開始(タイムスタンプには使えない)
+    pub fn begin_query(
+        &mut self,
+        heap: &QueryHeap,
+        query_type: QueryType,
+        index: u32,
+    ) -> &mut Self {
+        unsafe { (*self.0).BeginQuery(heap.0, query_type as _, index) };
+        self
+    }
+    /// クエリを終了(タイムスタンプはこれだけで書き込まれる)
+    pub fn end_query(&mut self, heap: &QueryHeap, query_type: QueryType, index: u32) -> &mut Self {
+        unsafe { (*self.0).EndQuery(heap.0, query_type as _, index) };
+        self
+    }
+    /// クエリの結果をバッファに書き出す(dest_offsetは8バイトアラインメント)
+    pub fn resolve_query_data(
+        &mut self,
+        heap: &QueryHeap,
+        query_type: QueryType,
+        range: std::ops::Range<u32>,
+        dest: &Resource,
+        dest_offset: u64,
+    ) -> &mut Self {
+        unsafe {
+            (*self.0).ResolveQueryData(
+                heap.0,
+                query_type as _,
+                range.start,
+                range.end - range.start,
+                dest.0,
+                dest_offset,
+            )
+        };
+        self
+    }
+}
+
+impl CommandQueue {
+    /// タイムスタンプの周波数(ticks/秒)を取得
+    pub fn timestamp_frequency(&self) -> IOResult<u64> {
+        let mut f = 0;
+        unsafe { (*self.0).GetTimestampFrequency(&mut f) }.to_result_with(|| f)
+    }
+    /// GPUとCPUのタイムスタンプを同時に取得
+    pub fn clock_calibration(&self) -> IOResult<(u64, u64)> {
+        let (mut gpu, mut cpu) = (0, 0);
+        unsafe { (*self.0).GetClockCalibration(&mut gpu, &mut cpu) }.to_result_with(|| (gpu, cpu))
+    }
+}
+
+/// タイムスタンプの差をミリ秒に変換
+pub fn ticks_to_milliseconds(ticks: u64, frequency: u64) -> f64 {
+    ticks as f64 * 1000.0 / frequency as f64
+}
+
+/// 計測された区間
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProfiledRange {
+    pub name: String,
+    /// 親の区間名を"/"でつないだもの
+    pub path: String,
+    pub depth: u32,
+    /// 同じフレーム内での親区間のインデックス
+    pub parent: Option<usize>,
+    /// フレーム内最初のタイムスタンプからの開始時刻
+    pub begin_ms: f64,
+    pub duration_ms: f64,
+}
+
+struct RecordedScope {
+    name: String,
+    path: String,
+    depth: u32,
+    parent: Option<usize>,
+}
+/// 1フレームぶんの計測区間の入れ子を記録し、タイムスタンプクエリのインデックスを割り当てる
+/// (区間nの開始はbase + 2n、終了はbase + 2n + 1)
+pub struct ScopeRecorder {
+    base: u32,
+    capacity: u32,
+    scopes: Vec<RecordedScope>,
+    stack: Vec<Option<usize>>,
+}
+impl ScopeRecorder {
+    /// baseから始まるcapacity個の区間を記録する
+    pub fn new(base: u32, capacity: u32) -> Self {
+        ScopeRecorder {
+            base,
+            capacity,
+            scopes: Vec::with_capacity(capacity as _),
+            stack: Vec::new(),
+        }
+    }
+    /// 記録内容を破棄
+    pub fn reset(&mut self) {
+        self.scopes.clear();
+        self.stack.clear();
+    }
+
+    /// 区間の開始。書き込むべきクエリのインデックスを返す(容量を超えていたらNone)
+    pub fn begin(&mut self, name: &str) -> Option<u32> {
+        if self.scopes.len() as u32 >= self.capacity {
+            self.stack.push(None);
+            return None;
+        }
+        let parent = self.stack.iter().rev().find_map(|&s| s);
+        let path = match parent {
+            Some(p) => format!("{}/{}", self.scopes[p].path, name),
+            None => name.to_owned(),
+        };
+        let index = self.scopes.len();
+        self.scopes.push(RecordedScope {
+            name: name.to_owned(),
+            path,
+            depth: self.stack.len() as _,
+            parent,
+        });
+        self.stack.push(Some(index));
+        Some(self.base + index as u32 * 2)
+    }
+    /// 区間の終了。書き込むべきクエリのインデックスを返す(開始時に容量を超えていたらNone)
+    pub fn end(&mut self) -> IOResult<Option<u32>> {
+        match self.stack.pop() {
+            Some(s) => Ok(s.map(|index| self.base + index as u32 * 2 + 1)),
+            None => Err(IOError::other("ending a profile scope that has not begun")),
+        }
+    }
+
+    /// 閉じていない区間があるか
+    pub fn is_open(&self) -> bool {
+        !self.stack.is_empty()
+    }
+    /// 使用したクエリの範囲
+    pub fn query_range(&self) -> std::ops::Range<u32> {
+        self.base..self.base + self.scopes.len() as u32 * 2
+    }
+
+    /// 解決済みのタイムスタンプ(query_rangeの順に並んだもの)から区間を計算する
+    pub fn resolve(&self, timestamps: &[u64], frequency: u64) -> IOResult<Vec<ProfiledRange>> {
+        if timestamps.len() < self.scopes.len() * 2 {
+            return invalid(format!(
+                "{} timestamps are not enough for {} profile scopes",
+                timestamps.len(),
+                self.scopes.len()
+            ));
+        }
+        let origin = timestamps
+            .iter()
+            .step_by(2)
+            .take(self.scopes.len())
+            .min()
+            .cloned()
+            .unwrap_or(0);
+
+        Ok(self
+            .scopes
+            .iter()
+            .enumerate()
+            .map(|(n, s)| {
+                let (b, e) = (timestamps[n * 2], timestamps[n * 2 + 1]);
+                ProfiledRange {
+                    name: s.name.clone(),
+                    path: s.path.clone(),
+                    depth: s.depth,
+                    parent: s.parent,
+                    begin_ms: ticks_to_milliseconds(b.saturating_sub(origin), frequency),
+                    duration_ms: ticks_to_milliseconds(e.saturating_sub(b), frequency),
+                }
+            })
+            .collect())
+    }
+}
+
+/// 区間ごとの統計
+#[derive(Debug, Clone, PartialEq)]
+pub struct RangeStats {
+    /// 計測されたフレーム数
+    pub frames: u64,
+    pub total_ms: f64,
+    pub min_ms: f64,
+    pub max_ms: f64,
+    pub last_ms: f64,
+}
+impl RangeStats {
+    pub fn average_ms(&self) -> f64 {
+        if self.frames == 0 {
+            0.0
+        } else {
+            self.total_ms / self.frames as f64
+        }
+    }
+}
+/// フレームをまたいだ区間統計の集計(同じパスの区間はフレーム内で合算される)
+#[derive(Debug, Clone, Default)]
+pub struct ProfileAggregator {
+    frames: u64,
+    stats: HashMap<String, RangeStats>,
+}
+impl ProfileAggregator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// 1フレームぶんの計測結果を追加
+    pub fn add_frame(&mut self, ranges: &[ProfiledRange]) {
+        self.frames += 1;
+        let mut frame_totals: Vec<(&str, f64)> = Vec::new();
+        for r in ranges {
+            match frame_totals.iter_mut().find(|(p, _)| *p == r.path) {
+                Some((_, t)) => *t += r.duration_ms,
+                None => frame_totals.push((&r.path, r.duration_ms)),
+            }
+        }
+        for (path, ms) in frame_totals {
+            let s = self
+                .stats
+                .entry(path.to_owned())
+                .or_insert_with(|| RangeStats {
+                    frames: 0,
+                    total_ms: 0.0,
+                    min_ms: f64::INFINITY,
+                    max_ms: 0.0,
+                    last_ms: 0.0,
+                });
+            s.frames += 1;
+            s.total_ms += ms;
+            s.min_ms = s.min_ms.min(ms);
+            s.max_ms = s.max_ms.max(ms);
+            s.last_ms = ms;
+        }
+    }
+    /// 集計したフレーム数
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+    /// パスを指定して統計を取得
+    pub fn stats(&self, path: &str) -> Option<&RangeStats> {
+        self.stats.get(path)
+    }
+    /// パス順に並べた統計
+    pub fn sorted_stats(&self) -> Vec<(&str, &RangeStats)> {
+        let mut v = self
+            .stats
+            .iter()
+            .map(|(k, v)| (k.as_str(), v))
+            .collect::<Vec<_>>();
+        v.sort_by(|a, b| a.0.cmp(b.0));
+        v
+    }
+    /// 集計をリセット
+    pub fn reset(&mut self) {
+        self.frames = 0;
+        self.stats.clear();
+    }
+}
+
+/// タイムスタンプクエリによるGPU区間計測
+/// フレームごと(frames_in_flight個のスロット)にクエリとリードバック領域を持つ
+pub struct GpuProfiler {
+    heap: QueryHeap,
+    readback: Resource,
+    frames: Vec<ScopeRecorder>,
+    current: usize,
+    frequency: u64,
+    aggregator: ProfileAggregator,
+}
+impl GpuProfiler {
+    /// queueはタイムスタンプ周波数の取得に使う(計測対象のコマンドリストを実行するキュー)
+    pub fn new(
+        device: &Device,
+        queue: &CommandQueue,
+        frames_in_flight: usize,
+        max_scopes_per_frame: u32,
+    ) -> IOResult<Self> {
+        let queries_per_frame = max_scopes_per_frame * 2;
+        let heap = device.new_query_heap(
+            QueryHeapType::Timestamp,
+            queries_per_frame * frames_in_flight as u32,
+        )?;
+        let readback = device.new_resource_committed(
+            &HeapProperty::readback(),
+            &ResourceDesc::buffer(heap.count() as usize * size_of::<u64>()),
+            ResourceState::CopyDest,
+            None,
+        )?;
+        let frames = (0..frames_in_flight as u32)
+            .map(|n| ScopeRecorder::new(n * queries_per_frame, max_scopes_per_frame))
+            .collect();
+
+        Ok(GpuProfiler {
+            heap,
+            readback,
+            frames,
+            current: 0,
+            frequency: queue.timestamp_frequency()?,
+            aggregator: ProfileAggregator::new(),
+        })
+    }
+
+    /// フレームの記録開始(前回このスロットで記録した内容は破棄される)
+    pub fn begin_frame(&mut self, frame_index: usize) {
+        self.current = frame_index % self.frames.len();
+        self.frames[self.current].reset();
+    }
+    /// 区間の開始
+    pub fn begin_scope(&mut self, cmd: &mut GraphicsCommandList, name: &str) {
+        if let Some(q) = self.frames[self.current].begin(name) {
+            cmd.end_query(&self.heap, QueryType::Timestamp, q);
+        }
+    }
+    /// 区間の終了
+    pub fn end_scope(&mut self, cmd: &mut GraphicsCommandList) -> IOResult<()> {
+        if let Some(q) = self.frames[self.current].end()? {
+            cmd.end_query(&self.heap, QueryType::Timestamp, q);
+        }
+        Ok(())
+    }
+    /// 区間を開始し、スコープを抜けると終了するガードを返す
+    pub fn scope<'p>(
+        &'p mut self,
+        cmd: &'p mut GraphicsCommandList,
+        name: &str,
+    ) -> ProfileScope<'p> {
+        self.begin_scope(cmd, name);
+        ProfileScope {
+            profiler: self,
+            cmd,
+        }
+    }
+    /// フレームの記録終了(クエリ結果をリードバックバッファへ書き出すコマンドを積む)
+    pub fn end_frame(&mut self, cmd: &mut GraphicsCommandList) -> IOResult<()> {
+        let frame = &self.frames[self.current];
+        if frame.is_open() {
+            return Err(IOError::other("unclosed profile scope at the end of frame"));
+        }
+        let range = frame.query_range();
+        if range.start != range.end {
+            cmd.resolve_query_data(
+                &self.heap,
+                QueryType::Timestamp,
+                range.clone(),
+                &self.readback,
+                (range.start as usize * size_of::<u64>()) as _,
+            );
+        }
+        Ok(())
+    }
+    /// フレームの計測結果を読み出す(該当フレームのコマンド完了をフェンスで待ってから呼ぶこと)
+    pub fn collect(&mut self, frame_index: usize) -> IOResult<Vec<ProfiledRange>> {
+        let frame = &self.frames[frame_index % self.frames.len()];
+        let range = frame.query_range();
+        let bytes = range.start as usize * size_of::<u64>()..range.end as usize * size_of::<u64>();
+        if bytes.start == bytes.end {
+            self.aggregator.add_frame(&[]);
+            return Ok(Vec::new());
+        }
+        let ptr = self.readback.map(bytes.clone())?;
+        let timestamps = unsafe {
+            std::slice::from_raw_parts(
+                (ptr as *const u8).add(bytes.start) as *const u64,
+                (range.end - range.start) as _,
+            )
+        };
+        let ranges = frame.resolve(timestamps, self.frequency);
+        self.readback.unmap(..0);
+        let ranges = ranges?;
+        self.aggregator.add_frame(&ranges);
+        Ok(ranges)
+    }
+
+    /// タイムスタンプの周波数
+    pub fn frequency(&self) -> u64 {
+        self.frequency
+    }
+    /// フレームをまたいだ集計
+    pub fn aggregator(&self) -> &ProfileAggregator {
+        &self.aggregator
+    }
+    /// フレームをまたいだ集計(リセット用)
+    pub fn aggregator_mut(&mut self) -> &mut ProfileAggregator {
+        &mut self.aggregator
+    }
+}
+
+/// 計測区間のガード(コマンドリストとして使える)
+pub struct ProfileScope<'p> {
+    profiler: &'p mut GpuProfiler,
+    cmd: &'p mut GraphicsCommandList,
+}
+impl<'p> ProfileScope<'p> {
+    /// 入れ子の区間を開始
+    pub fn scope<'s>(&'s mut self, name: &str) -> ProfileScope<'s> {
+        self.profiler.begin_scope(self.cmd, name);
+        ProfileScope {
+            profiler: &mut *self.profiler,
+            cmd: &mut *self.cmd,
+        }
+    }
+}
+impl<'p> Deref for ProfileScope<'p> {
+    type Target = GraphicsCommandList;
+    fn deref(&self) -> &GraphicsCommandList {
+        self.cmd
+    }
+}
+impl<'p> std::ops::DerefMut for ProfileScope<'p> {
+    fn deref_mut(&mut self) -> &mut GraphicsCommandList {
+        self.cmd
+    }
+}
+impl<'p> Drop for ProfileScope<'p> {
+    fn drop(&mut self) {
+        // ガードは必ず開始した区間を閉じるので失敗しない
+        let _ = self.profiler.end_scope(self.cmd);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(path: &str, duration_ms: f64) -> ProfiledRange {
+        ProfiledRange {
+            name: path.rsplit('/').next().unwrap().to_owned(),
+            path: path.to_owned(),
+            depth: path.matches('/').count() as _,
+            parent: None,
+            begin_ms: 0.0,
+            duration_ms,
+        }
+    }
+
+    #[test]
+    fn nested_scopes_get_query_pairs() {
+        let mut r = ScopeRecorder::new(8, 4);
+        assert_eq!(r.begin("Frame"), Some(8));
+        assert_eq!(r.begin("Shadow"), Some(10));
+        assert_eq!(r.end().unwrap(), Some(11));
+        assert_eq!(r.begin("Lighting"), Some(12));
+        assert!(r.is_open());
+        assert_eq!(r.end().unwrap(), Some(13));
+        assert_eq!(r.end().unwrap(), Some(9));
+        assert!(!r.is_open());
+        assert_eq!(r.query_range(), 8..14);
+
+        // 1tick = 1ms
+        let ranges = r.resolve(&[100, 160, 110, 130, 140, 150], 1000).unwrap();
+        assert_eq!(
+            ranges
+                .iter()
+                .map(|r| (
+                    r.path.as_str(),
+                    r.depth,
+                    r.parent,
+                    r.begin_ms,
+                    r.duration_ms
+                ))
+                .collect::<Vec<_>>(),
+            vec![
+                ("Frame", 0, None, 0.0, 60.0),
+                ("Frame/Shadow", 1, Some(0), 10.0, 20.0),
+                ("Frame/Lighting", 1, Some(0), 40.0, 10.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn scopes_over_capacity_are_dropped() {
+        let mut r = ScopeRecorder::new(0, 1);
+        assert_eq!(r.begin("A"), Some(0));
+        assert_eq!(r.begin("B"), None);
+        assert_eq!(r.begin("C"), None);
+        assert_eq!(r.end().unwrap(), None);
+        assert_eq!(r.end().unwrap(), None);
+        assert_eq!(r.end().unwrap(), Some(1));
+        assert_eq!(r.query_range(), 0..2);
+        assert_eq!(r.resolve(&[5, 7], 1000).unwrap().len(), 1);
+
+        r.reset();
+        assert_eq!(r.query_range(), 0..0);
+        assert_eq!(r.begin("B"), Some(0));
+    }
+
+    #[test]
+    fn unbalanced_or_short_input_is_an_error() {
+        let mut r = ScopeRecorder::new(0, 4);
+        assert!(r.end().is_err());
+        r.begin("A");
+        r.end().unwrap();
+        r.begin("B");
+        r.end().unwrap();
+        assert!(r.resolve(&[0, 1, 2], 1000).is_err());
+        assert!(r.resolve(&[], 1000).is_err());
+    }
+
+    #[test]
+    fn aggregates_per_frame_totals() {
+        let mut a = ProfileAggregator::new();
+        // 同じパスの区間はフレーム内で合算する
+        a.add_frame(&[
+            range("Frame", 10.0),
+            range("Frame/Draw", 2.0),
+            range("Frame/Draw", 3.0),
+        ]);
+        a.add_frame(&[range("Frame", 14.0), range("Frame/Draw", 1.0)]);
+        a.add_frame(&[range("Frame", 12.0)]);
+        assert_eq!(a.frames(), 3);
+
+        let f = a.stats("Frame").unwrap();
+        assert_eq!(
+            (f.frames, f.min_ms, f.max_ms, f.last_ms),
+            (3, 10.0, 14.0, 12.0)
+        );
+        assert_eq!(f.average_ms(), 12.0);
+        let d = a.stats("Frame/Draw").unwrap();
+        assert_eq!(
+            (d.frames, d.total_ms, d.min_ms, d.max_ms),
+            (2, 6.0, 1.0, 5.0)
+        );
+        assert_eq!(
+            a.sorted_stats().iter().map(|(p, _)| *p).collect::<Vec<_>>(),
+            vec!["Frame", "Frame/Draw"]
+        );
+
+        a.reset();
+        assert_eq!(a.frames(), 0);
+        assert!(a.stats("Frame").is_none());
+    }
+}
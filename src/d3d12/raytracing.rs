@@ -0,0 +1,807 @@
+//! DirectX Raytracing
+
+use super::*;
+use std::any::Any;
+use std::io::ErrorKind;
+use std::marker::PhantomData;
+
+fn unsupported() -> IOError {
+    IOError::other("DirectX Raytracing is not supported on this device")
+}
+
+/// ボトムレベル高速化構造のジオメトリ1つぶん
+#[repr(transparent)]
+#[derive(Clone, Copy)]
+pub struct RaytracingGeometry(D3D12_RAYTRACING_GEOMETRY_DESC);
+unsafe impl MarkForSameBits<D3D12_RAYTRACING_GEOMETRY_DESC> for RaytracingGeometry {}
+impl RaytracingGeometry {
+    /// 三角形(インデックスなし)
+    pub fn triangles(
+        vertices: GraphicsVirtualPtr,
+        stride: u64,
+        vertex_count: u32,
+        vertex_format: dxgi::Format,
+    ) -> Self {
+        RaytracingGeometry(D3D12_RAYTRACING_GEOMETRY_DESC {
+            Type: D3D12_RAYTRACING_GEOMETRY_TYPE_TRIANGLES,
+            Flags: D3D12_RAYTRACING_GEOMETRY_FLAG_NONE,
+            u: D3D12_RAYTRACING_GEOMETRY_DESC_u {
+                Triangles: D3D12_RAYTRACING_GEOMETRY_TRIANGLES_DESC {
+                    Transform3x4: 0,
+                    IndexFormat: DXGI_FORMAT_UNKNOWN,
+                    VertexFormat: vertex_format,
+                    IndexCount: 0,
+                    VertexCount: vertex_count,
+                    IndexBuffer: 0,
+                    VertexBuffer: D3D12_GPU_VIRTUAL_ADDRESS_AND_STRIDE {
+                        StartAddress: vertices.0,
+                        StrideInBytes: stride,
+                    },
+                },
+            },
+        })
+    }
+    /// プロシージャルプリミティブのAABB(x,y,zの最小/最大のf32 6つ)の配列
+    pub fn aabbs(aabbs: GraphicsVirtualPtr, stride: u64, count: u64) -> Self {
+        RaytracingGeometry(D3D12_RAYTRACING_GEOMETRY_DESC {
+            Type: D3D12_RAYTRACING_GEOMETRY_TYPE_PROCEDURAL_PRIMITIVE_AABBS,
+            Flags: D3D12_RAYTRACING_GEOMETRY_FLAG_NONE,
+            u: D3D12_RAYTRACING_GEOMETRY_DESC_u {
+                AABBs: D3D12_RAYTRACING_GEOMETRY_AABBS_DESC {
+                    AABBCount: count,
+                    AABBs: D3D12_GPU_VIRTUAL_ADDRESS_AND_STRIDE {
+                        StartAddress: aabbs.0,
+                        StrideInBytes: stride,
+                    },
+                },
+            },
+        })
+    }
+    /// インデックスバッファを使う(三角形のみ、formatはR16_UINTかR32_UINT)
+    pub fn indices(
+        mut self,
+        indices: GraphicsVirtualPtr,
+        count: u32,
+        format: dxgi::Format,
+    ) -> Self {
+        debug_assert_eq!(self.0.Type, D3D12_RAYTRACING_GEOMETRY_TYPE_TRIANGLES);
+        let t = unsafe { &mut self.0.u.Triangles };
+        t.IndexBuffer = indices.0;
+        t.IndexCount = count;
+        t.IndexFormat = format;
+        self
+    }
+    /// 3x4行列(f32 12個)で頂点を変換してから構築する(三角形のみ、16バイト境界)
+    pub fn transform(mut self, transform3x4: GraphicsVirtualPtr) -> Self {
+        debug_assert_eq!(self.0.Type, D3D12_RAYTRACING_GEOMETRY_TYPE_TRIANGLES);
+        self.0.u.Triangles.Transform3x4 = transform3x4.0;
+        self
+    }
+    /// AnyHitシェーダを呼ばない
+    pub fn opaque(mut self) -> Self {
+        self.0.Flags |= D3D12_RAYTRACING_GEOMETRY_FLAG_OPAQUE;
+        self
+    }
+    pub fn flags(mut self, flags: D3D12_RAYTRACING_GEOMETRY_FLAGS) -> Self {
+        self.0.Flags = flags;
+        self
+    }
+}
+
+/// トップレベル高速化構造に置くインスタンス(GPUから見えるバッファに並べて使う)
+#[repr(transparent)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RaytracingInstance(D3D12_RAYTRACING_INSTANCE_DESC);
+unsafe impl MarkForSameBits<D3D12_RAYTRACING_INSTANCE_DESC> for RaytracingInstance {}
+impl RaytracingInstance {
+    /// 単位行列、マスク0xffでボトムレベル高速化構造を置く
+    pub fn new(blas: GraphicsVirtualPtr) -> Self {
+        RaytracingInstance(D3D12_RAYTRACING_INSTANCE_DESC {
+            Transform: [1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0],
+            InstanceID_InstanceMask: 0xff << 24,
+            InstanceContributionToHitGroupIndex_Flags: 0,
+            AccelerationStructure: blas.0,
+        })
+    }
+    /// 行優先の3x4行列
+    pub fn transform(mut self, transform: [f32; 12]) -> Self {
+        self.0.Transform = transform;
+        self
+    }
+    /// シェーダからInstanceID()で見える値(下位24bit)
+    pub fn instance_id(mut self, id: u32) -> Self {
+        let v = &mut self.0.InstanceID_InstanceMask;
+        *v = (*v & 0xff00_0000) | (id & 0x00ff_ffff);
+        self
+    }
+    /// TraceRayのInstanceInclusionMaskとANDされるマスク
+    pub fn mask(mut self, mask: u8) -> Self {
+        let v = &mut self.0.InstanceID_InstanceMask;
+        *v = (*v & 0x00ff_ffff) | (mask as u32) << 24;
+        self
+    }
+    /// ヒットグループのインデックスに加算される値(下位24bit)
+    pub fn hit_group_offset(mut self, offset: u32) -> Self {
+        let v = &mut self.0.InstanceContributionToHitGroupIndex_Flags;
+        *v = (*v & 0xff00_0000) | (offset & 0x00ff_ffff);
+        self
+    }
+    pub fn flags(mut self, flags: D3D12_RAYTRACING_INSTANCE_FLAGS) -> Self {
+        let v = &mut self.0.InstanceContributionToHitGroupIndex_Flags;
+        *v = (*v & 0x00ff_ffff) | (flags & 0xff) << 24;
+        self
+    }
+
+    pub fn id(&self) -> u32 {
+        self.0.InstanceID_InstanceMask & 0x00ff_ffff
+    }
+    pub fn instance_mask(&self) -> u8 {
+        (self.0.InstanceID_InstanceMask >> 24) as u8
+    }
+    pub fn hit_group_contribution(&self) -> u32 {
+        self.0.InstanceContributionToHitGroupIndex_Flags & 0x00ff_ffff
+    }
+    pub fn instance_flags(&self) -> D3D12_RAYTRACING_INSTANCE_FLAGS {
+        self.0.InstanceContributionToHitGroupIndex_Flags >> 24
+    }
+}
+
+/// 高速化構造の構築に必要な入力
+#[derive(Clone, Copy)]
+pub struct AccelerationStructureInputs<'a>(
+    D3D12_BUILD_RAYTRACING_ACCELERATION_STRUCTURE_INPUTS,
+    PhantomData<&'a [RaytracingGeometry]>,
+);
+impl<'a> AccelerationStructureInputs<'a> {
+    /// ジオメトリの集まりからボトムレベル高速化構造を作る
+    pub fn bottom_level(geometries: &'a [RaytracingGeometry]) -> Self {
+        AccelerationStructureInputs(
+            D3D12_BUILD_RAYTRACING_ACCELERATION_STRUCTURE_INPUTS {
+                Type: D3D12_RAYTRACING_ACCELERATION_STRUCTURE_TYPE_BOTTOM_LEVEL,
+                Flags: D3D12_RAYTRACING_ACCELERATION_STRUCTURE_BUILD_FLAG_NONE,
+                NumDescs: geometries.len() as _,
+                DescsLayout: D3D12_ELEMENTS_LAYOUT_ARRAY,
+                InstanceDescsOrGeometryDescs: geometries.as_ptr() as usize as _,
+            },
+            PhantomData,
+        )
+    }
+    /// GPU上に並べたインスタンス(RaytracingInstance、16バイト境界)からトップレベル高速化構造を作る
+    pub fn top_level(instances: GraphicsVirtualPtr, count: u32) -> Self {
+        AccelerationStructureInputs(
+            D3D12_BUILD_RAYTRACING_ACCELERATION_STRUCTURE_INPUTS {
+                Type: D3D12_RAYTRACING_ACCELERATION_STRUCTURE_TYPE_TOP_LEVEL,
+                Flags: D3D12_RAYTRACING_ACCELERATION_STRUCTURE_BUILD_FLAG_NONE,
+                NumDescs: count,
+                DescsLayout: D3D12_ELEMENTS_LAYOUT_ARRAY,
+                InstanceDescsOrGeometryDescs: instances.0,
+            },
+            PhantomData,
+        )
+    }
+    pub fn flags(mut self, flags: D3D12_RAYTRACING_ACCELERATION_STRUCTURE_BUILD_FLAGS) -> Self {
+        self.0.Flags = flags;
+        self
+    }
+    pub fn is_top_level(&self) -> bool {
+        self.0.Type == D3D12_RAYTRACING_ACCELERATION_STRUCTURE_TYPE_TOP_LEVEL
+    }
+}
+
+/// 高速化構造の構築に必要なバッファサイズ(256バイト単位に切り上げ済み)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AccelerationStructureSizes {
+    pub result: u64,
+    pub scratch: u64,
+    pub update_scratch: u64,
+}
+impl From<D3D12_RAYTRACING_ACCELERATION_STRUCTURE_PREBUILD_INFO> for AccelerationStructureSizes {
+    fn from(v: D3D12_RAYTRACING_ACCELERATION_STRUCTURE_PREBUILD_INFO) -> Self {
+        let a = D3D12_RAYTRACING_ACCELERATION_STRUCTURE_BYTE_ALIGNMENT as u64;
+        AccelerationStructureSizes {
+            result: align_up(v.ResultDataMaxSizeInBytes, a),
+            scratch: align_up(v.ScratchDataSizeInBytes, a),
+            update_scratch: align_up(v.UpdateScratchDataSizeInBytes, a),
+        }
+    }
+}
+
+impl Device {
+    /// レイトレーシング(ID3D12Device5)が使えるか
+    pub fn supports_raytracing(&self) -> bool {
+        query_ext::<ID3D12Device5>(self.0 as _).is_some()
+    }
+    /// 高速化構造の構築に必要なサイズを問い合わせる
+    pub fn raytracing_prebuild_info(
+        &self,
+        inputs: &AccelerationStructureInputs,
+    ) -> IOResult<AccelerationStructureSizes> {
+        let device5 = query_ext::<ID3D12Device5>(self.0 as _).ok_or_else(unsupported)?;
+        let mut info = D3D12_RAYTRACING_ACCELERATION_STRUCTURE_PREBUILD_INFO::default();
+        unsafe {
+            (*device5.0).GetRaytracingAccelerationStructurePrebuildInfo(&inputs.0, &mut info)
+        };
+
+        Ok(info.into())
+    }
+    /// レイトレーシングパイプライン(ステートオブジェクト)を作る
+    pub fn new_state_object(&self, builder: &StateObjectBuilder) -> IOResult<StateObject> {
+        let device5 = query_ext::<ID3D12Device5>(self.0 as _).ok_or_else(unsupported)?;
+        let mut storage = Vec::new();
+        let subobjects = builder.subobjects(&mut storage);
+        let desc = D3D12_STATE_OBJECT_DESC {
+            Type: D3D12_STATE_OBJECT_TYPE_RAYTRACING_PIPELINE,
+            NumSubobjects: subobjects.len() as _,
+            pSubobjects: subobjects.as_ptr(),
+        };
+        let mut handle = std::ptr::null_mut();
+
+        unsafe {
+            (*device5.0)
+                .CreateStateObject(&desc, &ID3D12StateObject::uuidof(), &mut handle)
+                .to_result_with(|| StateObject(handle as _))
+        }
+    }
+}
+
+impl GraphicsCommandList {
+    fn list4(&self) -> IOResult<ComPtr<ID3D12GraphicsCommandList4>> {
+        query_ext::<ID3D12GraphicsCommandList4>(self.0 as _).ok_or_else(unsupported)
+    }
+
+    /// 高速化構造を構築する(sourceを渡すとALLOW_UPDATEで作った構造を更新する)
+    /// destとscratchは256バイト境界でUAV状態にあること
+    pub fn build_raytracing_acceleration_structure(
+        &mut self,
+        inputs: &AccelerationStructureInputs,
+        dest: GraphicsVirtualPtr,
+        scratch: GraphicsVirtualPtr,
+        source: Option<GraphicsVirtualPtr>,
+    ) -> IOResult<&mut Self> {
+        let a = D3D12_RAYTRACING_ACCELERATION_STRUCTURE_BYTE_ALIGNMENT as u64;
+        if dest.0 % a != 0 || scratch.0 % a != 0 {
+            return invalid(format!(
+                "acceleration structure and scratch addresses must be {}-byte aligned",
+                a
+            ));
+        }
+        let list4 = self.list4()?;
+        let mut inputs = inputs.0;
+        if source.is_some() {
+            inputs.Flags |= D3D12_RAYTRACING_ACCELERATION_STRUCTURE_BUILD_FLAG_PERFORM_UPDATE;
+        }
+        let desc = D3D12_BUILD_RAYTRACING_ACCELERATION_STRUCTURE_DESC {
+            DestAccelerationStructureData: dest.0,
+            Inputs: inputs,
+            SourceAccelerationStructureData: source.map_or(0, |p| p.0),
+            ScratchAccelerationStructureData: scratch.0,
+        };
+        unsafe { (*list4.0).BuildRaytracingAccelerationStructure(&desc, 0, std::ptr::null()) };
+
+        Ok(self)
+    }
+    /// レイトレーシングパイプラインを設定する
+    pub fn set_pipeline_state1(&mut self, state: &StateObject) -> IOResult<&mut Self> {
+        let list4 = self.list4()?;
+        unsafe { (*list4.0).SetPipelineState1(state.0) };
+        Ok(self)
+    }
+    /// レイを飛ばす(ShaderTableLayout::dispatch_descで作った記述を渡す)
+    pub fn dispatch_rays(&mut self, desc: &D3D12_DISPATCH_RAYS_DESC) -> IOResult<&mut Self> {
+        let list4 = self.list4()?;
+        unsafe { (*list4.0).DispatchRays(desc) };
+        Ok(self)
+    }
+}
+
+enum Subobject<'a> {
+    DxilLibrary(&'a [u8], Vec<usize>),
+    HitGroup {
+        export: usize,
+        closest_hit: Option<usize>,
+        any_hit: Option<usize>,
+        intersection: Option<usize>,
+    },
+    ShaderConfig(u32, u32),
+    PipelineConfig(u32),
+    GlobalRootSignature(&'a RootSignature),
+    LocalRootSignature(&'a RootSignature, Vec<usize>),
+}
+
+/// レイトレーシングパイプラインを組み立てる
+pub struct StateObjectBuilder<'a> {
+    names: Vec<Vec<u16>>,
+    entries: Vec<Subobject<'a>>,
+}
+impl<'a> Default for StateObjectBuilder<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl<'a> StateObjectBuilder<'a> {
+    pub fn new() -> Self {
+        StateObjectBuilder {
+            names: Vec::new(),
+            entries: Vec::new(),
+        }
+    }
+    fn name(&mut self, s: &str) -> usize {
+        self.names.push(s.encode_utf16().chain(Some(0)).collect());
+        self.names.len() - 1
+    }
+
+    /// DXILライブラリ(exportsが空ならすべてのシェーダを公開する)
+    pub fn dxil_library(mut self, bytecode: &'a [u8], exports: &[&str]) -> Self {
+        let exports = exports.iter().map(|e| self.name(e)).collect();
+        self.entries.push(Subobject::DxilLibrary(bytecode, exports));
+        self
+    }
+    /// ヒットグループ(intersectionがあればプロシージャルプリミティブ用)
+    pub fn hit_group(
+        mut self,
+        name: &str,
+        closest_hit: Option<&str>,
+        any_hit: Option<&str>,
+        intersection: Option<&str>,
+    ) -> Self {
+        let export = self.name(name);
+        let closest_hit = closest_hit.map(|s| self.name(s));
+        let any_hit = any_hit.map(|s| self.name(s));
+        let intersection = intersection.map(|s| self.name(s));
+        self.entries.push(Subobject::HitGroup {
+            export,
+            closest_hit,
+            any_hit,
+            intersection,
+        });
+        self
+    }
+    /// ペイロードとヒット属性の最大バイト数
+    pub fn shader_config(mut self, max_payload_size: u32, max_attribute_size: u32) -> Self {
+        self.entries.push(Subobject::ShaderConfig(
+            max_payload_size,
+            max_attribute_size,
+        ));
+        self
+    }
+    /// TraceRayの最大再帰深度
+    pub fn pipeline_config(mut self, max_trace_recursion_depth: u32) -> Self {
+        self.entries
+            .push(Subobject::PipelineConfig(max_trace_recursion_depth));
+        self
+    }
+    pub fn global_root_signature(mut self, rs: &'a RootSignature) -> Self {
+        self.entries.push(Subobject::GlobalRootSignature(rs));
+        self
+    }
+    /// ローカルルートシグネチャ(LOCAL_ROOT_SIGNATUREフラグつきで作ったもの)をexportsに関連付ける
+    pub fn local_root_signature(mut self, rs: &'a RootSignature, exports: &[&str]) -> Self {
+        let exports = exports.iter().map(|e| self.name(e)).collect();
+        self.entries
+            .push(Subobject::LocalRootSignature(rs, exports));
+        self
+    }
+
+    /// サブオブジェクトの配列を作る(記述の実体はstorageが持つ)
+    fn subobjects(&self, storage: &mut Vec<Box<dyn Any>>) -> Vec<D3D12_STATE_SUBOBJECT> {
+        fn keep<T: 'static>(storage: &mut Vec<Box<dyn Any>>, v: T) -> *const c_void {
+            let b = Box::new(v);
+            let p = &*b as *const T as *const c_void;
+            storage.push(b);
+            p
+        }
+        let name = |i: usize| self.names[i].as_ptr();
+        let opt_name = |i: Option<usize>| i.map_or(std::ptr::null(), name);
+
+        // 関連付けはサブオブジェクトを指すので、配列が再確保されないよう先に確保しておく
+        let count = self
+            .entries
+            .iter()
+            .map(|e| match e {
+                Subobject::LocalRootSignature(..) => 2,
+                _ => 1,
+            })
+            .sum();
+        let mut subobjects = Vec::with_capacity(count);
+        for e in &self.entries {
+            let (ty, desc) = match e {
+                Subobject::DxilLibrary(bytecode, exports) => {
+                    let exports = exports
+                        .iter()
+                        .map(|&i| D3D12_EXPORT_DESC {
+                            Name: name(i),
+                            ExportToRename: std::ptr::null(),
+                            Flags: 0,
+                        })
+                        .collect::<Vec<_>>();
+                    let desc = D3D12_DXIL_LIBRARY_DESC {
+                        DXILLibrary: D3D12_SHADER_BYTECODE {
+                            pShaderBytecode: bytecode.as_ptr() as _,
+                            BytecodeLength: bytecode.len() as _,
+                        },
+                        NumExports: exports.len() as _,
+                        pExports: exports.as_ptr(),
+                    };
+                    storage.push(Box::new(exports));
+                    (D3D12_STATE_SUBOBJECT_TYPE_DXIL_LIBRARY, keep(storage, desc))
+                }
+                &Subobject::HitGroup {
+                    export,
+                    closest_hit,
+                    any_hit,
+                    intersection,
+                } => {
+                    let desc = D3D12_HIT_GROUP_DESC {
+                        HitGroupExport: name(export),
+                        Type: if intersection.is_some() {
+                            D3D12_HIT_GROUP_TYPE_PROCEDURAL_PRIMITIVE
+                        } else {
+                            D3D12_HIT_GROUP_TYPE_TRIANGLES
+                        },
+                        AnyHitShaderImport: opt_name(any_hit),
+                        ClosestHitShaderImport: opt_name(closest_hit),
+                        IntersectionShaderImport: opt_name(intersection),
+                    };
+                    (D3D12_STATE_SUBOBJECT_TYPE_HIT_GROUP, keep(storage, desc))
+                }
+                &Subobject::ShaderConfig(payload, attribute) => {
+                    let desc = D3D12_RAYTRACING_SHADER_CONFIG {
+                        MaxPayloadSizeInBytes: payload,
+                        MaxAttributeSizeInBytes: attribute,
+                    };
+                    (
+                        D3D12_STATE_SUBOBJECT_TYPE_RAYTRACING_SHADER_CONFIG,
+                        keep(storage, desc),
+                    )
+                }
+                &Subobject::PipelineConfig(depth) => {
+                    let desc = D3D12_RAYTRACING_PIPELINE_CONFIG {
+                        MaxTraceRecursionDepth: depth,
+                    };
+                    (
+                        D3D12_STATE_SUBOBJECT_TYPE_RAYTRACING_PIPELINE_CONFIG,
+                        keep(storage, desc),
+                    )
+                }
+                Subobject::GlobalRootSignature(rs) => {
+                    let desc = D3D12_ROOT_SIGNATURE_SUBOBJECT {
+                        pRootSignature: rs.0,
+                    };
+                    (
+                        D3D12_STATE_SUBOBJECT_TYPE_GLOBAL_ROOT_SIGNATURE,
+                        keep(storage, desc),
+                    )
+                }
+                Subobject::LocalRootSignature(rs, exports) => {
+                    let desc = D3D12_ROOT_SIGNATURE_SUBOBJECT {
+                        pRootSignature: rs.0,
+                    };
+                    subobjects.push(D3D12_STATE_SUBOBJECT {
+                        Type: D3D12_STATE_SUBOBJECT_TYPE_LOCAL_ROOT_SIGNATURE,
+                        pDesc: keep(storage, desc),
+                    });
+                    let exports = exports.iter().map(|&i| name(i)).collect::<Vec<_>>();
+                    let desc = D3D12_SUBOBJECT_TO_EXPORTS_ASSOCIATION {
+                        pSubobjectToAssociate: subobjects.last().unwrap() as *const _,
+                        NumExports: exports.len() as _,
+                        pExports: exports.as_ptr(),
+                    };
+                    storage.push(Box::new(exports));
+                    (
+                        D3D12_STATE_SUBOBJECT_TYPE_SUBOBJECT_TO_EXPORTS_ASSOCIATION,
+                        keep(storage, desc),
+                    )
+                }
+            };
+            subobjects.push(D3D12_STATE_SUBOBJECT {
+                Type: ty,
+                pDesc: desc,
+            });
+        }
+        debug_assert_eq!(subobjects.len(), count);
+
+        subobjects
+    }
+}
+
+/// レイトレーシングパイプライン
+pub struct StateObject(*mut ID3D12StateObject);
+HandleWrapper!(for StateObject[ID3D12StateObject] + FromRawHandle);
+unsafe impl Sync for StateObject {}
+unsafe impl Send for StateObject {}
+impl StateObject {
+    /// シェーダ(またはヒットグループ)の識別子
+    pub fn shader_identifier(&self, export_name: &str) -> IOResult<[u8; 32]> {
+        let props =
+            query_ext::<ID3D12StateObjectProperties>(self.0 as _).ok_or_else(unsupported)?;
+        let name: Vec<u16> = export_name.encode_utf16().chain(Some(0)).collect();
+        let p = unsafe { (*props.0).GetShaderIdentifier(name.as_ptr()) };
+        if p.is_null() {
+            return Err(IOError::new(
+                ErrorKind::NotFound,
+                format!("no shader exported as {:?}", export_name),
+            ));
+        }
+        let mut id = [0u8; D3D12_SHADER_IDENTIFIER_SIZE_IN_BYTES as usize];
+        unsafe { std::ptr::copy_nonoverlapping(p as *const u8, id.as_mut_ptr(), id.len()) };
+
+        Ok(id)
+    }
+    /// パイプラインのスタックサイズ
+    pub fn pipeline_stack_size(&self) -> IOResult<u64> {
+        let props =
+            query_ext::<ID3D12StateObjectProperties>(self.0 as _).ok_or_else(unsupported)?;
+        Ok(unsafe { (*props.0).GetPipelineStackSize() })
+    }
+    pub fn set_pipeline_stack_size(&self, size: u64) -> IOResult<()> {
+        let props =
+            query_ext::<ID3D12StateObjectProperties>(self.0 as _).ok_or_else(unsupported)?;
+        unsafe { (*props.0).SetPipelineStackSize(size) };
+        Ok(())
+    }
+}
+
+/// シェーダテーブルの種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShaderTableKind {
+    RayGeneration,
+    Miss,
+    HitGroup,
+    Callable,
+}
+
+/// シェーダテーブル1つぶんの配置
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ShaderTableRegion {
+    /// バッファ先頭からのオフセット(64バイト境界)
+    pub offset: u64,
+    /// レコード1つの大きさ(32バイト境界)
+    pub stride: u64,
+    pub count: u32,
+}
+impl ShaderTableRegion {
+    /// 識別子とローカルルート引数(args_sizeバイト)を持つレコードをcount個、offset以降に置く
+    fn new(offset: u64, count: u32, args_size: u64) -> Self {
+        ShaderTableRegion {
+            offset: align_up(offset, D3D12_RAYTRACING_SHADER_TABLE_BYTE_ALIGNMENT as _),
+            stride: align_up(
+                D3D12_SHADER_IDENTIFIER_SIZE_IN_BYTES as u64 + args_size,
+                D3D12_RAYTRACING_SHADER_RECORD_BYTE_ALIGNMENT as _,
+            ),
+            count,
+        }
+    }
+    pub fn size(&self) -> u64 {
+        self.stride * self.count as u64
+    }
+    pub fn end(&self) -> u64 {
+        self.offset + self.size()
+    }
+    /// ローカルルート引数に使えるバイト数
+    pub fn args_capacity(&self) -> u64 {
+        self.stride - D3D12_SHADER_IDENTIFIER_SIZE_IN_BYTES as u64
+    }
+    fn range_and_stride(
+        &self,
+        base: GraphicsVirtualPtr,
+    ) -> D3D12_GPU_VIRTUAL_ADDRESS_RANGE_AND_STRIDE {
+        D3D12_GPU_VIRTUAL_ADDRESS_RANGE_AND_STRIDE {
+            StartAddress: if self.count == 0 {
+                0
+            } else {
+                base.0 + self.offset
+            },
+            SizeInBytes: self.size(),
+            StrideInBytes: self.stride,
+        }
+    }
+}
+
+/// レイ生成/ミス/ヒットグループ/コーラブルのシェーダテーブルを1つのバッファに並べたときの配置
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ShaderTableLayout {
+    pub ray_generation: ShaderTableRegion,
+    pub miss: ShaderTableRegion,
+    pub hit_group: ShaderTableRegion,
+    pub callable: ShaderTableRegion,
+}
+impl ShaderTableLayout {
+    /// 各テーブルの(レコード数, ローカルルート引数の最大バイト数)から配置を決める
+    /// (レイ生成シェーダのレコードは1つ)
+    pub fn new(
+        ray_generation_args: u64,
+        miss: (u32, u64),
+        hit_group: (u32, u64),
+        callable: (u32, u64),
+    ) -> Self {
+        let ray_generation = ShaderTableRegion::new(0, 1, ray_generation_args);
+        let miss = ShaderTableRegion::new(ray_generation.end(), miss.0, miss.1);
+        let hit_group = ShaderTableRegion::new(miss.end(), hit_group.0, hit_group.1);
+        let callable = ShaderTableRegion::new(hit_group.end(), callable.0, callable.1);
+
+        ShaderTableLayout {
+            ray_generation,
+            miss,
+            hit_group,
+            callable,
+        }
+    }
+    pub fn region(&self, kind: ShaderTableKind) -> &ShaderTableRegion {
+        match kind {
+            ShaderTableKind::RayGeneration => &self.ray_generation,
+            ShaderTableKind::Miss => &self.miss,
+            ShaderTableKind::HitGroup => &self.hit_group,
+            ShaderTableKind::Callable => &self.callable,
+        }
+    }
+    /// バッファ全体の大きさ
+    pub fn total_size(&self) -> u64 {
+        self.callable.end()
+    }
+    /// レコードのバッファ先頭からのオフセット
+    pub fn record_offset(&self, kind: ShaderTableKind, index: u32) -> Option<u64> {
+        let r = self.region(kind);
+        if index < r.count {
+            Some(r.offset + r.stride * index as u64)
+        } else {
+            None
+        }
+    }
+    /// レコードを書き込む(余りは0で埋める)
+    pub fn write_record(
+        &self,
+        buffer: &mut [u8],
+        kind: ShaderTableKind,
+        index: u32,
+        identifier: &[u8; 32],
+        args: &[u8],
+    ) -> IOResult<()> {
+        let r = self.region(kind);
+        let offset = match self.record_offset(kind, index) {
+            Some(o) => o,
+            None => {
+                return invalid(format!(
+                    "{:?} record index {} is out of range ({} records)",
+                    kind, index, r.count
+                ))
+            }
+        };
+        if args.len() as u64 > r.args_capacity() {
+            return invalid(format!(
+                "{} bytes of local root arguments do not fit in a {:?} record ({} bytes)",
+                args.len(),
+                kind,
+                r.args_capacity()
+            ));
+        }
+        if (buffer.len() as u64) < offset + r.stride {
+            return invalid(format!(
+                "shader table buffer is too small ({} bytes, {} required)",
+                buffer.len(),
+                self.total_size()
+            ));
+        }
+
+        let record = &mut buffer[offset as usize..(offset + r.stride) as usize];
+        let (id, rest) = record.split_at_mut(identifier.len());
+        id.copy_from_slice(identifier);
+        rest[..args.len()].copy_from_slice(args);
+        rest[args.len()..].iter_mut().for_each(|b| *b = 0);
+
+        Ok(())
+    }
+    /// baseに置いたシェーダテーブルでwidth x height x depthのレイを飛ばす記述(baseは64バイト境界)
+    pub fn dispatch_desc(
+        &self,
+        base: GraphicsVirtualPtr,
+        width: u32,
+        height: u32,
+        depth: u32,
+    ) -> D3D12_DISPATCH_RAYS_DESC {
+        debug_assert!(base.0 % D3D12_RAYTRACING_SHADER_TABLE_BYTE_ALIGNMENT as u64 == 0);
+
+        D3D12_DISPATCH_RAYS_DESC {
+            RayGenerationShaderRecord: D3D12_GPU_VIRTUAL_ADDRESS_RANGE {
+                StartAddress: base.0 + self.ray_generation.offset,
+                SizeInBytes: self.ray_generation.stride,
+            },
+            MissShaderTable: self.miss.range_and_stride(base),
+            HitGroupTable: self.hit_group.range_and_stride(base),
+            CallableShaderTable: self.callable.range_and_stride(base),
+            Width: width,
+            Height: height,
+            Depth: depth,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ShaderTableKind::*;
+
+    fn layout() -> ShaderTableLayout {
+        ShaderTableLayout::new(8, (2, 0), (3, 40), (0, 16))
+    }
+
+    #[test]
+    fn tables_and_records_are_aligned() {
+        let l = layout();
+        let summary = |r: &ShaderTableRegion| (r.offset, r.stride, r.count);
+        assert_eq!(summary(&l.ray_generation), (0, 64, 1));
+        assert_eq!(summary(&l.miss), (64, 32, 2));
+        assert_eq!(summary(&l.hit_group), (128, 96, 3));
+        // 416バイト目で終わるので次のテーブルは64バイト境界の448から
+        assert_eq!(summary(&l.callable), (448, 64, 0));
+        assert_eq!(l.total_size(), 448);
+
+        assert_eq!(l.record_offset(Miss, 1), Some(96));
+        assert_eq!(l.record_offset(HitGroup, 2), Some(320));
+        assert_eq!(l.record_offset(HitGroup, 3), None);
+        assert_eq!(l.record_offset(Callable, 0), None);
+    }
+
+    #[test]
+    fn args_capacity_fills_the_record() {
+        let l = layout();
+        assert_eq!(l.ray_generation.args_capacity(), 32);
+        assert_eq!(l.miss.args_capacity(), 0);
+        assert_eq!(l.hit_group.args_capacity(), 64);
+        assert_eq!(l.callable.args_capacity(), 32);
+    }
+
+    #[test]
+    fn write_record_pads_with_zero() {
+        let l = layout();
+        let mut buffer = vec![0xffu8; l.total_size() as usize];
+        l.write_record(&mut buffer, HitGroup, 1, &[7; 32], &[1, 2, 3])
+            .unwrap();
+        assert!(buffer[..224].iter().all(|&b| b == 0xff));
+        assert!(buffer[224..256].iter().all(|&b| b == 7));
+        assert_eq!(&buffer[256..259], &[1, 2, 3]);
+        assert!(buffer[259..320].iter().all(|&b| b == 0));
+        assert!(buffer[320..].iter().all(|&b| b == 0xff));
+    }
+
+    #[test]
+    fn write_record_errors() {
+        let l = layout();
+        let mut buffer = vec![0u8; l.total_size() as usize];
+        let err = |r: IOResult<()>| {
+            let e = r.expect_err("should fail");
+            assert_eq!(e.kind(), ErrorKind::InvalidInput);
+            e.to_string()
+        };
+
+        assert!(err(l.write_record(&mut buffer, Miss, 2, &[0; 32], &[]))
+            .contains("index 2 is out of range (2 records)"));
+        assert!(err(l.write_record(&mut buffer, Miss, 0, &[0; 32], &[1]))
+            .contains("1 bytes of local root arguments"));
+        assert!(
+            err(l.write_record(&mut buffer, HitGroup, 0, &[0; 32], &[0; 65]))
+                .contains("(64 bytes)")
+        );
+        assert!(
+            err(l.write_record(&mut buffer[..400], HitGroup, 2, &[0; 32], &[]))
+                .contains("too small (400 bytes, 448 required)")
+        );
+    }
+
+    #[test]
+    fn dispatch_desc_skips_empty_tables() {
+        let d = layout().dispatch_desc(GraphicsVirtualPtr(0x10000), 1920, 1080, 1);
+        assert_eq!(d.RayGenerationShaderRecord.StartAddress, 0x10000);
+        assert_eq!(d.RayGenerationShaderRecord.SizeInBytes, 64);
+        assert_eq!(
+            (
+                d.HitGroupTable.StartAddress,
+                d.HitGroupTable.SizeInBytes,
+                d.HitGroupTable.StrideInBytes
+            ),
+            (0x10080, 288, 96)
+        );
+        assert_eq!(d.CallableShaderTable.StartAddress, 0);
+        assert_eq!(d.CallableShaderTable.SizeInBytes, 0);
+        assert_eq!((d.Width, d.Height, d.Depth), (1920, 1080, 1));
+    }
+}
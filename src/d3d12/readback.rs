@@ -0,0 +1,304 @@
+//! GPU Readback
+
+use super::*;
+
+/// CPU側に読み戻した画像(行は隙間なく詰められている)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReadbackImage {
+    pub width: u32,
+    pub height: u32,
+    pub depth: u32,
+    pub format: dxgi::Format,
+    /// 1行(圧縮フォーマットでは1ブロック行)のバイト数
+    pub row_size: usize,
+    /// 1スライスあたりの行数
+    pub row_count: u32,
+    pub data: Vec<u8>,
+}
+impl ReadbackImage {
+    /// フットプリントに従って配置されたデータから、行を詰めた画像を作る
+    /// srcはフットプリントのOffsetの基準となる位置から始まるメモリ
+    pub fn from_footprint(src: &[u8], footprint: &CopyableFootprint) -> Self {
+        let layout = &footprint.placed_footprint;
+        let row_size = footprint.row_size_in_bytes as usize;
+        let row_pitch = layout.Footprint.RowPitch as usize;
+        let rows = footprint.row_count as usize * layout.Footprint.Depth as usize;
+        let mut data = Vec::with_capacity(row_size * rows);
+        for r in 0..rows {
+            let s = layout.Offset as usize + r * row_pitch;
+            data.extend_from_slice(&src[s..s + row_size]);
+        }
+
+        ReadbackImage {
+            width: layout.Footprint.Width,
+            height: layout.Footprint.Height,
+            depth: layout.Footprint.Depth,
+            format: layout.Footprint.Format,
+            row_size,
+            row_count: footprint.row_count,
+            data,
+        }
+    }
+
+    /// y行目(深さzのスライス)のデータ
+    pub fn row(&self, y: u32, z: u32) -> &[u8] {
+        let s = (z as usize * self.row_count as usize + y as usize) * self.row_size;
+        &self.data[s..s + self.row_size]
+    }
+    /// 1ブロック(非圧縮フォーマットでは1ピクセル)の(バイト数, 幅, 高さ)
+    fn block(&self) -> (usize, u32, u32) {
+        match dxgi::format_block_info(self.format) {
+            Some(b) => (b.bytes as usize, b.width, b.height),
+            None => ((self.row_size / self.width.max(1) as usize).max(1), 1, 1),
+        }
+    }
+    /// 深さzのスライスの(x, y)のピクセルデータ(圧縮フォーマットでは(x, y)を含むブロックのデータ)
+    pub fn pixel(&self, x: u32, y: u32, z: u32) -> &[u8] {
+        let (bytes, width, height) = self.block();
+        let x = (x / width) as usize;
+        &self.row(y / height, z)[x * bytes..(x + 1) * bytes]
+    }
+    /// 各バイトの差がtoleranceを超えるピクセル(圧縮フォーマットではブロック)の数を数える(ゴールデンイメージとの比較用)
+    /// 大きさやフォーマットが異なる場合はNone
+    pub fn mismatched_pixels(&self, other: &Self, tolerance: u8) -> Option<usize> {
+        if (
+            self.width,
+            self.height,
+            self.depth,
+            self.format,
+            self.row_size,
+        ) != (
+            other.width,
+            other.height,
+            other.depth,
+            other.format,
+            other.row_size,
+        ) {
+            return None;
+        }
+        let (bytes, _, _) = self.block();
+
+        Some(
+            self.data
+                .chunks(bytes)
+                .zip(other.data.chunks(bytes))
+                .filter(|(a, b)| {
+                    a.iter()
+                        .zip(b.iter())
+                        .any(|(&x, &y)| (x as i16 - y as i16).abs() > tolerance as i16)
+                })
+                .count(),
+        )
+    }
+}
+
+/// 読み戻し待ちのテクスチャ
+pub struct TextureReadback {
+    buffer: Resource,
+    footprint: CopyableFootprint,
+    total_bytes: u64,
+}
+impl Device {
+    /// テクスチャのサブリソースをリードバックバッファへコピーするコマンドを積む
+    /// resourceはstateの状態であること(コピーの前後でCopySourceとの間を遷移する)
+    /// マルチサンプルのテクスチャはコピーできないので、先に解決しておくこと
+    pub fn readback_texture(
+        &self,
+        cmd: &mut GraphicsCommandList,
+        resource: &Resource,
+        subresource: u32,
+        state: ResourceState,
+    ) -> IOResult<TextureReadback> {
+        let desc = resource.desc();
+        let samples = desc.as_ref().SampleDesc.Count;
+        if samples > 1 {
+            return invalid(format!(
+                "multisampled textures ({} samples) must be resolved before readback",
+                samples
+            ));
+        }
+        let (mut footprints, total_bytes) =
+            self.get_copyable_footprints(desc.as_ref(), subresource..subresource + 1, 0);
+        let footprint = match footprints.next() {
+            Some(f) => f,
+            None => {
+                return invalid(format!(
+                    "subresource {} has no copyable footprint",
+                    subresource
+                ))
+            }
+        };
+        let buffer = self.new_resource_committed(
+            &HeapProperty::readback(),
+            &ResourceDesc::buffer(total_bytes as _),
+            ResourceState::CopyDest,
+            None,
+        )?;
+
+        let transit = state != ResourceState::CopySource;
+        if transit {
+            cmd.resource_barrier(&[ResourceBarrier::transition_subresource(
+                resource,
+                subresource,
+                state as _,
+                ResourceState::CopySource as _,
+            )]);
+        }
+        cmd.copy_texture_region(
+            &TextureCopyLocation::with_subresource_index(resource, subresource),
+            None,
+            &TextureCopyLocation::with_placed_footprint(&buffer, footprint.placed_footprint),
+            0,
+            0,
+            0,
+        );
+        if transit {
+            cmd.resource_barrier(&[ResourceBarrier::transition_subresource(
+                resource,
+                subresource,
+                ResourceState::CopySource as _,
+                state as _,
+            )]);
+        }
+
+        Ok(TextureReadback {
+            buffer,
+            footprint,
+            total_bytes,
+        })
+    }
+
+    /// テクスチャのサブリソースの内容をその場で読み戻す(スクリーンショット用)
+    /// 一時的なコマンドリストを実行し、完了を待つ
+    /// (コピーキューでは遷移できないので、stateはCommonかCopySourceであること)
+    pub fn capture_texture(
+        &self,
+        queue: &mut CommandQueue,
+        resource: &Resource,
+        subresource: u32,
+        state: ResourceState,
+    ) -> IOResult<ReadbackImage> {
+        let mut alloc = self.new_command_allocator(queue.command_type())?;
+        let mut cmd = self.new_graphics_command_list(&mut alloc, None)?;
+        let mut readback = self.readback_texture(&mut cmd, resource, subresource, state)?;
+        cmd.close()?;
+
+        let mut fence = self.new_fence(0, FENCE_FLAG_NONE)?;
+        queue.execute(&[cmd.0 as _]);
+        queue.signal(&fence, 1)?;
+        fence.wait(1)?;
+        readback.read()
+    }
+}
+impl TextureReadback {
+    /// 読み戻した画像を取り出す(コピーコマンドの完了を待ってから呼ぶこと)
+    pub fn read(&mut self) -> IOResult<ReadbackImage> {
+        let ptr = self.buffer.map(0..self.total_bytes as usize)?;
+        let image = ReadbackImage::from_footprint(
+            unsafe { std::slice::from_raw_parts(ptr as *const u8, self.total_bytes as _) },
+            &self.footprint,
+        );
+        self.buffer.unmap(..0);
+
+        Ok(image)
+    }
+    /// コピー先のフットプリント
+    pub fn footprint(&self) -> &CopyableFootprint {
+        &self.footprint
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn footprint(
+        format: dxgi::Format,
+        width: u32,
+        height: u32,
+        rows: u32,
+        row_size: u64,
+    ) -> CopyableFootprint {
+        CopyableFootprint {
+            placed_footprint: D3D12_PLACED_SUBRESOURCE_FOOTPRINT {
+                Offset: 16,
+                Footprint: D3D12_SUBRESOURCE_FOOTPRINT {
+                    Format: format,
+                    Width: width,
+                    Height: height,
+                    Depth: 1,
+                    RowPitch: 256,
+                },
+            },
+            row_count: rows,
+            row_size_in_bytes: row_size,
+        }
+    }
+
+    #[test]
+    fn rows_are_packed_from_pitched_data() {
+        let mut src = vec![0u8; 16 + 256 + 8];
+        src[16..24].copy_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8]);
+        src[272..280].copy_from_slice(&[9, 10, 11, 12, 13, 14, 15, 16]);
+        let image =
+            ReadbackImage::from_footprint(&src, &footprint(DXGI_FORMAT_R8G8B8A8_UNORM, 2, 2, 2, 8));
+        assert_eq!(image.data, (1..=16).collect::<Vec<u8>>());
+        assert_eq!(image.row(1, 0), &[9, 10, 11, 12, 13, 14, 15, 16]);
+        assert_eq!(image.pixel(1, 1, 0), &[13, 14, 15, 16]);
+    }
+
+    #[test]
+    fn block_compressed_pixels_address_blocks() {
+        // 8x8のBC1は2x2ブロック(1ブロック8バイト)
+        let src = (0..16 + 256 + 16).map(|n| n as u8).collect::<Vec<_>>();
+        let image =
+            ReadbackImage::from_footprint(&src, &footprint(DXGI_FORMAT_BC1_UNORM, 8, 8, 2, 16));
+        assert_eq!(image.pixel(0, 0, 0), &src[16..24]);
+        assert_eq!(image.pixel(7, 3, 0), &src[24..32]);
+        assert_eq!(image.pixel(5, 6, 0), &src[272 + 8..272 + 16]);
+
+        let mut other = image.clone();
+        other.data[9] ^= 0x80;
+        other.data[31] ^= 0x80;
+        assert_eq!(image.mismatched_pixels(&other, 0), Some(2));
+    }
+
+    #[test]
+    fn volume_pixels_address_depth_slices() {
+        // 2x1x2のR8G8B8A8: 各スライスは1行、スライスの間隔は1行分のピッチ
+        let mut fp = footprint(DXGI_FORMAT_R8G8B8A8_UNORM, 2, 1, 1, 8);
+        fp.placed_footprint.Footprint.Depth = 2;
+        let mut src = vec![0u8; 16 + 256 + 8];
+        src[16..24].copy_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8]);
+        src[272..280].copy_from_slice(&[9, 10, 11, 12, 13, 14, 15, 16]);
+        let image = ReadbackImage::from_footprint(&src, &fp);
+        assert_eq!(image.depth, 2);
+        assert_eq!(image.pixel(1, 0, 0), &[5, 6, 7, 8]);
+        assert_eq!(image.pixel(0, 0, 1), &[9, 10, 11, 12]);
+        assert_eq!(image.pixel(1, 0, 1), &[13, 14, 15, 16]);
+    }
+
+    #[test]
+    fn mismatch_tolerance_and_shape() {
+        let a = ReadbackImage {
+            width: 2,
+            height: 1,
+            depth: 1,
+            format: DXGI_FORMAT_R8G8_UNORM,
+            row_size: 4,
+            row_count: 1,
+            data: vec![10, 20, 30, 40],
+        };
+        let b = ReadbackImage {
+            data: vec![12, 20, 30, 45],
+            ..a.clone()
+        };
+        assert_eq!(a.mismatched_pixels(&b, 2), Some(1));
+        assert_eq!(a.mismatched_pixels(&b, 5), Some(0));
+        let c = ReadbackImage {
+            format: DXGI_FORMAT_R16_UNORM,
+            ..a.clone()
+        };
+        assert_eq!(a.mismatched_pixels(&c, 0), None);
+    }
+}
@@ -0,0 +1,1059 @@
+//! Command Recording IR
+
+use super::*;
+use std::fmt;
+
+/// 記録されたオブジェクトの識別子(記録時のポインタ値)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ObjectId(pub usize);
+impl ObjectId {
+    fn of<T>(p: *mut T) -> Self {
+        ObjectId(p as usize)
+    }
+    fn ptr<T>(self) -> *mut T {
+        self.0 as *mut T
+    }
+}
+
+/// 記録されたリソースバリア
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordedBarrier {
+    Transition {
+        resource: ObjectId,
+        subresource: u32,
+        before: D3D12_RESOURCE_STATES,
+        after: D3D12_RESOURCE_STATES,
+    },
+    Aliasing {
+        before: Option<ObjectId>,
+        after: ObjectId,
+    },
+    UnorderedAccess {
+        resource: Option<ObjectId>,
+    },
+}
+impl RecordedBarrier {
+    fn opt(p: *mut ID3D12Resource) -> Option<ObjectId> {
+        if p.is_null() {
+            None
+        } else {
+            Some(ObjectId::of(p))
+        }
+    }
+    fn from_raw(b: &D3D12_RESOURCE_BARRIER) -> Self {
+        unsafe {
+            match b.Type {
+                D3D12_RESOURCE_BARRIER_TYPE_ALIASING => {
+                    let a = b.u.Aliasing();
+                    RecordedBarrier::Aliasing {
+                        before: Self::opt(a.pResourceBefore),
+                        after: ObjectId::of(a.pResourceAfter),
+                    }
+                }
+                D3D12_RESOURCE_BARRIER_TYPE_UAV => RecordedBarrier::UnorderedAccess {
+                    resource: Self::opt(b.u.UAV().pResource),
+                },
+                _ => {
+                    let t = b.u.Transition();
+                    RecordedBarrier::Transition {
+                        resource: ObjectId::of(t.pResource),
+                        subresource: t.Subresource,
+                        before: t.StateBefore,
+                        after: t.StateAfter,
+                    }
+                }
+            }
+        }
+    }
+    fn to_raw(self) -> D3D12_RESOURCE_BARRIER {
+        let mut b = D3D12_RESOURCE_BARRIER {
+            Type: D3D12_RESOURCE_BARRIER_TYPE_TRANSITION,
+            Flags: 0,
+            u: unsafe { std::mem::zeroed() },
+        };
+        unsafe {
+            match self {
+                RecordedBarrier::Transition {
+                    resource,
+                    subresource,
+                    before,
+                    after,
+                } => {
+                    *b.u.Transition_mut() = D3D12_RESOURCE_TRANSITION_BARRIER {
+                        pResource: resource.ptr(),
+                        Subresource: subresource,
+                        StateBefore: before,
+                        StateAfter: after,
+                    }
+                }
+                RecordedBarrier::Aliasing { before, after } => {
+                    b.Type = D3D12_RESOURCE_BARRIER_TYPE_ALIASING;
+                    *b.u.Aliasing_mut() = D3D12_RESOURCE_ALIASING_BARRIER {
+                        pResourceBefore: before.map_or(std::ptr::null_mut(), |p| p.ptr()),
+                        pResourceAfter: after.ptr(),
+                    }
+                }
+                RecordedBarrier::UnorderedAccess { resource } => {
+                    b.Type = D3D12_RESOURCE_BARRIER_TYPE_UAV;
+                    *b.u.UAV_mut() = D3D12_RESOURCE_UAV_BARRIER {
+                        pResource: resource.map_or(std::ptr::null_mut(), |p| p.ptr()),
+                    }
+                }
+            }
+        }
+
+        b
+    }
+}
+
+/// 記録されたテクスチャコピーの位置
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordedCopyLocation {
+    Subresource {
+        resource: ObjectId,
+        index: u32,
+    },
+    Footprint {
+        resource: ObjectId,
+        offset: u64,
+        format: DXGI_FORMAT,
+        width: u32,
+        height: u32,
+        depth: u32,
+        row_pitch: u32,
+    },
+}
+impl RecordedCopyLocation {
+    fn from_raw(l: &D3D12_TEXTURE_COPY_LOCATION) -> Self {
+        unsafe {
+            if l.Type == D3D12_TEXTURE_COPY_TYPE_PLACED_FOOTPRINT {
+                let f = l.u.PlacedFootprint();
+                RecordedCopyLocation::Footprint {
+                    resource: ObjectId::of(l.pResource),
+                    offset: f.Offset,
+                    format: f.Footprint.Format,
+                    width: f.Footprint.Width,
+                    height: f.Footprint.Height,
+                    depth: f.Footprint.Depth,
+                    row_pitch: f.Footprint.RowPitch,
+                }
+            } else {
+                RecordedCopyLocation::Subresource {
+                    resource: ObjectId::of(l.pResource),
+                    index: *l.u.SubresourceIndex(),
+                }
+            }
+        }
+    }
+    fn to_raw(self) -> D3D12_TEXTURE_COPY_LOCATION {
+        let mut l = D3D12_TEXTURE_COPY_LOCATION {
+            pResource: std::ptr::null_mut(),
+            Type: D3D12_TEXTURE_COPY_TYPE_SUBRESOURCE_INDEX,
+            u: unsafe { std::mem::zeroed() },
+        };
+        unsafe {
+            match self {
+                RecordedCopyLocation::Subresource { resource, index } => {
+                    l.pResource = resource.ptr();
+                    *l.u.SubresourceIndex_mut() = index;
+                }
+                RecordedCopyLocation::Footprint {
+                    resource,
+                    offset,
+                    format,
+                    width,
+                    height,
+                    depth,
+                    row_pitch,
+                } => {
+                    l.pResource = resource.ptr();
+                    l.Type = D3D12_TEXTURE_COPY_TYPE_PLACED_FOOTPRINT;
+                    *l.u.PlacedFootprint_mut() = D3D12_PLACED_SUBRESOURCE_FOOTPRINT {
+                        Offset: offset,
+                        Footprint: D3D12_SUBRESOURCE_FOOTPRINT {
+                            Format: format,
+                            Width: width,
+                            Height: height,
+                            Depth: depth,
+                            RowPitch: row_pitch,
+                        },
+                    };
+                }
+            }
+        }
+
+        l
+    }
+}
+
+/// 記録されたコマンド
+#[derive(Debug, Clone, PartialEq)]
+pub enum RecordedCommand {
+    ResourceBarrier(Vec<RecordedBarrier>),
+    CopyResource {
+        src: ObjectId,
+        dst: ObjectId,
+    },
+    CopyBufferRegion {
+        src: ObjectId,
+        src_offset: u64,
+        dst: ObjectId,
+        dst_offset: u64,
+        bytes: u64,
+    },
+    CopyTextureRegion {
+        src: RecordedCopyLocation,
+        /// left, top, front, right, bottom, back
+        src_box: Option<[u32; 6]>,
+        dst: RecordedCopyLocation,
+        dst_x: u32,
+        dst_y: u32,
+        dst_z: u32,
+    },
+    ClearRenderTargetView {
+        view: usize,
+        color: [f32; 4],
+    },
+    ClearDepthStencilView {
+        view: usize,
+        depth: Option<f32>,
+        stencil: Option<u8>,
+    },
+    SetPipelineState(ObjectId),
+    SetRootSignature(ObjectId),
+    /// 値は32bit単位のビット列
+    SetRootConstants {
+        param_index: u32,
+        offset: u32,
+        values: Vec<u32>,
+    },
+    SetRootConstantBuffer {
+        param_index: u32,
+        address: D3D12_GPU_VIRTUAL_ADDRESS,
+    },
+    SetRootShaderResource {
+        param_index: u32,
+        address: D3D12_GPU_VIRTUAL_ADDRESS,
+    },
+    SetDescriptorHeaps(Vec<ObjectId>),
+    SetRootDescriptorTable {
+        param_index: u32,
+        base: u64,
+    },
+    SetRenderTargets {
+        views: Vec<usize>,
+        depth_stencil: Option<usize>,
+    },
+    /// x, y, width, height, min_depth, max_depth
+    SetViewports(Vec<[f32; 6]>),
+    /// left, top, right, bottom
+    SetScissorRects(Vec<[i32; 4]>),
+    SetPrimitiveTopology(D3D12_PRIMITIVE_TOPOLOGY),
+    /// (アドレス, サイズ, ストライド)
+    SetVertexBuffers {
+        start_slot: u32,
+        views: Vec<(D3D12_GPU_VIRTUAL_ADDRESS, u32, u32)>,
+    },
+    SetIndexBuffer {
+        address: D3D12_GPU_VIRTUAL_ADDRESS,
+        size: u32,
+        format: DXGI_FORMAT,
+    },
+    Draw {
+        vertex_count: u32,
+        instance_count: u32,
+    },
+    DrawIndexed {
+        index_count: u32,
+        instance_count: u32,
+        vertex_offset: i32,
+    },
+    Dispatch {
+        x: u32,
+        y: u32,
+        z: u32,
+    },
+    ExecuteBundle(ObjectId),
+}
+impl RecordedCommand {
+    fn objects(&mut self) -> Vec<&mut ObjectId> {
+        fn loc(l: &mut RecordedCopyLocation) -> &mut ObjectId {
+            match l {
+                RecordedCopyLocation::Subresource { resource, .. } => resource,
+                RecordedCopyLocation::Footprint { resource, .. } => resource,
+            }
+        }
+
+        match self {
+            RecordedCommand::ResourceBarrier(bs) => bs
+                .iter_mut()
+                .flat_map(|b| match b {
+                    RecordedBarrier::Transition { resource, .. } => vec![resource],
+                    RecordedBarrier::Aliasing { before, after } => {
+                        before.iter_mut().chain(Some(after)).collect()
+                    }
+                    RecordedBarrier::UnorderedAccess { resource } => resource.iter_mut().collect(),
+                })
+                .collect(),
+            RecordedCommand::CopyResource { src, dst } => vec![src, dst],
+            RecordedCommand::CopyBufferRegion { src, dst, .. } => vec![src, dst],
+            RecordedCommand::CopyTextureRegion { src, dst, .. } => vec![loc(src), loc(dst)],
+            RecordedCommand::SetPipelineState(o)
+            | RecordedCommand::SetRootSignature(o)
+            | RecordedCommand::ExecuteBundle(o) => vec![o],
+            RecordedCommand::SetDescriptorHeaps(hs) => hs.iter_mut().collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// コマンドリストに発行する
+    ///
+    /// # Safety
+    /// 参照しているオブジェクトがすべて生存していること
+    pub unsafe fn replay(&self, cmd: &mut GraphicsCommandList) {
+        let l = &*cmd.0;
+        match *self {
+            RecordedCommand::ResourceBarrier(ref bs) => {
+                let raw = bs.iter().map(|b| b.to_raw()).collect::<Vec<_>>();
+                l.ResourceBarrier(raw.len() as _, raw.as_ptr());
+            }
+            RecordedCommand::CopyResource { src, dst } => l.CopyResource(dst.ptr(), src.ptr()),
+            RecordedCommand::CopyBufferRegion {
+                src,
+                src_offset,
+                dst,
+                dst_offset,
+                bytes,
+            } => l.CopyBufferRegion(dst.ptr(), dst_offset, src.ptr(), src_offset, bytes),
+            RecordedCommand::CopyTextureRegion {
+                src,
+                src_box,
+                dst,
+                dst_x,
+                dst_y,
+                dst_z,
+            } => {
+                let b = src_box.map(|b| D3D12_BOX {
+                    left: b[0],
+                    top: b[1],
+                    front: b[2],
+                    right: b[3],
+                    bottom: b[4],
+                    back: b[5],
+                });
+                l.CopyTextureRegion(
+                    &dst.to_raw(),
+                    dst_x,
+                    dst_y,
+                    dst_z,
+                    &src.to_raw(),
+                    b.as_ref().map_or(std::ptr::null(), |p| p as _),
+                );
+            }
+            RecordedCommand::ClearRenderTargetView { view, ref color } => l.ClearRenderTargetView(
+                D3D12_CPU_DESCRIPTOR_HANDLE { ptr: view },
+                color,
+                0,
+                std::ptr::null(),
+            ),
+            RecordedCommand::ClearDepthStencilView {
+                view,
+                depth,
+                stencil,
+            } => {
+                cmd.clear_depth_stencil_view(
+                    D3D12_CPU_DESCRIPTOR_HANDLE { ptr: view },
+                    depth,
+                    stencil,
+                );
+            }
+            RecordedCommand::SetPipelineState(ps) => l.SetPipelineState(ps.ptr()),
+            RecordedCommand::SetRootSignature(rs) => l.SetGraphicsRootSignature(rs.ptr()),
+            RecordedCommand::SetRootConstants {
+                param_index,
+                offset,
+                ref values,
+            } => l.SetGraphicsRoot32BitConstants(
+                param_index,
+                values.len() as _,
+                values.as_ptr() as _,
+                offset,
+            ),
+            RecordedCommand::SetRootConstantBuffer {
+                param_index,
+                address,
+            } => l.SetGraphicsRootConstantBufferView(param_index, address),
+            RecordedCommand::SetRootShaderResource {
+                param_index,
+                address,
+            } => l.SetGraphicsRootShaderResourceView(param_index, address),
+            RecordedCommand::SetDescriptorHeaps(ref hs) => {
+                let raw = hs
+                    .iter()
+                    .map(|h| h.ptr::<ID3D12DescriptorHeap>())
+                    .collect::<Vec<_>>();
+                l.SetDescriptorHeaps(raw.len() as _, raw.as_ptr() as *mut _);
+            }
+            RecordedCommand::SetRootDescriptorTable { param_index, base } => l
+                .SetGraphicsRootDescriptorTable(
+                    param_index,
+                    D3D12_GPU_DESCRIPTOR_HANDLE { ptr: base },
+                ),
+            RecordedCommand::SetRenderTargets {
+                ref views,
+                depth_stencil,
+            } => {
+                let raw = views
+                    .iter()
+                    .map(|&ptr| D3D12_CPU_DESCRIPTOR_HANDLE { ptr })
+                    .collect::<Vec<_>>();
+                let ds = depth_stencil.map(|ptr| D3D12_CPU_DESCRIPTOR_HANDLE { ptr });
+                l.OMSetRenderTargets(
+                    raw.len() as _,
+                    raw.as_ptr(),
+                    false as _,
+                    ds.as_ref().map_or(std::ptr::null(), |p| p as _),
+                );
+            }
+            RecordedCommand::SetViewports(ref vps) => {
+                let raw = vps
+                    .iter()
+                    .map(|v| D3D12_VIEWPORT {
+                        TopLeftX: v[0],
+                        TopLeftY: v[1],
+                        Width: v[2],
+                        Height: v[3],
+                        MinDepth: v[4],
+                        MaxDepth: v[5],
+                    })
+                    .collect::<Vec<_>>();
+                l.RSSetViewports(raw.len() as _, raw.as_ptr());
+            }
+            RecordedCommand::SetScissorRects(ref rs) => {
+                let raw = rs
+                    .iter()
+                    .map(|r| D3D12_RECT {
+                        left: r[0],
+                        top: r[1],
+                        right: r[2],
+                        bottom: r[3],
+                    })
+                    .collect::<Vec<_>>();
+                l.RSSetScissorRects(raw.len() as _, raw.as_ptr());
+            }
+            RecordedCommand::SetPrimitiveTopology(tp) => l.IASetPrimitiveTopology(tp),
+            RecordedCommand::SetVertexBuffers {
+                start_slot,
+                ref views,
+            } => {
+                let raw = views
+                    .iter()
+                    .map(|&(a, s, st)| VertexBufferView {
+                        BufferLocation: a,
+                        SizeInBytes: s,
+                        StrideInBytes: st,
+                    })
+                    .collect::<Vec<_>>();
+                l.IASetVertexBuffers(start_slot, raw.len() as _, raw.as_ptr());
+            }
+            RecordedCommand::SetIndexBuffer {
+                address,
+                size,
+                format,
+            } => l.IASetIndexBuffer(&IndexBufferView {
+                BufferLocation: address,
+                SizeInBytes: size,
+                Format: format,
+            }),
+            RecordedCommand::Draw {
+                vertex_count,
+                instance_count,
+            } => l.DrawInstanced(vertex_count, instance_count, 0, 0),
+            RecordedCommand::DrawIndexed {
+                index_count,
+                instance_count,
+                vertex_offset,
+            } => l.DrawIndexedInstanced(index_count, instance_count, 0, vertex_offset, 0),
+            RecordedCommand::Dispatch { x, y, z } => l.Dispatch(x, y, z),
+            RecordedCommand::ExecuteBundle(b) => l.ExecuteBundle(b.ptr()),
+        }
+    }
+}
+
+/// 差分の1行
+#[derive(Debug, Clone, PartialEq)]
+pub enum CommandDiff<'s> {
+    Same(&'s RecordedCommand),
+    /// selfにだけある
+    Removed(&'s RecordedCommand),
+    /// otherにだけある
+    Added(&'s RecordedCommand),
+}
+
+/// 記録されたコマンド列
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct CommandStream(pub Vec<RecordedCommand>);
+impl CommandStream {
+    pub fn commands(&self) -> &[RecordedCommand] {
+        &self.0
+    }
+    /// オブジェクトの識別子を出現順の連番に置き換える(実行ごとに比較できるようにする)
+    pub fn normalized(&self) -> Self {
+        let mut seen: Vec<ObjectId> = Vec::new();
+        let mut s = self.clone();
+        for c in &mut s.0 {
+            for o in c.objects() {
+                let n = match seen.iter().position(|x| x == o) {
+                    Some(n) => n,
+                    None => {
+                        seen.push(*o);
+                        seen.len() - 1
+                    }
+                };
+                *o = ObjectId(n);
+            }
+        }
+
+        s
+    }
+    /// 最長共通部分列による差分
+    pub fn diff<'s>(&'s self, other: &'s Self) -> Vec<CommandDiff<'s>> {
+        let (a, b) = (&self.0, &other.0);
+        let mut lcs = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+        for i in (0..a.len()).rev() {
+            for j in (0..b.len()).rev() {
+                lcs[i][j] = if a[i] == b[j] {
+                    lcs[i + 1][j + 1] + 1
+                } else {
+                    lcs[i + 1][j].max(lcs[i][j + 1])
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        let mut out = Vec::new();
+        while i < a.len() && j < b.len() {
+            if a[i] == b[j] {
+                out.push(CommandDiff::Same(&a[i]));
+                i += 1;
+                j += 1;
+            } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+                out.push(CommandDiff::Removed(&a[i]));
+                i += 1;
+            } else {
+                out.push(CommandDiff::Added(&b[j]));
+                j += 1;
+            }
+        }
+        out.extend(a[i..].iter().map(CommandDiff::Removed));
+        out.extend(b[j..].iter().map(CommandDiff::Added));
+
+        out
+    }
+    /// コマンドリストに発行する
+    ///
+    /// # Safety
+    /// 参照しているオブジェクトがすべて生存していること
+    pub unsafe fn replay(&self, cmd: &mut GraphicsCommandList) {
+        for c in &self.0 {
+            c.replay(cmd);
+        }
+    }
+}
+impl fmt::Display for CommandStream {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (n, c) in self.0.iter().enumerate() {
+            writeln!(f, "{:4}: {:?}", n, c)?;
+        }
+        Ok(())
+    }
+}
+
+/// GraphicsCommandListと同じ操作を受け付けるもの
+pub trait GraphicsCommandSink {
+    fn resource_barrier(&mut self, barriers: &[ResourceBarrier]) -> &mut Self;
+    fn copy_resource(&mut self, src: &Resource, dst: &Resource) -> &mut Self;
+    fn copy_buffer_region(
+        &mut self,
+        src: &Resource,
+        range: std::ops::Range<usize>,
+        dst: &Resource,
+        dst_offset: usize,
+    ) -> &mut Self;
+    fn copy_texture_region(
+        &mut self,
+        src: &D3D12_TEXTURE_COPY_LOCATION,
+        src_box: Option<&D3D12_BOX>,
+        dst: &D3D12_TEXTURE_COPY_LOCATION,
+        dst_x: u32,
+        dst_y: u32,
+        dst_z: u32,
+    ) -> &mut Self;
+    fn clear_render_target_view(
+        &mut self,
+        target: D3D12_CPU_DESCRIPTOR_HANDLE,
+        color: &[f32; 4],
+    ) -> &mut Self;
+    fn clear_depth_stencil_view(
+        &mut self,
+        target: D3D12_CPU_DESCRIPTOR_HANDLE,
+        depth: Option<f32>,
+        stencil: Option<u8>,
+    ) -> &mut Self;
+    fn set_pipeline_state(
+        &mut self,
+        ps: &PipelineState,
+        signature: Option<&RootSignature>,
+    ) -> &mut Self;
+    fn set_root_signature(&mut self, signature: &RootSignature) -> &mut Self;
+    fn set_root_constants(&mut self, param_index: u32, offset: u32, values: &[f32]) -> &mut Self;
+    fn set_root_constant<C: RootConstant>(
+        &mut self,
+        param_index: u32,
+        offset: u32,
+        value: C,
+    ) -> &mut Self;
+    fn set_root_constant_buffer(
+        &mut self,
+        param_index: u32,
+        resource_ptr: D3D12_GPU_VIRTUAL_ADDRESS,
+    ) -> &mut Self;
+    fn set_root_resource_buffer(
+        &mut self,
+        param_index: u32,
+        resource_ptr: D3D12_GPU_VIRTUAL_ADDRESS,
+    ) -> &mut Self;
+    fn set_descriptor_heaps(&mut self, heaps: &[*mut ID3D12DescriptorHeap]) -> &mut Self;
+    fn set_root_descriptor_table(
+        &mut self,
+        param_index: u32,
+        table_start: &DeviceDescriptorHandle,
+    ) -> &mut Self;
+    fn set_render_targets(
+        &mut self,
+        handles: &[D3D12_CPU_DESCRIPTOR_HANDLE],
+        depth_stencil: Option<D3D12_CPU_DESCRIPTOR_HANDLE>,
+    ) -> &mut Self;
+    fn set_viewports(&mut self, vps: &[D3D12_VIEWPORT]) -> &mut Self;
+    fn set_scissor_rects(&mut self, scis: &[D3D12_RECT]) -> &mut Self;
+    fn set_view_states(&mut self, vps: &[(D3D12_VIEWPORT, D3D12_RECT)]) -> &mut Self {
+        let (vps, scis): (Vec<_>, Vec<_>) = vps.iter().cloned().unzip();
+        self.set_viewports(&vps).set_scissor_rects(&scis)
+    }
+    fn set_primitive_topology(&mut self, tp: D3D12_PRIMITIVE_TOPOLOGY) -> &mut Self;
+    fn set_vertex_buffers(&mut self, slot_from: u32, buffers: &[VertexBufferView]) -> &mut Self;
+    fn set_index_buffer(&mut self, buffer: &D3D12_INDEX_BUFFER_VIEW) -> &mut Self;
+    fn draw(&mut self, vertex_count: u32, instance_count: u32) -> &mut Self;
+    fn draw_indexed(
+        &mut self,
+        index_count: u32,
+        instance_count: u32,
+        vertex_offset: i32,
+    ) -> &mut Self;
+    fn dispatch(&mut self, x: u32, y: u32, z: u32) -> &mut Self;
+    fn execute(&mut self, cmd: &GraphicsCommandList) -> &mut Self;
+    fn close(&mut self) -> IOResult<()>;
+}
+
+macro_rules! ForwardSink {
+    ($($name: ident($($arg: ident: $t: ty),*);)*) => {
+        impl GraphicsCommandSink for GraphicsCommandList {
+            $(fn $name(&mut self, $($arg: $t),*) -> &mut Self {
+                GraphicsCommandList::$name(self, $($arg),*)
+            })*
+            fn set_root_constant<C: RootConstant>(
+                &mut self,
+                param_index: u32,
+                offset: u32,
+                value: C,
+            ) -> &mut Self {
+                GraphicsCommandList::set_root_constant(self, param_index, offset, value)
+            }
+            fn close(&mut self) -> IOResult<()> {
+                GraphicsCommandList::close(self)
+            }
+        }
+    };
+}
+ForwardSink! {
+    resource_barrier(barriers: &[ResourceBarrier]);
+    copy_resource(src: &Resource, dst: &Resource);
+    copy_buffer_region(src: &Resource, range: std::ops::Range<usize>, dst: &Resource, dst_offset: usize);
+    copy_texture_region(src: &D3D12_TEXTURE_COPY_LOCATION, src_box: Option<&D3D12_BOX>, dst: &D3D12_TEXTURE_COPY_LOCATION, dst_x: u32, dst_y: u32, dst_z: u32);
+    clear_render_target_view(target: D3D12_CPU_DESCRIPTOR_HANDLE, color: &[f32; 4]);
+    clear_depth_stencil_view(target: D3D12_CPU_DESCRIPTOR_HANDLE, depth: Option<f32>, stencil: Option<u8>);
+    set_pipeline_state(ps: &PipelineState, signature: Option<&RootSignature>);
+    set_root_signature(signature: &RootSignature);
+    set_root_constants(param_index: u32, offset: u32, values: &[f32]);
+    set_root_constant_buffer(param_index: u32, resource_ptr: D3D12_GPU_VIRTUAL_ADDRESS);
+    set_root_resource_buffer(param_index: u32, resource_ptr: D3D12_GPU_VIRTUAL_ADDRESS);
+    set_descriptor_heaps(heaps: &[*mut ID3D12DescriptorHeap]);
+    set_root_descriptor_table(param_index: u32, table_start: &DeviceDescriptorHandle);
+    set_render_targets(handles: &[D3D12_CPU_DESCRIPTOR_HANDLE], depth_stencil: Option<D3D12_CPU_DESCRIPTOR_HANDLE>);
+    set_viewports(vps: &[D3D12_VIEWPORT]);
+    set_scissor_rects(scis: &[D3D12_RECT]);
+    set_primitive_topology(tp: D3D12_PRIMITIVE_TOPOLOGY);
+    set_vertex_buffers(slot_from: u32, buffers: &[VertexBufferView]);
+    set_index_buffer(buffer: &D3D12_INDEX_BUFFER_VIEW);
+    draw(vertex_count: u32, instance_count: u32);
+    draw_indexed(index_count: u32, instance_count: u32, vertex_offset: i32);
+    dispatch(x: u32, y: u32, z: u32);
+    execute(cmd: &GraphicsCommandList);
+}
+
+/// コマンドをドライバに送らずに記録するもの
+#[derive(Debug, Clone, Default)]
+pub struct CommandRecorder {
+    stream: CommandStream,
+    closed: bool,
+}
+impl CommandRecorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    fn push(&mut self, c: RecordedCommand) -> &mut Self {
+        self.stream.0.push(c);
+        self
+    }
+    /// 記録されたコマンド列
+    pub fn stream(&self) -> &CommandStream {
+        &self.stream
+    }
+    /// 記録されたコマンド列を取り出す
+    pub fn into_stream(self) -> CommandStream {
+        self.stream
+    }
+    /// closeされたか
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+}
+impl GraphicsCommandSink for CommandRecorder {
+    fn resource_barrier(&mut self, barriers: &[ResourceBarrier]) -> &mut Self {
+        let bs = barriers
+            .iter()
+            .map(|b| RecordedBarrier::from_raw(&b.0))
+            .collect();
+        self.push(RecordedCommand::ResourceBarrier(bs))
+    }
+    fn copy_resource(&mut self, src: &Resource, dst: &Resource) -> &mut Self {
+        self.push(RecordedCommand::CopyResource {
+            src: ObjectId::of(src.0),
+            dst: ObjectId::of(dst.0),
+        })
+    }
+    fn copy_buffer_region(
+        &mut self,
+        src: &Resource,
+        range: std::ops::Range<usize>,
+        dst: &Resource,
+        dst_offset: usize,
+    ) -> &mut Self {
+        self.push(RecordedCommand::CopyBufferRegion {
+            src: ObjectId::of(src.0),
+            src_offset: range.start as _,
+            dst: ObjectId::of(dst.0),
+            dst_offset: dst_offset as _,
+            bytes: (range.end - range.start) as _,
+        })
+    }
+    fn copy_texture_region(
+        &mut self,
+        src: &D3D12_TEXTURE_COPY_LOCATION,
+        src_box: Option<&D3D12_BOX>,
+        dst: &D3D12_TEXTURE_COPY_LOCATION,
+        dst_x: u32,
+        dst_y: u32,
+        dst_z: u32,
+    ) -> &mut Self {
+        self.push(RecordedCommand::CopyTextureRegion {
+            src: RecordedCopyLocation::from_raw(src),
+            src_box: src_box.map(|b| [b.left, b.top, b.front, b.right, b.bottom, b.back]),
+            dst: RecordedCopyLocation::from_raw(dst),
+            dst_x,
+            dst_y,
+            dst_z,
+        })
+    }
+    fn clear_render_target_view(
+        &mut self,
+        target: D3D12_CPU_DESCRIPTOR_HANDLE,
+        color: &[f32; 4],
+    ) -> &mut Self {
+        self.push(RecordedCommand::ClearRenderTargetView {
+            view: target.ptr,
+            color: *color,
+        })
+    }
+    fn clear_depth_stencil_view(
+        &mut self,
+        target: D3D12_CPU_DESCRIPTOR_HANDLE,
+        depth: Option<f32>,
+        stencil: Option<u8>,
+    ) -> &mut Self {
+        self.push(RecordedCommand::ClearDepthStencilView {
+            view: target.ptr,
+            depth,
+            stencil,
+        })
+    }
+    fn set_pipeline_state(
+        &mut self,
+        ps: &PipelineState,
+        signature: Option<&RootSignature>,
+    ) -> &mut Self {
+        self.push(RecordedCommand::SetPipelineState(ObjectId::of(ps.0)));
+        if let Some(sig) = signature {
+            self.set_root_signature(sig)
+        } else {
+            self
+        }
+    }
+    fn set_root_signature(&mut self, signature: &RootSignature) -> &mut Self {
+        self.push(RecordedCommand::SetRootSignature(ObjectId::of(signature.0)))
+    }
+    fn set_root_constants(&mut self, param_index: u32, offset: u32, values: &[f32]) -> &mut Self {
+        self.push(RecordedCommand::SetRootConstants {
+            param_index,
+            offset,
+            values: values.iter().map(|v| v.to_bits()).collect(),
+        })
+    }
+    fn set_root_constant<C: RootConstant>(
+        &mut self,
+        param_index: u32,
+        offset: u32,
+        value: C,
+    ) -> &mut Self {
+        self.push(RecordedCommand::SetRootConstants {
+            param_index,
+            offset,
+            values: vec![value.passing_form()],
+        })
+    }
+    fn set_root_constant_buffer(
+        &mut self,
+        param_index: u32,
+        resource_ptr: D3D12_GPU_VIRTUAL_ADDRESS,
+    ) -> &mut Self {
+        self.push(RecordedCommand::SetRootConstantBuffer {
+            param_index,
+            address: resource_ptr,
+        })
+    }
+    fn set_root_resource_buffer(
+        &mut self,
+        param_index: u32,
+        resource_ptr: D3D12_GPU_VIRTUAL_ADDRESS,
+    ) -> &mut Self {
+        self.push(RecordedCommand::SetRootShaderResource {
+            param_index,
+            address: resource_ptr,
+        })
+    }
+    fn set_descriptor_heaps(&mut self, heaps: &[*mut ID3D12DescriptorHeap]) -> &mut Self {
+        self.push(RecordedCommand::SetDescriptorHeaps(
+            heaps.iter().map(|&h| ObjectId::of(h)).collect(),
+        ))
+    }
+    fn set_root_descriptor_table(
+        &mut self,
+        param_index: u32,
+        table_start: &DeviceDescriptorHandle,
+    ) -> &mut Self {
+        self.push(RecordedCommand::SetRootDescriptorTable {
+            param_index,
+            base: table_start.0.ptr,
+        })
+    }
+    fn set_render_targets(
+        &mut self,
+        handles: &[D3D12_CPU_DESCRIPTOR_HANDLE],
+        depth_stencil: Option<D3D12_CPU_DESCRIPTOR_HANDLE>,
+    ) -> &mut Self {
+        self.push(RecordedCommand::SetRenderTargets {
+            views: handles.iter().map(|h| h.ptr).collect(),
+            depth_stencil: depth_stencil.map(|h| h.ptr),
+        })
+    }
+    fn set_viewports(&mut self, vps: &[D3D12_VIEWPORT]) -> &mut Self {
+        self.push(RecordedCommand::SetViewports(
+            vps.iter()
+                .map(|v| {
+                    [
+                        v.TopLeftX, v.TopLeftY, v.Width, v.Height, v.MinDepth, v.MaxDepth,
+                    ]
+                })
+                .collect(),
+        ))
+    }
+    fn set_scissor_rects(&mut self, scis: &[D3D12_RECT]) -> &mut Self {
+        self.push(RecordedCommand::SetScissorRects(
+            scis.iter()
+                .map(|r| [r.left, r.top, r.right, r.bottom])
+                .collect(),
+        ))
+    }
+    fn set_primitive_topology(&mut self, tp: D3D12_PRIMITIVE_TOPOLOGY) -> &mut Self {
+        self.push(RecordedCommand::SetPrimitiveTopology(tp))
+    }
+    fn set_vertex_buffers(&mut self, slot_from: u32, buffers: &[VertexBufferView]) -> &mut Self {
+        self.push(RecordedCommand::SetVertexBuffers {
+            start_slot: slot_from,
+            views: buffers
+                .iter()
+                .map(|v| (v.BufferLocation, v.SizeInBytes, v.StrideInBytes))
+                .collect(),
+        })
+    }
+    fn set_index_buffer(&mut self, buffer: &D3D12_INDEX_BUFFER_VIEW) -> &mut Self {
+        self.push(RecordedCommand::SetIndexBuffer {
+            address: buffer.BufferLocation,
+            size: buffer.SizeInBytes,
+            format: buffer.Format,
+        })
+    }
+    fn draw(&mut self, vertex_count: u32, instance_count: u32) -> &mut Self {
+        self.push(RecordedCommand::Draw {
+            vertex_count,
+            instance_count,
+        })
+    }
+    fn draw_indexed(
+        &mut self,
+        index_count: u32,
+        instance_count: u32,
+        vertex_offset: i32,
+    ) -> &mut Self {
+        self.push(RecordedCommand::DrawIndexed {
+            index_count,
+            instance_count,
+            vertex_offset,
+        })
+    }
+    fn dispatch(&mut self, x: u32, y: u32, z: u32) -> &mut Self {
+        self.push(RecordedCommand::Dispatch { x, y, z })
+    }
+    fn execute(&mut self, cmd: &GraphicsCommandList) -> &mut Self {
+        self.push(RecordedCommand::ExecuteBundle(ObjectId::of(cmd.0)))
+    }
+    fn close(&mut self) -> IOResult<()> {
+        self.closed = true;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rtv(ptr: usize) -> D3D12_CPU_DESCRIPTOR_HANDLE {
+        D3D12_CPU_DESCRIPTOR_HANDLE { ptr }
+    }
+    fn copy(src: usize, dst: usize) -> RecordedCommand {
+        RecordedCommand::CopyResource {
+            src: ObjectId(src),
+            dst: ObjectId(dst),
+        }
+    }
+
+    #[test]
+    fn records_commands_in_order() {
+        let mut r = CommandRecorder::new();
+        r.clear_render_target_view(rtv(0x100), &[0.0, 0.5, 1.0, 1.0])
+            .clear_depth_stencil_view(rtv(0x200), Some(1.0), None)
+            .set_render_targets(&[rtv(0x100)], Some(rtv(0x200)))
+            .set_root_constants(0, 2, &[1.5])
+            .set_primitive_topology(D3D_PRIMITIVE_TOPOLOGY_TRIANGLELIST)
+            .draw_indexed(36, 2, -4)
+            .dispatch(8, 4, 1);
+        assert!(!r.is_closed());
+        r.close().unwrap();
+        assert!(r.is_closed());
+
+        assert_eq!(
+            r.into_stream().0,
+            vec![
+                RecordedCommand::ClearRenderTargetView {
+                    view: 0x100,
+                    color: [0.0, 0.5, 1.0, 1.0],
+                },
+                RecordedCommand::ClearDepthStencilView {
+                    view: 0x200,
+                    depth: Some(1.0),
+                    stencil: None,
+                },
+                RecordedCommand::SetRenderTargets {
+                    views: vec![0x100],
+                    depth_stencil: Some(0x200),
+                },
+                RecordedCommand::SetRootConstants {
+                    param_index: 0,
+                    offset: 2,
+                    values: vec![1.5f32.to_bits()],
+                },
+                RecordedCommand::SetPrimitiveTopology(D3D_PRIMITIVE_TOPOLOGY_TRIANGLELIST),
+                RecordedCommand::DrawIndexed {
+                    index_count: 36,
+                    instance_count: 2,
+                    vertex_offset: -4,
+                },
+                RecordedCommand::Dispatch { x: 8, y: 4, z: 1 },
+            ]
+        );
+    }
+
+    #[test]
+    fn barriers_round_trip() {
+        let barriers = [
+            RecordedBarrier::Transition {
+                resource: ObjectId(0x10),
+                subresource: 3,
+                before: D3D12_RESOURCE_STATE_COPY_DEST,
+                after: D3D12_RESOURCE_STATE_PIXEL_SHADER_RESOURCE,
+            },
+            RecordedBarrier::Aliasing {
+                before: None,
+                after: ObjectId(0x20),
+            },
+            RecordedBarrier::UnorderedAccess {
+                resource: Some(ObjectId(0x30)),
+            },
+        ];
+        for b in barriers {
+            assert_eq!(RecordedBarrier::from_raw(&b.to_raw()), b);
+        }
+    }
+
+    #[test]
+    fn normalization_ignores_object_addresses() {
+        let a = CommandStream(vec![copy(0x5000, 0x7000), copy(0x7000, 0x5000)]);
+        let b = CommandStream(vec![copy(0x9000, 0x1000), copy(0x1000, 0x9000)]);
+        assert_ne!(a, b);
+        assert_eq!(a.normalized(), b.normalized());
+        assert_eq!(a.normalized().0, vec![copy(0, 1), copy(1, 0)]);
+        let c = CommandStream(vec![copy(0x9000, 0x1000), copy(0x9000, 0x1000)]);
+        assert_ne!(a.normalized(), c.normalized());
+    }
+
+    #[test]
+    fn diff_finds_longest_common_subsequence() {
+        let d = |x| RecordedCommand::Dispatch { x, y: 1, z: 1 };
+        let a = CommandStream(vec![d(1), d(2), d(3), d(4)]);
+        let b = CommandStream(vec![d(1), d(3), d(4), d(5)]);
+        assert_eq!(
+            a.diff(&b),
+            vec![
+                CommandDiff::Same(&d(1)),
+                CommandDiff::Removed(&d(2)),
+                CommandDiff::Same(&d(3)),
+                CommandDiff::Same(&d(4)),
+                CommandDiff::Added(&d(5)),
+            ]
+        );
+        assert!(a.diff(&a).iter().all(|c| matches!(c, CommandDiff::Same(_))));
+        assert_eq!(
+            format!("{}", CommandStream(vec![d(7)])),
+            "   0: Dispatch { x: 7, y: 1, z: 1 }\n"
+        );
+    }
+}
@@ -0,0 +1,632 @@
+//! Render Passes
+
+use super::*;
+
+/// パス開始時の内容の扱い
+#[derive(Clone)]
+pub enum BeginningAccess {
+    /// 以前の内容は不要
+    Discard,
+    /// 以前の内容を保持
+    Preserve,
+    /// 指定値でクリア
+    Clear(OptimizedClearValue),
+    /// アクセスしない
+    NoAccess,
+}
+impl BeginningAccess {
+    fn to_desc(&self) -> D3D12_RENDER_PASS_BEGINNING_ACCESS {
+        let (ty, cv) = match self {
+            BeginningAccess::Discard => (D3D12_RENDER_PASS_BEGINNING_ACCESS_TYPE_DISCARD, None),
+            BeginningAccess::Preserve => (D3D12_RENDER_PASS_BEGINNING_ACCESS_TYPE_PRESERVE, None),
+            BeginningAccess::Clear(cv) => (
+                D3D12_RENDER_PASS_BEGINNING_ACCESS_TYPE_CLEAR,
+                Some(cv.clone().into()),
+            ),
+            BeginningAccess::NoAccess => (D3D12_RENDER_PASS_BEGINNING_ACCESS_TYPE_NO_ACCESS, None),
+        };
+
+        D3D12_RENDER_PASS_BEGINNING_ACCESS {
+            Type: ty,
+            ClearValue: cv.unwrap_or_else(|| unsafe { std::mem::zeroed() }),
+        }
+    }
+}
+
+/// リゾルブの方法
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResolveMode {
+    Decompress = D3D12_RESOLVE_MODE_DECOMPRESS,
+    Min = D3D12_RESOLVE_MODE_MIN,
+    Max = D3D12_RESOLVE_MODE_MAX,
+    Average = D3D12_RESOLVE_MODE_AVERAGE,
+}
+/// リゾルブするサブリソース1つぶん
+#[repr(transparent)]
+#[derive(Clone, Copy)]
+pub struct ResolveSubresource(D3D12_RENDER_PASS_ENDING_ACCESS_RESOLVE_SUBRESOURCE_PARAMETERS);
+unsafe impl MarkForSameBits<D3D12_RENDER_PASS_ENDING_ACCESS_RESOLVE_SUBRESOURCE_PARAMETERS>
+    for ResolveSubresource
+{
+}
+impl ResolveSubresource {
+    /// src_rectの範囲を(dst_x, dst_y)へリゾルブ
+    pub fn new(
+        src_subresource: u32,
+        dst_subresource: u32,
+        dst_x: u32,
+        dst_y: u32,
+        src_rect: D3D12_RECT,
+    ) -> Self {
+        ResolveSubresource(
+            D3D12_RENDER_PASS_ENDING_ACCESS_RESOLVE_SUBRESOURCE_PARAMETERS {
+                SrcSubresource: src_subresource,
+                DstSubresource: dst_subresource,
+                DstX: dst_x,
+                DstY: dst_y,
+                SrcRect: src_rect,
+            },
+        )
+    }
+    /// サブリソース全体(width x height)をリゾルブ
+    pub fn whole(src_subresource: u32, dst_subresource: u32, width: u32, height: u32) -> Self {
+        Self::new(
+            src_subresource,
+            dst_subresource,
+            0,
+            0,
+            D3D12_RECT {
+                left: 0,
+                top: 0,
+                right: width as _,
+                bottom: height as _,
+            },
+        )
+    }
+}
+/// リゾルブのパラメータ
+/// 実行時、sourceはRenderTarget(デプスステンシルならDepthWrite)、destはResolveDest状態であること
+#[derive(Clone)]
+pub struct ResolveParameters<'a> {
+    pub source: &'a Resource,
+    pub dest: &'a Resource,
+    pub subresources: Vec<ResolveSubresource>,
+    pub format: DXGI_FORMAT,
+    pub mode: ResolveMode,
+    /// リゾルブ後もsourceの内容を保持する
+    pub preserve_source: bool,
+}
+
+/// パス終了時の内容の扱い
+#[derive(Clone)]
+pub enum EndingAccess<'a> {
+    /// 内容は不要
+    Discard,
+    /// 内容を保持
+    Preserve,
+    /// 別のリソースへリゾルブ
+    Resolve(ResolveParameters<'a>),
+    /// アクセスしない
+    NoAccess,
+}
+impl<'a> EndingAccess<'a> {
+    fn to_desc(&self) -> D3D12_RENDER_PASS_ENDING_ACCESS {
+        let mut desc = D3D12_RENDER_PASS_ENDING_ACCESS {
+            Type: D3D12_RENDER_PASS_ENDING_ACCESS_TYPE_DISCARD,
+            Resolve: unsafe { std::mem::zeroed() },
+        };
+        match self {
+            EndingAccess::Discard => (),
+            EndingAccess::Preserve => desc.Type = D3D12_RENDER_PASS_ENDING_ACCESS_TYPE_PRESERVE,
+            EndingAccess::NoAccess => desc.Type = D3D12_RENDER_PASS_ENDING_ACCESS_TYPE_NO_ACCESS,
+            EndingAccess::Resolve(p) => {
+                desc.Type = D3D12_RENDER_PASS_ENDING_ACCESS_TYPE_RESOLVE;
+                desc.Resolve = D3D12_RENDER_PASS_ENDING_ACCESS_RESOLVE_PARAMETERS {
+                    pSrcResource: p.source.0,
+                    pDstResource: p.dest.0,
+                    SubresourceCount: p.subresources.len() as _,
+                    pSubresourceParameters: p.subresources.as_ptr() as _,
+                    Format: p.format,
+                    ResolveMode: p.mode as _,
+                    PreserveResolveSource: p.preserve_source as _,
+                };
+            }
+        }
+
+        desc
+    }
+}
+
+/// レンダーターゲット1枚ぶんのパス記述
+#[derive(Clone)]
+pub struct RenderPassRenderTarget<'a> {
+    pub view: D3D12_CPU_DESCRIPTOR_HANDLE,
+    pub beginning: BeginningAccess,
+    pub ending: EndingAccess<'a>,
+}
+/// デプスステンシルのパス記述
+#[derive(Clone)]
+pub struct RenderPassDepthStencil<'a> {
+    pub view: D3D12_CPU_DESCRIPTOR_HANDLE,
+    pub depth_beginning: BeginningAccess,
+    pub stencil_beginning: BeginningAccess,
+    pub depth_ending: EndingAccess<'a>,
+    pub stencil_ending: EndingAccess<'a>,
+}
+
+/// レンダーパス
+#[derive(Clone)]
+pub struct RenderPass<'a> {
+    pub render_targets: Vec<RenderPassRenderTarget<'a>>,
+    pub depth_stencil: Option<RenderPassDepthStencil<'a>>,
+    pub flags: D3D12_RENDER_PASS_FLAGS,
+}
+impl<'a> Default for RenderPass<'a> {
+    fn default() -> Self {
+        RenderPass {
+            render_targets: Vec::new(),
+            depth_stencil: None,
+            flags: D3D12_RENDER_PASS_FLAG_NONE,
+        }
+    }
+}
+impl<'a> RenderPass<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// レンダーターゲットを追加
+    pub fn render_target(
+        mut self,
+        view: D3D12_CPU_DESCRIPTOR_HANDLE,
+        beginning: BeginningAccess,
+        ending: EndingAccess<'a>,
+    ) -> Self {
+        self.render_targets.push(RenderPassRenderTarget {
+            view,
+            beginning,
+            ending,
+        });
+        self
+    }
+    /// デプスステンシルを設定
+    pub fn depth_stencil(
+        mut self,
+        view: D3D12_CPU_DESCRIPTOR_HANDLE,
+        depth: (BeginningAccess, EndingAccess<'a>),
+        stencil: (BeginningAccess, EndingAccess<'a>),
+    ) -> Self {
+        self.depth_stencil = Some(RenderPassDepthStencil {
+            view,
+            depth_beginning: depth.0,
+            stencil_beginning: stencil.0,
+            depth_ending: depth.1,
+            stencil_ending: stencil.1,
+        });
+        self
+    }
+    /// パス中のUAVへの書き込みを許可
+    pub fn allow_uav_writes(mut self) -> Self {
+        self.flags |= D3D12_RENDER_PASS_FLAG_ALLOW_UAV_WRITES;
+        self
+    }
+    /// このパスは後で再開される(終了時の処理を行わない)
+    pub fn suspending(mut self) -> Self {
+        self.flags |= D3D12_RENDER_PASS_FLAG_SUSPENDING_PASS;
+        self
+    }
+    /// 中断したパスを再開する(開始時の処理を行わない)
+    pub fn resuming(mut self) -> Self {
+        self.flags |= D3D12_RENDER_PASS_FLAG_RESUMING_PASS;
+        self
+    }
+
+    /// 互換パスでの開始時の処理
+    /// Discardは内容を保持しても正しいので何もしない(ビューからは元のリソースを辿れないため)
+    pub fn emulated_begin(&self) -> Vec<EmulatedPassOp<'_, 'a>> {
+        let mut ops = Vec::new();
+        if self.flags & D3D12_RENDER_PASS_FLAG_RESUMING_PASS == 0 {
+            for rt in &self.render_targets {
+                if let BeginningAccess::Clear(OptimizedClearValue::Color(_, r, g, b, a)) =
+                    rt.beginning
+                {
+                    ops.push(EmulatedPassOp::ClearRenderTarget {
+                        view: rt.view,
+                        color: [r, g, b, a],
+                    });
+                }
+            }
+            if let Some(ref ds) = self.depth_stencil {
+                let depth = match ds.depth_beginning {
+                    BeginningAccess::Clear(OptimizedClearValue::DepthStencil(_, d, _)) => Some(d),
+                    _ => None,
+                };
+                let stencil = match ds.stencil_beginning {
+                    BeginningAccess::Clear(OptimizedClearValue::DepthStencil(_, _, s)) => Some(s),
+                    _ => None,
+                };
+                if depth.is_some() || stencil.is_some() {
+                    ops.push(EmulatedPassOp::ClearDepthStencil {
+                        view: ds.view,
+                        depth,
+                        stencil,
+                    });
+                }
+            }
+        }
+        let depth_stencil = self.depth_stencil.as_ref().and_then(|ds| {
+            let unused = matches!(ds.depth_beginning, BeginningAccess::NoAccess)
+                && matches!(ds.stencil_beginning, BeginningAccess::NoAccess);
+            if unused {
+                None
+            } else {
+                Some(ds.view)
+            }
+        });
+        ops.push(EmulatedPassOp::SetRenderTargets {
+            views: self.render_targets.iter().map(|rt| rt.view).collect(),
+            depth_stencil,
+        });
+
+        ops
+    }
+    /// 互換パスでの終了時の処理
+    /// Discardは開始時と同じく何もしない
+    pub fn emulated_end(&self) -> Vec<EmulatedPassOp<'_, 'a>> {
+        let mut ops = Vec::new();
+        if self.flags & D3D12_RENDER_PASS_FLAG_SUSPENDING_PASS != 0 {
+            return ops;
+        }
+        for rt in &self.render_targets {
+            if let EndingAccess::Resolve(ref p) = rt.ending {
+                ops.push(EmulatedPassOp::Resolve {
+                    params: p,
+                    source_state: ResourceState::RenderTarget,
+                });
+            }
+        }
+        if let Some(ref ds) = self.depth_stencil {
+            for e in &[&ds.depth_ending, &ds.stencil_ending] {
+                if let EndingAccess::Resolve(ref p) = e {
+                    ops.push(EmulatedPassOp::Resolve {
+                        params: p,
+                        source_state: ResourceState::DepthWrite,
+                    });
+                }
+            }
+        }
+
+        ops
+    }
+}
+
+/// 互換パスで発行されるコマンド
+#[derive(Clone)]
+pub enum EmulatedPassOp<'p, 'a> {
+    ClearRenderTarget {
+        view: D3D12_CPU_DESCRIPTOR_HANDLE,
+        color: [f32; 4],
+    },
+    ClearDepthStencil {
+        view: D3D12_CPU_DESCRIPTOR_HANDLE,
+        depth: Option<f32>,
+        stencil: Option<u8>,
+    },
+    SetRenderTargets {
+        views: Vec<D3D12_CPU_DESCRIPTOR_HANDLE>,
+        depth_stencil: Option<D3D12_CPU_DESCRIPTOR_HANDLE>,
+    },
+    /// sourceをsource_stateからResolveSourceに遷移してリゾルブし、元に戻す
+    Resolve {
+        params: &'p ResolveParameters<'a>,
+        source_state: ResourceState,
+    },
+}
+
+impl GraphicsCommandList {
+    /// レンダーパスAPI(ID3D12GraphicsCommandList4)が使えるか
+    pub fn supports_render_pass(&self) -> bool {
+        query_ext::<ID3D12GraphicsCommandList4>(self.0 as _).is_some()
+    }
+
+    /// レンダーパスの開始(使えない場合は互換パスで代替する)
+    pub fn begin_render_pass(&mut self, pass: &RenderPass) -> IOResult<&mut Self> {
+        let list4 = match query_ext::<ID3D12GraphicsCommandList4>(self.0 as _) {
+            Some(l) => l,
+            None => return self.begin_render_pass_emulated(pass),
+        };
+        let rts = pass
+            .render_targets
+            .iter()
+            .map(|rt| D3D12_RENDER_PASS_RENDER_TARGET_DESC {
+                cpuDescriptor: rt.view,
+                BeginningAccess: rt.beginning.to_desc(),
+                EndingAccess: rt.ending.to_desc(),
+            })
+            .collect::<Vec<_>>();
+        let ds = pass
+            .depth_stencil
+            .as_ref()
+            .map(|ds| D3D12_RENDER_PASS_DEPTH_STENCIL_DESC {
+                cpuDescriptor: ds.view,
+                DepthBeginningAccess: ds.depth_beginning.to_desc(),
+                StencilBeginningAccess: ds.stencil_beginning.to_desc(),
+                DepthEndingAccess: ds.depth_ending.to_desc(),
+                StencilEndingAccess: ds.stencil_ending.to_desc(),
+            });
+        unsafe {
+            (*list4.0).BeginRenderPass(
+                rts.len() as _,
+                rts.as_ptr(),
+                ds.as_ref().map_or(std::ptr::null(), |p| p as _),
+                pass.flags,
+            );
+        }
+
+        Ok(self)
+    }
+    /// レンダーパスの終了(begin_render_passと同じパスを渡す)
+    pub fn end_render_pass(&mut self, pass: &RenderPass) -> IOResult<&mut Self> {
+        match query_ext::<ID3D12GraphicsCommandList4>(self.0 as _) {
+            Some(list4) => {
+                unsafe { (*list4.0).EndRenderPass() };
+                Ok(self)
+            }
+            None => self.end_render_pass_emulated(pass),
+        }
+    }
+
+    /// レンダーパスの開始を従来のコマンドで代替する
+    pub fn begin_render_pass_emulated(&mut self, pass: &RenderPass) -> IOResult<&mut Self> {
+        for op in pass.emulated_begin() {
+            self.record_pass_op(op)?;
+        }
+        Ok(self)
+    }
+    /// レンダーパスの終了を従来のコマンドで代替する
+    pub fn end_render_pass_emulated(&mut self, pass: &RenderPass) -> IOResult<&mut Self> {
+        for op in pass.emulated_end() {
+            self.record_pass_op(op)?;
+        }
+        Ok(self)
+    }
+
+    fn record_pass_op(&mut self, op: EmulatedPassOp) -> IOResult<()> {
+        match op {
+            EmulatedPassOp::ClearRenderTarget { view, color } => {
+                self.clear_render_target_view(view, &color);
+            }
+            EmulatedPassOp::ClearDepthStencil {
+                view,
+                depth,
+                stencil,
+            } => {
+                self.clear_depth_stencil_view(view, depth, stencil);
+            }
+            EmulatedPassOp::SetRenderTargets {
+                views,
+                depth_stencil,
+            } => {
+                self.set_render_targets(&views, depth_stencil);
+            }
+            EmulatedPassOp::Resolve {
+                params,
+                source_state,
+            } => {
+                // 範囲指定やAverage以外のリゾルブにはID3D12GraphicsCommandList1が必要
+                let list1 = query_ext::<ID3D12GraphicsCommandList1>(self.0 as _);
+                if list1.is_none() && params.mode != ResolveMode::Average {
+                    return Err(IOError::other(
+                        "resolve modes other than Average require ID3D12GraphicsCommandList1",
+                    ));
+                }
+                for sr in &params.subresources {
+                    self.resource_barrier(&[ResourceBarrier::transition_subresource(
+                        params.source,
+                        sr.0.SrcSubresource,
+                        source_state as _,
+                        ResourceState::ResolveSource as _,
+                    )]);
+                    match list1 {
+                        Some(ref l) => unsafe {
+                            let mut rect = sr.0.SrcRect;
+                            (*l.0).ResolveSubresourceRegion(
+                                params.dest.0,
+                                sr.0.DstSubresource,
+                                sr.0.DstX,
+                                sr.0.DstY,
+                                params.source.0,
+                                sr.0.SrcSubresource,
+                                &mut rect,
+                                params.format,
+                                params.mode as _,
+                            );
+                        },
+                        None => unsafe {
+                            (*self.0).ResolveSubresource(
+                                params.dest.0,
+                                sr.0.DstSubresource,
+                                params.source.0,
+                                sr.0.SrcSubresource,
+                                params.format,
+                            );
+                        },
+                    }
+                    self.resource_barrier(&[ResourceBarrier::transition_subresource(
+                        params.source,
+                        sr.0.SrcSubresource,
+                        ResourceState::ResolveSource as _,
+                        source_state as _,
+                    )]);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn view(n: usize) -> D3D12_CPU_DESCRIPTOR_HANDLE {
+        D3D12_CPU_DESCRIPTOR_HANDLE { ptr: n }
+    }
+    fn color(r: f32) -> BeginningAccess {
+        BeginningAccess::Clear(OptimizedClearValue::Color(
+            DXGI_FORMAT_R8G8B8A8_UNORM,
+            r,
+            0.0,
+            0.0,
+            1.0,
+        ))
+    }
+    fn depth_stencil(d: f32, s: u8) -> BeginningAccess {
+        BeginningAccess::Clear(OptimizedClearValue::DepthStencil(
+            DXGI_FORMAT_D24_UNORM_S8_UINT,
+            d,
+            s,
+        ))
+    }
+    fn resolve<'a>(source: &'a Resource, dest: &'a Resource) -> EndingAccess<'a> {
+        EndingAccess::Resolve(ResolveParameters {
+            source,
+            dest,
+            subresources: vec![ResolveSubresource::whole(0, 0, 4, 4)],
+            format: DXGI_FORMAT_R8G8B8A8_UNORM,
+            mode: ResolveMode::Average,
+            preserve_source: false,
+        })
+    }
+    fn set_render_targets(op: &EmulatedPassOp) -> (Vec<usize>, Option<usize>) {
+        match op {
+            EmulatedPassOp::SetRenderTargets {
+                views,
+                depth_stencil,
+            } => (
+                views.iter().map(|v| v.ptr).collect(),
+                depth_stencil.map(|v| v.ptr),
+            ),
+            _ => panic!("expected SetRenderTargets"),
+        }
+    }
+
+    #[test]
+    fn begin_clears_then_binds_targets() {
+        let pass = RenderPass::new()
+            .render_target(view(1), color(0.5), EndingAccess::Preserve)
+            .render_target(view(2), BeginningAccess::Preserve, EndingAccess::Preserve)
+            .render_target(view(3), BeginningAccess::Discard, EndingAccess::Discard)
+            .depth_stencil(
+                view(9),
+                (depth_stencil(1.0, 0), EndingAccess::Discard),
+                (BeginningAccess::Preserve, EndingAccess::Preserve),
+            );
+        let ops = pass.emulated_begin();
+        assert_eq!(ops.len(), 3);
+        match ops[0] {
+            EmulatedPassOp::ClearRenderTarget { view, color } => {
+                assert_eq!(view.ptr, 1);
+                assert_eq!(color, [0.5, 0.0, 0.0, 1.0]);
+            }
+            _ => panic!("expected ClearRenderTarget"),
+        }
+        match ops[1] {
+            EmulatedPassOp::ClearDepthStencil {
+                view,
+                depth,
+                stencil,
+            } => assert_eq!((view.ptr, depth, stencil), (9, Some(1.0), None)),
+            _ => panic!("expected ClearDepthStencil"),
+        }
+        assert_eq!(set_render_targets(&ops[2]), (vec![1, 2, 3], Some(9)));
+        // Preserve/Discardだけなら何も発行しない
+        assert!(pass.emulated_end().is_empty());
+    }
+
+    #[test]
+    fn stencil_only_clear_and_unused_depth_stencil() {
+        let pass = RenderPass::new().depth_stencil(
+            view(9),
+            (BeginningAccess::Preserve, EndingAccess::Preserve),
+            (depth_stencil(0.0, 7), EndingAccess::Preserve),
+        );
+        let ops = pass.emulated_begin();
+        match ops[0] {
+            EmulatedPassOp::ClearDepthStencil { depth, stencil, .. } => {
+                assert_eq!((depth, stencil), (None, Some(7)))
+            }
+            _ => panic!("expected ClearDepthStencil"),
+        }
+        assert_eq!(set_render_targets(&ops[1]), (vec![], Some(9)));
+
+        let pass = RenderPass::new()
+            .render_target(view(1), BeginningAccess::Preserve, EndingAccess::Preserve)
+            .depth_stencil(
+                view(9),
+                (BeginningAccess::NoAccess, EndingAccess::NoAccess),
+                (BeginningAccess::NoAccess, EndingAccess::NoAccess),
+            );
+        let ops = pass.emulated_begin();
+        assert_eq!(ops.len(), 1);
+        assert_eq!(set_render_targets(&ops[0]), (vec![1], None));
+    }
+
+    #[test]
+    fn end_resolves_color_then_depth_stencil() {
+        let (msaa, single, depth, depth_single) = (
+            Resource(std::ptr::null_mut()),
+            Resource(std::ptr::null_mut()),
+            Resource(std::ptr::null_mut()),
+            Resource(std::ptr::null_mut()),
+        );
+        let pass = RenderPass::new()
+            .render_target(view(1), color(0.0), resolve(&msaa, &single))
+            .render_target(view(2), BeginningAccess::Preserve, EndingAccess::Preserve)
+            .depth_stencil(
+                view(9),
+                (depth_stencil(1.0, 0), resolve(&depth, &depth_single)),
+                (BeginningAccess::NoAccess, EndingAccess::NoAccess),
+            );
+        let ops = pass.emulated_end();
+        assert_eq!(ops.len(), 2);
+        match ops[0] {
+            EmulatedPassOp::Resolve {
+                params,
+                source_state,
+            } => {
+                assert!(std::ptr::eq(params.source, &msaa));
+                assert!(std::ptr::eq(params.dest, &single));
+                assert_eq!(source_state, ResourceState::RenderTarget);
+            }
+            _ => panic!("expected Resolve"),
+        }
+        match ops[1] {
+            EmulatedPassOp::Resolve {
+                params,
+                source_state,
+            } => {
+                assert!(std::ptr::eq(params.source, &depth));
+                assert_eq!(source_state, ResourceState::DepthWrite);
+            }
+            _ => panic!("expected Resolve"),
+        }
+    }
+
+    #[test]
+    fn suspending_and_resuming_skip_the_boundary_ops() {
+        let (msaa, single) = (
+            Resource(std::ptr::null_mut()),
+            Resource(std::ptr::null_mut()),
+        );
+        let pass = RenderPass::new()
+            .render_target(view(1), color(1.0), resolve(&msaa, &single))
+            .resuming()
+            .suspending();
+        let ops = pass.emulated_begin();
+        assert_eq!(ops.len(), 1);
+        assert_eq!(set_render_targets(&ops[0]), (vec![1], None));
+        assert!(pass.emulated_end().is_empty());
+    }
+}
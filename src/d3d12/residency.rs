@@ -0,0 +1,412 @@
+//! Residency Management
+
+use super::*;
+
+/// 常駐管理の対象の識別子
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ResidencyHandle(pub u32);
+
+#[derive(Debug, Clone, Copy)]
+struct ResidencyEntry {
+    size: u64,
+    resident: bool,
+    last_used: u64,
+}
+
+/// 常駐状態の変更内容
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ResidencyPlan {
+    pub make_resident: Vec<ResidencyHandle>,
+    pub evict: Vec<ResidencyHandle>,
+}
+impl ResidencyPlan {
+    pub fn is_empty(&self) -> bool {
+        self.make_resident.is_empty() && self.evict.is_empty()
+    }
+}
+
+/// 予算に収まるように常駐させるものを決める(最も長く使われていないものから退避する)
+///
+/// 退避の候補はframes_in_flightフレーム以上使われていないものに限る(GPUが使用中のものは退避しない)
+pub struct ResidencyPolicy {
+    entries: Vec<Option<ResidencyEntry>>,
+    free: Vec<u32>,
+    frame: u64,
+    frames_in_flight: u64,
+    resident_bytes: u64,
+}
+impl ResidencyPolicy {
+    pub fn new(frames_in_flight: u64) -> Self {
+        ResidencyPolicy {
+            entries: Vec::new(),
+            free: Vec::new(),
+            frame: 0,
+            frames_in_flight: frames_in_flight.max(1),
+            resident_bytes: 0,
+        }
+    }
+
+    /// 管理対象を追加する(作成直後のオブジェクトは常駐している)
+    pub fn track(&mut self, size: u64) -> ResidencyHandle {
+        let e = ResidencyEntry {
+            size,
+            resident: true,
+            last_used: self.frame,
+        };
+        self.resident_bytes += size;
+        match self.free.pop() {
+            Some(n) => {
+                self.entries[n as usize] = Some(e);
+                ResidencyHandle(n)
+            }
+            None => {
+                self.entries.push(Some(e));
+                ResidencyHandle(self.entries.len() as u32 - 1)
+            }
+        }
+    }
+    /// 管理対象から外す
+    pub fn untrack(&mut self, handle: ResidencyHandle) -> bool {
+        match self
+            .entries
+            .get_mut(handle.0 as usize)
+            .and_then(Option::take)
+        {
+            Some(e) => {
+                if e.resident {
+                    self.resident_bytes -= e.size;
+                }
+                self.free.push(handle.0);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// 次のフレームに進める
+    pub fn begin_frame(&mut self) {
+        self.frame += 1;
+    }
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+    /// 現在のフレームで使うことを記録する
+    pub fn mark_used(&mut self, handle: ResidencyHandle) {
+        if let Some(Some(e)) = self.entries.get_mut(handle.0 as usize) {
+            e.last_used = self.frame;
+        }
+    }
+
+    pub fn is_resident(&self, handle: ResidencyHandle) -> Option<bool> {
+        self.entries
+            .get(handle.0 as usize)
+            .and_then(|e| e.as_ref())
+            .map(|e| e.resident)
+    }
+    pub fn size(&self, handle: ResidencyHandle) -> Option<u64> {
+        self.entries
+            .get(handle.0 as usize)
+            .and_then(|e| e.as_ref())
+            .map(|e| e.size)
+    }
+    /// 常駐している管理対象の合計サイズ
+    pub fn resident_bytes(&self) -> u64 {
+        self.resident_bytes
+    }
+
+    /// 現在のフレームで使うものを常駐させ、予算を超えるぶんを古いものから退避させる
+    /// (返した内容は実行されたものとして状態を更新する)
+    ///
+    /// 使用中のものだけで予算を超える場合は予算を超えたままになる
+    pub fn plan(&mut self, budget: u64) -> ResidencyPlan {
+        let mut plan = ResidencyPlan::default();
+        let mut candidates = Vec::new();
+        for (n, e) in self.entries.iter_mut().enumerate() {
+            let e = match e {
+                Some(e) => e,
+                None => continue,
+            };
+            if e.last_used == self.frame && !e.resident {
+                e.resident = true;
+                self.resident_bytes += e.size;
+                plan.make_resident.push(ResidencyHandle(n as _));
+            } else if e.resident && e.last_used + self.frames_in_flight <= self.frame {
+                candidates.push((e.last_used, n));
+            }
+        }
+
+        candidates.sort_unstable();
+        for (_, n) in candidates {
+            if self.resident_bytes <= budget {
+                break;
+            }
+            let e = self.entries[n].as_mut().expect("candidate must be tracked");
+            e.resident = false;
+            self.resident_bytes -= e.size;
+            plan.evict.push(ResidencyHandle(n as _));
+        }
+
+        plan
+    }
+}
+
+/// QueryVideoMemoryInfoの結果から管理対象に使える予算を求める(管理対象外の使用量を差し引く)
+pub fn residency_budget(info: &dxgi::VideoMemoryInfo, tracked_resident_bytes: u64) -> u64 {
+    let untracked = info.CurrentUsage.saturating_sub(tracked_resident_bytes);
+    info.Budget.saturating_sub(untracked)
+}
+
+/// リソースとヒープの常駐管理
+pub struct ResidencyManager {
+    device: Device,
+    adapter: dxgi::Adapter,
+    policy: ResidencyPolicy,
+    objects: Vec<Option<ComPtr<ID3D12Pageable>>>,
+    budget_override: Option<u64>,
+}
+unsafe impl Send for ResidencyManager {}
+impl Device {
+    /// 常駐管理の作成(予算はadapterのローカルメモリから取得する)
+    pub fn new_residency_manager(
+        &self,
+        adapter: &dxgi::Adapter,
+        frames_in_flight: u64,
+    ) -> ResidencyManager {
+        ResidencyManager {
+            device: self.clone(),
+            adapter: adapter.clone(),
+            policy: ResidencyPolicy::new(frames_in_flight),
+            objects: Vec::new(),
+            budget_override: None,
+        }
+    }
+}
+impl ResidencyManager {
+    fn track(&mut self, object: *mut ID3D12Pageable, size: u64) -> ResidencyHandle {
+        unsafe { (*object).AddRef() };
+        let handle = self.policy.track(size);
+        let n = handle.0 as usize;
+        if self.objects.len() <= n {
+            self.objects.resize_with(n + 1, || None);
+        }
+        self.objects[n] = Some(ComPtr(object));
+        handle
+    }
+    /// コミット済みリソースを管理対象にする(配置リソースはヒープをtrack_heapで管理する)
+    ///
+    /// # Safety
+    /// resourceはコミット済みリソースであること(配置リソースは単独で退避/常駐させられない)
+    pub unsafe fn track_resource(&mut self, resource: &Resource) -> IOResult<ResidencyHandle> {
+        // 予約済みリソースはヒープを持たないので取得に失敗する
+        let mut props = std::mem::zeroed();
+        let mut flags = 0;
+        if (*resource.0).GetHeapProperties(&mut props, &mut flags) < 0 {
+            return invalid(
+                "reserved resources cannot be tracked (track the heaps of their tiles instead)"
+                    .to_owned(),
+            );
+        }
+        let info = self.device.get_resource_allocation_info(&[resource.desc()]);
+        Ok(self.track(resource.0 as _, info.SizeInBytes))
+    }
+    /// ヒープを管理対象にする
+    pub fn track_heap(&mut self, heap: &Heap) -> ResidencyHandle {
+        let size = unsafe { (*heap.0).GetDesc() }.SizeInBytes;
+        self.track(heap.0 as _, size)
+    }
+    /// 管理対象から外す
+    pub fn untrack(&mut self, handle: ResidencyHandle) {
+        if self.policy.untrack(handle) {
+            self.objects[handle.0 as usize] = None;
+        }
+    }
+
+    pub fn policy(&self) -> &ResidencyPolicy {
+        &self.policy
+    }
+    /// 管理対象のサイズ
+    pub fn size(&self, handle: ResidencyHandle) -> IOResult<u64> {
+        match self.policy.size(handle) {
+            Some(size) => Ok(size),
+            None => invalid(format!("residency handle {} is not tracked", handle.0)),
+        }
+    }
+    /// 予算を固定する(Noneでアダプタの予算に戻す)
+    pub fn set_budget_override(&mut self, budget: Option<u64>) {
+        self.budget_override = budget;
+    }
+    /// 管理対象に使える予算
+    pub fn budget(&self) -> IOResult<u64> {
+        if let Some(b) = self.budget_override {
+            return Ok(b);
+        }
+        let info = self
+            .adapter
+            .query_video_memory_info(0, dxgi::MemorySegmentGroup::Local)?;
+        Ok(residency_budget(&info, self.policy.resident_bytes()))
+    }
+
+    /// 次のフレームに進める
+    pub fn begin_frame(&mut self) {
+        self.policy.begin_frame();
+    }
+    /// 現在のフレームで使うことを記録する
+    pub fn mark_used(&mut self, handle: ResidencyHandle) {
+        self.policy.mark_used(handle);
+    }
+
+    fn pageables(&self, handles: &[ResidencyHandle]) -> Vec<*mut ID3D12Pageable> {
+        handles
+            .iter()
+            .filter_map(|h| self.objects[h.0 as usize].as_ref().map(|p| p.0))
+            .collect()
+    }
+    fn plan_and_evict(&mut self) -> IOResult<(ResidencyPlan, Vec<*mut ID3D12Pageable>)> {
+        let plan = self.policy.plan(self.budget()?);
+        let mut evict = self.pageables(&plan.evict);
+        if !evict.is_empty() {
+            unsafe { (*self.device.0).Evict(evict.len() as _, evict.as_mut_ptr()) }.checked()?;
+        }
+        let resident = self.pageables(&plan.make_resident);
+
+        Ok((plan, resident))
+    }
+    /// 予算に合わせて退避/常駐させる(常駐が完了するまで戻らない)
+    pub fn update(&mut self) -> IOResult<ResidencyPlan> {
+        let (plan, mut resident) = self.plan_and_evict()?;
+        if !resident.is_empty() {
+            unsafe { (*self.device.0).MakeResident(resident.len() as _, resident.as_mut_ptr()) }
+                .checked()?;
+        }
+
+        Ok(plan)
+    }
+    /// 予算に合わせて退避/常駐させる(常駐の完了時にfenceをvalueにする。キューはfenceを待ってから実行すること)
+    ///
+    /// EnqueueMakeResidentが使えない場合は常駐を待ってからfenceをシグナルする
+    pub fn update_enqueued(&mut self, fence: &mut Fence, value: u64) -> IOResult<ResidencyPlan> {
+        let (plan, mut resident) = self.plan_and_evict()?;
+        if resident.is_empty() {
+            fence.signal(value)?;
+            return Ok(plan);
+        }
+        match query_ext::<ID3D12Device3>(self.device.0 as _) {
+            Some(d3) => unsafe {
+                (*d3.0).EnqueueMakeResident(
+                    D3D12_RESIDENCY_FLAG_NONE,
+                    resident.len() as _,
+                    resident.as_ptr(),
+                    fence.0,
+                    value,
+                )
+            }
+            .checked()?,
+            None => {
+                unsafe {
+                    (*self.device.0).MakeResident(resident.len() as _, resident.as_mut_ptr())
+                }
+                .checked()?;
+                fence.signal(value)?;
+            }
+        }
+
+        Ok(plan)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evicts_least_recently_used_outside_frames_in_flight() {
+        let mut p = ResidencyPolicy::new(2);
+        let a = p.track(100);
+        let b = p.track(200);
+        let c = p.track(300);
+        assert_eq!(p.resident_bytes(), 600);
+        assert!(p.plan(1000).is_empty());
+
+        // どれもまだGPUが使っているかもしれないので予算を超えたままになる
+        p.begin_frame();
+        p.mark_used(a);
+        assert!(p.plan(400).is_empty());
+        assert_eq!(p.resident_bytes(), 600);
+
+        p.begin_frame();
+        p.mark_used(a);
+        let plan = p.plan(400);
+        assert_eq!(plan.evict, vec![b]);
+        assert!(plan.make_resident.is_empty());
+        assert_eq!(p.resident_bytes(), 400);
+        assert_eq!(p.is_resident(b), Some(false));
+
+        // 退避したものを使うと常駐させ、代わりに古いものを退避する
+        p.begin_frame();
+        p.mark_used(a);
+        p.mark_used(b);
+        assert_eq!(
+            p.plan(400),
+            ResidencyPlan {
+                make_resident: vec![b],
+                evict: vec![c],
+            }
+        );
+        assert_eq!(p.resident_bytes(), 300);
+        assert_eq!(p.is_resident(c), Some(false));
+    }
+
+    #[test]
+    fn shrinking_budget_evicts_oldest_first() {
+        let mut p = ResidencyPolicy::new(1);
+        let handles = (0..4).map(|_| p.track(64)).collect::<Vec<_>>();
+        for (n, &h) in handles.iter().enumerate() {
+            for _ in 0..=n {
+                p.begin_frame();
+            }
+            p.mark_used(h);
+        }
+        p.begin_frame();
+
+        for (budget, evicted) in [
+            (256, vec![]),
+            (200, vec![handles[0]]),
+            (64, vec![handles[1], handles[2]]),
+        ] {
+            assert_eq!(p.plan(budget).evict, evicted);
+        }
+        assert_eq!(p.resident_bytes(), 64);
+        assert_eq!(p.is_resident(handles[3]), Some(true));
+        assert_eq!(p.plan(0).evict, vec![handles[3]]);
+        assert_eq!(p.resident_bytes(), 0);
+    }
+
+    #[test]
+    fn untracked_slots_are_reused() {
+        let mut p = ResidencyPolicy::new(2);
+        let a = p.track(100);
+        let b = p.track(50);
+        assert!(p.untrack(a));
+        assert!(!p.untrack(a));
+        assert_eq!(p.resident_bytes(), 50);
+        assert_eq!(p.size(a), None);
+        assert_eq!(p.is_resident(a), None);
+
+        let c = p.track(25);
+        assert_eq!(c, a);
+        assert_eq!(p.size(c), Some(25));
+        assert_eq!(p.size(b), Some(50));
+        assert_eq!(p.resident_bytes(), 75);
+    }
+
+    #[test]
+    fn budget_excludes_untracked_usage() {
+        let mut info: dxgi::VideoMemoryInfo = unsafe { std::mem::zeroed() };
+        info.Budget = 1000;
+        info.CurrentUsage = 700;
+        assert_eq!(residency_budget(&info, 500), 800);
+        assert_eq!(residency_budget(&info, 900), 1000);
+        info.CurrentUsage = 2500;
+        assert_eq!(residency_budget(&info, 500), 0);
+    }
+}
@@ -0,0 +1,720 @@
+//! Multi-Queue Scheduling
+
+use super::*;
+use std::collections::{HashMap, VecDeque};
+
+/// スケジューラ内のキューの識別子
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct QueueId(pub u32);
+
+/// 投入の完了を表すハンドル(キューのフェンスがvalueに達したら完了)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SubmissionHandle {
+    pub queue: QueueId,
+    pub value: u64,
+}
+
+/// スケジューラが扱うキュー
+pub trait SchedulerQueue {
+    fn kind(&self) -> CommandType;
+    fn execute(&mut self, lists: &[*mut ID3D12CommandList]) -> IOResult<()>;
+    /// このキューのフェンスをvalueにする
+    fn signal(&mut self, value: u64) -> IOResult<()>;
+    /// otherのフェンスがvalueに達するまでこのキューを待たせる
+    fn wait_queue(&mut self, other: &Self, value: u64) -> IOResult<()>;
+    fn completed_value(&self) -> u64;
+    /// このキューのフェンスがvalueに達するまでCPUで待つ
+    fn wait_host(&mut self, value: u64) -> IOResult<()>;
+}
+
+/// フェンスつきのコマンドキュー
+pub struct FencedQueue {
+    queue: CommandQueue,
+    fence: Fence,
+    kind: CommandType,
+}
+impl Device {
+    /// スケジューラで使うキューの作成
+    pub fn new_fenced_queue(&self, kind: CommandType, priority: i32) -> IOResult<FencedQueue> {
+        Ok(FencedQueue {
+            queue: self.new_command_queue(kind, priority)?,
+            fence: self.new_fence(0, D3D12_FENCE_FLAG_NONE)?,
+            kind,
+        })
+    }
+}
+impl FencedQueue {
+    pub fn queue(&self) -> &CommandQueue {
+        &self.queue
+    }
+    pub fn fence(&self) -> &Fence {
+        &self.fence
+    }
+}
+impl SchedulerQueue for FencedQueue {
+    fn kind(&self) -> CommandType {
+        self.kind
+    }
+    fn execute(&mut self, lists: &[*mut ID3D12CommandList]) -> IOResult<()> {
+        self.queue.execute(lists);
+        Ok(())
+    }
+    fn signal(&mut self, value: u64) -> IOResult<()> {
+        self.queue.signal(&self.fence, value).map(drop)
+    }
+    fn wait_queue(&mut self, other: &Self, value: u64) -> IOResult<()> {
+        self.queue.wait(&other.fence, value).map(drop)
+    }
+    fn completed_value(&self) -> u64 {
+        self.fence.completed_value()
+    }
+    fn wait_host(&mut self, value: u64) -> IOResult<()> {
+        self.fence.wait(value)
+    }
+}
+
+/// リソースの識別子(リソースのポインタ。スケジューラは参照を保持しないので、使い終わるまで生かしておくこと)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ResourceKey(usize);
+impl ResourceKey {
+    pub fn of(resource: &Resource) -> Self {
+        ResourceKey(resource.0 as _)
+    }
+}
+
+/// 書き込みを含む(または他の読み取りと並行できない)状態か
+fn is_write_state(state: ResourceState) -> bool {
+    matches!(
+        state,
+        ResourceState::Present
+            | ResourceState::RenderTarget
+            | ResourceState::UnorderedAccess
+            | ResourceState::DepthWrite
+            | ResourceState::StreamOut
+            | ResourceState::CopyDest
+            | ResourceState::ResolveDest
+    )
+}
+
+/// キューの種類ごとに使えるリソースの状態か
+pub fn queue_supports_state(kind: CommandType, state: ResourceState) -> bool {
+    match kind {
+        CommandType::Direct | CommandType::Bundle => true,
+        CommandType::Compute => matches!(
+            state,
+            ResourceState::Present
+                | ResourceState::VertexAndConstantBuffer
+                | ResourceState::UnorderedAccess
+                | ResourceState::NonPixelShaderResource
+                | ResourceState::IndirectArgument
+                | ResourceState::CopyDest
+                | ResourceState::CopySource
+        ),
+        CommandType::Copy => matches!(
+            state,
+            ResourceState::Present | ResourceState::CopyDest | ResourceState::CopySource
+        ),
+    }
+}
+
+/// キューをまたいで使うときのリソースの状態遷移
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OwnershipTransfer {
+    pub resource: ResourceKey,
+    pub before: ResourceState,
+    pub after: ResourceState,
+}
+impl OwnershipTransfer {
+    /// 全サブリソースのトランジションバリア
+    pub fn barrier(&self) -> ResourceBarrier {
+        let mut b = D3D12_RESOURCE_BARRIER {
+            Type: D3D12_RESOURCE_BARRIER_TYPE_TRANSITION,
+            Flags: D3D12_RESOURCE_BARRIER_FLAG_NONE,
+            u: unsafe { std::mem::zeroed() },
+        };
+        unsafe {
+            *b.u.Transition_mut() = D3D12_RESOURCE_TRANSITION_BARRIER {
+                pResource: self.resource.0 as _,
+                Subresource: D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES,
+                StateBefore: self.before as _,
+                StateAfter: self.after as _,
+            };
+        }
+        ResourceBarrier(b)
+    }
+}
+
+/// 投入の宣言(依存する投入と使うリソース)
+///
+/// リソースの状態は投入中の状態で、コマンドリストはその状態のまま終えること
+#[derive(Debug, Clone, Default)]
+pub struct Submission {
+    dependencies: Vec<SubmissionHandle>,
+    accesses: Vec<(ResourceKey, ResourceState)>,
+}
+impl Submission {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// handleの完了後に実行する
+    pub fn after(mut self, handle: SubmissionHandle) -> Self {
+        self.dependencies.push(handle);
+        self
+    }
+    /// stateの状態でリソースを使う
+    pub fn access(self, resource: &Resource, state: ResourceState) -> Self {
+        self.access_key(ResourceKey::of(resource), state)
+    }
+    pub fn access_key(mut self, resource: ResourceKey, state: ResourceState) -> Self {
+        self.accesses.push((resource, state));
+        self
+    }
+}
+
+/// リソースを最後に使ったキューと状態
+struct ResourceUse {
+    queue: QueueId,
+    state: ResourceState,
+    /// キューごとの最後に書き込んだ(または状態を変えた)投入のフェンス値(0は未使用)
+    written: Vec<u64>,
+    /// それ以降に同じ状態で読んだ投入も含めたフェンス値
+    used: Vec<u64>,
+}
+
+/// キュー間の同期を自動で入れる投入スケジューラ
+///
+/// 各キューがどの投入まで待ったかを記録し(ベクタークロック)、既に待ったものや
+/// 他の待ちから推移的に保証されるもの、完了済みのものへの待ちは入れない
+pub struct QueueScheduler<Q: SchedulerQueue> {
+    queues: Vec<Q>,
+    /// clocks[q][p]: キューqが待ったキューpのフェンス値(clocks[q][q]はqの最新の値)
+    clocks: Vec<Vec<u64>>,
+    /// 未完了の投入の時点でのクロック
+    history: Vec<VecDeque<(u64, Vec<u64>)>>,
+    resources: HashMap<ResourceKey, ResourceUse>,
+}
+impl<Q: SchedulerQueue> QueueScheduler<Q> {
+    pub fn new(queues: Vec<Q>) -> Self {
+        let n = queues.len();
+        QueueScheduler {
+            queues,
+            clocks: vec![vec![0; n]; n],
+            history: vec![VecDeque::new(); n],
+            resources: HashMap::new(),
+        }
+    }
+
+    pub fn queue(&self, id: QueueId) -> &Q {
+        &self.queues[id.0 as usize]
+    }
+    pub fn queue_mut(&mut self, id: QueueId) -> &mut Q {
+        &mut self.queues[id.0 as usize]
+    }
+    /// キューに最後に投入したもの
+    pub fn last_submission(&self, id: QueueId) -> SubmissionHandle {
+        let q = id.0 as usize;
+        SubmissionHandle {
+            queue: id,
+            value: self.clocks[q][q],
+        }
+    }
+
+    /// 完了済みか
+    pub fn is_complete(&self, handle: SubmissionHandle) -> bool {
+        self.queue(handle.queue).completed_value() >= handle.value
+    }
+    /// 完了するまでCPUで待つ
+    pub fn wait(&mut self, handle: SubmissionHandle) -> IOResult<()> {
+        self.queue_mut(handle.queue).wait_host(handle.value)
+    }
+    /// すべてのキューの完了を待つ
+    pub fn wait_idle(&mut self) -> IOResult<()> {
+        for q in 0..self.queues.len() {
+            let v = self.clocks[q][q];
+            self.queues[q].wait_host(v)?;
+        }
+        self.retire();
+        Ok(())
+    }
+
+    /// 完了した投入の記録を捨てる
+    fn retire(&mut self) {
+        for (q, h) in self.history.iter_mut().enumerate() {
+            let completed = self.queues[q].completed_value();
+            while h.front().is_some_and(|&(v, _)| v <= completed) {
+                h.pop_front();
+            }
+        }
+    }
+    fn snapshot(&self, queue: usize, value: u64) -> Option<&[u64]> {
+        let h = &self.history[queue];
+        h.binary_search_by_key(&value, |&(v, _)| v)
+            .ok()
+            .map(|n| &h[n].1[..])
+    }
+    fn signal(&mut self, q: usize) -> IOResult<u64> {
+        let value = self.clocks[q][q] + 1;
+        self.queues[q].signal(value)?;
+        self.clocks[q][q] = value;
+        self.history[q].push_back((value, self.clocks[q].clone()));
+        Ok(value)
+    }
+    fn wait_queue(&mut self, q: usize, p: usize, value: u64) -> IOResult<()> {
+        let (waiter, target) = if q < p {
+            let (a, b) = self.queues.split_at_mut(p);
+            (&mut a[q], &b[0])
+        } else {
+            let (a, b) = self.queues.split_at_mut(q);
+            (&mut b[0], &a[p])
+        };
+        waiter.wait_queue(target, value)
+    }
+
+    /// キューqがneedに挙げたフェンス値を待つ必要のあるものだけを返す
+    fn required_waits(&self, q: usize, need: &[u64]) -> Vec<(usize, u64)> {
+        let candidates = need
+            .iter()
+            .enumerate()
+            .filter(|&(p, &v)| {
+                p != q && v > self.clocks[q][p] && v > self.queues[p].completed_value()
+            })
+            .map(|(p, &v)| (p, v))
+            .collect::<Vec<_>>();
+
+        candidates
+            .iter()
+            .filter(|&&(p, v)| {
+                !candidates
+                    .iter()
+                    .any(|&(p2, v2)| p2 != p && self.snapshot(p2, v2).is_some_and(|c| c[p] >= v))
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// キューqにneedのフェンス値を待たせる(必要なものだけ)
+    fn wait_for(&mut self, q: usize, need: &[u64]) -> IOResult<()> {
+        for (p, v) in self.required_waits(q, need) {
+            self.wait_queue(q, p, v)?;
+            if let Some(c) = self.snapshot(p, v).map(|c| c.to_vec()) {
+                for (k, x) in c.into_iter().enumerate() {
+                    self.clocks[q][k] = self.clocks[q][k].max(x);
+                }
+            }
+            self.clocks[q][p] = self.clocks[q][p].max(v);
+        }
+        Ok(())
+    }
+
+    /// コマンドリストを投入する
+    ///
+    /// 同じ状態での読み取りどうしは待ち合わせず、書き込みや状態の変更はそれまでのすべての使用を待つ
+    ///
+    /// 別の種類のキューから移ってきたリソースの状態遷移が必要なときは
+    /// record_transfer(キュー, キューの種類, バリア)でバリアだけを記録したコマンドリストを作らせて実行する
+    /// (作ったコマンドリストは実行完了まで保持すること)
+    pub fn submit<R>(
+        &mut self,
+        queue: QueueId,
+        lists: &[*mut ID3D12CommandList],
+        submission: &Submission,
+        mut record_transfer: R,
+    ) -> IOResult<SubmissionHandle>
+    where
+        R: FnMut(QueueId, CommandType, &[ResourceBarrier]) -> IOResult<*mut ID3D12CommandList>,
+    {
+        let n = self.queues.len();
+        let q = queue.0 as usize;
+        if q >= n {
+            return invalid(format!("queue {} is not registered", q));
+        }
+        self.retire();
+
+        let mut need = vec![0u64; n];
+        for d in &submission.dependencies {
+            let p = d.queue.0 as usize;
+            if p >= n || d.value > self.clocks[p][p] {
+                return invalid(format!("dependency {:?} has not been submitted", d));
+            }
+            need[p] = need[p].max(d.value);
+        }
+
+        let kind = self.queues[q].kind();
+        let mut pre_barriers = Vec::new();
+        let mut shared_reads = Vec::with_capacity(submission.accesses.len());
+        for &(key, state) in &submission.accesses {
+            let (last_queue, last_state, used) = match self.resources.get(&key) {
+                Some(u) => (u.queue, u.state, u.used.clone()),
+                None => {
+                    shared_reads.push(!is_write_state(state));
+                    continue;
+                }
+            };
+            let p = last_queue.0 as usize;
+            let same_state = last_state == state && (p == q || queue_supports_state(kind, state));
+            let shared = same_state && !is_write_state(state);
+            shared_reads.push(shared);
+            let after = if shared {
+                &self.resources[&key].written
+            } else {
+                &used
+            };
+            for (p, &v) in after.iter().enumerate() {
+                need[p] = need[p].max(v);
+            }
+            if p == q || same_state {
+                continue;
+            }
+
+            let transfer = |before, after| OwnershipTransfer {
+                resource: key,
+                before,
+                after,
+            };
+            let p_kind = self.queues[p].kind();
+            if queue_supports_state(kind, last_state) {
+                pre_barriers.push(transfer(last_state, state).barrier());
+                continue;
+            }
+            // 移る先のキューで扱えない状態なので、元のキューで遷移させる
+            let (release, acquire) = if queue_supports_state(p_kind, state) {
+                (transfer(last_state, state), None)
+            } else {
+                (
+                    transfer(last_state, ResourceState::Present),
+                    Some(transfer(ResourceState::Present, state)),
+                )
+            };
+            // 元のキューでの遷移は他のキューでの使用が終わってから
+            self.wait_for(p, &used)?;
+            let list = record_transfer(last_queue, p_kind, &[release.barrier()])?;
+            self.queues[p].execute(&[list])?;
+            need[p] = self.signal(p)?;
+            if let Some(a) = acquire.filter(|a| a.before != a.after) {
+                pre_barriers.push(a.barrier());
+            }
+        }
+
+        self.wait_for(q, &need)?;
+
+        if pre_barriers.is_empty() {
+            self.queues[q].execute(lists)?;
+        } else {
+            let mut all = Vec::with_capacity(lists.len() + 1);
+            all.push(record_transfer(queue, kind, &pre_barriers)?);
+            all.extend_from_slice(lists);
+            self.queues[q].execute(&all)?;
+        }
+        let value = self.signal(q)?;
+
+        for (&(key, state), shared) in submission.accesses.iter().zip(shared_reads) {
+            let u = self.resources.entry(key).or_insert_with(|| ResourceUse {
+                queue,
+                state,
+                written: vec![0; n],
+                used: vec![0; n],
+            });
+            if !shared {
+                // この投入はそれまでの使用をすべて待っている
+                u.written.iter_mut().for_each(|v| *v = 0);
+                u.written[q] = value;
+                u.used.clone_from(&u.written);
+            }
+            u.queue = queue;
+            u.state = state;
+            u.used[q] = value;
+        }
+
+        Ok(SubmissionHandle { queue, value })
+    }
+
+    /// リソースの追跡をやめる(解放する前に呼ぶ)
+    pub fn forget_resource(&mut self, resource: ResourceKey) {
+        self.resources.remove(&resource);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    type Log = Rc<RefCell<Vec<String>>>;
+    struct FakeQueue {
+        id: usize,
+        kind: CommandType,
+        completed: Rc<RefCell<u64>>,
+        log: Log,
+    }
+    impl SchedulerQueue for FakeQueue {
+        fn kind(&self) -> CommandType {
+            self.kind
+        }
+        fn execute(&mut self, lists: &[*mut ID3D12CommandList]) -> IOResult<()> {
+            let lists = lists.iter().map(|&p| p as usize).collect::<Vec<_>>();
+            self.log
+                .borrow_mut()
+                .push(format!("q{} exec {:?}", self.id, lists));
+            Ok(())
+        }
+        fn signal(&mut self, value: u64) -> IOResult<()> {
+            self.log
+                .borrow_mut()
+                .push(format!("q{} signal {}", self.id, value));
+            Ok(())
+        }
+        fn wait_queue(&mut self, other: &Self, value: u64) -> IOResult<()> {
+            self.log
+                .borrow_mut()
+                .push(format!("q{} wait q{}={}", self.id, other.id, value));
+            Ok(())
+        }
+        fn completed_value(&self) -> u64 {
+            *self.completed.borrow()
+        }
+        fn wait_host(&mut self, value: u64) -> IOResult<()> {
+            *self.completed.borrow_mut() = value;
+            Ok(())
+        }
+    }
+
+    fn scheduler(kinds: &[CommandType]) -> (QueueScheduler<FakeQueue>, Log, Vec<Rc<RefCell<u64>>>) {
+        let log = Log::default();
+        let completed = kinds
+            .iter()
+            .map(|_| Rc::new(RefCell::new(0)))
+            .collect::<Vec<_>>();
+        let queues = kinds
+            .iter()
+            .enumerate()
+            .map(|(id, &kind)| FakeQueue {
+                id,
+                kind,
+                completed: completed[id].clone(),
+                log: log.clone(),
+            })
+            .collect();
+        (QueueScheduler::new(queues), log, completed)
+    }
+    fn take(log: &Log) -> Vec<String> {
+        std::mem::take(&mut *log.borrow_mut())
+    }
+    /// バリアだけのコマンドリストは1000+バリア数とする
+    fn record(
+        _: QueueId,
+        _: CommandType,
+        barriers: &[ResourceBarrier],
+    ) -> IOResult<*mut ID3D12CommandList> {
+        Ok((1000 + barriers.len()) as _)
+    }
+    const LIST: *mut ID3D12CommandList = 1 as _;
+    const RESOURCE: ResourceKey = ResourceKey(0x100);
+    fn access(state: ResourceState) -> Submission {
+        Submission::new().access_key(RESOURCE, state)
+    }
+
+    #[test]
+    fn waits_only_when_not_implied() {
+        use CommandType::*;
+        let (mut s, log, completed) = scheduler(&[Direct, Compute, Copy]);
+        let (d, c, y) = (QueueId(0), QueueId(1), QueueId(2));
+
+        let h1 = s.submit(y, &[LIST], &Submission::new(), record).unwrap();
+        let h2 = s
+            .submit(c, &[LIST], &Submission::new().after(h1), record)
+            .unwrap();
+        assert_eq!(
+            take(&log),
+            [
+                "q2 exec [1]",
+                "q2 signal 1",
+                "q1 wait q2=1",
+                "q1 exec [1]",
+                "q1 signal 1"
+            ]
+        );
+        // h1はh2から推移的に保証される
+        let h3 = s
+            .submit(d, &[LIST], &Submission::new().after(h1).after(h2), record)
+            .unwrap();
+        assert_eq!(take(&log), ["q0 wait q1=1", "q0 exec [1]", "q0 signal 1"]);
+        // 既に待った
+        s.submit(d, &[LIST], &Submission::new().after(h1), record)
+            .unwrap();
+        assert_eq!(take(&log), ["q0 exec [1]", "q0 signal 2"]);
+
+        // 完了済みのものは待たない
+        let h4 = s.submit(y, &[LIST], &Submission::new(), record).unwrap();
+        *completed[2].borrow_mut() = 2;
+        take(&log);
+        s.submit(c, &[LIST], &Submission::new().after(h4).after(h3), record)
+            .unwrap();
+        assert_eq!(take(&log), ["q1 wait q0=1", "q1 exec [1]", "q1 signal 2"]);
+        assert!(s.is_complete(h4) && !s.is_complete(h3));
+
+        let unsubmitted = SubmissionHandle { queue: d, value: 9 };
+        assert!(s
+            .submit(c, &[LIST], &Submission::new().after(unsubmitted), record)
+            .is_err());
+    }
+
+    #[test]
+    fn transitions_on_the_queue_that_supports_them() {
+        use CommandType::*;
+        use ResourceState::*;
+        let (mut s, log, _) = scheduler(&[Direct, Compute, Copy]);
+        let (d, c, y) = (QueueId(0), QueueId(1), QueueId(2));
+
+        s.submit(y, &[LIST], &access(CopyDest), record).unwrap();
+        // 直接キューはCopyDestを扱えるので移る先でバリアを張る
+        s.submit(d, &[LIST], &access(PixelShaderResource), record)
+            .unwrap();
+        assert_eq!(
+            take(&log),
+            [
+                "q2 exec [1]",
+                "q2 signal 1",
+                "q0 wait q2=1",
+                "q0 exec [1001, 1]",
+                "q0 signal 1"
+            ]
+        );
+        // コンピュートキューはPixelShaderResourceを扱えないので元のキューで遷移させる
+        s.submit(c, &[LIST], &access(UnorderedAccess), record)
+            .unwrap();
+        assert_eq!(
+            take(&log),
+            [
+                "q0 exec [1001]",
+                "q0 signal 2",
+                "q1 wait q0=2",
+                "q1 exec [1]",
+                "q1 signal 1"
+            ]
+        );
+        // 同じ状態なので待つだけ
+        s.submit(d, &[LIST], &access(UnorderedAccess), record)
+            .unwrap();
+        assert_eq!(take(&log), ["q0 wait q1=1", "q0 exec [1]", "q0 signal 3"]);
+        s.submit(d, &[LIST], &access(RenderTarget), record).unwrap();
+        take(&log);
+        s.submit(y, &[LIST], &access(CopySource), record).unwrap();
+        assert_eq!(
+            take(&log),
+            [
+                "q0 exec [1001]",
+                "q0 signal 5",
+                "q2 wait q0=5",
+                "q2 exec [1]",
+                "q2 signal 2"
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_in_the_same_state_do_not_wait_for_each_other() {
+        use CommandType::*;
+        use ResourceState::*;
+        let (mut s, log, _) = scheduler(&[Direct, Compute]);
+        let (d, c) = (QueueId(0), QueueId(1));
+
+        s.submit(c, &[LIST], &access(UnorderedAccess), record)
+            .unwrap();
+        s.submit(d, &[LIST], &access(NonPixelShaderResource), record)
+            .unwrap();
+        assert_eq!(
+            take(&log),
+            [
+                "q1 exec [1]",
+                "q1 signal 1",
+                "q0 wait q1=1",
+                "q0 exec [1001, 1]",
+                "q0 signal 1"
+            ]
+        );
+        // 状態を変えた投入だけを待つ
+        s.submit(c, &[LIST], &access(NonPixelShaderResource), record)
+            .unwrap();
+        assert_eq!(take(&log), ["q1 wait q0=1", "q1 exec [1]", "q1 signal 2"]);
+        s.submit(d, &[LIST], &access(NonPixelShaderResource), record)
+            .unwrap();
+        assert_eq!(take(&log), ["q0 exec [1]", "q0 signal 2"]);
+        s.submit(c, &[LIST], &access(NonPixelShaderResource), record)
+            .unwrap();
+        assert_eq!(take(&log), ["q1 exec [1]", "q1 signal 3"]);
+
+        // 書き込みは読み取りをすべて待つ
+        s.submit(d, &[LIST], &access(UnorderedAccess), record)
+            .unwrap();
+        assert_eq!(
+            take(&log),
+            ["q0 wait q1=3", "q0 exec [1001, 1]", "q0 signal 3"]
+        );
+        // 書き込みどうしは同じ状態でも待つ
+        s.submit(c, &[LIST], &access(UnorderedAccess), record)
+            .unwrap();
+        assert_eq!(take(&log), ["q1 wait q0=3", "q1 exec [1]", "q1 signal 4"]);
+    }
+
+    #[test]
+    fn releasing_transition_waits_for_other_readers() {
+        use CommandType::*;
+        use ResourceState::*;
+        let (mut s, log, _) = scheduler(&[Direct, Compute, Compute, Copy]);
+        let (c1, c2, y) = (QueueId(1), QueueId(2), QueueId(3));
+
+        s.submit(c1, &[LIST], &access(UnorderedAccess), record)
+            .unwrap();
+        s.submit(c1, &[LIST], &access(NonPixelShaderResource), record)
+            .unwrap();
+        s.submit(c2, &[LIST], &access(NonPixelShaderResource), record)
+            .unwrap();
+        s.submit(c1, &[LIST], &access(NonPixelShaderResource), record)
+            .unwrap();
+        assert_eq!(
+            take(&log),
+            [
+                "q1 exec [1]",
+                "q1 signal 1",
+                "q1 exec [1]",
+                "q1 signal 2",
+                "q2 wait q1=2",
+                "q2 exec [1]",
+                "q2 signal 1",
+                "q1 exec [1]",
+                "q1 signal 3"
+            ]
+        );
+        // コピーキューはNonPixelShaderResourceを扱えないのでq1で遷移させるが、q2の読み取りの後にする
+        s.submit(y, &[LIST], &access(CopySource), record).unwrap();
+        assert_eq!(
+            take(&log),
+            [
+                "q1 wait q2=1",
+                "q1 exec [1001]",
+                "q1 signal 4",
+                "q3 wait q1=4",
+                "q3 exec [1]",
+                "q3 signal 1"
+            ]
+        );
+    }
+
+    #[test]
+    fn ownership_transfer_barrier() {
+        let b = OwnershipTransfer {
+            resource: RESOURCE,
+            before: ResourceState::CopyDest,
+            after: ResourceState::PixelShaderResource,
+        }
+        .barrier();
+        let b = b.as_ref();
+        assert_eq!(b.Type, D3D12_RESOURCE_BARRIER_TYPE_TRANSITION);
+        let t = unsafe { b.u.Transition() };
+        assert_eq!(t.pResource as usize, 0x100);
+        assert_eq!(t.Subresource, D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES);
+        assert_eq!(t.StateBefore, D3D12_RESOURCE_STATE_COPY_DEST);
+        assert_eq!(t.StateAfter, D3D12_RESOURCE_STATE_PIXEL_SHADER_RESOURCE);
+    }
+}
@@ -0,0 +1,143 @@
+//! Resource Sharing
+
+use super::*;
+use std::marker::PhantomData;
+use winapi::um::handleapi::CloseHandle;
+use winapi::um::winnt::GENERIC_ALL;
+
+/// 破棄時に閉じられる共有用NTハンドル(Tは共有されているオブジェクトの種類)
+pub struct SharedHandle<T>(HANDLE, PhantomData<fn() -> T>);
+impl<T> SharedHandle<T> {
+    /// 生のハンドルから作る(所有権を引き取る)
+    ///
+    /// # Safety
+    /// hはT型のオブジェクトを共有している有効なNTハンドルであること
+    pub unsafe fn from_raw(h: HANDLE) -> Self {
+        SharedHandle(h, PhantomData)
+    }
+    /// 生のハンドル(所有権は移らない)
+    pub fn as_raw(&self) -> HANDLE {
+        self.0
+    }
+    /// 生のハンドルを取り出す(以降は呼び出し側で閉じること)
+    pub fn into_raw(self) -> HANDLE {
+        let h = self.0;
+        std::mem::forget(self);
+        h
+    }
+}
+impl<T> Drop for SharedHandle<T> {
+    fn drop(&mut self) {
+        if !self.0.is_null() {
+            unsafe { CloseHandle(self.0) };
+        }
+    }
+}
+unsafe impl<T> Sync for SharedHandle<T> {}
+unsafe impl<T> Send for SharedHandle<T> {}
+
+/// 共有できるオブジェクト
+pub trait Shareable: Sized {
+    /// 開くときに要求するインターフェイス
+    fn iid() -> GUID;
+    fn as_device_child(&self) -> *mut ID3D12DeviceChild;
+    /// OpenSharedHandleで得られたポインタから作る
+    ///
+    /// # Safety
+    /// pはiid()のインターフェイスであること
+    unsafe fn from_opened(p: *mut c_void, device: &Device) -> Self;
+}
+impl Shareable for Resource {
+    fn iid() -> GUID {
+        ID3D12Resource::uuidof()
+    }
+    fn as_device_child(&self) -> *mut ID3D12DeviceChild {
+        self.0 as _
+    }
+    unsafe fn from_opened(p: *mut c_void, _device: &Device) -> Self {
+        Resource(p as _)
+    }
+}
+impl Shareable for Fence {
+    fn iid() -> GUID {
+        ID3D12Fence::uuidof()
+    }
+    fn as_device_child(&self) -> *mut ID3D12DeviceChild {
+        self.0 as _
+    }
+    unsafe fn from_opened(p: *mut c_void, _device: &Device) -> Self {
+        Fence(p as _)
+    }
+}
+impl Shareable for Heap {
+    fn iid() -> GUID {
+        ID3D12Heap::uuidof()
+    }
+    fn as_device_child(&self) -> *mut ID3D12DeviceChild {
+        self.0 as _
+    }
+    unsafe fn from_opened(p: *mut c_void, device: &Device) -> Self {
+        Heap(p as _, device.0)
+    }
+}
+
+impl Device {
+    /// 共有可能なフェンスを作る(cross_adapterなら別アダプタのデバイスとも共有できる)
+    pub fn new_shared_fence(&self, initial_value: u64, cross_adapter: bool) -> IOResult<Fence> {
+        let flags = if cross_adapter {
+            D3D12_FENCE_FLAG_SHARED | D3D12_FENCE_FLAG_SHARED_CROSS_ADAPTER
+        } else {
+            D3D12_FENCE_FLAG_SHARED
+        };
+        self.new_fence(initial_value, flags)
+    }
+
+    /// オブジェクトを共有するハンドルを作る(nameをつけると他のプロセスから名前で開ける)
+    /// リソース/ヒープはSHAREDフラグつきで作られていること
+    /// create_shared_handleと違い、ハンドルは型つきで破棄時に閉じられる
+    pub fn share<T: Shareable>(
+        &self,
+        obj: &T,
+        security_attributes: Option<&winapi::um::minwinbase::SECURITY_ATTRIBUTES>,
+        name: Option<&str>,
+    ) -> IOResult<SharedHandle<T>> {
+        let name = name.map(|n| n.encode_utf16().chain(Some(0)).collect::<Vec<u16>>());
+        let mut h = std::ptr::null_mut();
+
+        unsafe {
+            (*self.0)
+                .CreateSharedHandle(
+                    obj.as_device_child(),
+                    security_attributes.map_or_else(std::ptr::null, |p| p as *const _),
+                    GENERIC_ALL,
+                    name.as_ref().map_or(std::ptr::null(), |n| n.as_ptr()),
+                    &mut h,
+                )
+                .to_result_with(|| SharedHandle::from_raw(h))
+        }
+    }
+    /// 共有されたオブジェクトを開く
+    pub fn open_shared_handle<T: Shareable>(&self, handle: &SharedHandle<T>) -> IOResult<T> {
+        let mut p = std::ptr::null_mut();
+
+        unsafe {
+            (*self.0)
+                .OpenSharedHandle(handle.0, &T::iid(), &mut p)
+                .to_result_with(|| T::from_opened(p, self))
+        }
+    }
+    /// 名前つきで共有されたオブジェクトのハンドルを開く
+    pub fn open_shared_handle_by_name<T: Shareable>(
+        &self,
+        name: &str,
+    ) -> IOResult<SharedHandle<T>> {
+        let name: Vec<u16> = name.encode_utf16().chain(Some(0)).collect();
+        let mut h = std::ptr::null_mut();
+
+        unsafe {
+            (*self.0)
+                .OpenSharedHandleByName(name.as_ptr(), GENERIC_ALL, &mut h)
+                .to_result_with(|| SharedHandle::from_raw(h))
+        }
+    }
+}
@@ -0,0 +1,404 @@
+//! Streaming Uploads
+
+use super::*;
+use std::collections::VecDeque;
+
+/// ステージングメモリのリングアロケータ(バッチのフェンス値が完了したら領域を再利用する)
+///
+/// 位置は単調増加で管理し、容量で割った余りがバッファ内の位置になる
+pub struct StagingRing {
+    capacity: u64,
+    head: u64,
+    tail: u64,
+    /// (バッチのフェンス値, バッチの終端位置)
+    in_flight: VecDeque<(u64, u64)>,
+}
+impl StagingRing {
+    pub fn new(capacity: u64) -> Self {
+        StagingRing {
+            capacity,
+            head: 0,
+            tail: 0,
+            in_flight: VecDeque::new(),
+        }
+    }
+
+    pub fn capacity(&self) -> u64 {
+        self.capacity
+    }
+    /// 使用中(未完了のバッチと記録中のバッチ)の大きさ
+    pub fn used(&self) -> u64 {
+        self.head - self.tail
+    }
+    /// 最も古い未完了のバッチのフェンス値
+    pub fn oldest_in_flight(&self) -> Option<u64> {
+        self.in_flight.front().map(|&(v, _)| v)
+    }
+
+    /// 連続した領域を確保してバッファ内の位置を返す(空きがなければNone)
+    ///
+    /// alignmentは容量の約数であること
+    pub fn allocate(&mut self, size: u64, alignment: u64) -> Option<u64> {
+        if self.head == self.tail {
+            self.head = 0;
+            self.tail = 0;
+        }
+        let mut start = align_up(self.head, alignment);
+        let pos = start % self.capacity;
+        if pos + size > self.capacity {
+            // 末尾に収まらないので先頭に戻る
+            start += self.capacity - pos;
+        }
+        if start + size - self.tail > self.capacity {
+            return None;
+        }
+        self.head = start + size;
+        Some(start % self.capacity)
+    }
+    /// これまでに確保した領域をフェンス値valueのバッチとして閉じる
+    pub fn close_batch(&mut self, value: u64) {
+        self.in_flight.push_back((value, self.head));
+    }
+    /// completedまで完了したバッチの領域を解放する
+    pub fn retire(&mut self, completed: u64) {
+        while let Some(&(v, end)) = self.in_flight.front() {
+            if v > completed {
+                break;
+            }
+            self.tail = end;
+            self.in_flight.pop_front();
+        }
+    }
+}
+
+/// アップロードの完了チケット(アップローダのフェンスがvalueに達したら完了)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct UploadTicket {
+    pub value: u64,
+}
+
+/// コピーキューで非同期にアップロードするもの
+///
+/// 転送先のリソースはCOMMON状態で渡すこと(コピーキューで暗黙にCopyDestへ昇格し、完了後にCOMMONへ戻る)。
+/// チケットが完了するまで他のキューで使わないこと
+pub struct StreamingUploader {
+    device: Device,
+    queue: CommandQueue,
+    fence: Fence,
+    staging: Resource,
+    staging_ptr: *mut u8,
+    ring: StagingRing,
+    list: GraphicsCommandList,
+    free_allocators: Vec<CommandAllocator>,
+    /// (使ったバッチのフェンス値, アロケータ)
+    busy_allocators: VecDeque<(u64, CommandAllocator)>,
+    recording: Option<CommandAllocator>,
+    batch_bytes: u64,
+    batch_size: u64,
+    submitted: u64,
+}
+unsafe impl Send for StreamingUploader {}
+impl Device {
+    /// ストリーミングアップローダの作成
+    ///
+    /// staging_sizeはステージングメモリの大きさ(512バイト単位に切り上げる)。
+    /// 記録中のバッチがbatch_sizeバイトに達したら自動で投入する
+    pub fn new_streaming_uploader(
+        &self,
+        staging_size: u64,
+        batch_size: u64,
+        priority: i32,
+    ) -> IOResult<StreamingUploader> {
+        let a = D3D12_TEXTURE_DATA_PLACEMENT_ALIGNMENT as u64;
+        let staging_size = align_up(staging_size.max(1), a);
+        let mut staging = self.new_resource_committed(
+            &HeapProperty::upload(),
+            &ResourceDesc::buffer(staging_size as _),
+            ResourceState::GenericRead,
+            None,
+        )?;
+        let staging_ptr = staging.map(..0)? as *mut u8;
+        let mut alloc = self.new_command_allocator(CommandType::Copy)?;
+        let mut list = self.new_graphics_command_list(&mut alloc, None)?;
+        list.close()?;
+
+        Ok(StreamingUploader {
+            device: self.clone(),
+            queue: self.new_command_queue(CommandType::Copy, priority)?,
+            fence: self.new_fence(0, D3D12_FENCE_FLAG_NONE)?,
+            staging,
+            staging_ptr,
+            ring: StagingRing::new(staging_size),
+            list,
+            free_allocators: vec![alloc],
+            busy_allocators: VecDeque::new(),
+            recording: None,
+            batch_bytes: 0,
+            batch_size,
+            submitted: 0,
+        })
+    }
+}
+impl StreamingUploader {
+    pub fn queue(&self) -> &CommandQueue {
+        &self.queue
+    }
+    pub fn fence(&self) -> &Fence {
+        &self.fence
+    }
+    /// ステージングメモリの使用量
+    pub fn staging_used(&self) -> u64 {
+        self.ring.used()
+    }
+    /// 最後に投入したバッチのフェンス値
+    pub fn submitted_value(&self) -> u64 {
+        self.submitted
+    }
+
+    /// 記録中のバッチのチケット
+    fn current_ticket(&self) -> UploadTicket {
+        UploadTicket {
+            value: self.submitted + 1,
+        }
+    }
+    fn begin_batch(&mut self) -> IOResult<()> {
+        if self.recording.is_some() {
+            return Ok(());
+        }
+        let completed = self.fence.completed_value();
+        while let Some(&(v, _)) = self.busy_allocators.front() {
+            if v > completed {
+                break;
+            }
+            let (_, mut a) = self.busy_allocators.pop_front().expect("checked above");
+            a.reset()?;
+            self.free_allocators.push(a);
+        }
+        let alloc = match self.free_allocators.pop() {
+            Some(a) => a,
+            None => self.device.new_command_allocator(CommandType::Copy)?,
+        };
+        self.list.reset(&alloc, None)?;
+        self.recording = Some(alloc);
+        Ok(())
+    }
+    /// ステージングメモリを確保する(空きがなければ記録中のバッチを投入し、古いバッチの完了を待つ)
+    fn reserve(&mut self, size: u64, alignment: u64) -> IOResult<u64> {
+        if size > self.ring.capacity() {
+            return invalid(format!(
+                "upload of {} bytes does not fit into {} bytes of staging memory",
+                size,
+                self.ring.capacity()
+            ));
+        }
+        loop {
+            self.ring.retire(self.fence.completed_value());
+            if let Some(offset) = self.ring.allocate(size, alignment) {
+                return Ok(offset);
+            }
+            if self.recording.is_some() {
+                self.flush()?;
+                continue;
+            }
+            match self.ring.oldest_in_flight() {
+                Some(oldest) => self.fence.wait(oldest)?,
+                None => {
+                    return Err(IOError::other(
+                        "staging memory is full without any batch in flight",
+                    ))
+                }
+            }
+        }
+    }
+    fn staging_slice(&mut self, offset: u64, size: u64) -> &mut [u8] {
+        unsafe { std::slice::from_raw_parts_mut(self.staging_ptr.add(offset as _), size as _) }
+    }
+    fn finish_job(&mut self, size: u64) -> IOResult<UploadTicket> {
+        let ticket = self.current_ticket();
+        self.batch_bytes += size;
+        if self.batch_bytes >= self.batch_size {
+            self.flush()?;
+        }
+        Ok(ticket)
+    }
+
+    /// バッファへのアップロードを積む
+    pub fn upload_buffer(
+        &mut self,
+        dst: &Resource,
+        dst_offset: u64,
+        data: &[u8],
+    ) -> IOResult<UploadTicket> {
+        if data.is_empty() {
+            return Ok(UploadTicket {
+                value: self.submitted,
+            });
+        }
+        let size = data.len() as u64;
+        let offset = self.reserve(size, 16)?;
+        self.staging_slice(offset, size).copy_from_slice(data);
+        self.begin_batch()?;
+        self.list.copy_buffer_region(
+            &self.staging,
+            offset as usize..(offset + size) as usize,
+            dst,
+            dst_offset as _,
+        );
+
+        self.finish_job(size)
+    }
+    /// テクスチャのサブリソースへのアップロードを積む
+    pub fn upload_texture(
+        &mut self,
+        dst: &Resource,
+        first_subresource: u32,
+        subresources: &[SubresourceData],
+    ) -> IOResult<UploadTicket> {
+        let desc = dst.desc();
+        let (footprints, total_bytes) = self.device.get_copyable_footprints(
+            desc.as_ref(),
+            first_subresource..first_subresource + subresources.len() as u32,
+            0,
+        );
+        let footprints = footprints.collect::<Vec<_>>();
+        let offset = self.reserve(total_bytes, D3D12_TEXTURE_DATA_PLACEMENT_ALIGNMENT as _)?;
+        write_subresources(
+            self.staging_slice(offset, total_bytes),
+            &footprints,
+            subresources,
+        )?;
+        self.begin_batch()?;
+        for (n, fp) in footprints.iter().enumerate() {
+            let mut placed = fp.placed_footprint;
+            placed.Offset += offset;
+            let src = TextureCopyLocation::with_placed_footprint(&self.staging, placed);
+            let dst =
+                TextureCopyLocation::with_subresource_index(dst, first_subresource + n as u32);
+            self.list.copy_texture_region(&src, None, &dst, 0, 0, 0);
+        }
+
+        self.finish_job(total_bytes)
+    }
+
+    /// 記録中のバッチを投入する(投入したものがなければNone)
+    pub fn flush(&mut self) -> IOResult<Option<UploadTicket>> {
+        let alloc = match self.recording.take() {
+            Some(a) => a,
+            None => return Ok(None),
+        };
+        self.list.close()?;
+        let value = self.submitted + 1;
+        self.queue.execute(&[self.list.0 as _]);
+        self.queue.signal(&self.fence, value)?;
+        self.submitted = value;
+        self.ring.close_batch(value);
+        self.busy_allocators.push_back((value, alloc));
+        self.batch_bytes = 0;
+
+        Ok(Some(UploadTicket { value }))
+    }
+
+    /// 完了済みか
+    pub fn is_complete(&self, ticket: UploadTicket) -> bool {
+        self.fence.completed_value() >= ticket.value
+    }
+    /// 完了するまでqueueを待たせる(チケットのバッチが記録中なら投入する)
+    pub fn wait_on_queue(
+        &mut self,
+        queue: &mut CommandQueue,
+        ticket: UploadTicket,
+    ) -> IOResult<()> {
+        if ticket.value > self.submitted {
+            self.flush()?;
+        }
+        queue.wait(&self.fence, ticket.value).map(drop)
+    }
+    /// 完了するまでCPUで待つ(チケットのバッチが記録中なら投入する)
+    pub fn wait(&mut self, ticket: UploadTicket) -> IOResult<()> {
+        if ticket.value > self.submitted {
+            self.flush()?;
+        }
+        self.fence.wait(ticket.value)
+    }
+}
+impl Drop for StreamingUploader {
+    fn drop(&mut self) {
+        // 実行中のコピーが終わるまでステージングメモリを解放しない
+        let _ = self.fence.wait(self.submitted);
+        self.staging.unmap(..);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allocations_are_aligned_and_contiguous() {
+        let mut ring = StagingRing::new(1024);
+        assert_eq!(ring.allocate(100, 16), Some(0));
+        assert_eq!(ring.allocate(100, 512), Some(512));
+        assert_eq!(ring.allocate(10, 16), Some(624));
+        assert_eq!(ring.used(), 634);
+        assert_eq!(ring.oldest_in_flight(), None);
+    }
+
+    #[test]
+    fn exactly_full_and_over_budget() {
+        let mut ring = StagingRing::new(1024);
+        assert_eq!(ring.allocate(1025, 16), None);
+        assert_eq!(ring.allocate(1024, 16), Some(0));
+        assert_eq!(ring.used(), 1024);
+        assert_eq!(ring.allocate(1, 1), None);
+        ring.close_batch(1);
+
+        // 完了するまでは空かない
+        ring.retire(0);
+        assert_eq!(ring.allocate(1, 1), None);
+        ring.retire(1);
+        assert_eq!(ring.used(), 0);
+        assert_eq!(ring.allocate(1024, 16), Some(0));
+    }
+
+    #[test]
+    fn wraps_around_to_the_front() {
+        let mut ring = StagingRing::new(1024);
+        assert_eq!(ring.allocate(640, 16), Some(0));
+        ring.close_batch(1);
+        assert_eq!(ring.allocate(256, 16), Some(640));
+        ring.close_batch(2);
+        ring.retire(1);
+        assert_eq!(ring.oldest_in_flight(), Some(2));
+
+        // 末尾の128バイトには収まらないので先頭から確保する(末尾は捨てる)
+        assert_eq!(ring.allocate(200, 16), Some(0));
+        assert_eq!(ring.used(), 584);
+        // バッチ2(640..896)に重なる確保は待たされる
+        assert_eq!(ring.allocate(500, 16), None);
+        assert_eq!(ring.allocate(432, 16), Some(208));
+        assert_eq!(ring.used(), 1024);
+        assert_eq!(ring.allocate(1, 1), None);
+        ring.close_batch(3);
+
+        ring.retire(2);
+        assert_eq!(ring.oldest_in_flight(), Some(3));
+        assert_eq!(ring.used(), 768);
+        assert_eq!(ring.allocate(256, 16), Some(640));
+        ring.retire(3);
+        assert_eq!(ring.oldest_in_flight(), None);
+        assert_eq!(ring.used(), 256);
+    }
+
+    #[test]
+    fn wrapping_exactly_to_the_tail() {
+        let mut ring = StagingRing::new(1024);
+        assert_eq!(ring.allocate(512, 512), Some(0));
+        ring.close_batch(1);
+        assert_eq!(ring.allocate(512, 512), Some(512));
+        ring.close_batch(2);
+        ring.retire(1);
+        assert_eq!(ring.allocate(512, 512), Some(0));
+        assert_eq!(ring.used(), 1024);
+        assert_eq!(ring.allocate(16, 16), None);
+    }
+}
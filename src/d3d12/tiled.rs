@@ -0,0 +1,823 @@
+//! Tiled (Reserved) Resources
+
+use super::*;
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+/// タイル1枚のバイト数
+pub const TILE_SIZE_IN_BYTES: u64 = D3D12_TILED_RESOURCE_TILE_SIZE_IN_BYTES as _;
+
+pub use winapi::um::d3d12::{
+    D3D12_TILE_MAPPING_FLAG_NONE as TILE_MAPPING_FLAG_NONE,
+    D3D12_TILE_MAPPING_FLAG_NO_HAZARD as TILE_MAPPING_FLAG_NO_HAZARD,
+};
+
+/// タイル1枚に収まるテクセル数
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TileShape {
+    pub width: u32,
+    pub height: u32,
+    pub depth: u32,
+}
+/// 標準タイル形状(Standard Swizzle)をフォーマットから求める
+/// 1Dテクスチャ、バッファ、扱えないフォーマットではNone
+pub fn standard_tile_shape(format: dxgi::Format, volume: bool) -> Option<TileShape> {
+    let block = dxgi::format_block_info(format)?;
+    let (w, h, d) = match (volume, block.bytes) {
+        (false, 1) => (256, 256, 1),
+        (false, 2) => (256, 128, 1),
+        (false, 4) => (128, 128, 1),
+        (false, 8) => (128, 64, 1),
+        (false, 16) => (64, 64, 1),
+        (true, 1) => (64, 32, 32),
+        (true, 2) => (32, 32, 32),
+        (true, 4) => (32, 32, 16),
+        (true, 8) => (32, 16, 16),
+        (true, 16) => (16, 16, 16),
+        _ => return None,
+    };
+
+    Some(TileShape {
+        width: w * block.width,
+        height: h * block.height,
+        depth: d,
+    })
+}
+
+/// サブリソース1つぶんのタイル配置
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SubresourceTiling {
+    pub width_in_tiles: u32,
+    pub height_in_tiles: u32,
+    pub depth_in_tiles: u32,
+    /// リソース全体でのタイル番号の開始位置(パックされたミップではNone)
+    pub start_tile: Option<u32>,
+}
+/// パックされたミップ(ミップテール)の情報
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PackedMipInfo {
+    pub standard_mips: u32,
+    pub packed_mips: u32,
+    /// 配列スライス1枚ぶんのミップテールのタイル数
+    pub tile_count: u32,
+    /// 最初の配列スライスのミップテールのタイル番号
+    pub start_tile: u32,
+}
+
+/// タイルの指定
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum TileKey {
+    /// 通常のミップ上のタイル
+    Tile {
+        subresource: u32,
+        x: u32,
+        y: u32,
+        z: u32,
+    },
+    /// 配列スライスごとのミップテール全体
+    MipTail { slice: u32 },
+}
+
+/// 予約済みリソースのタイル構成
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResourceTiling {
+    pub total_tiles: u32,
+    pub mip_levels: u32,
+    pub array_size: u32,
+    pub tile_shape: TileShape,
+    pub packed_mips: PackedMipInfo,
+    /// サブリソース順(mip + slice * mip_levels)
+    pub subresources: Vec<SubresourceTiling>,
+}
+impl ResourceTiling {
+    /// サブリソース番号
+    pub fn subresource(&self, mip: u32, slice: u32) -> u32 {
+        mip + slice * self.mip_levels
+    }
+    /// パックされたミップかどうか
+    pub fn is_packed(&self, mip: u32) -> bool {
+        mip >= self.packed_mips.standard_mips
+    }
+    /// 配列スライス1枚ぶんのタイル数
+    pub fn tiles_per_slice(&self) -> u32 {
+        self.total_tiles / self.array_size.max(1)
+    }
+    /// パックされていないミップのタイル配置
+    pub fn subresource_tiling(&self, mip: u32, slice: u32) -> Option<&SubresourceTiling> {
+        if self.is_packed(mip) || slice >= self.array_size {
+            return None;
+        }
+        self.subresources.get(self.subresource(mip, slice) as usize)
+    }
+
+    /// テクセル座標を含むタイル
+    pub fn tile_at_texel(&self, mip: u32, slice: u32, x: u32, y: u32, z: u32) -> Option<TileKey> {
+        if mip >= self.mip_levels || slice >= self.array_size {
+            return None;
+        }
+        if self.is_packed(mip) {
+            return Some(TileKey::MipTail { slice });
+        }
+        let key = TileKey::Tile {
+            subresource: self.subresource(mip, slice),
+            x: x / self.tile_shape.width,
+            y: y / self.tile_shape.height,
+            z: z / self.tile_shape.depth,
+        };
+
+        if self.tile_index(key).is_some() {
+            Some(key)
+        } else {
+            None
+        }
+    }
+    /// ミップ1枚ぶんのタイルを列挙する(パックされたミップではミップテール)
+    pub fn mip_tiles(&self, mip: u32, slice: u32) -> Vec<TileKey> {
+        if mip >= self.mip_levels || slice >= self.array_size {
+            return Vec::new();
+        }
+        if self.is_packed(mip) {
+            return vec![TileKey::MipTail { slice }];
+        }
+        let subresource = self.subresource(mip, slice);
+        let t = match self.subresources.get(subresource as usize) {
+            Some(t) => t,
+            None => return Vec::new(),
+        };
+        let mut tiles =
+            Vec::with_capacity((t.width_in_tiles * t.height_in_tiles * t.depth_in_tiles) as usize);
+        for z in 0..t.depth_in_tiles {
+            for y in 0..t.height_in_tiles {
+                for x in 0..t.width_in_tiles {
+                    tiles.push(TileKey::Tile {
+                        subresource,
+                        x,
+                        y,
+                        z,
+                    });
+                }
+            }
+        }
+
+        tiles
+    }
+
+    /// リソース全体でのタイル番号(ミップテールでは先頭のタイル)
+    pub fn tile_index(&self, key: TileKey) -> Option<u32> {
+        match key {
+            TileKey::Tile {
+                subresource,
+                x,
+                y,
+                z,
+            } => {
+                let t = self.subresources.get(subresource as usize)?;
+                if x >= t.width_in_tiles || y >= t.height_in_tiles || z >= t.depth_in_tiles {
+                    return None;
+                }
+                Some(t.start_tile? + x + t.width_in_tiles * (y + t.height_in_tiles * z))
+            }
+            TileKey::MipTail { slice } => {
+                if slice >= self.array_size || self.packed_mips.packed_mips == 0 {
+                    return None;
+                }
+                Some(self.packed_mips.start_tile + slice * self.tiles_per_slice())
+            }
+        }
+    }
+    /// 割り当てに必要なタイル数
+    pub fn tile_count(&self, key: TileKey) -> u32 {
+        match key {
+            TileKey::Tile { .. } => 1,
+            TileKey::MipTail { .. } => self.packed_mips.tile_count,
+        }
+    }
+    /// UpdateTileMappingsに渡す領域
+    pub fn region(
+        &self,
+        key: TileKey,
+    ) -> (D3D12_TILED_RESOURCE_COORDINATE, D3D12_TILE_REGION_SIZE) {
+        let (coord, count) = match key {
+            TileKey::Tile {
+                subresource,
+                x,
+                y,
+                z,
+            } => (
+                D3D12_TILED_RESOURCE_COORDINATE {
+                    X: x,
+                    Y: y,
+                    Z: z,
+                    Subresource: subresource,
+                },
+                1,
+            ),
+            TileKey::MipTail { slice } => (
+                D3D12_TILED_RESOURCE_COORDINATE {
+                    X: 0,
+                    Y: 0,
+                    Z: 0,
+                    Subresource: self.subresource(self.packed_mips.standard_mips, slice),
+                },
+                self.packed_mips.tile_count,
+            ),
+        };
+
+        (
+            coord,
+            D3D12_TILE_REGION_SIZE {
+                NumTiles: count,
+                UseBox: 0,
+                Width: 0,
+                Height: 0,
+                Depth: 0,
+            },
+        )
+    }
+}
+
+/// 空き範囲のリスト(昇順で重ならず、隣接する範囲は結合される)
+#[derive(Debug, Clone)]
+pub(crate) struct FreeRanges<T> {
+    ranges: Vec<Range<T>>,
+}
+impl<T: Copy + Ord> FreeRanges<T> {
+    pub(crate) fn new() -> Self {
+        FreeRanges { ranges: Vec::new() }
+    }
+    pub(crate) fn ranges(&self) -> &[Range<T>] {
+        &self.ranges
+    }
+    /// 範囲を空きに戻す(前後と結合する)
+    pub(crate) fn insert(&mut self, r: Range<T>) {
+        if r.start >= r.end {
+            return;
+        }
+        let n = self.ranges.partition_point(|f| f.start < r.start);
+        self.ranges.insert(n, r);
+        if n + 1 < self.ranges.len() && self.ranges[n].end == self.ranges[n + 1].start {
+            self.ranges[n].end = self.ranges.remove(n + 1).end;
+        }
+        if n > 0 && self.ranges[n - 1].end == self.ranges[n].start {
+            self.ranges[n - 1].end = self.ranges.remove(n).end;
+        }
+    }
+    /// n番目の空き範囲からtakeを取り除く(takeはその範囲に含まれること)
+    pub(crate) fn remove(&mut self, n: usize, take: Range<T>) {
+        let r = self.ranges[n].clone();
+        debug_assert!(r.start <= take.start && take.end <= r.end);
+        match (r.start < take.start, take.end < r.end) {
+            (true, true) => {
+                self.ranges[n].end = take.start;
+                self.ranges.insert(n + 1, take.end..r.end);
+            }
+            (true, false) => self.ranges[n].end = take.start,
+            (false, true) => self.ranges[n].start = take.end,
+            (false, false) => {
+                self.ranges.remove(n);
+            }
+        }
+    }
+}
+
+/// ヒープ上のタイルの割り当て管理
+pub struct TilePool {
+    heap: Option<Heap>,
+    capacity: u32,
+    free: FreeRanges<u32>,
+}
+impl Device {
+    /// capacity枚ぶんのタイルのヒープを作成して、それを割り当てるプールを作る
+    /// (flagsはマップするリソースの種類に合わせたヒープフラグ)
+    pub fn new_tile_pool(&self, capacity: u32, flags: D3D12_HEAP_FLAGS) -> IOResult<TilePool> {
+        let mut pool = TilePool::new(capacity);
+        pool.heap = Some(self.new_heap(&HeapProperty::default(), pool.heap_size() as _, flags)?);
+
+        Ok(pool)
+    }
+}
+impl TilePool {
+    /// ヒープを持たないプール(割り当ての計算だけを行う)
+    pub fn new(capacity: u32) -> Self {
+        let mut pool = TilePool {
+            heap: None,
+            capacity,
+            free: FreeRanges::new(),
+        };
+        pool.release(std::slice::from_ref(&(0..capacity)));
+
+        pool
+    }
+    /// タイルを割り当てるヒープ
+    pub fn heap(&self) -> Option<&Heap> {
+        self.heap.as_ref()
+    }
+    /// 管理しているタイル数
+    pub fn capacity(&self) -> u32 {
+        self.capacity
+    }
+    /// 空きタイル数
+    pub fn free_tiles(&self) -> u32 {
+        self.free.ranges().iter().map(|r| r.end - r.start).sum()
+    }
+    /// 必要なヒープのサイズ
+    pub fn heap_size(&self) -> u64 {
+        self.capacity as u64 * TILE_SIZE_IN_BYTES
+    }
+
+    /// タイルを割り当てる(連続しているとは限らない)。足りない場合はNone
+    pub fn allocate(&mut self, count: u32) -> Option<Vec<Range<u32>>> {
+        if count > self.free_tiles() {
+            return None;
+        }
+        // なるべく連続した範囲から取る
+        if let Some(n) = self
+            .free
+            .ranges()
+            .iter()
+            .position(|r| r.end - r.start >= count)
+        {
+            let start = self.free.ranges()[n].start;
+            self.free.remove(n, start..start + count);
+            return Some(std::iter::once(start..start + count).collect());
+        }
+        let mut ranges = Vec::new();
+        let mut rest = count;
+        while rest > 0 {
+            let r = self.free.ranges()[0].clone();
+            let n = rest.min(r.end - r.start);
+            self.free.remove(0, r.start..r.start + n);
+            ranges.push(r.start..r.start + n);
+            rest -= n;
+        }
+
+        Some(ranges)
+    }
+    /// タイルを返却する
+    pub fn release(&mut self, ranges: &[Range<u32>]) {
+        for r in ranges {
+            self.free.insert(r.clone());
+        }
+    }
+}
+
+/// タイルマッピングの変更1件分
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TileUpdate {
+    pub key: TileKey,
+    /// 割り当てたヒープ上のタイル範囲(Noneはマッピング解除)
+    pub heap_tiles: Option<Vec<Range<u32>>>,
+}
+
+/// 予約済みリソース1つぶんのタイルマッピングの状態
+pub struct TileMap {
+    tiling: ResourceTiling,
+    mapped: BTreeMap<TileKey, Vec<Range<u32>>>,
+    pending: Vec<TileUpdate>,
+}
+impl TileMap {
+    pub fn new(tiling: ResourceTiling) -> Self {
+        TileMap {
+            tiling,
+            mapped: BTreeMap::new(),
+            pending: Vec::new(),
+        }
+    }
+    pub fn tiling(&self) -> &ResourceTiling {
+        &self.tiling
+    }
+    /// マップ済みかどうか
+    pub fn is_mapped(&self, key: TileKey) -> bool {
+        self.mapped.contains_key(&key)
+    }
+    /// 割り当てられているヒープ上のタイル範囲
+    pub fn heap_tiles(&self, key: TileKey) -> Option<&[Range<u32>]> {
+        self.mapped.get(&key).map(|v| &v[..])
+    }
+    /// マップ済みのタイル数
+    pub fn mapped_tiles(&self) -> u32 {
+        self.mapped
+            .values()
+            .flat_map(|v| v.iter())
+            .map(|r| r.end - r.start)
+            .sum()
+    }
+
+    /// プールからタイルを割り当ててマップする(マップ済みなら何もしない)
+    pub fn map(&mut self, pool: &mut TilePool, key: TileKey) -> IOResult<()> {
+        if self.tiling.tile_index(key).is_none() {
+            return invalid(format!("{:?} is out of the resource", key));
+        }
+        if self.is_mapped(key) {
+            return Ok(());
+        }
+        let tiles = pool
+            .allocate(self.tiling.tile_count(key))
+            .ok_or_else(|| IOError::other("tile pool exhausted"))?;
+        self.mapped.insert(key, tiles.clone());
+        self.pending.push(TileUpdate {
+            key,
+            heap_tiles: Some(tiles),
+        });
+
+        Ok(())
+    }
+    /// マッピングを解除してタイルをプールに返す
+    pub fn unmap(&mut self, pool: &mut TilePool, key: TileKey) -> bool {
+        match self.mapped.remove(&key) {
+            Some(tiles) => {
+                pool.release(&tiles);
+                self.pending.push(TileUpdate {
+                    key,
+                    heap_tiles: None,
+                });
+                true
+            }
+            None => false,
+        }
+    }
+    /// すべてのマッピングを解除する
+    pub fn unmap_all(&mut self, pool: &mut TilePool) {
+        let keys = self.mapped.keys().cloned().collect::<Vec<_>>();
+        for k in keys {
+            self.unmap(pool, k);
+        }
+    }
+    /// まだキューに反映していない変更を取り出す
+    pub fn take_updates(&mut self) -> Vec<TileUpdate> {
+        std::mem::take(&mut self.pending)
+    }
+}
+
+impl Device {
+    /// 予約済みリソースのタイル構成を取得する
+    pub fn get_resource_tiling(&self, resource: &Resource) -> ResourceTiling {
+        let desc = resource.desc();
+        let rd: &D3D12_RESOURCE_DESC = desc.as_ref();
+        let (array_size, depth) = if rd.Dimension == D3D12_RESOURCE_DIMENSION_TEXTURE3D {
+            (1, rd.DepthOrArraySize as u32)
+        } else {
+            (rd.DepthOrArraySize as u32, 1)
+        };
+        let mip_levels = match rd.MipLevels {
+            0 => full_mip_levels(rd.Width, rd.Height, depth),
+            n => n as u32,
+        };
+        let mut total_tiles = 0;
+        let mut packed: D3D12_PACKED_MIP_INFO = unsafe { std::mem::zeroed() };
+        let mut shape: D3D12_TILE_SHAPE = unsafe { std::mem::zeroed() };
+        let mut count = mip_levels * array_size;
+        let mut tilings: Vec<D3D12_SUBRESOURCE_TILING> =
+            vec![unsafe { std::mem::zeroed() }; count as usize];
+        unsafe {
+            (*self.0).GetResourceTiling(
+                resource.0,
+                &mut total_tiles,
+                &mut packed,
+                &mut shape,
+                &mut count,
+                0,
+                tilings.as_mut_ptr(),
+            );
+        }
+        tilings.truncate(count as usize);
+
+        ResourceTiling {
+            total_tiles,
+            mip_levels,
+            array_size,
+            tile_shape: TileShape {
+                width: shape.WidthInTexels,
+                height: shape.HeightInTexels,
+                depth: shape.DepthInTexels,
+            },
+            packed_mips: PackedMipInfo {
+                standard_mips: packed.NumStandardMips as _,
+                packed_mips: packed.NumPackedMips as _,
+                tile_count: packed.NumTilesForPackedMips,
+                start_tile: packed.StartTileIndexInOverallResource,
+            },
+            subresources: tilings
+                .into_iter()
+                .map(|t| SubresourceTiling {
+                    width_in_tiles: t.WidthInTiles,
+                    height_in_tiles: t.HeightInTiles as _,
+                    depth_in_tiles: t.DepthInTiles as _,
+                    start_tile: if t.StartTileIndexInOverallResource == D3D12_PACKED_TILE {
+                        None
+                    } else {
+                        Some(t.StartTileIndexInOverallResource)
+                    },
+                })
+                .collect(),
+        }
+    }
+}
+
+/// タイル範囲の扱い
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TileRange {
+    /// ヒープ上の連続したタイルに割り当てる
+    Heap { offset: u32, count: u32 },
+    /// 同じタイル1枚をcount回割り当てる
+    ReuseSingle { offset: u32, count: u32 },
+    /// マッピングを解除する
+    Null { count: u32 },
+    /// 変更しない
+    Skip { count: u32 },
+}
+impl TileRange {
+    fn decompose(self) -> (D3D12_TILE_RANGE_FLAGS, u32, u32) {
+        match self {
+            TileRange::Heap { offset, count } => (D3D12_TILE_RANGE_FLAG_NONE, offset, count),
+            TileRange::ReuseSingle { offset, count } => {
+                (D3D12_TILE_RANGE_FLAG_REUSE_SINGLE_TILE, offset, count)
+            }
+            TileRange::Null { count } => (D3D12_TILE_RANGE_FLAG_NULL, 0, count),
+            TileRange::Skip { count } => (D3D12_TILE_RANGE_FLAG_SKIP, 0, count),
+        }
+    }
+}
+
+impl CommandQueue {
+    /// タイルのマッピングを更新する
+    pub fn update_tile_mappings(
+        &mut self,
+        resource: &Resource,
+        regions: &[(D3D12_TILED_RESOURCE_COORDINATE, D3D12_TILE_REGION_SIZE)],
+        heap: Option<&Heap>,
+        ranges: &[TileRange],
+        flags: D3D12_TILE_MAPPING_FLAGS,
+    ) -> &mut Self {
+        let (coords, sizes): (Vec<_>, Vec<_>) = regions.iter().cloned().unzip();
+        let mut range_flags = Vec::with_capacity(ranges.len());
+        let mut offsets = Vec::with_capacity(ranges.len());
+        let mut counts = Vec::with_capacity(ranges.len());
+        for (f, o, c) in ranges.iter().map(|r| r.decompose()) {
+            range_flags.push(f);
+            offsets.push(o);
+            counts.push(c);
+        }
+        unsafe {
+            (*self.0).UpdateTileMappings(
+                resource.0,
+                regions.len() as _,
+                coords.as_ptr(),
+                sizes.as_ptr(),
+                heap.map_or(std::ptr::null_mut(), |h| h.0),
+                ranges.len() as _,
+                range_flags.as_ptr(),
+                offsets.as_ptr(),
+                counts.as_ptr(),
+                flags,
+            );
+        }
+        self
+    }
+    /// タイルのマッピングをコピーする
+    pub fn copy_tile_mappings(
+        &mut self,
+        dst: &Resource,
+        dst_start: &D3D12_TILED_RESOURCE_COORDINATE,
+        src: &Resource,
+        src_start: &D3D12_TILED_RESOURCE_COORDINATE,
+        size: &D3D12_TILE_REGION_SIZE,
+        flags: D3D12_TILE_MAPPING_FLAGS,
+    ) -> &mut Self {
+        unsafe {
+            (*self.0).CopyTileMappings(dst.0, dst_start, src.0, src_start, size, flags);
+        }
+        self
+    }
+    /// TileMapに溜まった変更をまとめて反映する(割り当てたタイルはpoolのヒープにマップする)
+    pub fn apply_tile_updates(
+        &mut self,
+        resource: &Resource,
+        pool: &TilePool,
+        tiling: &ResourceTiling,
+        updates: &[TileUpdate],
+    ) -> IOResult<&mut Self> {
+        let heap = match pool.heap() {
+            Some(h) => h,
+            None => {
+                return invalid(
+                    "tile pool has no heap (create it with Device::new_tile_pool)".to_owned(),
+                )
+            }
+        };
+        if updates.is_empty() {
+            return Ok(self);
+        }
+        let mut regions = Vec::with_capacity(updates.len());
+        let mut ranges = Vec::with_capacity(updates.len());
+        for u in updates {
+            let (coord, size) = tiling.region(u.key);
+            match u.heap_tiles {
+                Some(ref tiles) => {
+                    // 連続していない割り当ては範囲ごとに領域を分ける
+                    let mut first = 0;
+                    for r in tiles {
+                        let mut c = coord;
+                        let mut s = size;
+                        if let TileKey::MipTail { .. } = u.key {
+                            c.X = first;
+                        }
+                        s.NumTiles = r.end - r.start;
+                        regions.push((c, s));
+                        ranges.push(TileRange::Heap {
+                            offset: r.start,
+                            count: r.end - r.start,
+                        });
+                        first += r.end - r.start;
+                    }
+                }
+                None => {
+                    regions.push((coord, size));
+                    ranges.push(TileRange::Null {
+                        count: size.NumTiles,
+                    });
+                }
+            }
+        }
+
+        Ok(self.update_tile_mappings(
+            resource,
+            &regions,
+            Some(heap),
+            &ranges,
+            TILE_MAPPING_FLAG_NONE,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 256x256のRGBA8、4ミップ(最後の2つはパック)、2スライス
+    fn tiling() -> ResourceTiling {
+        let sub = |w, h, start| SubresourceTiling {
+            width_in_tiles: w,
+            height_in_tiles: h,
+            depth_in_tiles: 1,
+            start_tile: start,
+        };
+        ResourceTiling {
+            total_tiles: 12,
+            mip_levels: 4,
+            array_size: 2,
+            tile_shape: standard_tile_shape(DXGI_FORMAT_R8G8B8A8_UNORM, false).unwrap(),
+            packed_mips: PackedMipInfo {
+                standard_mips: 2,
+                packed_mips: 2,
+                tile_count: 1,
+                start_tile: 5,
+            },
+            subresources: vec![
+                sub(2, 2, Some(0)),
+                sub(1, 1, Some(4)),
+                sub(0, 0, None),
+                sub(0, 0, None),
+                sub(2, 2, Some(6)),
+                sub(1, 1, Some(10)),
+                sub(0, 0, None),
+                sub(0, 0, None),
+            ],
+        }
+    }
+
+    #[test]
+    fn standard_tile_shapes() {
+        let shape = |f, v| standard_tile_shape(f, v).map(|s| (s.width, s.height, s.depth));
+        assert_eq!(shape(DXGI_FORMAT_R8_UNORM, false), Some((256, 256, 1)));
+        assert_eq!(
+            shape(DXGI_FORMAT_R16G16B16A16_FLOAT, false),
+            Some((128, 64, 1))
+        );
+        assert_eq!(shape(DXGI_FORMAT_R32_FLOAT, true), Some((32, 32, 16)));
+        // 圧縮フォーマットはブロック単位
+        assert_eq!(shape(DXGI_FORMAT_BC1_UNORM, false), Some((512, 256, 1)));
+        assert_eq!(shape(DXGI_FORMAT_BC7_UNORM, false), Some((256, 256, 1)));
+        assert_eq!(shape(DXGI_FORMAT_UNKNOWN, false), None);
+    }
+
+    #[test]
+    fn tile_indices() {
+        let t = tiling();
+        assert_eq!(t.tiles_per_slice(), 6);
+        assert_eq!(
+            t.tile_at_texel(0, 0, 200, 130, 0),
+            Some(TileKey::Tile {
+                subresource: 0,
+                x: 1,
+                y: 1,
+                z: 0
+            })
+        );
+        assert_eq!(
+            t.tile_index(TileKey::Tile {
+                subresource: 0,
+                x: 1,
+                y: 1,
+                z: 0
+            }),
+            Some(3)
+        );
+        assert_eq!(
+            t.tile_index(TileKey::Tile {
+                subresource: 5,
+                x: 0,
+                y: 0,
+                z: 0
+            }),
+            Some(10)
+        );
+        assert_eq!(t.tile_at_texel(0, 0, 256, 0, 0), None);
+        assert_eq!(
+            t.tile_at_texel(3, 1, 0, 0, 0),
+            Some(TileKey::MipTail { slice: 1 })
+        );
+        assert_eq!(t.tile_index(TileKey::MipTail { slice: 1 }), Some(11));
+        assert_eq!(t.tile_index(TileKey::MipTail { slice: 2 }), None);
+        assert_eq!(t.mip_tiles(0, 1).len(), 4);
+        assert_eq!(t.mip_tiles(2, 0), vec![TileKey::MipTail { slice: 0 }]);
+        assert!(t.subresource_tiling(2, 0).is_none());
+
+        let (coord, size) = t.region(TileKey::MipTail { slice: 1 });
+        assert_eq!((coord.Subresource, size.NumTiles), (6, 1));
+    }
+
+    #[test]
+    fn pool_prefers_contiguous_ranges() {
+        let mut pool = TilePool::new(8);
+        assert!(pool.heap().is_none());
+        assert_eq!(pool.heap_size(), 8 * 65536);
+        let a = pool.allocate(3).unwrap();
+        let b = pool.allocate(2).unwrap();
+        let c = pool.allocate(3).unwrap();
+        assert_eq!((a, b, c), (vec![0..3], vec![3..5], vec![5..8]));
+        assert!(pool.allocate(1).is_none());
+
+        pool.release(&[0..3]);
+        pool.release(&[5..8]);
+        // 3枚の範囲に収まらない4枚は分割して割り当てる
+        assert_eq!(pool.allocate(4), Some(vec![0..3, 5..6]));
+        assert_eq!(pool.free_tiles(), 2);
+        pool.release(&[0..3, 3..5, 5..6]);
+        assert_eq!(pool.allocate(6), Some(vec![0..6]));
+    }
+
+    #[test]
+    fn tile_map_records_updates() {
+        let mut pool = TilePool::new(3);
+        let mut map = TileMap::new(tiling());
+        let tile = TileKey::Tile {
+            subresource: 4,
+            x: 1,
+            y: 0,
+            z: 0,
+        };
+        map.map(&mut pool, tile).unwrap();
+        map.map(&mut pool, tile).unwrap();
+        map.map(&mut pool, TileKey::MipTail { slice: 0 }).unwrap();
+        assert_eq!(map.mapped_tiles(), 2);
+        assert_eq!(map.heap_tiles(tile), Some(&[0..1][..]));
+        assert!(map
+            .map(
+                &mut pool,
+                TileKey::Tile {
+                    subresource: 4,
+                    x: 2,
+                    y: 0,
+                    z: 0
+                }
+            )
+            .is_err());
+
+        assert!(map.unmap(&mut pool, tile));
+        assert!(!map.unmap(&mut pool, tile));
+        assert_eq!(
+            map.take_updates(),
+            vec![
+                TileUpdate {
+                    key: tile,
+                    heap_tiles: Some(vec![0..1])
+                },
+                TileUpdate {
+                    key: TileKey::MipTail { slice: 0 },
+                    heap_tiles: Some(vec![1..2])
+                },
+                TileUpdate {
+                    key: tile,
+                    heap_tiles: None
+                },
+            ]
+        );
+        map.unmap_all(&mut pool);
+        assert_eq!(pool.free_tiles(), 3);
+        assert_eq!(map.take_updates().len(), 1);
+
+        let mut full = TilePool::new(0);
+        assert!(map.map(&mut full, tile).is_err());
+    }
+}
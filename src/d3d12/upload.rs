@@ -0,0 +1,413 @@
+//! Texture Upload Helpers
+
+use super::*;
+
+/// アップロードするサブリソース1つぶんのデータ
+#[derive(Debug, Clone, Copy)]
+pub struct SubresourceData<'a> {
+    pub data: &'a [u8],
+    /// 1行(圧縮フォーマットでは1ブロック行)ぶんのバイト間隔
+    pub row_pitch: usize,
+    /// 1スライス(3Dテクスチャの深さ方向1枚)ぶんのバイト間隔
+    pub slice_pitch: usize,
+}
+impl<'a> SubresourceData<'a> {
+    /// 隙間なく詰められたデータ
+    pub fn packed(data: &'a [u8], row_pitch: usize, row_count: usize) -> Self {
+        SubresourceData {
+            data,
+            row_pitch,
+            slice_pitch: row_pitch * row_count,
+        }
+    }
+}
+
+/// 完全なミップマップチェーンのレベル数
+pub fn full_mip_levels(width: u64, height: u32, depth: u32) -> u32 {
+    let largest = width.max(height as u64).max(depth as u64).max(1);
+    64 - largest.leading_zeros()
+}
+
+/// GetCopyableFootprintsと同じ計算をCPU側で行う(平面フォーマット以外)
+/// (CopyableFootprintのVec, TotalBytes)の順で返す。フォーマットが扱えない場合はNone
+pub fn copyable_footprints(
+    rd: &D3D12_RESOURCE_DESC,
+    subresource_range: std::ops::Range<u32>,
+    base_offset: u64,
+) -> Option<(Vec<CopyableFootprint>, u64)> {
+    if rd.Dimension == D3D12_RESOURCE_DIMENSION_BUFFER {
+        let fp = CopyableFootprint {
+            placed_footprint: D3D12_PLACED_SUBRESOURCE_FOOTPRINT {
+                Offset: base_offset,
+                Footprint: D3D12_SUBRESOURCE_FOOTPRINT {
+                    Format: DXGI_FORMAT_UNKNOWN,
+                    Width: rd.Width as _,
+                    Height: 1,
+                    Depth: 1,
+                    RowPitch: align_up(rd.Width, D3D12_TEXTURE_DATA_PITCH_ALIGNMENT as _) as _,
+                },
+            },
+            row_count: 1,
+            row_size_in_bytes: rd.Width,
+        };
+        return Some((vec![fp], rd.Width));
+    }
+
+    let block = dxgi::format_block_info(rd.Format)?;
+    let mip_levels = if rd.MipLevels == 0 {
+        let depth = if rd.Dimension == D3D12_RESOURCE_DIMENSION_TEXTURE3D {
+            rd.DepthOrArraySize as u32
+        } else {
+            1
+        };
+        full_mip_levels(rd.Width, rd.Height, depth)
+    } else {
+        rd.MipLevels as u32
+    };
+
+    let mut footprints = Vec::with_capacity(subresource_range.len());
+    let (mut offset, mut total) = (0u64, 0u64);
+    for sr in subresource_range {
+        let mip = sr % mip_levels;
+        let width = (rd.Width >> mip).max(1);
+        let height = if rd.Dimension == D3D12_RESOURCE_DIMENSION_TEXTURE1D {
+            1
+        } else {
+            (rd.Height >> mip).max(1)
+        };
+        let depth = if rd.Dimension == D3D12_RESOURCE_DIMENSION_TEXTURE3D {
+            (rd.DepthOrArraySize as u32 >> mip).max(1)
+        } else {
+            1
+        };
+        let aligned_width = align_up(width, block.width as _);
+        let aligned_height = align_up(height as _, block.height as _) as u32;
+        let row_count = aligned_height / block.height;
+        let row_size = aligned_width / block.width as u64 * block.bytes as u64;
+        let row_pitch = align_up(row_size, D3D12_TEXTURE_DATA_PITCH_ALIGNMENT as _);
+
+        offset = align_up(offset, D3D12_TEXTURE_DATA_PLACEMENT_ALIGNMENT as _);
+        footprints.push(CopyableFootprint {
+            placed_footprint: D3D12_PLACED_SUBRESOURCE_FOOTPRINT {
+                Offset: base_offset + offset,
+                Footprint: D3D12_SUBRESOURCE_FOOTPRINT {
+                    Format: rd.Format,
+                    Width: aligned_width as _,
+                    Height: aligned_height,
+                    Depth: depth,
+                    RowPitch: row_pitch as _,
+                },
+            },
+            row_count,
+            row_size_in_bytes: row_size,
+        });
+        total = offset + row_pitch * (row_count * depth - 1) as u64 + row_size;
+        offset += row_pitch * (row_count * depth) as u64;
+    }
+
+    Some((footprints, total))
+}
+
+/// フットプリントに従ってサブリソースのデータを行ごとにコピーする
+/// dstはフットプリントのOffsetの基準となる位置から始まるメモリ
+/// 数が合わない場合や、データ/書き込み先が足りない場合は何も書き込まずにエラーを返す
+pub fn write_subresources(
+    dst: &mut [u8],
+    footprints: &[CopyableFootprint],
+    subresources: &[SubresourceData],
+) -> IOResult<()> {
+    if footprints.len() != subresources.len() {
+        return invalid(format!(
+            "{} subresources were given for {} footprints",
+            subresources.len(),
+            footprints.len()
+        ));
+    }
+    for (n, (fp, sr)) in footprints.iter().zip(subresources).enumerate() {
+        let layout = &fp.placed_footprint;
+        let (rows, depth) = (fp.row_count as usize, layout.Footprint.Depth as usize);
+        if rows == 0 || depth == 0 {
+            continue;
+        }
+        let row_size = fp.row_size_in_bytes as usize;
+        let row_pitch = layout.Footprint.RowPitch as usize;
+        let src_end = (depth - 1) * sr.slice_pitch + (rows - 1) * sr.row_pitch + row_size;
+        if sr.data.len() < src_end {
+            return invalid(format!(
+                "subresource {} has {} bytes but the footprint reads {} bytes",
+                n,
+                sr.data.len(),
+                src_end
+            ));
+        }
+        let dst_end = layout.Offset as usize + (depth * rows - 1) * row_pitch + row_size;
+        if dst.len() < dst_end {
+            return invalid(format!(
+                "destination has {} bytes but subresource {} ends at {}",
+                dst.len(),
+                n,
+                dst_end
+            ));
+        }
+    }
+
+    for (fp, sr) in footprints.iter().zip(subresources) {
+        let layout = &fp.placed_footprint;
+        let row_size = fp.row_size_in_bytes as usize;
+        let row_pitch = layout.Footprint.RowPitch as usize;
+        let slice_pitch = row_pitch * fp.row_count as usize;
+        for z in 0..layout.Footprint.Depth as usize {
+            for y in 0..fp.row_count as usize {
+                let src = z * sr.slice_pitch + y * sr.row_pitch;
+                let d = layout.Offset as usize + z * slice_pitch + y * row_pitch;
+                dst[d..d + row_size].copy_from_slice(&sr.data[src..src + row_size]);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+impl Device {
+    /// テクスチャへのアップロードコマンドを積む
+    /// resourceの書き込むサブリソースはCopyDest状態であること。コピー後はそれらだけstate_afterに遷移する
+    /// 返されるアップロードバッファはコマンドの実行完了まで保持すること
+    pub fn upload_texture(
+        &self,
+        cmd: &mut GraphicsCommandList,
+        resource: &Resource,
+        first_subresource: u32,
+        subresources: &[SubresourceData],
+        state_after: ResourceState,
+    ) -> IOResult<Resource> {
+        let desc = resource.desc();
+        let (footprints, total_bytes) = self.get_copyable_footprints(
+            desc.as_ref(),
+            first_subresource..first_subresource + subresources.len() as u32,
+            0,
+        );
+        let footprints = footprints.collect::<Vec<_>>();
+
+        let mut upload = self.new_resource_committed(
+            &HeapProperty::upload(),
+            &ResourceDesc::buffer(total_bytes as _),
+            ResourceState::GenericRead,
+            None,
+        )?;
+        let ptr = upload.map(..0)?;
+        let written = write_subresources(
+            unsafe { std::slice::from_raw_parts_mut(ptr as *mut u8, total_bytes as _) },
+            &footprints,
+            subresources,
+        );
+        upload.unmap(..);
+        written?;
+
+        for (n, fp) in footprints.iter().enumerate() {
+            let src = TextureCopyLocation::with_placed_footprint(&upload, fp.placed_footprint);
+            let dst =
+                TextureCopyLocation::with_subresource_index(resource, first_subresource + n as u32);
+            cmd.copy_texture_region(&src, None, &dst, 0, 0, 0);
+        }
+        if state_after != ResourceState::CopyDest {
+            let barriers = (0..subresources.len() as u32)
+                .map(|n| {
+                    ResourceBarrier::transition_subresource(
+                        resource,
+                        first_subresource + n,
+                        ResourceState::CopyDest as _,
+                        state_after as _,
+                    )
+                })
+                .collect::<Vec<_>>();
+            cmd.resource_barrier(&barriers);
+        }
+
+        Ok(upload)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texture(
+        dimension: D3D12_RESOURCE_DIMENSION,
+        width: u64,
+        height: u32,
+        depth_or_array_size: u16,
+        mip_levels: u16,
+        format: dxgi::Format,
+    ) -> D3D12_RESOURCE_DESC {
+        D3D12_RESOURCE_DESC {
+            Dimension: dimension,
+            Width: width,
+            Height: height,
+            DepthOrArraySize: depth_or_array_size,
+            MipLevels: mip_levels,
+            Format: format,
+            Layout: D3D12_TEXTURE_LAYOUT_UNKNOWN,
+            ..*ResourceDesc::buffer(0).as_ref()
+        }
+    }
+    /// (Offset, Width, Height, Depth, RowPitch, row_count, row_size)
+    fn summary(fps: &[CopyableFootprint]) -> Vec<(u64, u32, u32, u32, u32, u32, u64)> {
+        fps.iter()
+            .map(|f| {
+                let p = &f.placed_footprint;
+                let fp = &p.Footprint;
+                (
+                    p.Offset,
+                    fp.Width,
+                    fp.Height,
+                    fp.Depth,
+                    fp.RowPitch,
+                    f.row_count,
+                    f.row_size_in_bytes,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn mip_levels() {
+        assert_eq!(full_mip_levels(1, 1, 1), 1);
+        assert_eq!(full_mip_levels(256, 256, 1), 9);
+        assert_eq!(full_mip_levels(300, 7, 1), 9);
+        assert_eq!(full_mip_levels(4, 4, 64), 7);
+    }
+
+    #[test]
+    fn buffer_footprint() {
+        let (fps, total) =
+            copyable_footprints(ResourceDesc::buffer(300).as_ref(), 0..1, 64).unwrap();
+        assert_eq!(summary(&fps), vec![(64, 300, 1, 1, 512, 1, 300)]);
+        assert_eq!(total, 300);
+    }
+
+    #[test]
+    fn mip_chain_is_pitch_and_placement_aligned() {
+        let rd = texture(
+            D3D12_RESOURCE_DIMENSION_TEXTURE2D,
+            100,
+            60,
+            1,
+            0,
+            DXGI_FORMAT_R8G8B8A8_UNORM,
+        );
+        let (fps, total) = copyable_footprints(&rd, 0..7, 0).unwrap();
+        assert_eq!(
+            summary(&fps),
+            vec![
+                (0, 100, 60, 1, 512, 60, 400),
+                (30720, 50, 30, 1, 256, 30, 200),
+                (38400, 25, 15, 1, 256, 15, 100),
+                (42496, 12, 7, 1, 256, 7, 48),
+                (44544, 6, 3, 1, 256, 3, 24),
+                (45568, 3, 1, 1, 256, 1, 12),
+                (46080, 1, 1, 1, 256, 1, 4),
+            ]
+        );
+        assert_eq!(total, 46084);
+
+        // 途中のサブリソースからでも先頭から詰める
+        let (fps, total) = copyable_footprints(&rd, 1..2, 1024).unwrap();
+        assert_eq!(summary(&fps), vec![(1024, 50, 30, 1, 256, 30, 200)]);
+        assert_eq!(total, 256 * 29 + 200);
+    }
+
+    #[test]
+    fn array_slices_restart_the_mip_chain() {
+        let rd = texture(
+            D3D12_RESOURCE_DIMENSION_TEXTURE2D,
+            4,
+            4,
+            2,
+            2,
+            DXGI_FORMAT_R32_FLOAT,
+        );
+        let (fps, _) = copyable_footprints(&rd, 1..3, 0).unwrap();
+        assert_eq!(
+            summary(&fps),
+            vec![(0, 2, 2, 1, 256, 2, 8), (512, 4, 4, 1, 256, 4, 16)]
+        );
+    }
+
+    #[test]
+    fn block_compressed_rows_are_block_rows() {
+        let rd = texture(
+            D3D12_RESOURCE_DIMENSION_TEXTURE2D,
+            10,
+            10,
+            1,
+            3,
+            DXGI_FORMAT_BC1_UNORM,
+        );
+        let (fps, total) = copyable_footprints(&rd, 0..3, 0).unwrap();
+        assert_eq!(
+            summary(&fps),
+            vec![
+                (0, 12, 12, 1, 256, 3, 24),
+                (1024, 8, 8, 1, 256, 2, 16),
+                (1536, 4, 4, 1, 256, 1, 8),
+            ]
+        );
+        assert_eq!(total, 1544);
+    }
+
+    #[test]
+    fn volume_slices_follow_rows() {
+        let rd = texture(
+            D3D12_RESOURCE_DIMENSION_TEXTURE3D,
+            8,
+            4,
+            4,
+            0,
+            DXGI_FORMAT_R8_UNORM,
+        );
+        let (fps, total) = copyable_footprints(&rd, 0..3, 0).unwrap();
+        assert_eq!(
+            summary(&fps),
+            vec![
+                (0, 8, 4, 4, 256, 4, 8),
+                (4096, 4, 2, 2, 256, 2, 4),
+                (5120, 2, 1, 1, 256, 1, 2),
+            ]
+        );
+        assert_eq!(total, 5122);
+        let unknown = texture(
+            D3D12_RESOURCE_DIMENSION_TEXTURE2D,
+            8,
+            8,
+            1,
+            1,
+            DXGI_FORMAT_UNKNOWN,
+        );
+        assert!(copyable_footprints(&unknown, 0..1, 0).is_none());
+    }
+
+    #[test]
+    fn write_rows_with_pitch() {
+        let rd = texture(
+            D3D12_RESOURCE_DIMENSION_TEXTURE2D,
+            2,
+            2,
+            1,
+            1,
+            DXGI_FORMAT_R8G8_UNORM,
+        );
+        let (fps, total) = copyable_footprints(&rd, 0..1, 0).unwrap();
+        let data = [1, 2, 3, 4, 0xee, 5, 6, 7, 8];
+        let mut dst = vec![0; total as usize];
+        write_subresources(&mut dst, &fps, &[SubresourceData::packed(&data, 5, 2)]).unwrap();
+        assert_eq!(&dst[..4], &[1, 2, 3, 4]);
+        assert_eq!(&dst[256..260], &[5, 6, 7, 8]);
+
+        // 足りないデータやコピー先、数の不一致はエラー
+        let short = SubresourceData::packed(&data[..8], 5, 2);
+        assert!(write_subresources(&mut dst, &fps, &[short]).is_err());
+        let sr = SubresourceData::packed(&data, 5, 2);
+        assert!(write_subresources(&mut dst[..259], &fps, &[sr]).is_err());
+        assert!(write_subresources(&mut dst, &fps, &[sr, sr]).is_err());
+    }
+}
@@ -0,0 +1,401 @@
+//! Resource Description Validation
+
+use super::*;
+
+fn is_depth_format(format: DXGI_FORMAT) -> bool {
+    matches!(
+        format,
+        DXGI_FORMAT_D32_FLOAT
+            | DXGI_FORMAT_D24_UNORM_S8_UINT
+            | DXGI_FORMAT_D16_UNORM
+            | DXGI_FORMAT_D32_FLOAT_S8X24_UINT
+            | DXGI_FORMAT_R32_TYPELESS
+            | DXGI_FORMAT_R24G8_TYPELESS
+            | DXGI_FORMAT_R16_TYPELESS
+            | DXGI_FORMAT_R32G8X24_TYPELESS
+    )
+}
+
+/// リソースの記述が作成可能な組み合わせになっているか調べる(デバイスに依存しない範囲で)
+pub fn validate_resource_desc(desc: &D3D12_RESOURCE_DESC) -> IOResult<()> {
+    let flags = desc.Flags;
+    let has = |f| flags & f != 0;
+    let rt = has(D3D12_RESOURCE_FLAG_ALLOW_RENDER_TARGET);
+    let ds = has(D3D12_RESOURCE_FLAG_ALLOW_DEPTH_STENCIL);
+    let uav = has(D3D12_RESOURCE_FLAG_ALLOW_UNORDERED_ACCESS);
+    let simultaneous = has(D3D12_RESOURCE_FLAG_ALLOW_SIMULTANEOUS_ACCESS);
+    let samples = desc.SampleDesc.Count;
+
+    if samples == 0 {
+        return invalid("SampleDesc.Count must be at least 1".to_owned());
+    }
+
+    if desc.Dimension == D3D12_RESOURCE_DIMENSION_BUFFER {
+        if desc.Width == 0 {
+            return invalid("buffer size must not be zero".to_owned());
+        }
+        if desc.Alignment != 0
+            && desc.Alignment != D3D12_DEFAULT_RESOURCE_PLACEMENT_ALIGNMENT as u64
+        {
+            return invalid(format!(
+                "buffer alignment must be 0 or 64KB (got {})",
+                desc.Alignment
+            ));
+        }
+        if desc.Height != 1 || desc.DepthOrArraySize != 1 || desc.MipLevels != 1 {
+            return invalid(format!(
+                "buffer must have Height, DepthOrArraySize and MipLevels of 1 (got {}, {}, {})",
+                desc.Height, desc.DepthOrArraySize, desc.MipLevels
+            ));
+        }
+        if desc.Format != DXGI_FORMAT_UNKNOWN {
+            return invalid(format!(
+                "buffer format must be DXGI_FORMAT_UNKNOWN (got {})",
+                desc.Format
+            ));
+        }
+        if samples != 1 || desc.SampleDesc.Quality != 0 {
+            return invalid("buffer cannot be multisampled".to_owned());
+        }
+        if desc.Layout != D3D12_TEXTURE_LAYOUT_ROW_MAJOR {
+            return invalid("buffer layout must be D3D12_TEXTURE_LAYOUT_ROW_MAJOR".to_owned());
+        }
+        if rt || ds {
+            return invalid("buffer cannot be a render target or a depth stencil".to_owned());
+        }
+        if simultaneous {
+            return invalid(
+                "ALLOW_SIMULTANEOUS_ACCESS cannot be set on a buffer (buffers always allow it)"
+                    .to_owned(),
+            );
+        }
+        return Ok(());
+    }
+
+    let (volume, max_extent, max_array) = match desc.Dimension {
+        D3D12_RESOURCE_DIMENSION_TEXTURE1D => (
+            false,
+            D3D12_REQ_TEXTURE1D_U_DIMENSION,
+            D3D12_REQ_TEXTURE1D_ARRAY_AXIS_DIMENSION,
+        ),
+        D3D12_RESOURCE_DIMENSION_TEXTURE2D => (
+            false,
+            D3D12_REQ_TEXTURE2D_U_OR_V_DIMENSION,
+            D3D12_REQ_TEXTURE2D_ARRAY_AXIS_DIMENSION,
+        ),
+        D3D12_RESOURCE_DIMENSION_TEXTURE3D => (
+            true,
+            D3D12_REQ_TEXTURE3D_U_V_OR_W_DIMENSION,
+            D3D12_REQ_TEXTURE3D_U_V_OR_W_DIMENSION,
+        ),
+        d => return invalid(format!("unknown resource dimension {}", d)),
+    };
+
+    // 大きさ
+    if desc.Width == 0 || desc.Height == 0 || desc.DepthOrArraySize == 0 {
+        return invalid(format!(
+            "texture extent must not be zero (got {}x{}x{})",
+            desc.Width, desc.Height, desc.DepthOrArraySize
+        ));
+    }
+    if desc.Dimension == D3D12_RESOURCE_DIMENSION_TEXTURE1D && desc.Height != 1 {
+        return invalid(format!(
+            "1D texture must have Height of 1 (got {})",
+            desc.Height
+        ));
+    }
+    if desc.Width > max_extent as u64 || desc.Height > max_extent {
+        return invalid(format!(
+            "texture extent {}x{} exceeds the limit of {}",
+            desc.Width, desc.Height, max_extent
+        ));
+    }
+    if desc.DepthOrArraySize as u32 > max_array {
+        return invalid(format!(
+            "{} {} exceeds the limit of {}",
+            if volume { "depth" } else { "array size" },
+            desc.DepthOrArraySize,
+            max_array
+        ));
+    }
+
+    // フォーマット
+    if desc.Format == DXGI_FORMAT_UNKNOWN {
+        return invalid("texture format must not be DXGI_FORMAT_UNKNOWN".to_owned());
+    }
+    let block = dxgi::format_block_info(desc.Format);
+    if let Some(b) = block.filter(|b| b.is_compressed()) {
+        if desc.Width % b.width as u64 != 0 || desc.Height % b.height != 0 {
+            return invalid(format!(
+                "{}x{} is not a multiple of the {}x{} block size of format {}",
+                desc.Width, desc.Height, b.width, b.height, desc.Format
+            ));
+        }
+        if rt || ds || uav {
+            return invalid(format!(
+                "block-compressed format {} cannot be a render target, depth stencil or UAV",
+                desc.Format
+            ));
+        }
+    }
+
+    // ミップ
+    let depth = if volume {
+        desc.DepthOrArraySize as u32
+    } else {
+        1
+    };
+    let max_mips = full_mip_levels(desc.Width, desc.Height, depth);
+    if desc.MipLevels as u32 > max_mips {
+        return invalid(format!(
+            "{} mip levels requested but a {}x{}x{} texture has at most {}",
+            desc.MipLevels, desc.Width, desc.Height, depth, max_mips
+        ));
+    }
+
+    // フラグの組み合わせ
+    if ds {
+        if rt || uav {
+            return invalid(
+                "ALLOW_DEPTH_STENCIL cannot be combined with ALLOW_RENDER_TARGET or ALLOW_UNORDERED_ACCESS"
+                    .to_owned(),
+            );
+        }
+        if simultaneous {
+            return invalid(
+                "ALLOW_DEPTH_STENCIL cannot be combined with ALLOW_SIMULTANEOUS_ACCESS".to_owned(),
+            );
+        }
+        if volume {
+            return invalid("3D textures cannot be depth stencils".to_owned());
+        }
+        if !is_depth_format(desc.Format) {
+            return invalid(format!(
+                "ALLOW_DEPTH_STENCIL requires a depth format (got {})",
+                desc.Format
+            ));
+        }
+    } else if has(D3D12_RESOURCE_FLAG_DENY_SHADER_RESOURCE) {
+        return invalid("DENY_SHADER_RESOURCE requires ALLOW_DEPTH_STENCIL".to_owned());
+    }
+
+    // マルチサンプル
+    if samples > 1 {
+        if desc.Dimension != D3D12_RESOURCE_DIMENSION_TEXTURE2D {
+            return invalid("only 2D textures can be multisampled".to_owned());
+        }
+        if desc.MipLevels != 1 {
+            return invalid(format!(
+                "multisampled textures must have exactly 1 mip level (got {})",
+                desc.MipLevels
+            ));
+        }
+        if uav {
+            return invalid("multisampled textures cannot allow unordered access".to_owned());
+        }
+        if simultaneous {
+            return invalid("multisampled textures cannot allow simultaneous access".to_owned());
+        }
+    }
+
+    // レイアウト
+    if desc.Layout == D3D12_TEXTURE_LAYOUT_ROW_MAJOR
+        && (desc.Dimension != D3D12_RESOURCE_DIMENSION_TEXTURE2D
+            || desc.MipLevels != 1
+            || desc.DepthOrArraySize != 1
+            || samples != 1
+            || ds)
+    {
+        return invalid(
+            "row-major textures must be single-mip, non-array, non-multisampled 2D textures without depth stencil"
+                .to_owned(),
+        );
+    }
+
+    // アラインメント
+    if desc.Alignment > u32::MAX as u64 {
+        return invalid(format!(
+            "texture alignment must be 0, 4KB, 64KB or 4MB (got {})",
+            desc.Alignment
+        ));
+    }
+    match desc.Alignment as u32 {
+        0 => (),
+        D3D12_SMALL_RESOURCE_PLACEMENT_ALIGNMENT => {
+            if samples > 1 {
+                return invalid(
+                    "4KB alignment is not available for multisampled textures (use 64KB or 4MB)"
+                        .to_owned(),
+                );
+            }
+            if rt || ds {
+                return invalid(
+                    "4KB alignment is not available for render targets or depth stencils"
+                        .to_owned(),
+                );
+            }
+            // 最も詳細なミップが64KBに収まること
+            if let Some(b) = block {
+                let bytes = (desc.Width / b.width as u64)
+                    * (desc.Height / b.height) as u64
+                    * depth as u64
+                    * b.bytes as u64;
+                if bytes > D3D12_DEFAULT_RESOURCE_PLACEMENT_ALIGNMENT as u64 {
+                    return invalid(format!(
+                        "4KB alignment requires the most detailed mip to fit in 64KB ({} bytes)",
+                        bytes
+                    ));
+                }
+            }
+        }
+        D3D12_DEFAULT_RESOURCE_PLACEMENT_ALIGNMENT => (),
+        D3D12_DEFAULT_MSAA_RESOURCE_PLACEMENT_ALIGNMENT if samples > 1 => (),
+        D3D12_DEFAULT_MSAA_RESOURCE_PLACEMENT_ALIGNMENT => {
+            return invalid("4MB alignment is only for multisampled textures".to_owned())
+        }
+        a => {
+            return invalid(format!(
+                "texture alignment must be 0, 4KB, 64KB or 4MB (got {})",
+                a
+            ))
+        }
+    }
+
+    Ok(())
+}
+
+impl ResourceDesc {
+    /// 作成可能な組み合わせになっているか調べる(ミップ数、アラインメント、フラグ、マルチサンプルなど)
+    pub fn validate(&self) -> IOResult<()> {
+        validate_resource_desc(&self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::ErrorKind;
+
+    fn tex2d(
+        width: u64,
+        height: u32,
+        mip_levels: u16,
+        format: dxgi::Format,
+    ) -> D3D12_RESOURCE_DESC {
+        D3D12_RESOURCE_DESC {
+            Dimension: D3D12_RESOURCE_DIMENSION_TEXTURE2D,
+            Width: width,
+            Height: height,
+            MipLevels: mip_levels,
+            Format: format,
+            Layout: D3D12_TEXTURE_LAYOUT_UNKNOWN,
+            ..*ResourceDesc::buffer(0).as_ref()
+        }
+    }
+    fn rejected(desc: &D3D12_RESOURCE_DESC) -> String {
+        let e = validate_resource_desc(desc).expect_err("should be rejected");
+        assert_eq!(e.kind(), ErrorKind::InvalidInput);
+        e.to_string()
+    }
+
+    #[test]
+    fn buffers() {
+        assert!(validate_resource_desc(ResourceDesc::buffer(256).as_ref()).is_ok());
+        assert!(rejected(ResourceDesc::buffer(0).as_ref()).contains("size"));
+        let b = D3D12_RESOURCE_DESC {
+            Alignment: 4096,
+            ..*ResourceDesc::buffer(256).as_ref()
+        };
+        assert!(rejected(&b).contains("alignment"));
+        let b = D3D12_RESOURCE_DESC {
+            Flags: D3D12_RESOURCE_FLAG_ALLOW_RENDER_TARGET,
+            ..*ResourceDesc::buffer(256).as_ref()
+        };
+        assert!(rejected(&b).contains("render target"));
+    }
+
+    #[test]
+    fn mip_levels() {
+        let rgba = DXGI_FORMAT_R8G8B8A8_UNORM;
+        assert!(validate_resource_desc(&tex2d(256, 128, 9, rgba)).is_ok());
+        assert!(validate_resource_desc(&tex2d(256, 128, 0, rgba)).is_ok());
+        assert!(rejected(&tex2d(256, 128, 10, rgba)).contains("at most 9"));
+        let volume = D3D12_RESOURCE_DESC {
+            Dimension: D3D12_RESOURCE_DIMENSION_TEXTURE3D,
+            DepthOrArraySize: 64,
+            ..tex2d(4, 4, 7, rgba)
+        };
+        assert!(validate_resource_desc(&volume).is_ok());
+        let array = D3D12_RESOURCE_DESC {
+            DepthOrArraySize: 64,
+            ..tex2d(4, 4, 7, rgba)
+        };
+        assert!(rejected(&array).contains("at most 3"));
+    }
+
+    #[test]
+    fn block_compressed() {
+        assert!(validate_resource_desc(&tex2d(64, 32, 1, DXGI_FORMAT_BC1_UNORM)).is_ok());
+        assert!(rejected(&tex2d(62, 32, 1, DXGI_FORMAT_BC1_UNORM)).contains("4x4 block"));
+        let rt = D3D12_RESOURCE_DESC {
+            Flags: D3D12_RESOURCE_FLAG_ALLOW_RENDER_TARGET,
+            ..tex2d(64, 32, 1, DXGI_FORMAT_BC1_UNORM)
+        };
+        assert!(rejected(&rt).contains("block-compressed"));
+    }
+
+    #[test]
+    fn depth_stencil_and_multisample() {
+        let ds = D3D12_RESOURCE_DESC {
+            Flags: D3D12_RESOURCE_FLAG_ALLOW_DEPTH_STENCIL,
+            ..tex2d(1920, 1080, 1, DXGI_FORMAT_D32_FLOAT)
+        };
+        assert!(validate_resource_desc(&ds).is_ok());
+        let ds_color = D3D12_RESOURCE_DESC {
+            Format: DXGI_FORMAT_R8G8B8A8_UNORM,
+            ..ds
+        };
+        assert!(rejected(&ds_color).contains("depth format"));
+        let ds_uav = D3D12_RESOURCE_DESC {
+            Flags: D3D12_RESOURCE_FLAG_ALLOW_DEPTH_STENCIL
+                | D3D12_RESOURCE_FLAG_ALLOW_UNORDERED_ACCESS,
+            ..ds
+        };
+        assert!(rejected(&ds_uav).contains("ALLOW_DEPTH_STENCIL"));
+        let deny = D3D12_RESOURCE_DESC {
+            Flags: D3D12_RESOURCE_FLAG_DENY_SHADER_RESOURCE,
+            ..tex2d(16, 16, 1, DXGI_FORMAT_R8G8B8A8_UNORM)
+        };
+        assert!(rejected(&deny).contains("DENY_SHADER_RESOURCE"));
+
+        let mut msaa = ds;
+        msaa.SampleDesc.Count = 4;
+        assert!(validate_resource_desc(&msaa).is_ok());
+        msaa.MipLevels = 2;
+        assert!(rejected(&msaa).contains("exactly 1 mip"));
+    }
+
+    #[test]
+    fn alignment() {
+        let small = D3D12_RESOURCE_DESC {
+            Alignment: D3D12_SMALL_RESOURCE_PLACEMENT_ALIGNMENT as u64,
+            ..tex2d(128, 128, 1, DXGI_FORMAT_R8G8B8A8_UNORM)
+        };
+        assert!(validate_resource_desc(&small).is_ok());
+        let too_large = D3D12_RESOURCE_DESC {
+            Width: 256,
+            ..small
+        };
+        assert!(rejected(&too_large).contains("64KB (131072 bytes)"));
+        let msaa_only = D3D12_RESOURCE_DESC {
+            Alignment: D3D12_DEFAULT_MSAA_RESOURCE_PLACEMENT_ALIGNMENT as u64,
+            ..small
+        };
+        assert!(rejected(&msaa_only).contains("4MB"));
+        let odd = D3D12_RESOURCE_DESC {
+            Alignment: 512,
+            ..small
+        };
+        assert!(rejected(&odd).contains("got 512"));
+    }
+}
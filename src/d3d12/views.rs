@@ -0,0 +1,836 @@
+//! Resource Views and Descriptor Copies
+
+use super::*;
+
+/// D3D12_DEFAULT_SHADER_4_COMPONENT_MAPPING(RGBAをそのまま読む)
+pub const DEFAULT_SHADER_4_COMPONENT_MAPPING: u32 = 0x1688;
+/// 定数バッファビューの大きさの単位
+pub const CONSTANT_BUFFER_ALIGNMENT: u32 = D3D12_CONSTANT_BUFFER_DATA_PLACEMENT_ALIGNMENT;
+/// UAVカウンタの位置の単位
+pub const UAV_COUNTER_ALIGNMENT: u64 = D3D12_UAV_COUNTER_PLACEMENT_ALIGNMENT as _;
+
+/// バッファビューの要素の解釈
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BufferViewLayout {
+    /// フォーマットつき(Buffer<T>)
+    Typed,
+    /// 構造化バッファ(要素のバイト数)
+    Structured(u32),
+    /// バイトアドレスバッファ(R32_TYPELESSで4バイト単位)
+    Raw,
+}
+
+/// ビューの次元
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ViewDimension {
+    Buffer {
+        first_element: u64,
+        num_elements: u32,
+        layout: BufferViewLayout,
+        /// UAVカウンタの位置(カウンタリソース内のバイトオフセット)
+        counter_offset: u64,
+    },
+    Texture1D,
+    Texture1DArray,
+    Texture2D,
+    Texture2DArray,
+    Texture2DMS,
+    Texture2DMSArray,
+    Texture3D,
+    TextureCube,
+    TextureCubeArray,
+}
+
+/// ビューの記述(SRV/UAV/RTV/DSVの記述に変換して使う)
+///
+/// ミップ: SRVではmost_detailed_mipからmip_levels段(u32::MAXで残りすべて)、
+/// それ以外ではmost_detailed_mipの1段だけを使う
+/// 配列: first_array_slice/array_size(キューブでは面の数、3DのUAV/RTVではW方向のスライス)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ViewDesc {
+    pub format: dxgi::Format,
+    pub dimension: ViewDimension,
+    pub most_detailed_mip: u32,
+    pub mip_levels: u32,
+    pub first_array_slice: u32,
+    pub array_size: u32,
+    pub plane_slice: u32,
+    pub min_lod_clamp: f32,
+    pub component_mapping: u32,
+}
+impl ViewDesc {
+    fn new(format: dxgi::Format, dimension: ViewDimension) -> Self {
+        ViewDesc {
+            format,
+            dimension,
+            most_detailed_mip: 0,
+            mip_levels: u32::MAX,
+            first_array_slice: 0,
+            array_size: 1,
+            plane_slice: 0,
+            min_lod_clamp: 0.0,
+            component_mapping: DEFAULT_SHADER_4_COMPONENT_MAPPING,
+        }
+    }
+    /// フォーマットつきバッファ
+    pub fn typed_buffer(format: dxgi::Format, first_element: u64, num_elements: u32) -> Self {
+        Self::new(
+            format,
+            ViewDimension::Buffer {
+                first_element,
+                num_elements,
+                layout: BufferViewLayout::Typed,
+                counter_offset: 0,
+            },
+        )
+    }
+    /// 構造化バッファ
+    pub fn structured_buffer(stride: u32, first_element: u64, num_elements: u32) -> Self {
+        Self::new(
+            DXGI_FORMAT_UNKNOWN,
+            ViewDimension::Buffer {
+                first_element,
+                num_elements,
+                layout: BufferViewLayout::Structured(stride),
+                counter_offset: 0,
+            },
+        )
+    }
+    /// バイトアドレスバッファ(要素は4バイト単位)
+    pub fn raw_buffer(first_element: u64, num_elements: u32) -> Self {
+        Self::new(
+            DXGI_FORMAT_R32_TYPELESS,
+            ViewDimension::Buffer {
+                first_element,
+                num_elements,
+                layout: BufferViewLayout::Raw,
+                counter_offset: 0,
+            },
+        )
+    }
+    /// テクスチャ/バッファ全体を見るビュー(次元はリソースの記述から決める)
+    /// フォーマットの指定がないバッファはバイトアドレスバッファとして見る
+    pub fn for_resource(desc: &D3D12_RESOURCE_DESC) -> IOResult<Self> {
+        let array = desc.DepthOrArraySize as u32;
+        let dimension = match desc.Dimension {
+            D3D12_RESOURCE_DIMENSION_BUFFER => {
+                return Ok(if desc.Format == DXGI_FORMAT_UNKNOWN {
+                    Self::raw_buffer(0, (desc.Width / 4) as _)
+                } else {
+                    let bytes = dxgi::format_block_info(desc.Format).map_or(0, |b| b.bytes);
+                    if bytes == 0 {
+                        return invalid(format!(
+                            "cannot derive a buffer view for format {}",
+                            desc.Format
+                        ));
+                    }
+                    Self::typed_buffer(desc.Format, 0, (desc.Width / bytes as u64) as _)
+                });
+            }
+            D3D12_RESOURCE_DIMENSION_TEXTURE1D if array > 1 => ViewDimension::Texture1DArray,
+            D3D12_RESOURCE_DIMENSION_TEXTURE1D => ViewDimension::Texture1D,
+            D3D12_RESOURCE_DIMENSION_TEXTURE2D if desc.SampleDesc.Count > 1 && array > 1 => {
+                ViewDimension::Texture2DMSArray
+            }
+            D3D12_RESOURCE_DIMENSION_TEXTURE2D if desc.SampleDesc.Count > 1 => {
+                ViewDimension::Texture2DMS
+            }
+            D3D12_RESOURCE_DIMENSION_TEXTURE2D if array > 1 => ViewDimension::Texture2DArray,
+            D3D12_RESOURCE_DIMENSION_TEXTURE2D => ViewDimension::Texture2D,
+            D3D12_RESOURCE_DIMENSION_TEXTURE3D => ViewDimension::Texture3D,
+            d => return invalid(format!("unknown resource dimension {}", d)),
+        };
+        let mut v = Self::new(desc.Format, dimension);
+        // MipLevels = 0は完全なミップチェーン
+        v.mip_levels = if desc.MipLevels == 0 {
+            u32::MAX
+        } else {
+            desc.MipLevels as _
+        };
+        v.array_size = if dimension == ViewDimension::Texture3D {
+            u32::MAX
+        } else {
+            array
+        };
+
+        Ok(v)
+    }
+
+    /// フォーマットを変える(型なしフォーマットのリソースなど)
+    pub fn format(mut self, format: dxgi::Format) -> Self {
+        self.format = format;
+        self
+    }
+    /// 使うミップの範囲
+    pub fn mips(mut self, most_detailed_mip: u32, mip_levels: u32) -> Self {
+        self.most_detailed_mip = most_detailed_mip;
+        self.mip_levels = mip_levels;
+        self
+    }
+    /// 使う配列要素の範囲(配列でなければ配列のビューにする)
+    pub fn array_slices(mut self, first: u32, size: u32) -> Self {
+        self.dimension = match self.dimension {
+            ViewDimension::Texture1D => ViewDimension::Texture1DArray,
+            ViewDimension::Texture2D => ViewDimension::Texture2DArray,
+            ViewDimension::Texture2DMS => ViewDimension::Texture2DMSArray,
+            ViewDimension::TextureCube => ViewDimension::TextureCubeArray,
+            d => d,
+        };
+        self.first_array_slice = first;
+        self.array_size = size;
+        self
+    }
+    pub fn plane(mut self, plane_slice: u32) -> Self {
+        self.plane_slice = plane_slice;
+        self
+    }
+    pub fn min_lod_clamp(mut self, clamp: f32) -> Self {
+        self.min_lod_clamp = clamp;
+        self
+    }
+    pub fn component_mapping(mut self, mapping: u32) -> Self {
+        self.component_mapping = mapping;
+        self
+    }
+    /// UAVカウンタの位置(4096バイト境界)
+    pub fn counter_offset(mut self, offset: u64) -> Self {
+        if let ViewDimension::Buffer {
+            ref mut counter_offset,
+            ..
+        } = self.dimension
+        {
+            *counter_offset = offset;
+        }
+        self
+    }
+    /// 2D配列をキューブ(6要素ごとに1つ)として見る(SRVのみ)
+    pub fn cube(mut self) -> IOResult<Self> {
+        match self.dimension {
+            ViewDimension::Texture2D | ViewDimension::Texture2DArray => (),
+            d => return invalid(format!("{:?} cannot be viewed as a cube", d)),
+        }
+        if self.array_size == 0 || self.array_size % 6 != 0 {
+            return invalid(format!(
+                "cube views need a multiple of 6 array slices (got {})",
+                self.array_size
+            ));
+        }
+        self.dimension = if self.array_size == 6 {
+            ViewDimension::TextureCube
+        } else {
+            ViewDimension::TextureCubeArray
+        };
+        Ok(self)
+    }
+
+    /// シェーダリソースビューの記述
+    pub fn srv(&self) -> IOResult<D3D12_SHADER_RESOURCE_VIEW_DESC> {
+        let mut d = D3D12_SHADER_RESOURCE_VIEW_DESC {
+            Format: self.format,
+            ViewDimension: 0,
+            Shader4ComponentMapping: self.component_mapping,
+            u: unsafe { std::mem::zeroed() },
+        };
+        let (mip, levels, clamp) = (self.most_detailed_mip, self.mip_levels, self.min_lod_clamp);
+        let (first, size) = (self.first_array_slice, self.array_size);
+        unsafe {
+            match self.dimension {
+                ViewDimension::Buffer {
+                    first_element,
+                    num_elements,
+                    layout,
+                    ..
+                } => {
+                    d.ViewDimension = D3D12_SRV_DIMENSION_BUFFER;
+                    let b = d.u.Buffer_mut();
+                    b.FirstElement = first_element;
+                    b.NumElements = num_elements;
+                    match layout {
+                        BufferViewLayout::Typed => (),
+                        BufferViewLayout::Structured(stride) => b.StructureByteStride = stride,
+                        BufferViewLayout::Raw => b.Flags = D3D12_BUFFER_SRV_FLAG_RAW,
+                    }
+                }
+                ViewDimension::Texture1D => {
+                    d.ViewDimension = D3D12_SRV_DIMENSION_TEXTURE1D;
+                    *d.u.Texture1D_mut() = D3D12_TEX1D_SRV {
+                        MostDetailedMip: mip,
+                        MipLevels: levels,
+                        ResourceMinLODClamp: clamp,
+                    };
+                }
+                ViewDimension::Texture1DArray => {
+                    d.ViewDimension = D3D12_SRV_DIMENSION_TEXTURE1DARRAY;
+                    *d.u.Texture1DArray_mut() = D3D12_TEX1D_ARRAY_SRV {
+                        MostDetailedMip: mip,
+                        MipLevels: levels,
+                        FirstArraySlice: first,
+                        ArraySize: size,
+                        ResourceMinLODClamp: clamp,
+                    };
+                }
+                ViewDimension::Texture2D => {
+                    d.ViewDimension = D3D12_SRV_DIMENSION_TEXTURE2D;
+                    *d.u.Texture2D_mut() = D3D12_TEX2D_SRV {
+                        MostDetailedMip: mip,
+                        MipLevels: levels,
+                        PlaneSlice: self.plane_slice,
+                        ResourceMinLODClamp: clamp,
+                    };
+                }
+                ViewDimension::Texture2DArray => {
+                    d.ViewDimension = D3D12_SRV_DIMENSION_TEXTURE2DARRAY;
+                    *d.u.Texture2DArray_mut() = D3D12_TEX2D_ARRAY_SRV {
+                        MostDetailedMip: mip,
+                        MipLevels: levels,
+                        FirstArraySlice: first,
+                        ArraySize: size,
+                        PlaneSlice: self.plane_slice,
+                        ResourceMinLODClamp: clamp,
+                    };
+                }
+                ViewDimension::Texture2DMS => {
+                    d.ViewDimension = D3D12_SRV_DIMENSION_TEXTURE2DMS;
+                }
+                ViewDimension::Texture2DMSArray => {
+                    d.ViewDimension = D3D12_SRV_DIMENSION_TEXTURE2DMSARRAY;
+                    *d.u.Texture2DMSArray_mut() = D3D12_TEX2DMS_ARRAY_SRV {
+                        FirstArraySlice: first,
+                        ArraySize: size,
+                    };
+                }
+                ViewDimension::Texture3D => {
+                    d.ViewDimension = D3D12_SRV_DIMENSION_TEXTURE3D;
+                    *d.u.Texture3D_mut() = D3D12_TEX3D_SRV {
+                        MostDetailedMip: mip,
+                        MipLevels: levels,
+                        ResourceMinLODClamp: clamp,
+                    };
+                }
+                ViewDimension::TextureCube => {
+                    d.ViewDimension = D3D12_SRV_DIMENSION_TEXTURECUBE;
+                    *d.u.TextureCube_mut() = D3D12_TEXCUBE_SRV {
+                        MostDetailedMip: mip,
+                        MipLevels: levels,
+                        ResourceMinLODClamp: clamp,
+                    };
+                }
+                ViewDimension::TextureCubeArray => {
+                    d.ViewDimension = D3D12_SRV_DIMENSION_TEXTURECUBEARRAY;
+                    *d.u.TextureCubeArray_mut() = D3D12_TEXCUBE_ARRAY_SRV {
+                        MostDetailedMip: mip,
+                        MipLevels: levels,
+                        First2DArrayFace: first,
+                        NumCubes: size / 6,
+                        ResourceMinLODClamp: clamp,
+                    };
+                }
+            }
+        }
+
+        Ok(d)
+    }
+
+    /// アンオーダードアクセスビューの記述
+    pub fn uav(&self) -> IOResult<D3D12_UNORDERED_ACCESS_VIEW_DESC> {
+        let mut d = D3D12_UNORDERED_ACCESS_VIEW_DESC {
+            Format: self.format,
+            ViewDimension: 0,
+            u: unsafe { std::mem::zeroed() },
+        };
+        let (mip, first, size) = (
+            self.most_detailed_mip,
+            self.first_array_slice,
+            self.array_size,
+        );
+        unsafe {
+            match self.dimension {
+                ViewDimension::Buffer {
+                    first_element,
+                    num_elements,
+                    layout,
+                    counter_offset,
+                } => {
+                    if counter_offset % UAV_COUNTER_ALIGNMENT != 0 {
+                        return invalid(format!(
+                            "UAV counter offset must be {}-byte aligned (got {})",
+                            UAV_COUNTER_ALIGNMENT, counter_offset
+                        ));
+                    }
+                    d.ViewDimension = D3D12_UAV_DIMENSION_BUFFER;
+                    let b = d.u.Buffer_mut();
+                    b.FirstElement = first_element;
+                    b.NumElements = num_elements;
+                    b.CounterOffsetInBytes = counter_offset;
+                    match layout {
+                        BufferViewLayout::Typed => (),
+                        BufferViewLayout::Structured(stride) => b.StructureByteStride = stride,
+                        BufferViewLayout::Raw => b.Flags = D3D12_BUFFER_UAV_FLAG_RAW,
+                    }
+                }
+                ViewDimension::Texture1D => {
+                    d.ViewDimension = D3D12_UAV_DIMENSION_TEXTURE1D;
+                    d.u.Texture1D_mut().MipSlice = mip;
+                }
+                ViewDimension::Texture1DArray => {
+                    d.ViewDimension = D3D12_UAV_DIMENSION_TEXTURE1DARRAY;
+                    *d.u.Texture1DArray_mut() = D3D12_TEX1D_ARRAY_UAV {
+                        MipSlice: mip,
+                        FirstArraySlice: first,
+                        ArraySize: size,
+                    };
+                }
+                ViewDimension::Texture2D => {
+                    d.ViewDimension = D3D12_UAV_DIMENSION_TEXTURE2D;
+                    *d.u.Texture2D_mut() = D3D12_TEX2D_UAV {
+                        MipSlice: mip,
+                        PlaneSlice: self.plane_slice,
+                    };
+                }
+                ViewDimension::Texture2DArray => {
+                    d.ViewDimension = D3D12_UAV_DIMENSION_TEXTURE2DARRAY;
+                    *d.u.Texture2DArray_mut() = D3D12_TEX2D_ARRAY_UAV {
+                        MipSlice: mip,
+                        FirstArraySlice: first,
+                        ArraySize: size,
+                        PlaneSlice: self.plane_slice,
+                    };
+                }
+                ViewDimension::Texture3D => {
+                    d.ViewDimension = D3D12_UAV_DIMENSION_TEXTURE3D;
+                    *d.u.Texture3D_mut() = D3D12_TEX3D_UAV {
+                        MipSlice: mip,
+                        FirstWSlice: first,
+                        WSize: size,
+                    };
+                }
+                dim => return invalid(format!("{:?} cannot be an unordered access view", dim)),
+            }
+        }
+
+        Ok(d)
+    }
+
+    /// レンダーターゲットビューの記述
+    pub fn rtv(&self) -> IOResult<D3D12_RENDER_TARGET_VIEW_DESC> {
+        let mut d = D3D12_RENDER_TARGET_VIEW_DESC {
+            Format: self.format,
+            ViewDimension: 0,
+            u: unsafe { std::mem::zeroed() },
+        };
+        let (mip, first, size) = (
+            self.most_detailed_mip,
+            self.first_array_slice,
+            self.array_size,
+        );
+        unsafe {
+            match self.dimension {
+                ViewDimension::Buffer {
+                    first_element,
+                    num_elements,
+                    layout: BufferViewLayout::Typed,
+                    ..
+                } => {
+                    d.ViewDimension = D3D12_RTV_DIMENSION_BUFFER;
+                    *d.u.Buffer_mut() = D3D12_BUFFER_RTV {
+                        FirstElement: first_element,
+                        NumElements: num_elements,
+                    };
+                }
+                ViewDimension::Texture1D => {
+                    d.ViewDimension = D3D12_RTV_DIMENSION_TEXTURE1D;
+                    d.u.Texture1D_mut().MipSlice = mip;
+                }
+                ViewDimension::Texture1DArray => {
+                    d.ViewDimension = D3D12_RTV_DIMENSION_TEXTURE1DARRAY;
+                    *d.u.Texture1DArray_mut() = D3D12_TEX1D_ARRAY_RTV {
+                        MipSlice: mip,
+                        FirstArraySlice: first,
+                        ArraySize: size,
+                    };
+                }
+                ViewDimension::Texture2D => {
+                    d.ViewDimension = D3D12_RTV_DIMENSION_TEXTURE2D;
+                    *d.u.Texture2D_mut() = D3D12_TEX2D_RTV {
+                        MipSlice: mip,
+                        PlaneSlice: self.plane_slice,
+                    };
+                }
+                ViewDimension::Texture2DArray => {
+                    d.ViewDimension = D3D12_RTV_DIMENSION_TEXTURE2DARRAY;
+                    *d.u.Texture2DArray_mut() = D3D12_TEX2D_ARRAY_RTV {
+                        MipSlice: mip,
+                        FirstArraySlice: first,
+                        ArraySize: size,
+                        PlaneSlice: self.plane_slice,
+                    };
+                }
+                ViewDimension::Texture2DMS => {
+                    d.ViewDimension = D3D12_RTV_DIMENSION_TEXTURE2DMS;
+                }
+                ViewDimension::Texture2DMSArray => {
+                    d.ViewDimension = D3D12_RTV_DIMENSION_TEXTURE2DMSARRAY;
+                    *d.u.Texture2DMSArray_mut() = D3D12_TEX2DMS_ARRAY_RTV {
+                        FirstArraySlice: first,
+                        ArraySize: size,
+                    };
+                }
+                ViewDimension::Texture3D => {
+                    d.ViewDimension = D3D12_RTV_DIMENSION_TEXTURE3D;
+                    *d.u.Texture3D_mut() = D3D12_TEX3D_RTV {
+                        MipSlice: mip,
+                        FirstWSlice: first,
+                        WSize: size,
+                    };
+                }
+                dim => return invalid(format!("{:?} cannot be a render target view", dim)),
+            }
+        }
+
+        Ok(d)
+    }
+
+    /// 深度ステンシルビューの記述(flagsはD3D12_DSV_FLAG_READ_ONLY_DEPTH/STENCIL)
+    pub fn dsv(&self, flags: D3D12_DSV_FLAGS) -> IOResult<D3D12_DEPTH_STENCIL_VIEW_DESC> {
+        let mut d = D3D12_DEPTH_STENCIL_VIEW_DESC {
+            Format: self.format,
+            ViewDimension: 0,
+            Flags: flags,
+            u: unsafe { std::mem::zeroed() },
+        };
+        let (mip, first, size) = (
+            self.most_detailed_mip,
+            self.first_array_slice,
+            self.array_size,
+        );
+        unsafe {
+            match self.dimension {
+                ViewDimension::Texture1D => {
+                    d.ViewDimension = D3D12_DSV_DIMENSION_TEXTURE1D;
+                    d.u.Texture1D_mut().MipSlice = mip;
+                }
+                ViewDimension::Texture1DArray => {
+                    d.ViewDimension = D3D12_DSV_DIMENSION_TEXTURE1DARRAY;
+                    *d.u.Texture1DArray_mut() = D3D12_TEX1D_ARRAY_DSV {
+                        MipSlice: mip,
+                        FirstArraySlice: first,
+                        ArraySize: size,
+                    };
+                }
+                ViewDimension::Texture2D => {
+                    d.ViewDimension = D3D12_DSV_DIMENSION_TEXTURE2D;
+                    d.u.Texture2D_mut().MipSlice = mip;
+                }
+                ViewDimension::Texture2DArray => {
+                    d.ViewDimension = D3D12_DSV_DIMENSION_TEXTURE2DARRAY;
+                    *d.u.Texture2DArray_mut() = D3D12_TEX2D_ARRAY_DSV {
+                        MipSlice: mip,
+                        FirstArraySlice: first,
+                        ArraySize: size,
+                    };
+                }
+                ViewDimension::Texture2DMS => {
+                    d.ViewDimension = D3D12_DSV_DIMENSION_TEXTURE2DMS;
+                }
+                ViewDimension::Texture2DMSArray => {
+                    d.ViewDimension = D3D12_DSV_DIMENSION_TEXTURE2DMSARRAY;
+                    *d.u.Texture2DMSArray_mut() = D3D12_TEX2DMS_ARRAY_DSV {
+                        FirstArraySlice: first,
+                        ArraySize: size,
+                    };
+                }
+                dim => return invalid(format!("{:?} cannot be a depth stencil view", dim)),
+            }
+        }
+
+        Ok(d)
+    }
+}
+
+/// 型なしの深度フォーマットに対する(DSVのフォーマット, SRVで深度を読むフォーマット)
+pub fn depth_view_formats(format: dxgi::Format) -> Option<(dxgi::Format, dxgi::Format)> {
+    match format {
+        DXGI_FORMAT_R32_TYPELESS | DXGI_FORMAT_D32_FLOAT => {
+            Some((DXGI_FORMAT_D32_FLOAT, DXGI_FORMAT_R32_FLOAT))
+        }
+        DXGI_FORMAT_R24G8_TYPELESS | DXGI_FORMAT_D24_UNORM_S8_UINT => Some((
+            DXGI_FORMAT_D24_UNORM_S8_UINT,
+            DXGI_FORMAT_R24_UNORM_X8_TYPELESS,
+        )),
+        DXGI_FORMAT_R16_TYPELESS | DXGI_FORMAT_D16_UNORM => {
+            Some((DXGI_FORMAT_D16_UNORM, DXGI_FORMAT_R16_UNORM))
+        }
+        DXGI_FORMAT_R32G8X24_TYPELESS | DXGI_FORMAT_D32_FLOAT_S8X24_UINT => Some((
+            DXGI_FORMAT_D32_FLOAT_S8X24_UINT,
+            DXGI_FORMAT_R32_FLOAT_X8X24_TYPELESS,
+        )),
+        _ => None,
+    }
+}
+
+/// サンプラーの記述(比較なし、全ミップ)
+pub fn sampler_desc(
+    filter: D3D12_FILTER,
+    address_mode: D3D12_TEXTURE_ADDRESS_MODE,
+) -> D3D12_SAMPLER_DESC {
+    D3D12_SAMPLER_DESC {
+        Filter: filter,
+        AddressU: address_mode,
+        AddressV: address_mode,
+        AddressW: address_mode,
+        MipLODBias: 0.0,
+        MaxAnisotropy: D3D12_MAX_MAXANISOTROPY,
+        ComparisonFunc: D3D12_COMPARISON_FUNC_NEVER,
+        BorderColor: [0.0; 4],
+        MinLOD: 0.0,
+        MaxLOD: D3D12_FLOAT32_MAX,
+    }
+}
+
+impl Device {
+    /// 定数バッファビューの作成(locationとsizeは256バイト単位)
+    pub fn create_constant_buffer_view(
+        &self,
+        location: GraphicsVirtualPtr,
+        size: u32,
+        handle: D3D12_CPU_DESCRIPTOR_HANDLE,
+    ) -> IOResult<()> {
+        let a = CONSTANT_BUFFER_ALIGNMENT;
+        if location.0 % a as u64 != 0 || size % a != 0 {
+            return invalid(format!(
+                "constant buffer location and size must be {}-byte aligned (got {:#x}, {})",
+                a, location.0, size
+            ));
+        }
+        let desc = D3D12_CONSTANT_BUFFER_VIEW_DESC {
+            BufferLocation: location.0,
+            SizeInBytes: size,
+        };
+        unsafe { (*self.0).CreateConstantBufferView(&desc, handle) };
+        Ok(())
+    }
+    /// アンオーダードアクセスビューの作成(counterはカウンタを置くバッファ)
+    pub fn create_unordered_access_view(
+        &self,
+        res: &Resource,
+        counter: Option<&Resource>,
+        desc: Option<&D3D12_UNORDERED_ACCESS_VIEW_DESC>,
+        handle: D3D12_CPU_DESCRIPTOR_HANDLE,
+    ) {
+        unsafe {
+            (*self.0).CreateUnorderedAccessView(
+                res.0,
+                counter.map_or(std::ptr::null_mut(), |c| c.0),
+                desc.map_or(std::ptr::null(), |x| x as _),
+                handle,
+            )
+        };
+    }
+    /// 深度ステンシルビューの作成
+    pub fn create_depth_stencil_view(
+        &self,
+        res: &Resource,
+        desc: Option<&D3D12_DEPTH_STENCIL_VIEW_DESC>,
+        handle: D3D12_CPU_DESCRIPTOR_HANDLE,
+    ) {
+        unsafe {
+            (*self.0).CreateDepthStencilView(
+                res.0,
+                desc.map_or(std::ptr::null(), |x| x as _),
+                handle,
+            )
+        };
+    }
+    /// サンプラーの作成
+    pub fn create_sampler(&self, desc: &D3D12_SAMPLER_DESC, handle: D3D12_CPU_DESCRIPTOR_HANDLE) {
+        unsafe { (*self.0).CreateSampler(desc, handle) };
+    }
+
+    /// デスクリプタをまとめてコピーする(範囲は(先頭, 個数)で、コピー元とコピー先の総数が一致すること)
+    /// コピー元はシェーダから見えないヒープにあること
+    pub fn copy_descriptors(
+        &self,
+        dest_ranges: &[(D3D12_CPU_DESCRIPTOR_HANDLE, u32)],
+        src_ranges: &[(D3D12_CPU_DESCRIPTOR_HANDLE, u32)],
+        contents: DescriptorHeapContents,
+    ) -> IOResult<()> {
+        let dest_total: u64 = dest_ranges.iter().map(|r| r.1 as u64).sum();
+        let src_total: u64 = src_ranges.iter().map(|r| r.1 as u64).sum();
+        if dest_total != src_total {
+            return invalid(format!(
+                "descriptor copy needs the same number of source and destination descriptors ({} vs {})",
+                src_total, dest_total
+            ));
+        }
+        let (dest_starts, dest_sizes): (Vec<_>, Vec<_>) = dest_ranges.iter().cloned().unzip();
+        let (src_starts, src_sizes): (Vec<_>, Vec<_>) = src_ranges.iter().cloned().unzip();
+
+        unsafe {
+            (*self.0).CopyDescriptors(
+                dest_starts.len() as _,
+                dest_starts.as_ptr(),
+                dest_sizes.as_ptr(),
+                src_starts.len() as _,
+                src_starts.as_ptr(),
+                src_sizes.as_ptr(),
+                contents as _,
+            )
+        };
+        Ok(())
+    }
+    /// 連続したデスクリプタをcount個コピーする
+    pub fn copy_descriptors_simple(
+        &self,
+        count: u32,
+        dest: D3D12_CPU_DESCRIPTOR_HANDLE,
+        src: D3D12_CPU_DESCRIPTOR_HANDLE,
+        contents: DescriptorHeapContents,
+    ) {
+        unsafe { (*self.0).CopyDescriptorsSimple(count, dest, src, contents as _) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texture(
+        dimension: D3D12_RESOURCE_DIMENSION,
+        depth_or_array_size: u16,
+        mip_levels: u16,
+        format: dxgi::Format,
+    ) -> D3D12_RESOURCE_DESC {
+        D3D12_RESOURCE_DESC {
+            Dimension: dimension,
+            Width: 256,
+            Height: 256,
+            DepthOrArraySize: depth_or_array_size,
+            MipLevels: mip_levels,
+            Format: format,
+            Layout: D3D12_TEXTURE_LAYOUT_UNKNOWN,
+            ..*ResourceDesc::buffer(0).as_ref()
+        }
+    }
+
+    #[test]
+    fn buffer_views_from_resource() {
+        let raw = ViewDesc::for_resource(ResourceDesc::buffer(1024).as_ref()).unwrap();
+        let srv = raw.srv().unwrap();
+        assert_eq!(srv.Format, DXGI_FORMAT_R32_TYPELESS);
+        assert_eq!(srv.ViewDimension, D3D12_SRV_DIMENSION_BUFFER);
+        let b = unsafe { srv.u.Buffer() };
+        assert_eq!((b.FirstElement, b.NumElements), (0, 256));
+        assert_eq!(b.Flags, D3D12_BUFFER_SRV_FLAG_RAW);
+
+        let typed = D3D12_RESOURCE_DESC {
+            Format: DXGI_FORMAT_R32G32B32A32_FLOAT,
+            ..*ResourceDesc::buffer(1024).as_ref()
+        };
+        let uav = ViewDesc::for_resource(&typed).unwrap().uav().unwrap();
+        let b = unsafe { uav.u.Buffer() };
+        assert_eq!((b.NumElements, b.Flags), (64, 0));
+
+        let structured = ViewDesc::structured_buffer(48, 4, 16).counter_offset(4096);
+        let uav = structured.uav().unwrap();
+        let b = unsafe { uav.u.Buffer() };
+        assert_eq!(
+            (b.FirstElement, b.NumElements, b.StructureByteStride),
+            (4, 16, 48)
+        );
+        assert_eq!(b.CounterOffsetInBytes, 4096);
+        match structured.counter_offset(100).uav() {
+            Err(e) => assert_eq!(e.kind(), std::io::ErrorKind::InvalidInput),
+            Ok(_) => panic!("misaligned counter offset should be rejected"),
+        }
+    }
+
+    #[test]
+    fn zero_mip_levels_means_all_mips() {
+        let v = ViewDesc::for_resource(&texture(
+            D3D12_RESOURCE_DIMENSION_TEXTURE2D,
+            4,
+            0,
+            DXGI_FORMAT_R8G8B8A8_UNORM,
+        ))
+        .unwrap();
+        assert_eq!(v.dimension, ViewDimension::Texture2DArray);
+        assert_eq!((v.mip_levels, v.array_size), (u32::MAX, 4));
+        let srv = v.srv().unwrap();
+        let t = unsafe { srv.u.Texture2DArray() };
+        assert_eq!((t.MipLevels, t.ArraySize), (u32::MAX, 4));
+
+        let v = ViewDesc::for_resource(&texture(
+            D3D12_RESOURCE_DIMENSION_TEXTURE3D,
+            32,
+            5,
+            DXGI_FORMAT_R16G16B16A16_FLOAT,
+        ))
+        .unwrap();
+        assert_eq!(v.dimension, ViewDimension::Texture3D);
+        assert_eq!((v.mip_levels, v.array_size), (5, u32::MAX));
+        let uav = v.mips(2, 1).uav().unwrap();
+        let t = unsafe { uav.u.Texture3D() };
+        assert_eq!((t.MipSlice, t.FirstWSlice, t.WSize), (2, 0, u32::MAX));
+    }
+
+    #[test]
+    fn cube_views() {
+        let array = ViewDesc::for_resource(&texture(
+            D3D12_RESOURCE_DIMENSION_TEXTURE2D,
+            12,
+            1,
+            DXGI_FORMAT_R8G8B8A8_UNORM,
+        ))
+        .unwrap();
+        let cubes = array.cube().unwrap();
+        assert_eq!(cubes.dimension, ViewDimension::TextureCubeArray);
+        let srv = cubes.srv().unwrap();
+        assert_eq!(srv.ViewDimension, D3D12_SRV_DIMENSION_TEXTURECUBEARRAY);
+        assert_eq!(unsafe { srv.u.TextureCubeArray() }.NumCubes, 2);
+        let one = array.array_slices(6, 6).cube().unwrap();
+        assert_eq!(one.dimension, ViewDimension::TextureCube);
+
+        assert!(array.array_slices(0, 8).cube().is_err());
+        assert!(cubes.rtv().is_err());
+        assert!(cubes.uav().is_err());
+    }
+
+    #[test]
+    fn render_target_and_depth_views() {
+        let mut ms = texture(
+            D3D12_RESOURCE_DIMENSION_TEXTURE2D,
+            2,
+            1,
+            DXGI_FORMAT_R24G8_TYPELESS,
+        );
+        ms.SampleDesc.Count = 4;
+        let (dsv_format, srv_format) = depth_view_formats(ms.Format).unwrap();
+        assert_eq!(srv_format, DXGI_FORMAT_R24_UNORM_X8_TYPELESS);
+        let v = ViewDesc::for_resource(&ms)
+            .unwrap()
+            .format(dsv_format)
+            .array_slices(1, 1);
+        assert_eq!(v.dimension, ViewDimension::Texture2DMSArray);
+        let dsv = v.dsv(D3D12_DSV_FLAG_READ_ONLY_DEPTH).unwrap();
+        assert_eq!(dsv.Format, DXGI_FORMAT_D24_UNORM_S8_UINT);
+        assert_eq!(dsv.ViewDimension, D3D12_DSV_DIMENSION_TEXTURE2DMSARRAY);
+        assert_eq!(dsv.Flags, D3D12_DSV_FLAG_READ_ONLY_DEPTH);
+        let t = unsafe { dsv.u.Texture2DMSArray() };
+        assert_eq!((t.FirstArraySlice, t.ArraySize), (1, 1));
+
+        let rt = ViewDesc::for_resource(&texture(
+            D3D12_RESOURCE_DIMENSION_TEXTURE2D,
+            1,
+            8,
+            DXGI_FORMAT_R8G8B8A8_UNORM,
+        ))
+        .unwrap()
+        .mips(3, 1);
+        let rtv = rt.rtv().unwrap();
+        assert_eq!(rtv.ViewDimension, D3D12_RTV_DIMENSION_TEXTURE2D);
+        assert_eq!(unsafe { rtv.u.Texture2D() }.MipSlice, 3);
+        assert!(ViewDesc::raw_buffer(0, 4).rtv().is_err());
+        assert!(ViewDesc::raw_buffer(0, 4).dsv(0).is_err());
+        assert_eq!(depth_view_formats(DXGI_FORMAT_R8G8B8A8_UNORM), None);
+    }
+}
@@ -9,9 +9,12 @@ use winapi::shared::dxgi1_3::DXGI_CREATE_FACTORY_DEBUG;
 use winapi::shared::dxgi1_4::*;
 use winapi::shared::dxgitype::*;
 use winapi::shared::guiddef::{GUID, REFIID};
-use winapi::shared::minwindef::UINT;
 use winapi::shared::minwindef::ULONG;
+use winapi::shared::minwindef::{DWORD, UINT};
+use winapi::shared::ntdef::HANDLE;
+use winapi::shared::winerror::WAIT_TIMEOUT;
 use winapi::um::libloaderapi::{FreeLibrary, GetProcAddress, LoadLibraryA};
+use winapi::um::winbase::{INFINITE, WAIT_ABANDONED};
 
 pub use winapi::shared::dxgiformat::DXGI_FORMAT as Format;
 pub use winapi::shared::dxgitype::DXGI_SAMPLE_DESC as SampleDesc;
@@ -30,6 +33,80 @@ pub use winapi::shared::dxgiformat::{
     DXGI_FORMAT_R32G32_FLOAT, DXGI_FORMAT_R32_FLOAT, DXGI_FORMAT_R8G8B8A8_UNORM,
 };
 
+/// フォーマットのメモリ上の単位(非圧縮フォーマットは1x1ピクセル、BC圧縮フォーマットは4x4ピクセル)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FormatBlockInfo {
+    /// 1ブロックのバイト数
+    pub bytes: u32,
+    /// 1ブロックの幅(ピクセル)
+    pub width: u32,
+    /// 1ブロックの高さ(ピクセル)
+    pub height: u32,
+}
+impl FormatBlockInfo {
+    const fn pixel(bytes: u32) -> Self {
+        FormatBlockInfo {
+            bytes,
+            width: 1,
+            height: 1,
+        }
+    }
+    const fn block_compressed(bytes: u32) -> Self {
+        FormatBlockInfo {
+            bytes,
+            width: 4,
+            height: 4,
+        }
+    }
+
+    /// 複数ピクセルをまとめて格納するフォーマットかどうか
+    pub fn is_compressed(&self) -> bool {
+        self.width > 1 || self.height > 1
+    }
+}
+/// フォーマットのブロック情報を取得
+/// 平面(planar)フォーマット・ビデオフォーマット・R1_UNORMなどの特殊なものはNone
+pub fn format_block_info(format: Format) -> Option<FormatBlockInfo> {
+    use winapi::shared::dxgiformat::*;
+
+    match format {
+        DXGI_FORMAT_R32G32B32A32_TYPELESS..=DXGI_FORMAT_R32G32B32A32_SINT => {
+            Some(FormatBlockInfo::pixel(16))
+        }
+        DXGI_FORMAT_R32G32B32_TYPELESS..=DXGI_FORMAT_R32G32B32_SINT => {
+            Some(FormatBlockInfo::pixel(12))
+        }
+        DXGI_FORMAT_R16G16B16A16_TYPELESS..=DXGI_FORMAT_R32G32_SINT => {
+            Some(FormatBlockInfo::pixel(8))
+        }
+        DXGI_FORMAT_R10G10B10A2_TYPELESS..=DXGI_FORMAT_R32_SINT => Some(FormatBlockInfo::pixel(4)),
+        DXGI_FORMAT_R8G8_TYPELESS..=DXGI_FORMAT_R16_SINT => Some(FormatBlockInfo::pixel(2)),
+        DXGI_FORMAT_R8_TYPELESS..=DXGI_FORMAT_A8_UNORM => Some(FormatBlockInfo::pixel(1)),
+        DXGI_FORMAT_R9G9B9E5_SHAREDEXP => Some(FormatBlockInfo::pixel(4)),
+        DXGI_FORMAT_R8G8_B8G8_UNORM | DXGI_FORMAT_G8R8_G8B8_UNORM => Some(FormatBlockInfo {
+            bytes: 4,
+            width: 2,
+            height: 1,
+        }),
+        DXGI_FORMAT_BC1_TYPELESS..=DXGI_FORMAT_BC1_UNORM_SRGB
+        | DXGI_FORMAT_BC4_TYPELESS..=DXGI_FORMAT_BC4_SNORM => {
+            Some(FormatBlockInfo::block_compressed(8))
+        }
+        DXGI_FORMAT_BC2_TYPELESS..=DXGI_FORMAT_BC3_UNORM_SRGB
+        | DXGI_FORMAT_BC5_TYPELESS..=DXGI_FORMAT_BC5_SNORM
+        | DXGI_FORMAT_BC6H_TYPELESS..=DXGI_FORMAT_BC7_UNORM_SRGB => {
+            Some(FormatBlockInfo::block_compressed(16))
+        }
+        DXGI_FORMAT_B5G6R5_UNORM | DXGI_FORMAT_B5G5R5A1_UNORM | DXGI_FORMAT_B4G4R4A4_UNORM => {
+            Some(FormatBlockInfo::pixel(2))
+        }
+        DXGI_FORMAT_B8G8R8A8_UNORM..=DXGI_FORMAT_B8G8R8X8_UNORM_SRGB => {
+            Some(FormatBlockInfo::pixel(4))
+        }
+        _ => None,
+    }
+}
+
 /// Driver object for IDXGIFactory2
 #[repr(transparent)]
 pub struct Factory(*mut IDXGIFactory2);
@@ -64,6 +141,7 @@ HandleWrapper!(for Device[IDXGIDevice1] + FromRawHandle);
 #[repr(transparent)]
 pub struct Surface(*mut IDXGISurface);
 HandleWrapper!(for Surface[IDXGISurface] + FromRawHandle);
+DebugNameByPrivateData!(for Factory, Adapter, Device, Surface, SwapChain);
 
 pub trait DeviceChild {
     fn parent(&self) -> IOResult<Device>;
@@ -103,6 +181,72 @@ impl Adapter {
         let mut s = std::mem::MaybeUninit::uninit();
         unsafe { (*self.0).GetDesc(s.as_mut_ptr()).to_result(s.assume_init()) }
     }
+
+    fn adapter3(&self) -> IOResult<ComPtr<IDXGIAdapter3>> {
+        let mut h = std::ptr::null_mut();
+        unsafe { (*self.0).QueryInterface(&IDXGIAdapter3::uuidof(), &mut h) }
+            .to_result_with(|| ComPtr(h as _))
+    }
+    /// ビデオメモリの予算と使用量を取得(IDXGIAdapter3が必要)
+    pub fn query_video_memory_info(
+        &self,
+        node_index: u32,
+        group: MemorySegmentGroup,
+    ) -> IOResult<VideoMemoryInfo> {
+        let a3 = self.adapter3()?;
+        let mut info = std::mem::MaybeUninit::uninit();
+        unsafe {
+            (*a3.0)
+                .QueryVideoMemoryInfo(node_index, group as _, info.as_mut_ptr())
+                .to_result_with(|| info.assume_init())
+        }
+    }
+    /// アプリケーションが最低限必要とするビデオメモリの量を申告する
+    pub fn set_video_memory_reservation(
+        &self,
+        node_index: u32,
+        group: MemorySegmentGroup,
+        reservation: u64,
+    ) -> IOResult<()> {
+        let a3 = self.adapter3()?;
+        unsafe { (*a3.0).SetVideoMemoryReservation(node_index, group as _, reservation) }.checked()
+    }
+    /// 予算が変わったときにeventをシグナルさせる(戻り値をドロップすると登録解除)
+    ///
+    /// # Safety
+    /// eventは有効なイベントハンドルで、戻り値をドロップするまで閉じないこと
+    pub unsafe fn register_budget_change_notification(
+        &self,
+        event: HANDLE,
+    ) -> IOResult<BudgetChangeNotification> {
+        let adapter = self.adapter3()?;
+        let mut cookie = 0;
+        (*adapter.0)
+            .RegisterVideoMemoryBudgetChangeNotificationEvent(event, &mut cookie)
+            .to_result_with(|| BudgetChangeNotification { adapter, cookie })
+    }
+}
+/// ビデオメモリの区分
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemorySegmentGroup {
+    /// GPU専用のメモリ
+    Local = DXGI_MEMORY_SEGMENT_GROUP_LOCAL,
+    /// システムメモリ側
+    NonLocal = DXGI_MEMORY_SEGMENT_GROUP_NON_LOCAL,
+}
+pub use winapi::shared::dxgi1_4::DXGI_QUERY_VIDEO_MEMORY_INFO as VideoMemoryInfo;
+/// ビデオメモリ予算の変更通知の登録
+pub struct BudgetChangeNotification {
+    adapter: ComPtr<IDXGIAdapter3>,
+    cookie: DWORD,
+}
+impl Drop for BudgetChangeNotification {
+    fn drop(&mut self) {
+        unsafe {
+            (*self.adapter.0).UnregisterVideoMemoryBudgetChangeNotification(self.cookie);
+        }
+    }
 }
 
 #[allow(non_camel_case_types)]
@@ -222,6 +366,51 @@ impl Debug {
     }
 }
 
+/// プロセス/API間で共有されたリソースの排他制御(d3d11::Texture2D::keyed_mutexで得る)
+#[repr(transparent)]
+pub struct KeyedMutex(*mut IDXGIKeyedMutex);
+HandleWrapper!(for KeyedMutex[IDXGIKeyedMutex] + FromRawHandle);
+DebugNameByPrivateData!(for KeyedMutex);
+impl KeyedMutex {
+    /// keyで取得する(タイムアウトしたらfalse、timeout_msがNoneなら無限に待つ)
+    pub fn acquire(&self, key: u64, timeout_ms: Option<u32>) -> IOResult<bool> {
+        let hr = unsafe { (*self.0).AcquireSync(key, timeout_ms.unwrap_or(INFINITE)) };
+        if hr == WAIT_TIMEOUT as HRESULT {
+            return Ok(false);
+        }
+        if hr == WAIT_ABANDONED as HRESULT {
+            return Err(IOError::other(
+                "keyed mutex was abandoned by the other side",
+            ));
+        }
+        hr.to_result(true)
+    }
+    /// keyで解放する(次にkeyで取得しようとしている側が取得できる)
+    pub fn release(&self, key: u64) -> IOResult<()> {
+        unsafe { (*self.0).ReleaseSync(key).checked() }
+    }
+    /// acquire_keyで取得して、スコープを抜けたらrelease_keyで解放する
+    pub fn lock(
+        &self,
+        acquire_key: u64,
+        release_key: u64,
+        timeout_ms: Option<u32>,
+    ) -> IOResult<Option<KeyedMutexGuard<'_>>> {
+        Ok(if self.acquire(acquire_key, timeout_ms)? {
+            Some(KeyedMutexGuard(self, release_key))
+        } else {
+            None
+        })
+    }
+}
+/// 破棄時に解放されるKeyedMutex
+pub struct KeyedMutexGuard<'m>(&'m KeyedMutex, u64);
+impl Drop for KeyedMutexGuard<'_> {
+    fn drop(&mut self) {
+        let _ = self.0.release(self.1);
+    }
+}
+
 /// スワップチェーン
 pub struct SwapChain(*mut IDXGISwapChain3, Format, usize);
 HandleWrapper!(for SwapChain[IDXGISwapChain3]);
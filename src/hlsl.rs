@@ -0,0 +1,617 @@
+//! HLSL Data Layouts
+
+use std::io::{Error as IOError, ErrorKind, Result as IOResult};
+use winapi::shared::dxgiformat::*;
+use winapi::um::d3d12::{
+    D3D12_INPUT_CLASSIFICATION, D3D12_INPUT_CLASSIFICATION_PER_INSTANCE_DATA,
+    D3D12_INPUT_CLASSIFICATION_PER_VERTEX_DATA, D3D12_INPUT_ELEMENT_DESC,
+};
+
+/// cbufferの詰め込み規則でHlslPackedとConstantBufferを実装する
+pub use comdrive_derive::ConstantBuffer;
+/// 頂点構造体にVertexを実装する(メンバーに#[semantic("名前", インデックス)]を付ける)
+pub use comdrive_derive::Vertex;
+
+/// 定数バッファのレジスタ(16バイト)
+pub const REGISTER_SIZE: usize = 16;
+
+/// cbufferの詰め込み規則で次のメンバーの位置を決める
+/// (構造体/配列/行列はレジスタの先頭から、それ以外はレジスタをまたがない位置に置く)
+pub const fn pack_offset(offset: usize, size: usize, align16: bool) -> usize {
+    let next = offset.div_ceil(REGISTER_SIZE) * REGISTER_SIZE;
+    if align16 || (offset % REGISTER_SIZE) + size > REGISTER_SIZE {
+        next
+    } else {
+        offset
+    }
+}
+
+/// cbufferの規則で詰め込める型
+pub trait HlslPacked {
+    /// 詰め込んだときの大きさ(末尾のパディングは含まない)
+    const SIZE: usize;
+    /// レジスタの先頭から始まるか(構造体/配列/行列)
+    const ALIGN16: bool;
+    /// outの先頭に詰め込んだ形で書き出す(outはSIZEバイト以上あること)
+    fn write_packed(&self, out: &mut [u8]);
+}
+
+/// 構造体のメンバーの配置
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PackedField {
+    pub name: &'static str,
+    pub offset: usize,
+    pub size: usize,
+}
+
+/// 定数バッファとして使える構造体(#[derive(ConstantBuffer)]で実装する)
+pub trait ConstantBuffer: HlslPacked {
+    /// メンバーの配置
+    fn fields() -> Vec<PackedField>;
+
+    /// GPU上の大きさ(16バイト単位)
+    fn gpu_size() -> usize {
+        pack_offset(Self::SIZE, 0, true)
+    }
+    /// 詰め込んだ形で書き出す(パディングは0で埋める)
+    fn write_to(&self, out: &mut [u8]) -> IOResult<()> {
+        if out.len() < Self::SIZE {
+            return Err(IOError::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "constant buffer needs {} bytes but the destination has {}",
+                    Self::SIZE,
+                    out.len()
+                ),
+            ));
+        }
+        for b in &mut out[..Self::SIZE] {
+            *b = 0;
+        }
+        self.write_packed(out);
+        Ok(())
+    }
+    /// 詰め込んだ形のバイト列(gpu_sizeバイト)
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut v = vec![0; Self::gpu_size()];
+        self.write_packed(&mut v);
+        v
+    }
+    /// シェーダのリフレクション情報と配置が一致するか調べる(メンバーは宣言順で対応させる)
+    fn check_reflection(reflection: &CBufferReflection) -> IOResult<()> {
+        let mismatch = |msg: String| {
+            Err(IOError::new(
+                ErrorKind::InvalidData,
+                format!("cbuffer {}: {}", reflection.name, msg),
+            ))
+        };
+        let fields = Self::fields();
+        if fields.len() != reflection.variables.len() {
+            return mismatch(format!(
+                "{} members declared but the shader has {}",
+                fields.len(),
+                reflection.variables.len()
+            ));
+        }
+        for (f, v) in fields.iter().zip(&reflection.variables) {
+            if f.offset != v.offset as usize || f.size != v.size as usize {
+                return mismatch(format!(
+                    "member {} is at {}+{} but shader variable {} is at {}+{}",
+                    f.name, f.offset, f.size, v.name, v.offset, v.size
+                ));
+            }
+        }
+        if Self::gpu_size() != reflection.size as usize {
+            return mismatch(format!(
+                "size is {} but the shader expects {}",
+                Self::gpu_size(),
+                reflection.size
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+macro_rules! PackedScalar {
+    ($($t: ty => |$v: ident| $e: expr),*) => {
+        $(
+            impl HlslPacked for $t {
+                const SIZE: usize = 4;
+                const ALIGN16: bool = false;
+                fn write_packed(&self, out: &mut [u8]) {
+                    let $v = *self;
+                    out[..4].copy_from_slice(&$e.to_le_bytes());
+                }
+            }
+        )*
+    };
+}
+PackedScalar!(f32 => |v| v, i32 => |v| v, u32 => |v| v, bool => |v| v as u32);
+
+/// ベクトル(float2/int3/uint4など)
+#[repr(transparent)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Vector<T, const N: usize>(pub [T; N]);
+impl<T, const N: usize> From<[T; N]> for Vector<T, N> {
+    fn from(v: [T; N]) -> Self {
+        Vector(v)
+    }
+}
+impl<T: HlslPacked, const N: usize> HlslPacked for Vector<T, N> {
+    const SIZE: usize = T::SIZE * N;
+    const ALIGN16: bool = false;
+    fn write_packed(&self, out: &mut [u8]) {
+        for (n, e) in self.0.iter().enumerate() {
+            e.write_packed(&mut out[n * T::SIZE..]);
+        }
+    }
+}
+pub type Float2 = Vector<f32, 2>;
+pub type Float3 = Vector<f32, 3>;
+pub type Float4 = Vector<f32, 4>;
+pub type Int2 = Vector<i32, 2>;
+pub type Int3 = Vector<i32, 3>;
+pub type Int4 = Vector<i32, 4>;
+pub type Uint2 = Vector<u32, 2>;
+pub type Uint3 = Vector<u32, 3>;
+pub type Uint4 = Vector<u32, 4>;
+
+/// row_majorの行列(m[行][列])、各行がレジスタ1つに入る
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RowMajor<const R: usize, const C: usize>(pub [[f32; C]; R]);
+/// column_major(HLSLの既定)の行列(m[行][列])、各列がレジスタ1つに入る
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColumnMajor<const R: usize, const C: usize>(pub [[f32; C]; R]);
+impl<const R: usize, const C: usize> HlslPacked for RowMajor<R, C> {
+    const SIZE: usize = REGISTER_SIZE * (R - 1) + 4 * C;
+    const ALIGN16: bool = true;
+    fn write_packed(&self, out: &mut [u8]) {
+        for (r, row) in self.0.iter().enumerate() {
+            for (c, e) in row.iter().enumerate() {
+                e.write_packed(&mut out[REGISTER_SIZE * r + 4 * c..]);
+            }
+        }
+    }
+}
+impl<const R: usize, const C: usize> HlslPacked for ColumnMajor<R, C> {
+    const SIZE: usize = REGISTER_SIZE * (C - 1) + 4 * R;
+    const ALIGN16: bool = true;
+    fn write_packed(&self, out: &mut [u8]) {
+        for (r, row) in self.0.iter().enumerate() {
+            for (c, e) in row.iter().enumerate() {
+                e.write_packed(&mut out[REGISTER_SIZE * c + 4 * r..]);
+            }
+        }
+    }
+}
+pub type Float4x4 = ColumnMajor<4, 4>;
+pub type Float3x4 = ColumnMajor<3, 4>;
+
+/// 配列(各要素はレジスタの先頭から始まる)
+impl<T: HlslPacked, const N: usize> HlslPacked for [T; N] {
+    const SIZE: usize = if N == 0 {
+        0
+    } else {
+        pack_offset(T::SIZE, 0, true) * (N - 1) + T::SIZE
+    };
+    const ALIGN16: bool = true;
+    fn write_packed(&self, out: &mut [u8]) {
+        let stride = pack_offset(T::SIZE, 0, true);
+        for (n, e) in self.iter().enumerate() {
+            e.write_packed(&mut out[n * stride..]);
+        }
+    }
+}
+
+/// シェーダのリフレクション情報にある変数
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CBufferVariable {
+    pub name: String,
+    pub offset: u32,
+    pub size: u32,
+}
+/// シェーダのリフレクション情報にあるcbuffer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CBufferReflection {
+    pub name: String,
+    pub size: u32,
+    pub variables: Vec<CBufferVariable>,
+}
+
+fn bad_data<T>(msg: &str) -> IOResult<T> {
+    Err(IOError::new(ErrorKind::InvalidData, msg.to_owned()))
+}
+fn read_u32(data: &[u8], offset: usize) -> IOResult<u32> {
+    match data.get(offset..offset + 4) {
+        Some(b) => Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+        None => bad_data("truncated shader reflection data"),
+    }
+}
+fn read_cstr(data: &[u8], offset: usize) -> IOResult<String> {
+    let s = data.get(offset..).unwrap_or(&[]);
+    match s.iter().position(|&b| b == 0) {
+        Some(n) => Ok(String::from_utf8_lossy(&s[..n]).into_owned()),
+        None => bad_data("unterminated string in shader reflection data"),
+    }
+}
+
+/// DXBCコンテナのチャンクを探す
+pub fn find_dxbc_chunk<'a>(dxbc: &'a [u8], fourcc: &[u8; 4]) -> IOResult<Option<&'a [u8]>> {
+    if dxbc.get(..4) != Some(b"DXBC") {
+        return bad_data("not a DXBC container (DXIL shaders keep reflection outside RDEF)");
+    }
+    let count = read_u32(dxbc, 28)? as usize;
+    for n in 0..count {
+        let offset = read_u32(dxbc, 32 + n * 4)? as usize;
+        if dxbc.get(offset..offset + 4) == Some(&fourcc[..]) {
+            let size = read_u32(dxbc, offset + 4)? as usize;
+            return match dxbc.get(offset + 8..offset + 8 + size) {
+                Some(c) => Ok(Some(c)),
+                None => bad_data("truncated DXBC chunk"),
+            };
+        }
+    }
+
+    Ok(None)
+}
+
+/// シェーダバイナリ(DXBC)のRDEFチャンクからcbufferの配置を読む
+pub fn read_cbuffer_reflection(dxbc: &[u8]) -> IOResult<Vec<CBufferReflection>> {
+    let rdef = match find_dxbc_chunk(dxbc, b"RDEF")? {
+        Some(c) => c,
+        None => return bad_data("shader has no RDEF chunk (stripped reflection?)"),
+    };
+    let cb_count = read_u32(rdef, 0)? as usize;
+    let cb_offset = read_u32(rdef, 4)? as usize;
+    let major = *rdef.get(17).unwrap_or(&0);
+    // SM5.0以降は変数の記述にテクスチャ/サンプラーの情報が増えている
+    let var_stride = if major >= 5 { 40 } else { 24 };
+
+    let mut out = Vec::with_capacity(cb_count);
+    for n in 0..cb_count {
+        let cb = cb_offset + n * 24;
+        let var_count = read_u32(rdef, cb + 4)? as usize;
+        let var_offset = read_u32(rdef, cb + 8)? as usize;
+        let mut variables = Vec::with_capacity(var_count);
+        for m in 0..var_count {
+            let v = var_offset + m * var_stride;
+            variables.push(CBufferVariable {
+                name: read_cstr(rdef, read_u32(rdef, v)? as usize)?,
+                offset: read_u32(rdef, v + 4)?,
+                size: read_u32(rdef, v + 8)?,
+            });
+        }
+        out.push(CBufferReflection {
+            name: read_cstr(rdef, read_u32(rdef, cb)? as usize)?,
+            size: read_u32(rdef, cb + 12)?,
+            variables,
+        });
+    }
+
+    Ok(out)
+}
+
+/// 頂点属性として使える型(対応するDXGIフォーマットを持つ)
+pub trait VertexAttribute {
+    const FORMAT: DXGI_FORMAT;
+}
+/// 正規化して読む整数の属性(UNORM/SNORM)
+#[repr(transparent)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Normalized<T>(pub T);
+macro_rules! VertexAttributeFormats {
+    ($($t: ty => $f: expr),*) => {
+        $(impl VertexAttribute for $t {
+            const FORMAT: DXGI_FORMAT = $f;
+        })*
+    };
+}
+VertexAttributeFormats!(
+    f32 => DXGI_FORMAT_R32_FLOAT, [f32; 2] => DXGI_FORMAT_R32G32_FLOAT,
+    [f32; 3] => DXGI_FORMAT_R32G32B32_FLOAT, [f32; 4] => DXGI_FORMAT_R32G32B32A32_FLOAT,
+    u32 => DXGI_FORMAT_R32_UINT, [u32; 2] => DXGI_FORMAT_R32G32_UINT,
+    [u32; 3] => DXGI_FORMAT_R32G32B32_UINT, [u32; 4] => DXGI_FORMAT_R32G32B32A32_UINT,
+    i32 => DXGI_FORMAT_R32_SINT, [i32; 2] => DXGI_FORMAT_R32G32_SINT,
+    [i32; 3] => DXGI_FORMAT_R32G32B32_SINT, [i32; 4] => DXGI_FORMAT_R32G32B32A32_SINT,
+    [u16; 2] => DXGI_FORMAT_R16G16_UINT, [u16; 4] => DXGI_FORMAT_R16G16B16A16_UINT,
+    [i16; 2] => DXGI_FORMAT_R16G16_SINT, [i16; 4] => DXGI_FORMAT_R16G16B16A16_SINT,
+    [u8; 4] => DXGI_FORMAT_R8G8B8A8_UINT, [i8; 4] => DXGI_FORMAT_R8G8B8A8_SINT,
+    Normalized<[u16; 2]> => DXGI_FORMAT_R16G16_UNORM, Normalized<[u16; 4]> => DXGI_FORMAT_R16G16B16A16_UNORM,
+    Normalized<[i16; 2]> => DXGI_FORMAT_R16G16_SNORM, Normalized<[i16; 4]> => DXGI_FORMAT_R16G16B16A16_SNORM,
+    Normalized<[u8; 4]> => DXGI_FORMAT_R8G8B8A8_UNORM, Normalized<[i8; 4]> => DXGI_FORMAT_R8G8B8A8_SNORM
+);
+impl<T, const N: usize> VertexAttribute for Vector<T, N>
+where
+    [T; N]: VertexAttribute,
+{
+    const FORMAT: DXGI_FORMAT = <[T; N] as VertexAttribute>::FORMAT;
+}
+
+/// 頂点構造体の1メンバー
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VertexElement {
+    /// セマンティクス名(0終端)
+    pub semantic: &'static str,
+    pub semantic_index: u32,
+    pub format: DXGI_FORMAT,
+    pub offset: u32,
+}
+impl VertexElement {
+    /// 終端を除いたセマンティクス名
+    pub fn semantic_name(&self) -> &'static str {
+        self.semantic.trim_end_matches('\0')
+    }
+    fn d3d12(
+        &self,
+        slot: u32,
+        class: D3D12_INPUT_CLASSIFICATION,
+        step_rate: u32,
+    ) -> D3D12_INPUT_ELEMENT_DESC {
+        debug_assert!(self.semantic.ends_with('\0'));
+        D3D12_INPUT_ELEMENT_DESC {
+            SemanticName: self.semantic.as_ptr() as _,
+            SemanticIndex: self.semantic_index,
+            Format: self.format,
+            InputSlot: slot,
+            AlignedByteOffset: self.offset,
+            InputSlotClass: class,
+            InstanceDataStepRate: step_rate,
+        }
+    }
+}
+
+/// 頂点バッファに入れる構造体(#[derive(Vertex)]で実装する)
+///
+/// セマンティクス名は静的な文字列を指すので、作った入力エレメントは構造体と独立して持ち回せる
+pub trait Vertex: Sized {
+    /// メンバーの配置
+    fn elements() -> Vec<VertexElement>;
+
+    /// 頂点データとしての入力エレメント(PipelineStateTracker::set_vertex_processing用)
+    fn input_elements(slot: u32) -> Vec<D3D12_INPUT_ELEMENT_DESC> {
+        Self::elements()
+            .iter()
+            .map(|e| e.d3d12(slot, D3D12_INPUT_CLASSIFICATION_PER_VERTEX_DATA, 0))
+            .collect()
+    }
+    /// インスタンスデータとしての入力エレメント
+    fn instance_input_elements(slot: u32, step_rate: u32) -> Vec<D3D12_INPUT_ELEMENT_DESC> {
+        Self::elements()
+            .iter()
+            .map(|e| {
+                e.d3d12(
+                    slot,
+                    D3D12_INPUT_CLASSIFICATION_PER_INSTANCE_DATA,
+                    step_rate,
+                )
+            })
+            .collect()
+    }
+    /// D3D11の入力エレメント(d3d11::Device::new_input_layout用)
+    fn d3d11_input_elements(slot: u32) -> Vec<crate::d3d11::InputElement> {
+        Self::elements()
+            .iter()
+            .map(|e| {
+                crate::d3d11::InputElement::per_vertex(
+                    e.semantic.as_ptr() as _,
+                    e.semantic_index,
+                    e.format,
+                    slot,
+                    e.offset,
+                )
+            })
+            .collect()
+    }
+    /// D3D11のインスタンスデータとしての入力エレメント
+    fn d3d11_instance_input_elements(slot: u32, step_rate: u32) -> Vec<crate::d3d11::InputElement> {
+        Self::elements()
+            .iter()
+            .map(|e| {
+                crate::d3d11::InputElement::per_instance(
+                    e.semantic.as_ptr() as _,
+                    e.semantic_index,
+                    e.format,
+                    slot,
+                    e.offset,
+                    step_rate,
+                )
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(ConstantBuffer)]
+    struct Scene {
+        view_proj: Float4x4,
+        light_dir: Float3,
+        intensity: f32,
+        jitter: Float2,
+        tint: Float3,
+        weights: [f32; 2],
+        bias: f32,
+    }
+
+    #[derive(ConstantBuffer)]
+    struct ArrayPacking {
+        m: Float4x4,
+        v: [Float4; 2],
+        a: [f32; 2],
+        b: f32,
+    }
+
+    #[derive(ConstantBuffer)]
+    struct Matrices {
+        row: RowMajor<3, 4>,
+        column: ColumnMajor<3, 4>,
+        tail: f32,
+    }
+
+    fn offsets<T: ConstantBuffer>() -> Vec<(&'static str, usize, usize)> {
+        T::fields()
+            .into_iter()
+            .map(|f| (f.name, f.offset, f.size))
+            .collect()
+    }
+    fn f32_at(bytes: &[u8], offset: usize) -> f32 {
+        f32::from_le_bytes([
+            bytes[offset],
+            bytes[offset + 1],
+            bytes[offset + 2],
+            bytes[offset + 3],
+        ])
+    }
+
+    #[test]
+    fn pack_offset_rules() {
+        assert_eq!(pack_offset(0, 4, false), 0);
+        assert_eq!(pack_offset(4, 12, false), 4);
+        assert_eq!(pack_offset(8, 12, false), 16);
+        assert_eq!(pack_offset(12, 4, false), 12);
+        assert_eq!(pack_offset(4, 4, true), 16);
+        assert_eq!(pack_offset(16, 4, true), 16);
+        assert_eq!(pack_offset(17, 0, true), 32);
+    }
+
+    #[test]
+    fn vectors_do_not_straddle_registers() {
+        assert_eq!(
+            offsets::<Scene>(),
+            vec![
+                ("view_proj", 0, 64),
+                ("light_dir", 64, 12),
+                ("intensity", 76, 4),
+                ("jitter", 80, 8),
+                ("tint", 96, 12),
+                ("weights", 112, 20),
+                ("bias", 132, 4),
+            ]
+        );
+    }
+
+    #[test]
+    fn arrays_pad_elements_to_registers() {
+        assert_eq!(<[f32; 2]>::SIZE, 20);
+        assert_eq!(<[Float3; 3]>::SIZE, 44);
+        assert_eq!(
+            offsets::<ArrayPacking>(),
+            vec![("m", 0, 64), ("v", 64, 32), ("a", 96, 20), ("b", 116, 4)]
+        );
+
+        let bytes = [1.0f32, 2.0].to_packed_bytes();
+        assert_eq!((f32_at(&bytes, 0), f32_at(&bytes, 16)), (1.0, 2.0));
+    }
+
+    #[test]
+    fn row_and_column_major_matrices() {
+        assert_eq!(RowMajor::<3, 4>::SIZE, 48);
+        assert_eq!(ColumnMajor::<3, 4>::SIZE, 60);
+        assert_eq!(
+            offsets::<Matrices>(),
+            vec![("row", 0, 48), ("column", 48, 60), ("tail", 108, 4)]
+        );
+
+        let m = [
+            [1.0, 2.0, 3.0, 4.0],
+            [5.0, 6.0, 7.0, 8.0],
+            [9.0, 10.0, 11.0, 12.0],
+        ];
+        let cb = Matrices {
+            row: RowMajor(m),
+            column: ColumnMajor(m),
+            tail: 13.0,
+        };
+        let bytes = cb.to_bytes();
+        // row_major: m[1][2]は2番目のレジスタの3要素目
+        assert_eq!(f32_at(&bytes, 16 + 8), 7.0);
+        // column_major: m[1][2]は3番目のレジスタの2要素目
+        assert_eq!(f32_at(&bytes, 48 + 32 + 4), 7.0);
+        assert_eq!(f32_at(&bytes, 108), 13.0);
+    }
+
+    #[test]
+    fn gpu_size_rounds_to_registers() {
+        assert_eq!(Scene::SIZE, 136);
+        assert_eq!(Scene::gpu_size(), 144);
+        assert_eq!(ArrayPacking::gpu_size(), 128);
+        assert_eq!(Matrices::gpu_size(), 112);
+
+        let mut out = [0xffu8; 8];
+        assert!(Matrices {
+            row: RowMajor([[0.0; 4]; 3]),
+            column: ColumnMajor([[0.0; 4]; 3]),
+            tail: 0.0,
+        }
+        .write_to(&mut out)
+        .is_err());
+    }
+
+    #[test]
+    fn rdef_reflection_matches_derived_layout() {
+        let dxbc = include_bytes!("../testdata/cbuffer.dxbc");
+        let cbuffers = read_cbuffer_reflection(dxbc).unwrap();
+        assert_eq!(cbuffers.len(), 1);
+        assert_eq!(cbuffers[0].name, "Scene");
+        assert_eq!(cbuffers[0].size, 144);
+        assert_eq!(cbuffers[0].variables[5].name, "weights");
+        Scene::check_reflection(&cbuffers[0]).unwrap();
+        assert!(ArrayPacking::check_reflection(&cbuffers[0]).is_err());
+
+        assert!(read_cbuffer_reflection(b"DXIL").is_err());
+        assert!(read_cbuffer_reflection(&dxbc[..60]).is_err());
+    }
+
+    #[derive(Clone, Copy, Vertex)]
+    #[repr(C)]
+    struct MeshVertex {
+        #[semantic("POSITION")]
+        pos: [f32; 3],
+        #[semantic("TEXCOORD", 1)]
+        uv: Float2,
+        #[semantic("COLOR")]
+        color: Normalized<[u8; 4]>,
+        #[semantic("BLENDINDICES")]
+        bones: [u16; 4],
+    }
+
+    #[test]
+    fn derived_vertex_elements() {
+        let e = MeshVertex::elements();
+        assert_eq!(
+            e.iter()
+                .map(|e| (e.semantic, e.semantic_index, e.format, e.offset))
+                .collect::<Vec<_>>(),
+            vec![
+                ("POSITION\0", 0, DXGI_FORMAT_R32G32B32_FLOAT, 0),
+                ("TEXCOORD\0", 1, DXGI_FORMAT_R32G32_FLOAT, 12),
+                ("COLOR\0", 0, DXGI_FORMAT_R8G8B8A8_UNORM, 20),
+                ("BLENDINDICES\0", 0, DXGI_FORMAT_R16G16B16A16_UINT, 24),
+            ]
+        );
+        assert_eq!(e[1].semantic_name(), "TEXCOORD");
+
+        let d = MeshVertex::instance_input_elements(2, 1);
+        assert_eq!(d.len(), 4);
+        assert_eq!((d[3].InputSlot, d[3].AlignedByteOffset), (2, 24));
+        assert_eq!(
+            d[3].InputSlotClass,
+            D3D12_INPUT_CLASSIFICATION_PER_INSTANCE_DATA
+        );
+        assert_eq!(d[3].InstanceDataStepRate, 1);
+    }
+
+    trait ToPackedBytes {
+        fn to_packed_bytes(&self) -> Vec<u8>;
+    }
+    impl<T: HlslPacked> ToPackedBytes for T {
+        fn to_packed_bytes(&self) -> Vec<u8> {
+            let mut v = vec![0; T::SIZE];
+            self.write_packed(&mut v);
+            v
+        }
+    }
+}
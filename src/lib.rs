@@ -1,5 +1,8 @@
 //! COM Driver
 
+// deriveマクロが生成する::comdriveのパスをクレート内でも解決させる
+extern crate self as comdrive;
+
 use univstring::*;
 use std::io::{Result as IOResult, Error as IOError};
 use winapi::shared::windef::HWND;
@@ -8,7 +11,7 @@ use winapi::shared::winerror::SUCCEEDED;
 use winapi::um::unknwnbase::IUnknown;
 use winapi::Interface;
 use winapi::um::unknwnbase::LPUNKNOWN;
-use winapi::shared::guiddef::{REFIID, REFCLSID};
+use winapi::shared::guiddef::{GUID, REFIID, REFCLSID};
 use winapi::shared::minwindef::{DWORD, LPVOID};
 
 pub trait ResultCarrier
@@ -77,6 +80,69 @@ macro_rules! HandleWrapper {
     }
 }
 
+/// デバッグ用の名前やデータを関連付けられる
+/// (D2D/DirectCompositionのオブジェクトはSetPrivateDataを持たないので対象外)
+pub trait DebugName
+{
+    /// デバッグ用の名前をつける(デバッグレイヤーやPIXに表示される)
+    fn set_name(&self, name: &str) -> IOResult<()>;
+    /// 任意のデータを関連付ける
+    fn set_private_data(&self, guid: &GUID, data: &[u8]) -> IOResult<()>;
+}
+/// SetNameを持つもの(ID3D12Object)
+macro_rules! DebugNameBySetName {
+    (for $($t: ty),*) => {
+        $(impl crate::DebugName for $t {
+            fn set_name(&self, name: &str) -> IOResult<()> {
+                let name: Vec<u16> = name.encode_utf16().chain(Some(0)).collect();
+                unsafe { (*self.0).SetName(name.as_ptr()).checked() }
+            }
+            fn set_private_data(&self, guid: &crate::GUID, data: &[u8]) -> IOResult<()> {
+                unsafe { (*self.0).SetPrivateData(guid, data.len() as _, data.as_ptr() as _).checked() }
+            }
+        })*
+    }
+}
+/// WKPDID_D3DDebugObjectNameで名前をつけるもの(ID3D11DeviceChild/IDXGIObject)
+macro_rules! DebugNameByPrivateData {
+    (for $($t: ty),*) => {
+        $(impl crate::DebugName for $t {
+            fn set_name(&self, name: &str) -> IOResult<()> {
+                use winapi::um::d3dcommon::WKPDID_D3DDebugObjectName;
+                // 上書きすると警告が出るので一度消す
+                self.set_private_data(&WKPDID_D3DDebugObjectName, &[])?;
+                self.set_private_data(&WKPDID_D3DDebugObjectName, name.as_bytes())
+            }
+            fn set_private_data(&self, guid: &crate::GUID, data: &[u8]) -> IOResult<()> {
+                let p = if data.is_empty() { std::ptr::null() } else { data.as_ptr() };
+                unsafe { (*self.0).SetPrivateData(guid, data.len() as _, p as _).checked() }
+            }
+        })*
+    }
+}
+
+/// PIXなどのツールで表示されるイベントを記録できる
+pub trait EventMarker
+{
+    /// イベントの開始(colorは0xAARRGGBB)
+    fn begin_event(&mut self, color: u32, name: &str) -> &mut Self;
+    /// イベントの終了
+    fn end_event(&mut self) -> &mut Self;
+    /// 単発のマーカー
+    fn set_marker(&mut self, color: u32, name: &str) -> &mut Self;
+    /// スコープを抜けるとend_eventされるイベント
+    fn scoped_event(&mut self, color: u32, name: &str) -> EventScope<'_, Self> where Self: Sized
+    {
+        self.begin_event(color, name);
+        EventScope(self)
+    }
+}
+/// 破棄時にイベントを閉じる
+pub struct EventScope<'a, T: EventMarker>(&'a mut T);
+impl<'a, T: EventMarker> std::ops::Deref for EventScope<'a, T> { type Target = T; fn deref(&self) -> &T { self.0 } }
+impl<'a, T: EventMarker> std::ops::DerefMut for EventScope<'a, T> { fn deref_mut(&mut self) -> &mut T { self.0 } }
+impl<'a, T: EventMarker> Drop for EventScope<'a, T> { fn drop(&mut self) { self.0.end_event(); } }
+
 /// IUnknown Receiver
 #[repr(transparent)]
 pub struct Unknown(*mut IUnknown);
@@ -103,6 +169,7 @@ pub mod d3d11on12;
 pub mod d2;
 pub mod dcomp;
 pub mod dwrite;
+pub mod hlsl;
 pub mod imaging;
 pub mod uianimation;
 
@@ -110,15 +177,15 @@ pub mod traits
 {
     pub use super::dcomp::{SurfaceFactoryProvider, TargetProvider, SurfaceFactory, Surface};
     pub use super::d2::{RenderTarget, GeometrySegment, Shape};
-    pub use super::{ResultCarrier, AsIUnknown, AsRawHandle, Handle};
+    pub use super::{ResultCarrier, AsIUnknown, AsRawHandle, Handle, DebugName, EventMarker};
+    pub use super::hlsl::{HlslPacked, ConstantBuffer, Vertex};
 }
 pub use self::traits::*;
 pub mod submods
 {
-    pub use super::{d3d, dxgi, d3d11, d3d12, d2, dcomp, dwrite, imaging};
+    pub use super::{d3d, dxgi, d3d11, d3d12, d2, dcomp, dwrite, hlsl, imaging};
 }
 
-use winapi::shared::guiddef::GUID;
 /// CoCreateInstance helper(Create InterProcess-Server Object)
 pub(crate) fn co_create_inproc_instance<I: Interface>(clsid: &GUID) -> IOResult<*mut I>
 {
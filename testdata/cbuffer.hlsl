@@ -0,0 +1,21 @@
+// cbuffer.dxbcの元になるシェーダ
+//   fxc /nologo /T vs_5_0 /E main /Fo cbuffer.dxbc cbuffer.hlsl
+
+cbuffer Scene : register(b0)
+{
+    float4x4 view_proj;
+    float3   light_dir;
+    float    intensity;
+    float2   jitter;
+    float3   tint;
+    float    weights[2];
+    float    bias;
+};
+
+float4 main(float3 position : POSITION) : SV_Position
+{
+    float3 lit = tint * (saturate(dot(position, light_dir)) * intensity + bias);
+    float4 p = mul(float4(position + lit * (weights[0] + weights[1]), 1.0f), view_proj);
+    p.xy += jitter * p.w;
+    return p;
+}
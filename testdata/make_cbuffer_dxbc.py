@@ -0,0 +1,61 @@
+#!/usr/bin/env python3
+"""Writes cbuffer.dxbc: a DXBC container holding only an SM5.0 RDEF chunk for
+the cbuffer declared in cbuffer.hlsl
+
+    cbuffer Scene : register(b0)
+    {
+        float4x4 view_proj;  // 0, 64
+        float3   light_dir;  // 64, 12
+        float    intensity;  // 76, 4
+        float2   jitter;     // 80, 8
+        float3   tint;       // 96, 12
+        float    weights[2]; // 112, 20
+        float    bias;       // 132, 4
+    };                       // 144 bytes
+
+The RDEF layout follows fxc's output (RD11 header, 40-byte variable descs).
+The container checksum is left zero; comdrive does not verify it.
+
+This is a stand-in until a blob compiled by fxc from cbuffer.hlsl (see the
+command line at the top of that file) is checked in; such a blob replaces
+cbuffer.dxbc as is and makes this script unnecessary.
+"""
+import struct
+
+VARS = [("view_proj", 0, 64), ("light_dir", 64, 12), ("intensity", 76, 4),
+        ("jitter", 80, 8), ("tint", 96, 12), ("weights", 112, 20), ("bias", 132, 4)]
+CB_NAME, CB_SIZE = "Scene", 144
+
+header_size = 60
+cb_offset = header_size
+var_offset = cb_offset + 24
+strings_offset = var_offset + 40 * len(VARS)
+
+strings = b""
+def string(s):
+    global strings
+    off = strings_offset + len(strings)
+    strings += s.encode() + b"\0"
+    return off
+
+cb_name = string(CB_NAME)
+var_names = [string(n) for n, _, _ in VARS]
+creator = string("comdrive test data")
+while len(strings) % 4:
+    strings += b"\xab"
+
+rdef = struct.pack("<IIIIBBHII", 1, cb_offset, 0, header_size, 0, 5, 0xFFFE, 0, creator)
+rdef += b"RD11" + struct.pack("<7I", 60, 24, 32, 40, 36, 12, 0)
+assert len(rdef) == header_size
+rdef += struct.pack("<6I", cb_name, len(VARS), var_offset, CB_SIZE, 0, 0)
+for name, (_, off, size) in zip(var_names, VARS):
+    rdef += struct.pack("<10I", name, off, size, 2, 0, 0, 0xFFFFFFFF, 0, 0xFFFFFFFF, 0)
+rdef += strings
+
+chunk = b"RDEF" + struct.pack("<I", len(rdef)) + rdef
+chunk_offset = 32 + 4
+total = chunk_offset + len(chunk)
+dxbc = b"DXBC" + b"\0" * 16 + struct.pack("<III", 1, total, 1) + struct.pack("<I", chunk_offset) + chunk
+
+with open(__file__.replace("make_cbuffer_dxbc.py", "cbuffer.dxbc"), "wb") as f:
+    f.write(dxbc)